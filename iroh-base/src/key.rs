@@ -1,6 +1,7 @@
 //! Cryptographic key handling for `iroh-net`.
 
 mod encryption;
+mod passphrase;
 
 use std::{
     fmt::{Debug, Display},
@@ -21,6 +22,7 @@ use ttl_cache::TtlCache;
 
 pub use self::encryption::SharedSecret;
 use self::encryption::{public_ed_box, secret_ed_box};
+pub use self::passphrase::{open_with_passphrase, seal_with_passphrase};
 
 #[derive(Debug)]
 struct CryptoKeys {
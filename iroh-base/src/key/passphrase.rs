@@ -0,0 +1,120 @@
+//! Encryption of secret keys with a passphrase, for backups and moving keys between devices.
+
+use aead::{AeadCore, AeadInPlace, KeyInit, OsRng};
+use anyhow::{anyhow, ensure, Result};
+use chacha20poly1305::XChaCha20Poly1305;
+use rand_core::RngCore;
+use sha2::Sha256;
+
+use super::encryption::NONCE_LEN;
+
+/// Version of the format written by [`seal_with_passphrase`].
+const VERSION: u8 = 1;
+/// Number of PBKDF2 iterations to derive the encryption key from the passphrase.
+const ITERATIONS: u32 = 100_000;
+/// Range of PBKDF2 iterations accepted when decrypting.
+///
+/// The iteration count is read from the encrypted data, so it is bounded to not spend an
+/// unbounded amount of time deriving the key for crafted input.
+const ACCEPTED_ITERATIONS: std::ops::RangeInclusive<u32> = 10_000..=10_000_000;
+const SALT_LEN: usize = 16;
+/// Length of the version, iteration count and salt, which are authenticated as associated data.
+const PARAMS_LEN: usize = 1 + 4 + SALT_LEN;
+const HEADER_LEN: usize = PARAMS_LEN + NONCE_LEN;
+
+/// Encrypts `secret` with a key derived from `passphrase`.
+///
+/// The result contains the salt and nonce and can be decrypted with [`open_with_passphrase`].
+pub fn seal_with_passphrase(passphrase: &str, secret: &[u8]) -> Vec<u8> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, ITERATIONS).into());
+
+    let mut out = Vec::with_capacity(HEADER_LEN + secret.len() + 16);
+    out.push(VERSION);
+    out.extend_from_slice(&ITERATIONS.to_be_bytes());
+    out.extend_from_slice(&salt);
+    out.extend_from_slice(&nonce);
+    let mut buffer = secret.to_vec();
+    cipher
+        .encrypt_in_place(&nonce, &out[..PARAMS_LEN], &mut buffer)
+        .expect("encryption failed");
+    out.extend_from_slice(&buffer);
+    out
+}
+
+/// Decrypts data created with [`seal_with_passphrase`].
+///
+/// Fails if the passphrase is wrong or the data was modified.
+pub fn open_with_passphrase(passphrase: &str, data: &[u8]) -> Result<Vec<u8>> {
+    ensure!(data.len() > HEADER_LEN, "too short");
+    ensure!(data[0] == VERSION, "unsupported version {}", data[0]);
+    let iterations = u32::from_be_bytes(data[1..5].try_into().expect("checked length"));
+    ensure!(
+        ACCEPTED_ITERATIONS.contains(&iterations),
+        "unsupported number of iterations {iterations}"
+    );
+    let salt = &data[5..5 + SALT_LEN];
+    let nonce: [u8; NONCE_LEN] = data[5 + SALT_LEN..HEADER_LEN]
+        .try_into()
+        .expect("checked length");
+
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt, iterations).into());
+    let mut buffer = data[HEADER_LEN..].to_vec();
+    cipher
+        .decrypt_in_place(&nonce.into(), &data[..PARAMS_LEN], &mut buffer)
+        .map_err(|_| anyhow!("decryption failed, wrong passphrase?"))?;
+    Ok(buffer)
+}
+
+/// Derive the encryption key with PBKDF2-HMAC-SHA256.
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    pbkdf2::pbkdf2_hmac_array::<Sha256, 32>(passphrase.as_bytes(), salt, iterations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passphrase_roundtrip() {
+        let secret = [7u8; 32];
+        let sealed = seal_with_passphrase("correct horse", &secret);
+        assert_eq!(
+            open_with_passphrase("correct horse", &sealed).unwrap(),
+            secret
+        );
+        assert!(open_with_passphrase("wrong horse", &sealed).is_err());
+
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open_with_passphrase("correct horse", &tampered).is_err());
+
+        // the header is authenticated
+        let mut tampered = sealed.clone();
+        tampered[4] ^= 1;
+        assert!(open_with_passphrase("correct horse", &tampered).is_err());
+        let mut tampered = sealed.clone();
+        tampered[PARAMS_LEN - 1] ^= 1;
+        assert!(open_with_passphrase("correct horse", &tampered).is_err());
+    }
+
+    #[test]
+    fn test_passphrase_iterations_bounded() {
+        let mut sealed = seal_with_passphrase("correct horse", &[7u8; 32]);
+        sealed[1..5].copy_from_slice(&u32::MAX.to_be_bytes());
+        let err = open_with_passphrase("correct horse", &sealed).unwrap_err();
+        assert!(err.to_string().contains("iterations"));
+    }
+
+    #[test]
+    fn test_pbkdf2_vector() {
+        // RFC 7914, section 11
+        let key = derive_key("passwd", b"salt", 1);
+        assert_eq!(
+            hex::encode(key),
+            "55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc"
+        );
+    }
+}
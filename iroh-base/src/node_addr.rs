@@ -39,11 +39,25 @@ impl NodeAddr {
         self
     }
 
+    /// Add the given transport addresses to the peer's [`AddrInfo`].
+    pub fn with_transport_addrs(
+        mut self,
+        addresses: impl IntoIterator<Item = TransportAddr>,
+    ) -> Self {
+        self.info.transport_addrs = addresses.into_iter().collect();
+        self
+    }
+
     /// Get the direct addresses of this peer.
     pub fn direct_addresses(&self) -> impl Iterator<Item = &SocketAddr> {
         self.info.direct_addresses.iter()
     }
 
+    /// Get the transport addresses of this peer.
+    pub fn transport_addrs(&self) -> impl Iterator<Item = &TransportAddr> {
+        self.info.transport_addrs.iter()
+    }
+
     /// Get the derp url of this peer.
     pub fn derp_url(&self) -> Option<&DerpUrl> {
         self.info.derp_url.as_ref()
@@ -58,6 +72,7 @@ impl From<(PublicKey, Option<DerpUrl>, &[SocketAddr])> for NodeAddr {
             info: AddrInfo {
                 derp_url,
                 direct_addresses: direct_addresses_iter.iter().copied().collect(),
+                transport_addrs: Default::default(),
             },
         }
     }
@@ -70,12 +85,87 @@ pub struct AddrInfo {
     pub derp_url: Option<DerpUrl>,
     /// Socket addresses where the peer might be reached directly.
     pub direct_addresses: BTreeSet<SocketAddr>,
+    /// Addresses where the peer might be reached over additional transports.
+    ///
+    /// This field was added after the others. Encodings which must stay readable by older
+    /// nodes use [`AddrInfoV0`] if there are no transport addresses.
+    #[serde(default)]
+    pub transport_addrs: BTreeSet<TransportAddr>,
 }
 
 impl AddrInfo {
     /// Return whether this addressing information is empty.
     pub fn is_empty(&self) -> bool {
-        self.derp_url.is_none() && self.direct_addresses.is_empty()
+        self.derp_url.is_none()
+            && self.direct_addresses.is_empty()
+            && self.transport_addrs.is_empty()
+    }
+}
+
+/// The serialized form of [`AddrInfo`] before transport addresses were added.
+///
+/// Postcard readers ignore trailing data, so older nodes read an encoded [`AddrInfo`] as
+/// this, without the transport addresses. Readers of data from older nodes fall back to
+/// decoding this form.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AddrInfoV0 {
+    /// The peer's home DERP url.
+    pub derp_url: Option<DerpUrl>,
+    /// Socket addresses where the peer might be reached directly.
+    pub direct_addresses: BTreeSet<SocketAddr>,
+}
+
+impl From<AddrInfoV0> for AddrInfo {
+    fn from(info: AddrInfoV0) -> Self {
+        let AddrInfoV0 {
+            derp_url,
+            direct_addresses,
+        } = info;
+        Self {
+            derp_url,
+            direct_addresses,
+            transport_addrs: Default::default(),
+        }
+    }
+}
+
+impl From<AddrInfo> for AddrInfoV0 {
+    /// Drops the transport addresses.
+    fn from(info: AddrInfo) -> Self {
+        Self {
+            derp_url: info.derp_url,
+            direct_addresses: info.direct_addresses,
+        }
+    }
+}
+
+/// The serialized form of [`NodeAddr`] before transport addresses were added.
+///
+/// See [`AddrInfoV0`].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NodeAddrV0 {
+    /// The node's public key.
+    pub node_id: PublicKey,
+    /// Addressing information to connect to [`Self::node_id`].
+    pub info: AddrInfoV0,
+}
+
+impl From<NodeAddrV0> for NodeAddr {
+    fn from(addr: NodeAddrV0) -> Self {
+        Self {
+            node_id: addr.node_id,
+            info: addr.info.into(),
+        }
+    }
+}
+
+impl From<NodeAddr> for NodeAddrV0 {
+    /// Drops the transport addresses.
+    fn from(addr: NodeAddr) -> Self {
+        Self {
+            node_id: addr.node_id,
+            info: addr.info.into(),
+        }
     }
 }
 
@@ -91,6 +181,7 @@ impl NodeAddr {
             info: AddrInfo {
                 derp_url,
                 direct_addresses: direct_addresses.into_iter().collect(),
+                transport_addrs: Default::default(),
             },
         }
     }
@@ -164,6 +255,56 @@ impl fmt::Debug for DerpUrl {
     }
 }
 
+/// The address of a node on an additional transport, besides UDP and DERP.
+///
+/// This is a [`Url`] whose scheme names the transport, e.g. `unix:///run/iroh.sock` for a
+/// Unix domain socket.  The rest of the URL is only interpreted by the transport itself.
+///
+/// Like for [`DerpUrl`], [`fmt::Debug`] prints the URL rather than the URL struct fields.
+#[derive(
+    Clone, derive_more::Display, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct TransportAddr(Url);
+
+impl TransportAddr {
+    /// Returns the scheme of the address, naming the transport it belongs to.
+    pub fn scheme(&self) -> &str {
+        self.0.scheme()
+    }
+}
+
+impl From<Url> for TransportAddr {
+    fn from(url: Url) -> Self {
+        Self(url)
+    }
+}
+
+impl FromStr for TransportAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let inner = Url::from_str(s).context("invalid URL")?;
+        Ok(TransportAddr(inner))
+    }
+}
+
+/// Dereference to the wrapped [`Url`].
+impl Deref for TransportAddr {
+    type Target = Url;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Debug for TransportAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TransportAddr")
+            .field(&DbgStr(self.0.as_str()))
+            .finish()
+    }
+}
+
 /// Helper struct to format a &str without allocating a String.
 ///
 /// Maybe this is entirely unneeded and the compiler would be smart enough to never allocate
@@ -205,4 +346,16 @@ mod tests {
         let url3 = DerpUrl::from(Url::parse("https://example.com/").unwrap());
         assert_eq!(url, url3);
     }
+
+    #[test]
+    fn test_transport_addr_debug_display() {
+        let addr: TransportAddr = "unix:///run/iroh.sock".parse().unwrap();
+
+        assert_eq!(addr.scheme(), "unix");
+        assert_eq!(
+            format!("{addr:?}"),
+            r#"TransportAddr("unix:///run/iroh.sock")"#
+        );
+        assert_eq!(format!("{addr}"), "unix:///run/iroh.sock");
+    }
 }
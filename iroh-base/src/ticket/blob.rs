@@ -8,7 +8,7 @@ use crate::{
 use anyhow::{ensure, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::node_addr::NodeAddr;
+use crate::node_addr::{NodeAddr, NodeAddrV0};
 
 /// A token containing everything to get a file from the provider.
 ///
@@ -26,25 +26,48 @@ pub struct BlobTicket {
 
 /// Wire format for [`BlobTicket`].
 ///
-/// In the future we might have multiple variants (not versions, since they
-/// might be both equally valid), so this is a single variant enum to force
-/// postcard to add a discriminator.
+/// Tickets without transport addresses are encoded as [`TicketWireFormat::Variant0`], so
+/// that they stay readable by nodes that do not know about transport addresses.
 #[derive(Serialize, Deserialize)]
 enum TicketWireFormat {
-    Variant0(BlobTicket),
+    Variant0(Variant0BlobTicket),
+    Variant1(BlobTicket),
+}
+
+/// A [`BlobTicket`] whose provider has no transport addresses.
+#[derive(Serialize, Deserialize)]
+struct Variant0BlobTicket {
+    node: NodeAddrV0,
+    format: BlobFormat,
+    hash: Hash,
 }
 
 impl Ticket for BlobTicket {
     const KIND: &'static str = "blob";
 
     fn to_bytes(&self) -> Vec<u8> {
-        let data = TicketWireFormat::Variant0(self.clone());
+        let data = if self.node.info.transport_addrs.is_empty() {
+            TicketWireFormat::Variant0(Variant0BlobTicket {
+                node: self.node.clone().into(),
+                format: self.format,
+                hash: self.hash,
+            })
+        } else {
+            TicketWireFormat::Variant1(self.clone())
+        };
         postcard::to_stdvec(&data).expect("postcard serialization failed")
     }
 
     fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, ticket::Error> {
         let res: TicketWireFormat = postcard::from_bytes(bytes).map_err(ticket::Error::Postcard)?;
-        let TicketWireFormat::Variant0(res) = res;
+        let res = match res {
+            TicketWireFormat::Variant0(Variant0BlobTicket { node, format, hash }) => BlobTicket {
+                node: node.into(),
+                format,
+                hash,
+            },
+            TicketWireFormat::Variant1(res) => res,
+        };
         if res.node.info.is_empty() {
             return Err(ticket::Error::Verify("addressing info cannot be empty"));
         }
@@ -181,4 +204,33 @@ mod tests {
         ").unwrap();
         assert_eq_hex!(base32, expected);
     }
+    #[test]
+    fn test_ticket_base32_transport_addrs() {
+        let hash =
+            Hash::from_str("0b84d358e4c8be6c38626b2182ff575818ba6bd3f4b90464994be14cb354a072")
+                .unwrap();
+        let node_id =
+            PublicKey::from_str("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                .unwrap();
+
+        let ticket = BlobTicket {
+            node: NodeAddr::new(node_id)
+                .with_transport_addrs(["unix:///run/a.sock".parse().unwrap()]),
+            format: BlobFormat::Raw,
+            hash,
+        };
+        let base32 = base32::parse_vec(ticket.to_string().strip_prefix("blob").unwrap()).unwrap();
+        let expected = parse_hexdump("
+            01 # discriminator for variant 1
+            ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6 # node id, 32 bytes, see above
+            00 # derp url
+            00 # number of addresses (0)
+            01 # number of transport addresses (1)
+            12 756e69783a2f2f2f72756e2f612e736f636b # transport address, 18 bytes, see above
+            00 # format (raw)
+            0b84d358e4c8be6c38626b2182ff575818ba6bd3f4b90464994be14cb354a072 # hash, 32 bytes, see above
+        ").unwrap();
+        assert_eq!(BlobTicket::from_bytes(&base32).unwrap(), ticket);
+        assert_eq_hex!(base32, expected);
+    }
 }
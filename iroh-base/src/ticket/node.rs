@@ -6,7 +6,7 @@ use anyhow::{ensure, Result};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    node_addr::NodeAddr,
+    node_addr::{NodeAddr, NodeAddrV0},
     ticket::{self, Ticket},
 };
 
@@ -20,22 +20,34 @@ pub struct NodeTicket {
 }
 
 /// Wire format for [`NodeTicket`].
+///
+/// Tickets without transport addresses are encoded as [`TicketWireFormat::Variant0`], so
+/// that they stay readable by nodes that do not know about transport addresses.
 #[derive(Serialize, Deserialize)]
 enum TicketWireFormat {
-    Variant0(NodeTicket),
+    Variant0(NodeAddrV0),
+    Variant1(NodeAddr),
 }
 
 impl Ticket for NodeTicket {
     const KIND: &'static str = "node";
 
     fn to_bytes(&self) -> Vec<u8> {
-        let data = TicketWireFormat::Variant0(self.clone());
+        let data = if self.node.info.transport_addrs.is_empty() {
+            TicketWireFormat::Variant0(self.node.clone().into())
+        } else {
+            TicketWireFormat::Variant1(self.node.clone())
+        };
         postcard::to_stdvec(&data).expect("postcard serialization failed")
     }
 
     fn from_bytes(bytes: &[u8]) -> std::result::Result<Self, ticket::Error> {
         let res: TicketWireFormat = postcard::from_bytes(bytes).map_err(ticket::Error::Postcard)?;
-        let TicketWireFormat::Variant0(res) = res;
+        let node = match res {
+            TicketWireFormat::Variant0(node) => node.into(),
+            TicketWireFormat::Variant1(node) => node,
+        };
+        let res = NodeTicket { node };
         if res.node.info.is_empty() {
             return Err(ticket::Error::Verify("addressing info cannot be empty"));
         }
@@ -148,4 +160,27 @@ mod tests {
         ").unwrap();
         assert_eq_hex!(base32, expected);
     }
+
+    #[test]
+    fn test_ticket_base32_transport_addrs() {
+        let node_id =
+            PublicKey::from_str("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                .unwrap();
+
+        let ticket = NodeTicket {
+            node: NodeAddr::new(node_id)
+                .with_transport_addrs(["unix:///run/a.sock".parse().unwrap()]),
+        };
+        let base32 = base32::parse_vec(ticket.to_string().strip_prefix("node").unwrap()).unwrap();
+        let expected = parse_hexdump("
+            01 # variant
+            ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6 # node id, 32 bytes, see above
+            00 # no derp url
+            00 # no direct addresses
+            01 # one transport address
+            12 756e69783a2f2f2f72756e2f612e736f636b # transport address, 18 bytes, see above
+        ").unwrap();
+        assert_eq!(NodeTicket::from_bytes(&base32).unwrap(), ticket);
+        assert_eq_hex!(base32, expected);
+    }
 }
@@ -1,9 +1,12 @@
-use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
 use clap::Parser;
+use dialoguer::{Confirm, Password};
 use futures::TryStreamExt;
-use iroh::base::base32::fmt_short;
+use iroh::base::base32::{self, fmt_short};
 
-use iroh::sync::AuthorId;
+use iroh::sync::{Author, AuthorId};
 use iroh::{client::Iroh, rpc_protocol::ProviderService};
 use quic_rpc::ServiceConnection;
 
@@ -22,6 +25,30 @@ pub enum AuthorCommands {
     /// List authors.
     #[clap(alias = "ls")]
     List,
+    /// Export the secret key of an author, encrypted with a passphrase.
+    ///
+    /// The passphrase is prompted for. Import the key on another node with `author import`.
+    Export {
+        author: AuthorId,
+        /// Write the exported key to this file instead of printing it.
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+    /// Import an author exported with `author export`.
+    Import {
+        /// The exported key. Read from `--file` if not set.
+        key: Option<String>,
+        /// Read the exported key from this file.
+        #[clap(short, long, conflicts_with = "key")]
+        file: Option<PathBuf>,
+        /// Switch to the imported author (only in the Iroh console).
+        #[clap(long)]
+        switch: bool,
+    },
+    /// Delete an author from this node.
+    ///
+    /// Entries signed by the author are kept. The active author cannot be deleted.
+    Delete { author: AuthorId },
 }
 
 impl AuthorCommands {
@@ -53,6 +80,67 @@ impl AuthorCommands {
                     println!("Active author is now {}", fmt_short(author_id.as_bytes()));
                 }
             }
+            Self::Export { author, out } => {
+                let Some(author) = iroh.authors.export(author).await? else {
+                    bail!("Author {} not found.", fmt_short(author.as_bytes()));
+                };
+                let passphrase = Password::new()
+                    .with_prompt("Passphrase")
+                    .with_confirmation("Repeat passphrase", "Passphrases do not match.")
+                    .interact()?;
+                let key = base32::fmt(author.to_encrypted_bytes(&passphrase));
+                match out {
+                    Some(out) => {
+                        tokio::fs::write(&out, key).await?;
+                        println!("Exported author {} to {}", author.id(), out.display());
+                    }
+                    None => println!("{key}"),
+                }
+            }
+            Self::Import { key, file, switch } => {
+                if switch && !env.is_console() {
+                    bail!("The --switch flag is only supported within the Iroh console.");
+                }
+
+                let key = match (key, file) {
+                    (Some(key), _) => key,
+                    (None, Some(file)) => tokio::fs::read_to_string(file).await?,
+                    (None, None) => bail!("Either the key or --file must be set."),
+                };
+                let bytes = base32::parse_vec(key.trim()).context("invalid exported key")?;
+                let passphrase = Password::new().with_prompt("Passphrase").interact()?;
+                let author = Author::from_encrypted_bytes(&passphrase, &bytes)?;
+                let author_id = iroh.authors.import(author).await?;
+                println!("{}", author_id);
+
+                if switch {
+                    env.set_author(author_id)?;
+                    println!("Active author is now {}", fmt_short(author_id.as_bytes()));
+                }
+            }
+            Self::Delete { author } => {
+                if env.author(None).ok() == Some(author) {
+                    bail!(
+                        "Author {} is the active author and cannot be deleted. Switch to another author first.",
+                        fmt_short(author.as_bytes())
+                    );
+                }
+                println!(
+                    "Deleting an author permanently removes its secret key from this node, \n\
+                    unless it was exported before. Entries signed by the author are kept."
+                );
+                let prompt = format!("Delete author {}?", fmt_short(author.as_bytes()));
+                if Confirm::new()
+                    .with_prompt(prompt)
+                    .interact()
+                    .unwrap_or(false)
+                {
+                    iroh.authors.delete(author).await?;
+                    println!("Author {} has been deleted.", fmt_short(author.as_bytes()));
+                } else {
+                    println!("Aborted.")
+                }
+            }
         }
         Ok(())
     }
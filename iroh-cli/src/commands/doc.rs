@@ -17,15 +17,17 @@ use quic_rpc::ServiceConnection;
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncReadExt;
 
-use iroh::bytes::{provider::AddProgress, Hash, Tag};
+use iroh::bytes::{provider::AddProgress, BlobFormat, Hash, HashAndFormat, Tag};
 use iroh::sync::{
-    store::{DownloadPolicy, FilterKind, Query, SortDirection},
-    AuthorId, NamespaceId,
+    bundle::SyncBundle,
+    metadata::DocMetadata,
+    store::{AreaOfInterest, DownloadPolicy, FilterKind, HistoryPolicy, Query, SortDirection},
+    AuthorHeads, AuthorId, NamespaceId,
 };
 use iroh::{
     client::{Doc, Entry, Iroh, LiveEvent},
     rpc_protocol::{DocTicket, ProviderService, SetTagOption, WrapOption},
-    sync_engine::Origin,
+    sync_engine::{LiveEventKind, Origin, SubscribeFilter},
     util::fs::{path_content_info, path_to_key, PathContent},
 };
 
@@ -84,6 +86,89 @@ pub enum DlPolicyCmd {
     },
 }
 
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum SyncAreaCmd {
+    /// Set the area of interest. Without any options, all entries are synced.
+    Set {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Only sync entries whose key starts with this prefix (parsed as UTF-8 string).
+        #[clap(long)]
+        prefix: Vec<String>,
+        /// Only sync entries of this author.
+        #[clap(long)]
+        author: Vec<AuthorId>,
+    },
+    Get {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+    },
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum MetaCmd {
+    /// Set the metadata of a document. Requires write access.
+    Set {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Name of the document.
+        name: String,
+        /// Description of the document.
+        #[clap(long)]
+        description: Option<String>,
+        /// Hash of an icon blob for the document.
+        #[clap(long)]
+        icon: Option<Hash>,
+    },
+    Get {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+    },
+}
+
+#[derive(Debug, Clone, clap::Subcommand)]
+pub enum HistoryPolicyCmd {
+    /// Set the history policy. Without any options, history is disabled.
+    Set {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Keep the last N superseded entries for each key and author.
+        #[clap(long, value_name = "N")]
+        keep_last: Option<u64>,
+        /// Keep superseded entries that are not older than this number of seconds.
+        #[clap(long, value_name = "SECONDS", conflicts_with = "keep_last")]
+        keep_secs: Option<u64>,
+    },
+    Get {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+    },
+}
+
 #[derive(Debug, Clone, Parser)]
 pub enum DocCommands {
     /// Set the active document (only works within the Iroh console).
@@ -135,6 +220,107 @@ pub enum DocCommands {
     /// Set the download policies for a document.
     #[clap(subcommand)]
     DlPolicy(DlPolicyCmd),
+    /// Set the area of interest for a document.
+    ///
+    /// The area of interest restricts which entries are synced with other peers.
+    #[clap(subcommand)]
+    SyncArea(SyncAreaCmd),
+    /// Set or show the metadata of a document.
+    ///
+    /// The metadata contains a human-readable name and description, and is synced with the
+    /// document.
+    #[clap(subcommand)]
+    Meta(MetaCmd),
+    /// Set or show the local label of a document.
+    ///
+    /// Labels are only stored on this node and are not synced. Without a label or `--remove`,
+    /// the current label is shown.
+    Label {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// The label to set.
+        label: Option<String>,
+        /// Remove the label.
+        #[clap(long, conflicts_with = "label")]
+        remove: bool,
+    },
+    /// Set the history policy for a document.
+    ///
+    /// The history policy decides which superseded versions of entries are kept locally.
+    #[clap(subcommand)]
+    HistoryPolicy(HistoryPolicyCmd),
+    /// Create a snapshot of all entries in a document and store it as a blob.
+    ///
+    /// Prints the hash and format of the snapshot, which can be shared as a blob ticket and
+    /// imported on another node with `doc import-snapshot`.
+    Snapshot {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Also include all content blobs available on this node, stored as a collection.
+        #[clap(long)]
+        content: bool,
+    },
+    /// Import a document from a snapshot blob.
+    ///
+    /// The snapshot must be available on this node, e.g. downloaded with `blob get`.
+    ImportSnapshot {
+        /// Hash of the snapshot blob, or of the collection if the snapshot includes content.
+        hash: Hash,
+        /// Whether the hash refers to a collection created with `doc snapshot --content`.
+        #[clap(long)]
+        collection: bool,
+    },
+    /// Write the latest timestamp of each author in a document to a file.
+    ///
+    /// Bring the file to another node to create a bundle with the entries missing here with
+    /// `doc export-bundle`.
+    Heads {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Path to write the heads to.
+        #[clap(short, long)]
+        out: String,
+    },
+    /// Export the entries of a document that another node is missing to a bundle file.
+    ///
+    /// The bundle can be imported on the other node with `doc import-bundle`, without a
+    /// connection between the nodes.
+    ExportBundle {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Path to the heads of the other node, created with `doc heads`.
+        ///
+        /// If not set, all entries are exported.
+        #[clap(long)]
+        heads: Option<String>,
+        /// Also include the content of the entries available on this node.
+        #[clap(long)]
+        content: bool,
+        /// Path to write the bundle to.
+        #[clap(short, long)]
+        out: String,
+    },
+    /// Import a bundle file created with `doc export-bundle`.
+    ImportBundle {
+        /// Path to the bundle.
+        path: String,
+    },
     /// Get entries in a document.
     ///
     /// Shows the author, content hash and content length for all entries for this key.
@@ -157,6 +343,29 @@ pub enum DocCommands {
         #[clap(short, long, value_enum, default_value_t=DisplayContentMode::Auto)]
         mode: DisplayContentMode,
     },
+    /// Show the history of a key in a document.
+    ///
+    /// Shows the current and all superseded entries for this key that were kept according to the
+    /// document's history policy, ordered by author and time.
+    History {
+        /// Document to operate on.
+        ///
+        /// Required unless the document is set through the IROH_DOC environment variable.
+        /// Within the Iroh console, the active document can also set with `doc switch`.
+        #[clap(short, long)]
+        doc: Option<NamespaceId>,
+        /// Key to the entry (parsed as UTF-8 string).
+        key: String,
+        /// Filter by author.
+        #[clap(long)]
+        author: Option<AuthorId>,
+        /// Sort in descending order
+        #[clap(long)]
+        desc: bool,
+        /// How to show the contents of the entries.
+        #[clap(short, long, value_enum, default_value_t=DisplayContentMode::ShortHash)]
+        mode: DisplayContentMode,
+    },
     /// Delete all entries below a key prefix.
     Del {
         /// Document to operate on.
@@ -253,6 +462,21 @@ pub enum DocCommands {
         /// Within the Iroh console, the active document can also set with `doc switch`.
         #[clap(short, long)]
         doc: Option<NamespaceId>,
+        /// Only show entries whose key starts with this prefix (parsed as UTF-8 string).
+        #[clap(long)]
+        prefix: Vec<String>,
+        /// Only show entries with exactly this key (parsed as UTF-8 string).
+        #[clap(long)]
+        key: Vec<String>,
+        /// Only show entries of this author.
+        #[clap(long)]
+        author: Vec<AuthorId>,
+        /// Only show events of this kind, e.g. `insert-remote` or `sync-finished`.
+        #[clap(long)]
+        kind: Vec<LiveEventKind>,
+        /// Show the current matching entries before watching for changes.
+        #[clap(long)]
+        replay: bool,
     },
     /// Stop syncing a document.
     Leave {
@@ -340,6 +564,9 @@ impl DocCommands {
                     bail!("The --switch flag is only supported within the Iroh console.");
                 }
 
+                if let Some(metadata) = &ticket.metadata {
+                    print_metadata(metadata);
+                }
                 let doc = iroh.docs.import(ticket).await?;
                 println!("{}", doc.id());
 
@@ -349,9 +576,16 @@ impl DocCommands {
                 }
             }
             Self::List => {
-                let mut stream = iroh.docs.list().await?;
-                while let Some((id, kind)) = stream.try_next().await? {
-                    println!("{id} {kind}")
+                let mut stream = iroh.docs.list_with_names().await?;
+                while let Some(doc) = stream.try_next().await? {
+                    let mut line = format!("{} {}", doc.id, doc.capability);
+                    if let Some(label) = doc.label {
+                        line.push_str(&format!(" [{label}]"));
+                    }
+                    if let Some(name) = doc.name {
+                        line.push_str(&format!(" {name}"));
+                    }
+                    println!("{line}")
                 }
             }
             Self::Share { doc, mode } => {
@@ -419,6 +653,27 @@ impl DocCommands {
                     println!("{}", fmt_entry(&doc, &entry, mode).await);
                 }
             }
+            Self::History {
+                doc,
+                key,
+                author,
+                desc,
+                mode,
+            } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let mut query = Query::history(key);
+                if let Some(author) = author {
+                    query = query.author(author);
+                }
+                if desc {
+                    query = query.sort_direction(SortDirection::Desc);
+                }
+                let mut stream = doc.get_many(query.build()).await?;
+                while let Some(entry) = stream.try_next().await? {
+                    let time = fmt_timestamp(entry.timestamp());
+                    println!("{time} {}", fmt_entry(&doc, &entry, mode).await);
+                }
+            }
             Self::Keys {
                 doc,
                 prefix,
@@ -546,9 +801,30 @@ impl DocCommands {
                     Err(err) => println!("<failed to get content: {err}>"),
                 }
             }
-            Self::Watch { doc } => {
+            Self::Watch {
+                doc,
+                prefix,
+                key,
+                author,
+                kind,
+                replay,
+            } => {
                 let doc = get_doc(iroh, env, doc).await?;
-                let mut stream = doc.subscribe().await?;
+                let filter = prefix
+                    .into_iter()
+                    .fold(SubscribeFilter::all(), |filter, prefix| {
+                        filter.with_key_prefix(prefix)
+                    });
+                let filter = key
+                    .into_iter()
+                    .fold(filter, |filter, key| filter.with_key_exact(key));
+                let filter = author
+                    .into_iter()
+                    .fold(filter, |filter, author| filter.with_author(author));
+                let filter = kind
+                    .into_iter()
+                    .fold(filter, |filter, kind| filter.with_kind(kind));
+                let mut stream = doc.subscribe_filtered(filter, replay).await?;
                 while let Some(event) = stream.next().await {
                     let event = event?;
                     match event {
@@ -604,12 +880,39 @@ impl DocCommands {
                                 ),
                             }
                         }
+                        LiveEvent::SyncProgress(event) => {
+                            println!(
+                                "syncing with peer {}: sent {}, received {}, ranges {} done, {} pending",
+                                fmt_short(event.peer),
+                                event.num_sent,
+                                event.num_recv,
+                                event.num_ranges_reconciled,
+                                event.num_ranges_pending
+                            )
+                        }
                         LiveEvent::NeighborUp(peer) => {
                             println!("neighbor peer up: {peer:?}");
                         }
                         LiveEvent::NeighborDown(peer) => {
                             println!("neighbor peer down: {peer:?}");
                         }
+                        LiveEvent::Conflict { key, heads } => {
+                            let key = std::str::from_utf8(&key).unwrap_or("<bad key>").bold();
+                            println!("conflict on {key}:");
+                            for entry in heads {
+                                println!(
+                                    "  {}",
+                                    fmt_entry(&doc, &entry, DisplayContentMode::ShortHash).await
+                                );
+                            }
+                        }
+                        LiveEvent::Replay { entry, .. } => {
+                            println!(
+                                "existing:      {}",
+                                fmt_entry(&doc, &entry, DisplayContentMode::Auto).await
+                            )
+                        }
+                        LiveEvent::ReplayFinished => println!("watching for changes"),
                     }
                 }
             }
@@ -666,11 +969,193 @@ impl DocCommands {
                     }
                 }
             }
+            Self::SyncArea(SyncAreaCmd::Set {
+                doc,
+                prefix,
+                author,
+            }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let area = prefix
+                    .into_iter()
+                    .fold(AreaOfInterest::full(), |area, prefix| {
+                        area.with_prefix(prefix)
+                    });
+                let area = author
+                    .into_iter()
+                    .fold(area, |area, author| area.with_author(author));
+                if let Err(e) = doc.set_sync_area(area).await {
+                    println!("Could not set the document's area of interest. {e}")
+                }
+            }
+            Self::SyncArea(SyncAreaCmd::Get { doc }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                match doc.get_sync_area().await {
+                    Ok(area) if area.is_full() => println!("Sync all entries in this document."),
+                    Ok(area) => {
+                        if let Some(prefixes) = area.prefixes {
+                            println!("Prefixes:");
+                            for prefix in prefixes {
+                                println!("{}", String::from_utf8_lossy(&prefix))
+                            }
+                        }
+                        if let Some(authors) = area.authors {
+                            println!("Authors:");
+                            for author in authors {
+                                println!("{author}")
+                            }
+                        }
+                    }
+                    Err(x) => {
+                        println!("Could not get the document's area of interest: {x}")
+                    }
+                }
+            }
+            Self::HistoryPolicy(HistoryPolicyCmd::Set {
+                doc,
+                keep_last,
+                keep_secs,
+            }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let history_policy = match (keep_last, keep_secs) {
+                    (Some(n), _) => HistoryPolicy::KeepLast(n),
+                    (None, Some(secs)) => HistoryPolicy::KeepNewerThan(Duration::from_secs(secs)),
+                    (None, None) => HistoryPolicy::Disabled,
+                };
+                if let Err(e) = doc.set_history_policy(history_policy).await {
+                    println!("Could not set the document's history policy. {e}")
+                }
+            }
+            Self::HistoryPolicy(HistoryPolicyCmd::Get { doc }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                match doc.get_history_policy().await {
+                    Ok(HistoryPolicy::Disabled) => {
+                        println!("History is disabled in this document.")
+                    }
+                    Ok(HistoryPolicy::KeepLast(n)) => {
+                        println!("Keep the last {n} superseded entries per key and author.")
+                    }
+                    Ok(HistoryPolicy::KeepNewerThan(max_age)) => {
+                        let max_age = HumanDuration(max_age);
+                        println!("Keep superseded entries newer than {max_age}.")
+                    }
+                    Err(x) => {
+                        println!("Could not get the document's history policy: {x}")
+                    }
+                }
+            }
+            Self::Snapshot { doc, content } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let (snapshot, tag) = doc.snapshot(content, SetTagOption::Auto).await?;
+                println!("Snapshot: {} ({:?})", snapshot.hash, snapshot.format);
+                println!("Tag: {tag}");
+            }
+            Self::ImportSnapshot { hash, collection } => {
+                let format = match collection {
+                    true => BlobFormat::HashSeq,
+                    false => BlobFormat::Raw,
+                };
+                let (doc, outcome) = iroh
+                    .docs
+                    .import_snapshot(HashAndFormat { hash, format })
+                    .await?;
+                println!(
+                    "Imported document {}: {} entries inserted, {} skipped",
+                    doc.id(),
+                    outcome.inserted,
+                    outcome.skipped
+                );
+            }
+            Self::Heads { doc, out } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let heads = doc.author_heads().await?;
+                let path = canonicalize_path(&out)?;
+                tokio::fs::write(&path, heads.encode(None)?).await?;
+                println!(
+                    "Wrote heads of {} authors to {}",
+                    heads.len(),
+                    path.display()
+                );
+            }
+            Self::ExportBundle {
+                doc,
+                heads,
+                content,
+                out,
+            } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let heads = match heads {
+                    Some(path) => {
+                        let bytes = tokio::fs::read(canonicalize_path(&path)?).await?;
+                        AuthorHeads::decode(&bytes)?
+                    }
+                    None => AuthorHeads::default(),
+                };
+                let bundle = doc.export_bundle(heads, content).await?;
+                let path = canonicalize_path(&out)?;
+                tokio::fs::write(&path, bundle.to_bytes()?).await?;
+                println!(
+                    "Wrote {} entries and {} blobs to {}",
+                    bundle.entries().len(),
+                    bundle.content().len(),
+                    path.display()
+                );
+            }
+            Self::ImportBundle { path } => {
+                let bytes = tokio::fs::read(canonicalize_path(&path)?).await?;
+                let bundle = SyncBundle::from_bytes(&bytes)?;
+                let (doc, outcome) = iroh.docs.import_bundle(bundle).await?;
+                println!(
+                    "Imported bundle into document {}: {} entries inserted, {} skipped",
+                    doc.id(),
+                    outcome.inserted,
+                    outcome.skipped
+                );
+            }
+            Self::Meta(MetaCmd::Set {
+                doc,
+                name,
+                description,
+                icon,
+            }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                let mut metadata = DocMetadata::new(name);
+                metadata.description = description;
+                metadata.icon = icon;
+                doc.set_metadata(metadata).await?;
+            }
+            Self::Meta(MetaCmd::Get { doc }) => {
+                let doc = get_doc(iroh, env, doc).await?;
+                match doc.metadata().await? {
+                    Some(metadata) => print_metadata(&metadata),
+                    None => println!("No metadata available for this document."),
+                }
+            }
+            Self::Label { doc, label, remove } => {
+                let doc = get_doc(iroh, env, doc).await?;
+                if label.is_some() || remove {
+                    doc.set_label(label).await?;
+                } else {
+                    match doc.label().await? {
+                        Some(label) => println!("{label}"),
+                        None => println!("This document has no label."),
+                    }
+                }
+            }
         }
         Ok(())
     }
 }
 
+fn print_metadata(metadata: &DocMetadata) {
+    println!("Name: {}", metadata.name);
+    if let Some(description) = &metadata.description {
+        println!("Description: {description}");
+    }
+    if let Some(icon) = &metadata.icon {
+        println!("Icon: {icon}");
+    }
+}
+
 async fn get_doc<C>(
     iroh: &Iroh<C>,
     env: &ConsoleEnv,
@@ -754,6 +1239,17 @@ where
     format!("@{author}: {key} = {content} ({len})")
 }
 
+/// Format an entry timestamp (microseconds since the Unix epoch) as RFC 3339.
+fn fmt_timestamp(timestamp: u64) -> String {
+    time::OffsetDateTime::from_unix_timestamp_nanos(timestamp as i128 * 1000)
+        .ok()
+        .and_then(|t| {
+            t.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
 fn canonicalize_path(path: &str) -> anyhow::Result<PathBuf> {
     let path = PathBuf::from(shellexpand::tilde(&path).to_string());
     Ok(path)
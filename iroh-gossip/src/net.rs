@@ -4,6 +4,7 @@ use anyhow::{anyhow, Context};
 use bytes::{Bytes, BytesMut};
 use futures::{stream::Stream, FutureExt};
 use genawaiter::sync::{Co, Gen};
+use iroh_base::node_addr::AddrInfoV0;
 use iroh_net::{
     dialer::Dialer, key::PublicKey, magic_endpoint::get_remote_node_id, AddrInfo, MagicEndpoint,
     NodeAddr,
@@ -645,7 +646,11 @@ fn decode_peer_data(peer_data: &PeerData) -> anyhow::Result<AddrInfo> {
     if bytes.is_empty() {
         return Ok(AddrInfo::default());
     }
-    let info = postcard::from_bytes(bytes)?;
+    // peers which do not know about transport addresses send the shorter encoding
+    let info = match postcard::from_bytes(bytes) {
+        Ok(info) => info,
+        Err(_) => postcard::from_bytes::<AddrInfoV0>(bytes)?.into(),
+    };
     Ok(info)
 }
 
@@ -665,6 +670,23 @@ mod test {
 
     use super::*;
 
+    #[test]
+    fn test_decode_peer_data_without_transport_addrs() {
+        let old = AddrInfoV0 {
+            derp_url: None,
+            direct_addresses: ["127.0.0.1:1234".parse().unwrap()].into(),
+        };
+        let data = PeerData::new(postcard::to_stdvec(&old).unwrap());
+        assert_eq!(decode_peer_data(&data).unwrap(), AddrInfo::from(old));
+
+        let info = AddrInfo {
+            transport_addrs: ["unix:///run/a.sock".parse().unwrap()].into(),
+            ..Default::default()
+        };
+        let data = encode_peer_data(&info).unwrap();
+        assert_eq!(decode_peer_data(&data).unwrap(), info);
+    }
+
     async fn create_endpoint(derp_map: DerpMap) -> anyhow::Result<MagicEndpoint> {
         MagicEndpoint::builder()
             .alpns(vec![GOSSIP_ALPN.to_vec()])
@@ -705,14 +727,17 @@ mod test {
         let addr1 = AddrInfo {
             derp_url: Some(derp_url.clone()),
             direct_addresses: Default::default(),
+            transport_addrs: Default::default(),
         };
         let addr2 = AddrInfo {
             derp_url: Some(derp_url.clone()),
             direct_addresses: Default::default(),
+            transport_addrs: Default::default(),
         };
         let addr3 = AddrInfo {
             derp_url: Some(derp_url.clone()),
             direct_addresses: Default::default(),
+            transport_addrs: Default::default(),
         };
 
         let go1 = Gossip::from_endpoint(ep1.clone(), Default::default(), &addr1);
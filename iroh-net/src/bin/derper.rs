@@ -8,6 +8,7 @@ use std::{
     path::{Path, PathBuf},
     pin::Pin,
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{anyhow, bail, Context as _, Result};
@@ -22,7 +23,8 @@ use iroh_net::derp::http::{
     ServerBuilder as DerpServerBuilder, TlsAcceptor, TlsConfig as DerpTlsConfig,
 };
 use iroh_net::derp::{self};
-use iroh_net::key::SecretKey;
+use iroh_net::discovery::http::{RecordStore, ANNOUNCE_PATH, DEFAULT_RECORD_TTL};
+use iroh_net::key::{PublicKey, SecretKey};
 use iroh_net::stun;
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
@@ -30,6 +32,7 @@ use tokio::net::{TcpListener, UdpSocket};
 use tokio_rustls_acme::{caches::DirCache, AcmeConfig};
 use tracing::{debug, debug_span, error, info, info_span, trace, warn, Instrument};
 use tracing_subscriber::{prelude::*, EnvFilter};
+use url::Url;
 
 use metrics::StunMetrics;
 
@@ -196,6 +199,14 @@ struct Config {
     tls: Option<TlsConfig>,
     /// Rate limiting configuration
     limits: Option<Limits>,
+    /// Node discovery configuration. If set, the server hosts a store for node address
+    /// records on [`ANNOUNCE_PATH`].
+    discovery: Option<DiscoveryConfig>,
+    /// Mesh configuration. If set, packets for clients connected to another derper of the
+    /// mesh are forwarded to that derper.
+    mesh: Option<MeshConfig>,
+    /// Access control. If set, the derper only relays for the clients it authorizes.
+    auth: Option<AuthConfig>,
     #[cfg(feature = "metrics")]
     /// Metrics serve address. If not set, metrics are not served.
     metrics_addr: Option<SocketAddr>,
@@ -228,12 +239,105 @@ struct TlsConfig {
     captive_portal_port: Option<u16>,
 }
 
+#[serde_as]
 #[derive(Serialize, Deserialize)]
 struct Limits {
     /// Rate limit for accepting new connection. Unlimited if not set.
     accept_conn_limit: Option<f64>,
     /// Burst limit for accepting new connection. Unlimited if not set.
     accept_conn_burst: Option<usize>,
+    /// Rate limit of the bytes per second a single client may send. Unlimited if not set.
+    client_bytes_per_second: Option<usize>,
+    /// Burst limit of the bytes a single client may send. Defaults to
+    /// `client_bytes_per_second`.
+    client_bytes_burst: Option<usize>,
+    /// Rate limit of the packets per second a single client may send. Unlimited if not set.
+    client_packets_per_second: Option<usize>,
+    /// Burst limit of the packets a single client may send. Defaults to
+    /// `client_packets_per_second`.
+    client_packets_burst: Option<usize>,
+    /// If set, only these nodes may connect.
+    #[serde_as(as = "Option<Vec<DisplayFromStr>>")]
+    allowed_nodes: Option<Vec<PublicKey>>,
+    /// Nodes that may not connect.
+    #[serde_as(as = "Option<Vec<DisplayFromStr>>")]
+    denied_nodes: Option<Vec<PublicKey>>,
+}
+
+impl Limits {
+    fn client_limits(&self) -> derp::ClientLimits {
+        let bytes_per_second = self.client_bytes_per_second.unwrap_or_default();
+        let packets_per_second = self.client_packets_per_second.unwrap_or_default();
+        derp::ClientLimits {
+            bytes_per_second,
+            bytes_burst: self.client_bytes_burst.unwrap_or(bytes_per_second),
+            packets_per_second,
+            packets_burst: self.client_packets_burst.unwrap_or(packets_per_second),
+            allow: self
+                .allowed_nodes
+                .as_ref()
+                .map(|nodes| nodes.iter().copied().collect()),
+            deny: self.denied_nodes.iter().flatten().copied().collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiscoveryConfig {
+    /// Time in seconds after which a published node address record expires. Defaults to
+    /// [`DEFAULT_RECORD_TTL`].
+    record_ttl: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MeshConfig {
+    /// Hex encoded 32 byte key, shared by all derpers of the mesh.
+    key: String,
+    /// The urls of the other derpers of the mesh.
+    peers: Vec<derp::DerpUrl>,
+}
+
+impl MeshConfig {
+    fn into_server_config(self) -> Result<derp::MeshConfig> {
+        let mut mesh_key = [0u8; 32];
+        hex::decode_to_slice(self.key.trim(), &mut mesh_key)
+            .context("mesh key must be 32 hex encoded bytes")?;
+        Ok(derp::MeshConfig {
+            mesh_key,
+            peers: self.peers,
+        })
+    }
+}
+
+/// How a private derper decides which clients it relays for.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+enum AuthConfig {
+    /// Allow the nodes listed in a file, one node id per line.
+    AllowList { path: PathBuf },
+    /// Allow the clients presenting a static token.
+    Token { token: String },
+    /// Ask an HTTP service, see [`derp::auth::HttpAuthenticator`].
+    Http { url: Url },
+    /// Ask a service listening on a Unix socket, see [`derp::auth::UnixSocketAuthenticator`].
+    #[cfg(unix)]
+    UnixSocket { path: PathBuf },
+}
+
+impl AuthConfig {
+    async fn authenticator(&self) -> Result<Arc<dyn derp::auth::ClientAuthenticator>> {
+        Ok(match self {
+            Self::AllowList { path } => {
+                Arc::new(derp::auth::AllowListAuthenticator::load(path).await?)
+            }
+            Self::Token { token } => Arc::new(derp::auth::TokenAuthenticator::new(token.clone())),
+            Self::Http { url } => Arc::new(derp::auth::HttpAuthenticator::new(url.clone())?),
+            #[cfg(unix)]
+            Self::UnixSocket { path } => {
+                Arc::new(derp::auth::UnixSocketAuthenticator::new(path.clone()))
+            }
+        })
+    }
 }
 
 impl Default for Config {
@@ -247,6 +351,9 @@ impl Default for Config {
             enable_derp: true,
             tls: None,
             limits: None,
+            discovery: None,
+            mesh: None,
+            auth: None,
             #[cfg(feature = "metrics")]
             metrics_addr: None,
         }
@@ -433,8 +540,30 @@ async fn run(
         (None, HeaderMap::new(), 0)
     };
 
+    let mesh = cfg.mesh.map(MeshConfig::into_server_config).transpose()?;
+    if let Some(mesh) = &mesh {
+        info!(peers = ?mesh.peers, "meshing with other derpers");
+    }
+
+    let client_limits = cfg
+        .limits
+        .as_ref()
+        .map(Limits::client_limits)
+        .unwrap_or_default();
+
+    let authenticator = match &cfg.auth {
+        Some(auth) => {
+            info!("only relaying for authorized clients");
+            Some(auth.authenticator().await?)
+        }
+        None => None,
+    };
+
     let mut builder = DerpServerBuilder::new(addr)
         .secret_key(secret_key.map(Into::into))
+        .mesh(mesh)
+        .client_limits(client_limits)
+        .authenticator(authenticator)
         .headers(headers)
         .tls_config(tls_config.clone())
         .derp_override(Box::new(derp_disabled_handler))
@@ -451,6 +580,25 @@ async fn run(
             Box::new(serve_no_content_handler),
         );
     }
+    if let Some(discovery) = cfg.discovery {
+        let ttl = discovery
+            .record_ttl
+            .map_or(DEFAULT_RECORD_TTL, Duration::from_secs);
+        info!(?ttl, "serving node discovery records on {ANNOUNCE_PATH}");
+        let store = Arc::new(RecordStore::new(ttl));
+        for method in [Method::GET, Method::PUT] {
+            let store = store.clone();
+            builder = builder.request_handler(
+                method,
+                ANNOUNCE_PATH,
+                Box::new(move |r: Request<Incoming>, response: ResponseBuilder| {
+                    store
+                        .handle_request(&r, response)
+                        .map_err(|err| Box::new(err) as HyperError)
+                }),
+            );
+        }
+    }
     let derp_server = builder.spawn().await?;
 
     // captive portal detections must be served over HTTP
@@ -28,6 +28,8 @@ pub fn default_na_derp_node() -> DerpNode {
         url: url.into(),
         stun_only: false,
         stun_port: DEFAULT_DERP_STUN_PORT,
+        discovery: false,
+        auth_token: None,
     }
 }
 
@@ -41,5 +43,7 @@ pub fn default_eu_derp_node() -> DerpNode {
         url: url.into(),
         stun_only: false,
         stun_port: DEFAULT_DERP_STUN_PORT,
+        discovery: false,
+        auth_token: None,
     }
 }
@@ -10,6 +10,7 @@
 
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
+pub mod auth;
 pub(crate) mod client;
 pub(crate) mod client_conn;
 pub(crate) mod clients;
@@ -25,5 +26,8 @@ pub use self::codec::MAX_PACKET_SIZE;
 pub use self::http::Client as HttpClient;
 pub use self::map::{DerpMap, DerpMode, DerpNode};
 pub use self::metrics::Metrics;
-pub use self::server::{ClientConnHandler, MaybeTlsStream as MaybeTlsStreamServer, Server};
+pub use self::server::{
+    ClientConnHandler, MaybeTlsStream as MaybeTlsStreamServer, MeshConfig, Server,
+};
+pub use self::types::{ClientLimits, MeshKey};
 pub use iroh_base::node_addr::DerpUrl;
@@ -0,0 +1,392 @@
+//! Access control for private derp servers.
+//!
+//! By default a derp [`Server`](super::Server) relays traffic for every client that connects
+//! to it. Setting a [`ClientAuthenticator`] restricts it to authorized clients: during the
+//! handshake every client identifies itself with its [`PublicKey`], and may present a token,
+//! which is configured on the client side with [`DerpNode::auth_token`](super::DerpNode).
+//!
+//! Clients that are not authorized are disconnected before the handshake completes.
+
+use std::collections::HashSet;
+use std::path::Path;
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use futures::future::{BoxFuture, FutureExt};
+use url::Url;
+
+use crate::key::PublicKey;
+
+/// How long an authenticator may take to decide on a client.
+pub(crate) const AUTH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Decides which clients may connect to a private derp server.
+pub trait ClientAuthenticator: std::fmt::Debug + Send + Sync + 'static {
+    /// Returns whether the client with the given [`PublicKey`] and token may connect.
+    ///
+    /// Errors are treated as a denial.
+    fn authenticate<'a>(
+        &'a self,
+        client: PublicKey,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>>;
+}
+
+/// Allows the clients whose [`PublicKey`] is on a list.
+#[derive(Debug, Clone)]
+pub struct AllowListAuthenticator {
+    nodes: HashSet<PublicKey>,
+}
+
+impl AllowListAuthenticator {
+    /// Creates an authenticator allowing the given nodes.
+    pub fn new(nodes: impl IntoIterator<Item = PublicKey>) -> Self {
+        Self {
+            nodes: nodes.into_iter().collect(),
+        }
+    }
+
+    /// Loads the allowed nodes from a file.
+    ///
+    /// The file contains one node id per line. Empty lines and lines starting with `#` are
+    /// ignored.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .with_context(|| format!("unable to read allowlist {}", path.display()))?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let nodes = content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                line.parse()
+                    .with_context(|| format!("invalid node id in allowlist: {line}"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { nodes })
+    }
+}
+
+impl ClientAuthenticator for AllowListAuthenticator {
+    fn authenticate<'a>(
+        &'a self,
+        client: PublicKey,
+        _token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        futures::future::ready(Ok(self.nodes.contains(&client))).boxed()
+    }
+}
+
+/// Allows the clients that present a static token.
+#[derive(Clone)]
+pub struct TokenAuthenticator {
+    token: String,
+}
+
+impl std::fmt::Debug for TokenAuthenticator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TokenAuthenticator").finish_non_exhaustive()
+    }
+}
+
+impl TokenAuthenticator {
+    /// Creates an authenticator allowing the clients that present `token`.
+    pub fn new(token: impl Into<String>) -> Self {
+        Self {
+            token: token.into(),
+        }
+    }
+}
+
+impl ClientAuthenticator for TokenAuthenticator {
+    fn authenticate<'a>(
+        &'a self,
+        _client: PublicKey,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        let allowed =
+            token.is_some_and(|token| constant_time_eq(token.as_bytes(), self.token.as_bytes()));
+        futures::future::ready(Ok(allowed)).boxed()
+    }
+}
+
+/// Compares two byte strings in time that only depends on their length.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Asks an HTTP service whether a client may connect.
+///
+/// For every client the authenticator sends `GET <url>?node=<node id>`, with the token, if
+/// any, as a bearer token in the `Authorization` header. A success status allows the client,
+/// `401 Unauthorized` and `403 Forbidden` deny it. Any other response is an error.
+#[derive(Debug, Clone)]
+pub struct HttpAuthenticator {
+    url: Url,
+    client: reqwest::Client,
+}
+
+impl HttpAuthenticator {
+    /// Creates an authenticator asking the service at `url`.
+    pub fn new(url: Url) -> Result<Self> {
+        let client = reqwest::Client::builder().timeout(AUTH_TIMEOUT).build()?;
+        Ok(Self { url, client })
+    }
+}
+
+impl ClientAuthenticator for HttpAuthenticator {
+    fn authenticate<'a>(
+        &'a self,
+        client: PublicKey,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        async move {
+            let mut url = self.url.clone();
+            url.query_pairs_mut()
+                .append_pair("node", &client.to_string());
+            let mut request = self.client.get(url);
+            if let Some(token) = token {
+                request = request.bearer_auth(token);
+            }
+            let response = request.send().await?;
+            let status = response.status();
+            if status.is_success() {
+                Ok(true)
+            } else if status == reqwest::StatusCode::UNAUTHORIZED
+                || status == reqwest::StatusCode::FORBIDDEN
+            {
+                Ok(false)
+            } else {
+                bail!("unexpected response from authenticator: {status}");
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Asks a service listening on a Unix socket whether a client may connect.
+///
+/// For every client the authenticator connects to the socket, writes a line with the node id,
+/// followed by a space and the token if the client presented one, and reads a line with the
+/// answer: `allow` or `deny`.
+///
+/// Tokens that are empty or contain whitespace or control characters cannot be written on
+/// this line unambiguously, and clients presenting them are denied without asking the service.
+#[cfg(unix)]
+#[derive(Debug, Clone)]
+pub struct UnixSocketAuthenticator {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocketAuthenticator {
+    /// Creates an authenticator asking the service listening on `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+#[cfg(unix)]
+impl ClientAuthenticator for UnixSocketAuthenticator {
+    fn authenticate<'a>(
+        &'a self,
+        client: PublicKey,
+        token: Option<&'a str>,
+    ) -> BoxFuture<'a, Result<bool>> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        async move {
+            if let Some(token) = token {
+                ensure!(
+                    is_line_token(token),
+                    "token is empty or contains whitespace or control characters"
+                );
+            }
+            let mut stream = tokio::net::UnixStream::connect(&self.path)
+                .await
+                .with_context(|| format!("unable to connect to {}", self.path.display()))?;
+            let request = match token {
+                Some(token) => format!("{client} {token}\n"),
+                None => format!("{client}\n"),
+            };
+            stream.write_all(request.as_bytes()).await?;
+            let mut answer = String::new();
+            BufReader::new(stream).read_line(&mut answer).await?;
+            match answer.trim() {
+                "allow" => Ok(true),
+                "deny" => Ok(false),
+                answer => bail!("unexpected answer from authenticator: {answer}"),
+            }
+        }
+        .boxed()
+    }
+}
+
+/// Whether `token` can be written after the node id on a line of the Unix socket protocol.
+#[cfg(unix)]
+fn is_line_token(token: &str) -> bool {
+    !token.is_empty() && !token.chars().any(|c| c.is_whitespace() || c.is_control())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::response::Builder as ResponseBuilder;
+    use hyper::body::Incoming;
+    use hyper::{Method, Request, StatusCode};
+
+    use super::*;
+    use crate::derp::http::{ClientBuilder, ServerBuilder};
+    use crate::key::SecretKey;
+
+    #[tokio::test]
+    async fn test_allowlist_authenticator() -> Result<()> {
+        let allowed = SecretKey::generate().public();
+        let denied = SecretKey::generate().public();
+        let content = format!("# our fleet\n\n{allowed}\n");
+        let auth = AllowListAuthenticator::parse(&content)?;
+        assert!(auth.authenticate(allowed, None).await?);
+        assert!(!auth.authenticate(denied, None).await?);
+
+        assert!(AllowListAuthenticator::parse("not a node id").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_token_authenticator() -> Result<()> {
+        let node = SecretKey::generate().public();
+        let auth = TokenAuthenticator::new("secret");
+        assert!(auth.authenticate(node, Some("secret")).await?);
+        assert!(!auth.authenticate(node, Some("secreT")).await?);
+        assert!(!auth.authenticate(node, Some("secret2")).await?);
+        assert!(!auth.authenticate(node, None).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_authenticator() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        // a service that only allows clients presenting the token "secret"
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .request_handler(
+                Method::GET,
+                "/auth",
+                Box::new(|r: Request<Incoming>, response: ResponseBuilder| {
+                    let allowed = r
+                        .headers()
+                        .get(http::header::AUTHORIZATION)
+                        .is_some_and(|value| value == "Bearer secret")
+                        && r.uri().query().is_some_and(|q| q.starts_with("node="));
+                    let status = match allowed {
+                        true => StatusCode::NO_CONTENT,
+                        false => StatusCode::FORBIDDEN,
+                    };
+                    Ok(response
+                        .status(status)
+                        .body(http_body_util::Full::new(Default::default()))?)
+                }),
+            )
+            .spawn()
+            .await?;
+        let url = format!("http://{}/auth", server.addr()).parse()?;
+        let auth = HttpAuthenticator::new(url)?;
+
+        let node = SecretKey::generate().public();
+        assert!(auth.authenticate(node, Some("secret")).await?);
+        assert!(!auth.authenticate(node, Some("other")).await?);
+        assert!(!auth.authenticate(node, None).await?);
+
+        server.shutdown().await;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_socket_authenticator() -> Result<()> {
+        use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+        let dir = testdir::testdir!();
+        let path = dir.join("auth.sock");
+        let listener = tokio::net::UnixListener::bind(&path)?;
+        let allowed = SecretKey::generate().public();
+        let service = tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await?;
+                let (reader, mut writer) = stream.into_split();
+                let mut line = String::new();
+                BufReader::new(reader).read_line(&mut line).await?;
+                let line = line.strip_suffix('\n').context("missing newline")?;
+                let (node, token) = match line.split_once(' ') {
+                    Some((node, token)) => (node, Some(token)),
+                    None => (line, None),
+                };
+                let allow = node == allowed.to_string() && token.map_or(true, |t| t == "secret");
+                let answer = match allow {
+                    true => "allow\n",
+                    false => "deny\n",
+                };
+                writer.write_all(answer.as_bytes()).await?;
+            }
+            #[allow(unreachable_code)]
+            anyhow::Ok(())
+        });
+
+        let auth = UnixSocketAuthenticator::new(path);
+        assert!(auth.authenticate(allowed, None).await?);
+        assert!(
+            !auth
+                .authenticate(SecretKey::generate().public(), None)
+                .await?
+        );
+        // the token is sent after the node id
+        assert!(auth.authenticate(allowed, Some("secret")).await?);
+        assert!(!auth.authenticate(allowed, Some("other")).await?);
+
+        // tokens that would change the request line are rejected
+        for token in ["", "secret deny", "secret\nallow", "secret\t", "secret\0"] {
+            assert!(auth.authenticate(allowed, Some(token)).await.is_err());
+        }
+
+        service.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_private_derp_server() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .authenticator(Some(Arc::new(TokenAuthenticator::new("secret"))))
+            .spawn()
+            .await?;
+        let url: Url = format!("http://{}", server.addr()).parse()?;
+
+        // a client presenting the token may connect
+        let (client, _receiver) = ClientBuilder::new(url.clone())
+            .auth_token(Some("secret".to_string()))
+            .build(SecretKey::generate());
+        client.connect().await?;
+
+        // other clients may not
+        let (client, _receiver) = ClientBuilder::new(url.clone())
+            .auth_token(Some("guess".to_string()))
+            .build(SecretKey::generate());
+        assert!(client.connect().await.is_err());
+        let (client, _receiver) = ClientBuilder::new(url).build(SecretKey::generate());
+        assert!(client.connect().await.is_err());
+
+        server.shutdown().await;
+        Ok(())
+    }
+}
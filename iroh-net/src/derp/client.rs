@@ -18,7 +18,7 @@ use super::{
         recv_frame, write_frame, DerpCodec, Frame, FrameType, MAX_PACKET_SIZE,
         PER_CLIENT_SEND_QUEUE_DEPTH, PROTOCOL_VERSION,
     },
-    types::{ClientInfo, RateLimiter, ServerInfo},
+    types::{ClientInfo, MeshKey, PacketRateLimiter, ServerInfo},
 };
 
 use crate::key::{PublicKey, SecretKey};
@@ -62,7 +62,7 @@ impl ClientReceiver {
     }
 }
 
-type DerpReader = FramedRead<Box<dyn AsyncRead + Unpin + Send + Sync + 'static>, DerpCodec>;
+type DerpReader = FramedRead<Box<dyn AsyncRead + Unpin + Send + 'static>, DerpCodec>;
 
 #[derive(derive_more::Debug)]
 pub struct InnerClient {
@@ -122,6 +122,39 @@ impl Client {
         Ok(())
     }
 
+    /// Asks the server to send a [`ReceivedMessage::PeerPresent`] for every client that is
+    /// connected to it, and from then on a [`ReceivedMessage::PeerPresent`] or
+    /// [`ReceivedMessage::PeerGone`] whenever a client connects or disconnects.
+    ///
+    /// The server closes the connection if this client did not present its mesh key.
+    pub async fn watch_connection_changes(&self) -> Result<()> {
+        self.inner
+            .writer_channel
+            .send(ClientWriterMessage::WatchConns)
+            .await?;
+        Ok(())
+    }
+
+    /// Forwards a packet sent by `srckey` to the node identified by `dstkey`, which is
+    /// connected to the server.
+    ///
+    /// Does not wait for the packet to be queued: errors if the write queue is full. The server
+    /// closes the connection if this client did not present its mesh key.
+    pub fn forward_packet(
+        &self,
+        srckey: PublicKey,
+        dstkey: PublicKey,
+        packet: Bytes,
+    ) -> Result<()> {
+        trace!(%srckey, %dstkey, len = packet.len(), "[DERP] forward");
+
+        self.inner
+            .writer_channel
+            .try_send(ClientWriterMessage::ForwardPacket((srckey, dstkey, packet)))
+            .map_err(|_| anyhow!("write queue full or closed"))?;
+        Ok(())
+    }
+
     /// The local address that the [`Client`] is listening on.
     pub fn local_addr(&self) -> Result<SocketAddr> {
         Ok(self.inner.local_addr)
@@ -165,6 +198,7 @@ fn process_incoming_frame(frame: Frame) -> Result<ReceivedMessage> {
             Ok(ReceivedMessage::KeepAlive)
         }
         Frame::PeerGone { peer } => Ok(ReceivedMessage::PeerGone(peer)),
+        Frame::PeerPresent { peer } => Ok(ReceivedMessage::PeerPresent(peer)),
         Frame::RecvPacket { src_key, content } => {
             let packet = ReceivedMessage::ReceivedPacket {
                 source: src_key,
@@ -205,6 +239,10 @@ enum ClientWriterMessage {
     Ping([u8; 8]),
     /// Tell the server whether or not this client is the user's preferred client
     NotePreferred(bool),
+    /// Ask the server to notify us about clients connecting and disconnecting
+    WatchConns,
+    /// Forward a packet (from the first to the second [`PublicKey`]) to the server
+    ForwardPacket((PublicKey, PublicKey, Bytes)),
     /// Shutdown the writer
     Shutdown,
 }
@@ -217,7 +255,7 @@ enum ClientWriterMessage {
 struct ClientWriter<W: AsyncWrite + Unpin + Send + 'static> {
     recv_msgs: mpsc::Receiver<ClientWriterMessage>,
     writer: FramedWrite<W, DerpCodec>,
-    rate_limiter: Option<RateLimiter>,
+    rate_limiter: Option<PacketRateLimiter>,
 }
 
 impl<W: AsyncWrite + Unpin + Send + 'static> ClientWriter<W> {
@@ -239,6 +277,24 @@ impl<W: AsyncWrite + Unpin + Send + 'static> ClientWriter<W> {
                     write_frame(&mut self.writer, Frame::NotePreferred { preferred }, None).await?;
                     self.writer.flush().await?;
                 }
+                ClientWriterMessage::WatchConns => {
+                    write_frame(&mut self.writer, Frame::WatchConns, None).await?;
+                    self.writer.flush().await?;
+                }
+                ClientWriterMessage::ForwardPacket((src_key, dst_key, packet)) => {
+                    ensure!(
+                        packet.len() <= MAX_PACKET_SIZE,
+                        "packet too big: {}",
+                        packet.len()
+                    );
+                    let frame = Frame::ForwardPacket {
+                        src_key,
+                        dst_key,
+                        packet,
+                    };
+                    write_frame(&mut self.writer, frame, None).await?;
+                    self.writer.flush().await?;
+                }
                 ClientWriterMessage::Shutdown => {
                     return Ok(());
                 }
@@ -253,19 +309,21 @@ impl<W: AsyncWrite + Unpin + Send + 'static> ClientWriter<W> {
 pub struct ClientBuilder {
     secret_key: SecretKey,
     reader: DerpReader,
-    writer: FramedWrite<Box<dyn AsyncWrite + Unpin + Send + Sync + 'static>, DerpCodec>,
+    writer: FramedWrite<Box<dyn AsyncWrite + Unpin + Send + 'static>, DerpCodec>,
     local_addr: SocketAddr,
     is_prober: bool,
     server_public_key: Option<PublicKey>,
     can_ack_pings: bool,
+    mesh_key: Option<MeshKey>,
+    auth_token: Option<String>,
 }
 
 impl ClientBuilder {
     pub fn new(
         secret_key: SecretKey,
         local_addr: SocketAddr,
-        reader: Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
-        writer: Box<dyn AsyncWrite + Unpin + Send + Sync + 'static>,
+        reader: Box<dyn AsyncRead + Unpin + Send + 'static>,
+        writer: Box<dyn AsyncWrite + Unpin + Send + 'static>,
     ) -> Self {
         Self {
             secret_key,
@@ -275,6 +333,8 @@ impl ClientBuilder {
             is_prober: false,
             server_public_key: None,
             can_ack_pings: false,
+            mesh_key: None,
+            auth_token: None,
         }
     }
 
@@ -295,7 +355,19 @@ impl ClientBuilder {
         self
     }
 
-    async fn server_handshake(&mut self) -> Result<(PublicKey, Option<RateLimiter>)> {
+    /// Set the mesh key, identifying this client as another server of the mesh.
+    pub fn mesh_key(mut self, mesh_key: Option<MeshKey>) -> Self {
+        self.mesh_key = mesh_key;
+        self
+    }
+
+    /// Set the token to present to a private derp server.
+    pub fn auth_token(mut self, auth_token: Option<String>) -> Self {
+        self.auth_token = auth_token;
+        self
+    }
+
+    async fn server_handshake(&mut self) -> Result<(PublicKey, Option<PacketRateLimiter>)> {
         debug!("server_handshake: started");
         let server_key = recv_server_key(&mut self.reader)
             .await
@@ -312,7 +384,8 @@ impl ClientBuilder {
             version: PROTOCOL_VERSION,
             can_ack_pings: self.can_ack_pings,
             is_prober: self.is_prober,
-            mesh_key: None,
+            mesh_key: self.mesh_key,
+            auth_token: self.auth_token.clone(),
         };
         debug!("server_handshake: sending client_key: {:?}", &client_info);
         let shared_secret = self.secret_key.shared(&server_key);
@@ -331,17 +404,14 @@ impl ClientBuilder {
         };
         let mut buf = encrypted_message.to_vec();
         shared_secret.open(&mut buf)?;
-        let info: ServerInfo = postcard::from_bytes(&buf)?;
+        let info = ServerInfo::from_bytes(&buf)?;
         if info.version != PROTOCOL_VERSION {
             bail!(
                 "incompatible protocol version, expected {PROTOCOL_VERSION}, got {}",
                 info.version
             );
         }
-        let rate_limiter = RateLimiter::new(
-            info.token_bucket_bytes_per_second,
-            info.token_bucket_bytes_burst,
-        )?;
+        let rate_limiter = PacketRateLimiter::new(&info)?;
 
         debug!("server_handshake: done");
         Ok((server_key, rate_limiter))
@@ -447,6 +517,9 @@ pub enum ReceivedMessage {
     /// Indicates that the client identified by the underlying public key had previously sent you a
     /// packet but has now disconnected from the server.
     PeerGone(PublicKey),
+    /// Indicates that the client identified by the underlying public key connected to the
+    /// server. Only sent to mesh clients that watch the server's connections.
+    PeerPresent(PublicKey),
     /// Sent by the server upon first connect.
     ServerInfo {
         /// How many bytes per second the server says it will accept, including all framing bytes.
@@ -494,7 +567,7 @@ pub enum ReceivedMessage {
 
 pub(crate) async fn send_packet<S: Sink<Frame, Error = std::io::Error> + Unpin>(
     mut writer: S,
-    rate_limiter: &Option<RateLimiter>,
+    rate_limiter: &Option<PacketRateLimiter>,
     dst_key: PublicKey,
     packet: Bytes,
 ) -> Result<()> {
@@ -506,7 +579,7 @@ pub(crate) async fn send_packet<S: Sink<Frame, Error = std::io::Error> + Unpin>(
 
     let frame = Frame::SendPacket { dst_key, packet };
     if let Some(rate_limiter) = rate_limiter {
-        if rate_limiter.check_n(frame.len()).is_err() {
+        if rate_limiter.check(frame.len()).is_err() {
             tracing::warn!("dropping send: rate limit reached");
             return Ok(());
         }
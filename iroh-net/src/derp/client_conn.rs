@@ -2,9 +2,10 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::{Context, Result};
+use anyhow::{ensure, Context, Result};
 use bytes::Bytes;
 use futures::{SinkExt, StreamExt};
+use iroh_base::key::PUBLIC_KEY_LENGTH;
 use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::sync::mpsc;
 use tokio_util::codec::Framed;
@@ -21,7 +22,7 @@ use super::server::MaybeTlsStream;
 use super::{
     codec::{write_frame, KEEP_ALIVE},
     metrics::Metrics,
-    types::{Packet, ServerMessage},
+    types::{Packet, PacketRateLimiter, PeerStatus, ServerMessage},
 };
 
 /// The [`super::server::Server`] side representation of a [`super::client::Client`]'s connection
@@ -58,6 +59,7 @@ pub(crate) struct ClientConnManager {
 ///  - information about a peer leaving the network (This should only happen for peers that this
 ///  client was previously communciating with)
 ///  - packets sent to this client from another client in the network
+///  - clients connecting and disconnecting, if this client is watching the server's connections
 #[derive(Debug)]
 pub(crate) struct ClientChannels {
     /// Queue of packets intended for the client
@@ -66,6 +68,8 @@ pub(crate) struct ClientChannels {
     pub(crate) disco_send_queue: mpsc::Sender<Packet>,
     /// Notify the client that a previous sender has disconnected
     pub(crate) peer_gone: mpsc::Sender<PublicKey>,
+    /// Notify a watching mesh client that a client connected or disconnected
+    pub(crate) peer_status: mpsc::UnboundedSender<PeerStatus>,
 }
 
 pub trait Io: AsyncRead + AsyncWrite + Unpin + std::fmt::Debug {}
@@ -80,6 +84,10 @@ pub struct ClientConnBuilder {
     pub(crate) write_timeout: Option<Duration>,
     pub(crate) channel_capacity: usize,
     pub(crate) server_channel: mpsc::Sender<ServerMessage>,
+    /// Whether the client presented the server's mesh key
+    pub(crate) can_mesh: bool,
+    /// Limits the packets the client may send, if any
+    pub(crate) rate_limiter: Option<PacketRateLimiter>,
 }
 
 impl ClientConnBuilder {
@@ -93,6 +101,8 @@ impl ClientConnBuilder {
             self.write_timeout,
             self.channel_capacity,
             self.server_channel,
+            self.can_mesh,
+            self.rate_limiter,
         )
     }
 }
@@ -109,6 +119,8 @@ impl ClientConnManager {
         write_timeout: Option<Duration>,
         channel_capacity: usize,
         server_channel: mpsc::Sender<ServerMessage>,
+        can_mesh: bool,
+        rate_limiter: Option<PacketRateLimiter>,
     ) -> ClientConnManager {
         let done = CancellationToken::new();
         let client_id = (key, conn_num);
@@ -116,6 +128,7 @@ impl ClientConnManager {
 
         let (disco_send_queue_s, disco_send_queue_r) = mpsc::channel(channel_capacity);
         let (peer_gone_s, peer_gone_r) = mpsc::channel(channel_capacity);
+        let (peer_status_s, peer_status_r) = mpsc::unbounded_channel();
 
         let preferred = Arc::from(AtomicBool::from(false));
 
@@ -125,9 +138,12 @@ impl ClientConnManager {
             send_queue: send_queue_r,
             disco_send_queue: disco_send_queue_r,
             peer_gone: peer_gone_r,
+            peer_status: peer_status_r,
             key,
             preferred: Arc::clone(&preferred),
             server_channel: server_channel.clone(),
+            can_mesh,
+            rate_limiter,
         };
 
         // start io loop
@@ -166,6 +182,7 @@ impl ClientConnManager {
                 send_queue: send_queue_s,
                 disco_send_queue: disco_send_queue_s,
                 peer_gone: peer_gone_s,
+                peer_status: peer_status_s,
             },
         }
     }
@@ -198,11 +215,14 @@ impl ClientConnManager {
 ///  - a PEER_GONE frame to inform the client that a peer they have previously sent messages to
 ///  is gone from the network
 ///  - packets from other peers
+///  - PEER_PRESENT and PEER_GONE frames to a mesh client watching the server's connections
 ///
 /// On the "read" side, it can:
 ///     - receive a ping and write a pong back
 ///     - note whether the client is `preferred`, aka this client is the preferred way
 ///     to speak to the node ID associated with that client.
+///     - if the client presented the mesh key, receive requests to watch the server's
+///     connections and packets forwarded by other servers of the mesh
 #[derive(Debug)]
 pub(crate) struct ClientConnIo {
     /// Io to talk to the client
@@ -215,6 +235,8 @@ pub(crate) struct ClientConnIo {
     disco_send_queue: mpsc::Receiver<Packet>,
     /// Notify the client that a previous sender has disconnected
     peer_gone: mpsc::Receiver<PublicKey>,
+    /// Notify a watching mesh client that a client connected or disconnected
+    peer_status: mpsc::UnboundedReceiver<PeerStatus>,
 
     /// [`PublicKey`] of this client
     key: PublicKey,
@@ -230,6 +252,13 @@ pub(crate) struct ClientConnIo {
     // might find that the alternative is better, once I have a better idea of how this is supposed
     // to be read.
     preferred: Arc<AtomicBool>,
+
+    /// Whether the client presented the server's mesh key, allowing it to watch connections and
+    /// forward packets
+    can_mesh: bool,
+
+    /// Limits the packets the client may send, packets over the limit are dropped
+    rate_limiter: Option<PacketRateLimiter>,
 }
 
 impl ClientConnIo {
@@ -270,6 +299,11 @@ impl ClientConnIo {
                     trace!("peer gone: {:?}", peer);
                     self.send_peer_gone(peer).await?;
                 }
+                status = self.peer_status.recv() => {
+                    let status = status.context("Server.peer_status dropped")?;
+                    trace!("peer status: {:?}", status);
+                    self.send_peer_status(status).await?;
+                }
                 packet = self.send_queue.recv() => {
                     let packet = packet.context("Server.send_queue dropped")?;
                     trace!("send packet");
@@ -321,6 +355,17 @@ impl ClientConnIo {
         write_frame(&mut self.io, Frame::PeerGone { peer }, self.timeout).await
     }
 
+    /// Sends a peer present or peer gone frame to a watching mesh client, does not flush
+    ///
+    /// Errors if the send does not happen within the `timeout` duration
+    async fn send_peer_status(&mut self, status: PeerStatus) -> Result<()> {
+        let frame = match status {
+            PeerStatus::Present(peer) => Frame::PeerPresent { peer },
+            PeerStatus::Gone(peer) => Frame::PeerGone { peer },
+        };
+        write_frame(&mut self.io, frame, self.timeout).await
+    }
+
     /// Writes contents to the client in a `RECV_PACKET` frame. If `srcKey.is_zero`, it uses the
     /// old DERPv1 framing format, otherwise uses the DERPv2 framing format. The bytes of contents
     /// are only valid until this function returns, do not retain the slices.
@@ -353,6 +398,14 @@ impl ClientConnIo {
             }
             Frame::SendPacket { dst_key, packet } => {
                 let packet_len = packet.len();
+                if let Some(rate_limiter) = &self.rate_limiter {
+                    if rate_limiter.check(PUBLIC_KEY_LENGTH + packet_len).is_err() {
+                        trace!("rate limit exceeded, dropping packet");
+                        inc!(Metrics, packets_rate_limited);
+                        inc_by!(Metrics, bytes_rate_limited, packet_len as u64);
+                        return Ok(());
+                    }
+                }
                 self.handle_frame_send_packet(dst_key, packet).await?;
                 inc_by!(Metrics, bytes_recv, packet_len as u64);
             }
@@ -363,6 +416,23 @@ impl ClientConnIo {
             Frame::Health { .. } => {
                 inc!(Metrics, other_packets_recv);
             }
+            Frame::WatchConns => {
+                ensure!(self.can_mesh, "watch conns requires the mesh key");
+                self.send_server(ServerMessage::WatchConns(self.key))
+                    .await?;
+                inc!(Metrics, other_packets_recv);
+            }
+            Frame::ForwardPacket {
+                src_key,
+                dst_key,
+                packet,
+            } => {
+                ensure!(self.can_mesh, "forward packet requires the mesh key");
+                let packet_len = packet.len();
+                self.handle_frame_forward_packet(src_key, dst_key, packet)
+                    .await?;
+                inc_by!(Metrics, bytes_recv, packet_len as u64);
+            }
             _ => {
                 inc!(Metrics, unknown_frames);
             }
@@ -435,6 +505,23 @@ impl ClientConnIo {
         self.transfer_packet(dst_key, packet).await
     }
 
+    /// Sends a packet forwarded by another server of the mesh to the server, which only
+    /// delivers it to its local clients.
+    async fn handle_frame_forward_packet(
+        &self,
+        src_key: PublicKey,
+        dst_key: PublicKey,
+        data: Bytes,
+    ) -> Result<()> {
+        inc!(Metrics, packets_forwarded_in);
+        let packet = Packet {
+            src: src_key,
+            bytes: data,
+        };
+        self.send_server(ServerMessage::ForwardedPacket((dst_key, packet)))
+            .await
+    }
+
     /// Send the given packet to the server. The server will attempt to
     /// send the packet to the destination, dropping the packet if the
     /// destination is not connected, or if the destination client can
@@ -458,6 +545,7 @@ mod tests {
     use std::sync::Arc;
 
     use crate::derp::codec::{recv_frame, FrameType};
+    use crate::derp::types::{ClientLimits, ServerInfo};
     use crate::key::SecretKey;
 
     use super::*;
@@ -469,6 +557,7 @@ mod tests {
         let (send_queue_s, send_queue_r) = mpsc::channel(10);
         let (disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
         let (peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_status_s, peer_status_r) = mpsc::unbounded_channel();
 
         let preferred = Arc::from(AtomicBool::from(true));
         let key = SecretKey::generate().public();
@@ -482,10 +571,13 @@ mod tests {
             send_queue: send_queue_r,
             disco_send_queue: disco_send_queue_r,
             peer_gone: peer_gone_r,
+            peer_status: peer_status_r,
 
             key,
             server_channel: server_channel_s,
             preferred: Arc::clone(&preferred),
+            can_mesh: false,
+            rate_limiter: None,
         };
 
         let done = CancellationToken::new();
@@ -603,6 +695,7 @@ mod tests {
         let (_send_queue_s, send_queue_r) = mpsc::channel(10);
         let (_disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
         let (_peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_status_s, peer_status_r) = mpsc::unbounded_channel();
 
         let preferred = Arc::from(AtomicBool::from(true));
         let key = SecretKey::generate().public();
@@ -617,10 +710,13 @@ mod tests {
             send_queue: send_queue_r,
             disco_send_queue: disco_send_queue_r,
             peer_gone: peer_gone_r,
+            peer_status: peer_status_r,
 
             key,
             server_channel: server_channel_s,
             preferred: Arc::clone(&preferred),
+            can_mesh: false,
+            rate_limiter: None,
         };
 
         let done = CancellationToken::new();
@@ -669,4 +765,74 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_client_conn_rate_limit() -> Result<()> {
+        let (_send_queue_s, send_queue_r) = mpsc::channel(10);
+        let (_disco_send_queue_s, disco_send_queue_r) = mpsc::channel(10);
+        let (_peer_gone_s, peer_gone_r) = mpsc::channel(10);
+        let (_peer_status_s, peer_status_r) = mpsc::unbounded_channel();
+
+        let key = SecretKey::generate().public();
+        let (io, io_rw) = tokio::io::duplex(1024);
+        let mut io_rw = Framed::new(io_rw, DerpCodec);
+        let (server_channel_s, mut server_channel_r) = mpsc::channel(10);
+
+        // allow a single packet, refilled once per second
+        let limits = ClientLimits {
+            packets_per_second: 1,
+            packets_burst: 1,
+            ..Default::default()
+        };
+        let rate_limiter = PacketRateLimiter::new(&ServerInfo::with_limits(&limits))?;
+        assert!(rate_limiter.is_some());
+
+        let conn_io = ClientConnIo {
+            io: Framed::new(MaybeTlsStream::Test(io), DerpCodec),
+            timeout: None,
+            send_queue: send_queue_r,
+            disco_send_queue: disco_send_queue_r,
+            peer_gone: peer_gone_r,
+            peer_status: peer_status_r,
+
+            key,
+            server_channel: server_channel_s,
+            preferred: Arc::from(AtomicBool::from(true)),
+            can_mesh: false,
+            rate_limiter,
+        };
+
+        let done = CancellationToken::new();
+        let io_done = done.clone();
+        let io_handle = tokio::task::spawn(async move { conn_io.run(io_done).await });
+
+        let target = SecretKey::generate().public();
+        for data in [&b"first"[..], &b"second"[..]] {
+            crate::derp::client::send_packet(
+                &mut io_rw,
+                &None,
+                target,
+                Bytes::copy_from_slice(data),
+            )
+            .await?;
+        }
+
+        // the first packet is relayed
+        match server_channel_r.recv().await.unwrap() {
+            ServerMessage::SendPacket((got_target, packet)) => {
+                assert_eq!(target, got_target);
+                assert_eq!(&b"first"[..], &packet.bytes);
+            }
+            m => {
+                bail!("expected ServerMessage::SendPacket, got {m:?}");
+            }
+        }
+        // the second packet exceeds the rate limit and is dropped
+        let res = tokio::time::timeout(Duration::from_millis(100), server_channel_r.recv()).await;
+        assert!(res.is_err(), "expected no message, got {res:?}");
+
+        done.cancel();
+        io_handle.await??;
+        Ok(())
+    }
 }
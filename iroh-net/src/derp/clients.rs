@@ -13,7 +13,7 @@ use tracing::{Instrument, Span};
 use super::{
     client_conn::{ClientConnBuilder, ClientConnManager},
     metrics::Metrics,
-    types::Packet,
+    types::{Packet, PeerStatus},
 };
 
 /// Number of times we try to send to a client connection before dropping the data;
@@ -103,6 +103,20 @@ impl Client {
         }
         res
     }
+
+    pub fn send_peer_status(&self, status: PeerStatus) -> Result<(), SendError> {
+        // the queue is unbounded, so that the watcher is always told about every client
+        match self.conn.client_channels.peer_status.send(status) {
+            Ok(_) => {
+                inc!(Metrics, other_packets_sent);
+                Ok(())
+            }
+            Err(_) => {
+                inc!(Metrics, other_packets_dropped);
+                Err(SendError::SenderClosed)
+            }
+        }
+    }
 }
 
 // TODO: in the goimpl, it also tries 3 times to send a packet. But, in go we can clone receiver
@@ -134,6 +148,8 @@ enum SendError {
 #[derive(Debug)]
 pub(crate) struct Clients {
     inner: HashMap<PublicKey, Client>,
+    /// Mesh clients that are notified when clients connect or disconnect
+    watchers: HashSet<PublicKey>,
 }
 
 impl Drop for Clients {
@@ -144,6 +160,7 @@ impl Clients {
     pub fn new() -> Self {
         Self {
             inner: HashMap::default(),
+            watchers: HashSet::default(),
         }
     }
 
@@ -189,6 +206,42 @@ impl Clients {
             tracing::warn!("multiple connections found for {key:?}, pruning old connection",);
             old_client.shutdown();
         }
+        // a new connection has to ask to watch connections again
+        self.watchers.remove(&key);
+        self.notify_watchers(PeerStatus::Present(key));
+    }
+
+    /// Registers `watcher` to be notified about clients connecting and disconnecting, and
+    /// sends it a [`PeerStatus::Present`] for every client that is currently connected.
+    pub fn watch(&mut self, watcher: PublicKey) {
+        let Some(client) = self.inner.get(&watcher) else {
+            tracing::warn!("Could not find watcher {watcher:?}");
+            return;
+        };
+        tracing::trace!("adding watcher: {:?}", watcher);
+        let mut res = Ok(());
+        for key in self.inner.keys().filter(|key| **key != watcher) {
+            res = client.send_peer_status(PeerStatus::Present(*key));
+            if res.is_err() {
+                break;
+            }
+        }
+        if self.process_result(&watcher, res).is_ok() {
+            self.watchers.insert(watcher);
+        }
+    }
+
+    fn notify_watchers(&mut self, status: PeerStatus) {
+        let peer = match status {
+            PeerStatus::Present(peer) | PeerStatus::Gone(peer) => peer,
+        };
+        let watchers: Vec<_> = self.watchers.iter().copied().collect();
+        for watcher in watchers.iter().filter(|watcher| **watcher != peer) {
+            if let Some(client) = self.inner.get(watcher) {
+                let res = client.send_peer_status(status);
+                let _ = self.process_result(watcher, res);
+            }
+        }
     }
 
     /// Removes the client from the map of clients, & sends a notification
@@ -200,6 +253,8 @@ impl Clients {
             for key in client.sent_to.iter() {
                 self.send_peer_gone(key, *peer);
             }
+            self.watchers.remove(peer);
+            self.notify_watchers(PeerStatus::Gone(*peer));
             tracing::warn!("pruning connection {peer:?}");
             client.shutdown();
         }
@@ -283,6 +338,8 @@ mod tests {
                 write_timeout: None,
                 channel_capacity: 10,
                 server_channel,
+                can_mesh: false,
+                rate_limiter: None,
             },
             FramedRead::new(test_io, DerpCodec),
         )
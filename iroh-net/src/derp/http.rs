@@ -2,10 +2,14 @@
 //! upgrades.
 //!
 mod client;
+pub(crate) mod proxy;
 mod server;
+mod websocket;
 
 pub use self::client::{Client, ClientBuilder, ClientError, ClientReceiver};
+pub use self::proxy::{Proxy, ProxyKind};
 pub use self::server::{Server, ServerBuilder, TlsAcceptor, TlsConfig};
+pub use self::websocket::WsStream;
 
 pub(crate) const HTTP_UPGRADE_PROTOCOL: &str = "iroh derp http";
 
@@ -35,6 +39,8 @@ pub(crate) fn make_tls_config() -> TlsConfig {
 mod tests {
     use super::*;
 
+    use std::time::Duration;
+
     use anyhow::Result;
     use bytes::Bytes;
     use reqwest::Url;
@@ -43,7 +49,7 @@ mod tests {
     use tracing::{info, info_span, Instrument};
     use tracing_subscriber::{prelude::*, EnvFilter};
 
-    use crate::derp::ReceivedMessage;
+    use crate::derp::{MeshConfig, ReceivedMessage};
     use crate::key::{PublicKey, SecretKey};
 
     #[tokio::test]
@@ -119,6 +125,164 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_websocket_clients_and_server() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .spawn()
+            .await?;
+        let server_url: Url = format!("http://{}", server.addr()).parse().unwrap();
+        // a proxy rejecting everything but websocket upgrades
+        let (proxy_url, proxy_task) = spawn_websocket_only_proxy(server.addr()).await?;
+
+        // a client always using a websocket, a client falling back to a websocket and a
+        // client using the plain upgrade can all talk to each other
+        let a_key = SecretKey::generate();
+        let (a_client, mut a_recv) = ClientBuilder::new(server_url.clone())
+            .websocket(true)
+            .build(a_key.clone());
+        let b_key = SecretKey::generate();
+        let (b_client, mut b_recv) = ClientBuilder::new(proxy_url).build(b_key.clone());
+        let (c_key, mut c_recv, c_task, c_client) =
+            create_test_client(SecretKey::generate(), server_url);
+        a_client.connect().await?;
+        b_client.connect().await?;
+
+        let msg = Bytes::from_static(b"hello over a websocket");
+        a_client.send(b_key.public(), msg.clone()).await?;
+        assert_eq!(
+            recv_packet(&mut b_recv).await?,
+            (a_key.public(), msg.clone())
+        );
+        b_client.send(a_key.public(), msg.clone()).await?;
+        assert_eq!(
+            recv_packet(&mut a_recv).await?,
+            (b_key.public(), msg.clone())
+        );
+        b_client.send(c_key, msg.clone()).await?;
+        let got = c_recv.recv().await.expect("expected message from client b");
+        assert_eq!(got, (b_key.public(), msg.clone()));
+        c_client.send(a_key.public(), msg.clone()).await?;
+        assert_eq!(recv_packet(&mut a_recv).await?, (c_key, msg));
+
+        a_client.close().await?;
+        b_client.close().await?;
+        c_client.close().await?;
+        c_task.abort();
+        proxy_task.abort();
+        server.shutdown().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_client_through_proxy() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let _guard = iroh_test::logging::setup();
+
+        let server = ServerBuilder::new("127.0.0.1:0".parse().unwrap())
+            .secret_key(Some(SecretKey::generate()))
+            .spawn()
+            .await?;
+        let server_url: Url = format!("http://{}", server.addr()).parse().unwrap();
+
+        // a stand-in for an HTTP proxy, tunneling CONNECT requests
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let proxy_url: Url = format!("http://{}", listener.local_addr()?).parse()?;
+        let (tunnels_s, mut tunnels_r) = mpsc::unbounded_channel();
+        let proxy_task = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let tunnels_s = tunnels_s.clone();
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    while !request.ends_with(b"\r\n\r\n") {
+                        request.push(stream.read_u8().await?);
+                    }
+                    let request = String::from_utf8(request)?;
+                    let target = request
+                        .strip_prefix("CONNECT ")
+                        .and_then(|rest| rest.split_whitespace().next())
+                        .ok_or_else(|| anyhow::anyhow!("not a CONNECT request"))?
+                        .to_string();
+                    let mut target_stream = tokio::net::TcpStream::connect(&target).await?;
+                    stream
+                        .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                        .await?;
+                    tunnels_s.send(target).ok();
+                    tokio::io::copy_bidirectional(&mut stream, &mut target_stream).await?;
+                    anyhow::Ok(())
+                });
+            }
+        });
+
+        let a_key = SecretKey::generate();
+        let (a_client, mut a_recv) = ClientBuilder::new(server_url.clone())
+            .proxy(Some(Proxy::new(&proxy_url)?))
+            .build(a_key.clone());
+        let (b_key, _b_recv, b_task, b_client) =
+            create_test_client(SecretKey::generate(), server_url);
+        a_client.connect().await?;
+        assert_eq!(tunnels_r.recv().await, Some(server.addr().to_string()));
+
+        let msg = Bytes::from_static(b"hello through the proxy");
+        b_client.send(a_key.public(), msg.clone()).await?;
+        assert_eq!(recv_packet(&mut a_recv).await?, (b_key, msg));
+
+        a_client.close().await?;
+        b_client.close().await?;
+        b_task.abort();
+        proxy_task.abort();
+        server.shutdown().await;
+        Ok(())
+    }
+
+    async fn recv_packet(receiver: &mut ClientReceiver) -> Result<(PublicKey, Bytes)> {
+        loop {
+            let (msg, _) = receiver
+                .recv()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("client closed"))??;
+            if let ReceivedMessage::ReceivedPacket { source, data } = msg {
+                return Ok((source, data));
+            }
+        }
+    }
+
+    /// Spawns a proxy to the derper at `addr` that rejects the plain derp upgrade.
+    async fn spawn_websocket_only_proxy(
+        addr: std::net::SocketAddr,
+    ) -> Result<(Url, JoinHandle<()>)> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?).parse()?;
+        let task = tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut head = vec![0u8; 4096];
+                    let len = stream.read(&mut head).await?;
+                    let head = &head[..len];
+                    if !String::from_utf8_lossy(head)
+                        .to_lowercase()
+                        .contains("upgrade: websocket")
+                    {
+                        stream
+                            .write_all(b"HTTP/1.1 400 Bad Request\r\ncontent-length: 0\r\n\r\n")
+                            .await?;
+                        return anyhow::Ok(());
+                    }
+                    let mut server = tokio::net::TcpStream::connect(addr).await?;
+                    server.write_all(head).await?;
+                    tokio::io::copy_bidirectional(&mut stream, &mut server).await?;
+                    anyhow::Ok(())
+                });
+            }
+        });
+        Ok((url, task))
+    }
+
     fn create_test_client(
         key: SecretKey,
         server_url: Url,
@@ -237,4 +401,98 @@ mod tests {
         client_b_task.abort();
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_http_mesh_clients_and_servers() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+
+        let mesh_key = [7u8; 32];
+        // reserve the ports up front, so that every server knows the urls of the others
+        let mut ports = Vec::new();
+        for _ in 0..3 {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+            ports.push(listener.local_addr()?.port());
+        }
+        let urls: Vec<Url> = ports
+            .iter()
+            .map(|port| format!("http://127.0.0.1:{port}").parse().unwrap())
+            .collect();
+
+        // start a mesh of three servers
+        let mut servers = Vec::new();
+        for (i, port) in ports.iter().enumerate() {
+            let peers = urls
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, url)| url.clone().into())
+                .collect();
+            let server = ServerBuilder::new(([127, 0, 0, 1], *port).into())
+                .secret_key(Some(SecretKey::generate()))
+                .mesh(Some(MeshConfig { mesh_key, peers }))
+                .spawn()
+                .instrument(info_span!("server", i))
+                .await?;
+            servers.push(server);
+        }
+
+        // home each client on a different server
+        let (a_key, mut a_recv, client_a_task, client_a) =
+            create_test_client(SecretKey::generate(), urls[0].clone());
+        let (b_key, mut b_recv, client_b_task, client_b) =
+            create_test_client(SecretKey::generate(), urls[1].clone());
+        let (c_key, mut c_recv, client_c_task, client_c) =
+            create_test_client(SecretKey::generate(), urls[2].clone());
+        client_a.ping().await?;
+        client_b.ping().await?;
+        client_c.ping().await?;
+
+        // the servers learn about the clients asynchronously, so retry until the mesh delivers
+        async fn send_until_received(
+            from: &Client,
+            to: PublicKey,
+            recv: &mut mpsc::Receiver<(PublicKey, Bytes)>,
+            msg: Bytes,
+        ) -> Result<PublicKey> {
+            for _ in 0..50 {
+                from.send(to, msg.clone()).await?;
+                if let Ok(Some((got_key, got_msg))) =
+                    tokio::time::timeout(Duration::from_millis(100), recv.recv()).await
+                {
+                    assert_eq!(msg, got_msg);
+                    return Ok(got_key);
+                }
+            }
+            anyhow::bail!("message was never delivered through the mesh");
+        }
+
+        info!("sending message from a to b");
+        let got_key = send_until_received(&client_a, b_key, &mut b_recv, "a to b".into()).await?;
+        assert_eq!(a_key, got_key);
+
+        info!("sending message from b to c");
+        let got_key = send_until_received(&client_b, c_key, &mut c_recv, "b to c".into()).await?;
+        assert_eq!(b_key, got_key);
+
+        info!("sending message from c to a");
+        let got_key = send_until_received(&client_c, a_key, &mut a_recv, "c to a".into()).await?;
+        assert_eq!(c_key, got_key);
+
+        // a client with the wrong mesh key is rejected
+        let (bad_client, _bad_recv) = ClientBuilder::new(urls[0].clone())
+            .mesh_key(Some([8u8; 32]))
+            .build(SecretKey::generate());
+        assert!(bad_client.connect().await.is_err());
+
+        for server in servers {
+            server.shutdown().await;
+        }
+        client_a.close().await?;
+        client_a_task.abort();
+        client_b.close().await?;
+        client_b_task.abort();
+        client_c.close().await?;
+        client_c_task.abort();
+        Ok(())
+    }
 }
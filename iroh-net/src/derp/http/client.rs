@@ -9,7 +9,7 @@ use anyhow::bail;
 use bytes::Bytes;
 use futures::future::BoxFuture;
 use hyper::body::Incoming;
-use hyper::header::UPGRADE;
+use hyper::header::{CONNECTION, HOST, UPGRADE};
 use hyper::upgrade::{Parts, Upgraded};
 use hyper::Request;
 use rand::Rng;
@@ -19,13 +19,16 @@ use tokio::net::TcpStream;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinSet;
 use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::protocol::Role;
 use tracing::{debug, error, info_span, trace, warn, Instrument};
 use url::Url;
 
+use super::proxy::Proxy;
+use super::websocket::{self, WsStream};
 use crate::derp::DerpUrl;
 use crate::derp::{
     client::Client as DerpClient, client::ClientBuilder as DerpClientBuilder,
-    client::ClientReceiver as DerpClientReceiver, ReceivedMessage,
+    client::ClientReceiver as DerpClientReceiver, types::MeshKey, ReceivedMessage,
 };
 use crate::dns::lookup_ipv4_ipv6;
 use crate::key::{PublicKey, SecretKey};
@@ -36,6 +39,13 @@ const PING_TIMEOUT: Duration = Duration::from_secs(5);
 const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
 const DNS_TIMEOUT: Duration = Duration::from_secs(1);
 
+/// The reader, writer and local address of an upgraded connection to the derper.
+type UpgradedIo = (
+    Box<dyn AsyncRead + Unpin + Send + 'static>,
+    Box<dyn AsyncWrite + Unpin + Send + 'static>,
+    SocketAddr,
+);
+
 /// Possible connection errors on the [`Client`]
 #[derive(Debug, thiserror::Error)]
 pub enum ClientError {
@@ -111,6 +121,19 @@ pub enum ClientError {
     /// The inner actor is gone, likely means things are shutdown.
     #[error("actor gone")]
     ActorGone,
+    /// Connecting through the proxy failed
+    #[error("proxy error: {0}")]
+    Proxy(String),
+}
+
+impl ClientError {
+    /// Whether the derper was reached, but the HTTP upgrade of the connection failed.
+    fn is_upgrade_failure(&self) -> bool {
+        matches!(
+            self,
+            Self::UnexpectedStatusCode(..) | Self::Hyper(_) | Self::Upgrade(_)
+        )
+    }
 }
 
 /// An HTTP DERP client.
@@ -156,6 +179,13 @@ struct Actor {
     conn_gen: usize,
     is_prober: bool,
     server_public_key: Option<PublicKey>,
+    #[debug(skip)]
+    mesh_key: Option<MeshKey>,
+    #[debug(skip)]
+    auth_token: Option<String>,
+    /// Whether to connect over a websocket instead of the plain derp upgrade.
+    websocket: bool,
+    proxy: Option<Proxy>,
     url: DerpUrl,
     #[debug("TlsConnector")]
     tls_connector: tokio_rustls::TlsConnector,
@@ -198,6 +228,14 @@ pub struct ClientBuilder {
     is_prober: bool,
     /// Expected PublicKey of the server
     server_public_key: Option<PublicKey>,
+    /// Default is None
+    mesh_key: Option<MeshKey>,
+    /// Default is None
+    auth_token: Option<String>,
+    /// Default is false
+    websocket: bool,
+    /// Default is None
+    proxy: Option<Proxy>,
     /// Server url.
     url: DerpUrl,
 }
@@ -221,6 +259,10 @@ impl ClientBuilder {
             address_family_selector: None,
             is_prober: false,
             server_public_key: None,
+            mesh_key: None,
+            auth_token: None,
+            websocket: false,
+            proxy: None,
             url: url.into(),
         }
     }
@@ -264,6 +306,36 @@ impl ClientBuilder {
         self
     }
 
+    /// Identifies this client as another server of the mesh, allowing it to watch the
+    /// connections of the server and to forward packets.
+    pub fn mesh_key(mut self, mesh_key: Option<MeshKey>) -> Self {
+        self.mesh_key = mesh_key;
+        self
+    }
+
+    /// Sets the token to present to a private derp server.
+    ///
+    /// See [`crate::derp::auth`] for details.
+    pub fn auth_token(mut self, auth_token: Option<String>) -> Self {
+        self.auth_token = auth_token;
+        self
+    }
+
+    /// Always connect over a websocket, instead of only falling back to a websocket when the
+    /// plain derp upgrade fails.
+    pub fn websocket(mut self, websocket: bool) -> Self {
+        self.websocket = websocket;
+        self
+    }
+
+    /// Connect to the derper through a proxy.
+    ///
+    /// See [`Proxy::from_env`] to use the proxy configured in the environment.
+    pub fn proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.proxy = proxy;
+        self
+    }
+
     /// Build the [`Client`]
     pub fn build(self, key: SecretKey) -> (Client, ClientReceiver) {
         // TODO: review TLS config
@@ -301,6 +373,10 @@ impl ClientBuilder {
             ping_tasks: Default::default(),
             is_prober: self.is_prober,
             server_public_key: self.server_public_key,
+            mesh_key: self.mesh_key,
+            auth_token: self.auth_token,
+            websocket: self.websocket,
+            proxy: self.proxy,
             url: self.url,
             tls_connector,
         };
@@ -498,11 +574,13 @@ impl Actor {
         async move {
             if self.derp_client.is_none() {
                 trace!("no connection, trying to connect");
-                let (derp_client, receiver) =
+                let (derp_client, receiver, websocket) =
                     tokio::time::timeout(CONNECT_TIMEOUT, self.connect_0())
                         .await
                         .map_err(|_| ClientError::ConnectTimeout)??;
 
+                // once the plain upgrade failed, keep reconnecting over a websocket
+                self.websocket = websocket;
                 self.derp_client = Some((derp_client, receiver));
                 self.next_conn();
             }
@@ -520,7 +598,46 @@ impl Actor {
         .await
     }
 
-    async fn connect_0(&self) -> Result<(DerpClient, DerpClientReceiver), ClientError> {
+    async fn connect_0(&self) -> Result<(DerpClient, DerpClientReceiver, bool), ClientError> {
+        let (reader, writer, local_addr, websocket) = if self.websocket {
+            let (reader, writer, local_addr) = self.connect_upgrade(true).await?;
+            (reader, writer, local_addr, true)
+        } else {
+            match self.connect_upgrade(false).await {
+                Ok((reader, writer, local_addr)) => (reader, writer, local_addr, false),
+                Err(err) if err.is_upgrade_failure() => {
+                    // a proxy might be in the way that only lets websockets through
+                    warn!("derp upgrade failed, falling back to websocket: {err}");
+                    let (reader, writer, local_addr) = self.connect_upgrade(true).await?;
+                    (reader, writer, local_addr, true)
+                }
+                Err(err) => return Err(err),
+            }
+        };
+
+        let (derp_client, receiver) =
+            DerpClientBuilder::new(self.secret_key.clone(), local_addr, reader, writer)
+                .can_ack_pings(self.can_ack_pings)
+                .prober(self.is_prober)
+                .server_public_key(self.server_public_key)
+                .mesh_key(self.mesh_key)
+                .auth_token(self.auth_token.clone())
+                .build()
+                .await
+                .map_err(|e| ClientError::Build(e.to_string()))?;
+
+        if self.is_preferred && derp_client.note_preferred(true).await.is_err() {
+            derp_client.close().await;
+            return Err(ClientError::Send);
+        }
+
+        trace!("connect_0 done");
+        Ok((derp_client, receiver, websocket))
+    }
+
+    /// Dials the derper and upgrades the connection, either with the plain derp upgrade or
+    /// to a websocket.
+    async fn connect_upgrade(&self, websocket: bool) -> Result<UpgradedIo, ClientError> {
         let tcp_stream = self.dial_url().await?;
 
         let local_addr = tcp_stream
@@ -529,6 +646,8 @@ impl Actor {
 
         debug!(server_addr = ?tcp_stream.peer_addr(), %local_addr, "TCP stream connected");
 
+        let websocket_key = websocket.then(websocket::generate_key);
+        let host = self.host_header();
         let response = if self.use_https() {
             debug!("Starting TLS handshake");
             let hostname = self
@@ -536,10 +655,10 @@ impl Actor {
                 .ok_or_else(|| ClientError::InvalidUrl("No tls servername".into()))?;
             let tls_stream = self.tls_connector.connect(hostname, tcp_stream).await?;
             debug!("tls_connector connect success");
-            Self::start_upgrade(tls_stream).await?
+            Self::start_upgrade(tls_stream, host, websocket_key.as_deref()).await?
         } else {
             debug!("Starting handshake");
-            Self::start_upgrade(tcp_stream).await?
+            Self::start_upgrade(tcp_stream, host, websocket_key.as_deref()).await?
         };
 
         if response.status() != hyper::StatusCode::SWITCHING_PROTOCOLS {
@@ -552,6 +671,13 @@ impl Actor {
                 response.status(),
             ));
         }
+        if let Some(key) = &websocket_key {
+            if !websocket::is_accepted(response.headers(), key) {
+                return Err(ClientError::Upgrade(
+                    "invalid Sec-WebSocket-Accept header".into(),
+                ));
+            }
+        }
 
         debug!("starting upgrade");
         let upgraded = match hyper::upgrade::on(response).await {
@@ -562,30 +688,28 @@ impl Actor {
             }
         };
 
-        debug!("connection upgraded");
-        let (reader, writer) =
-            downcast_upgrade(upgraded).map_err(|e| ClientError::Upgrade(e.to_string()))?;
-
-        let (derp_client, receiver) =
-            DerpClientBuilder::new(self.secret_key.clone(), local_addr, reader, writer)
-                .can_ack_pings(self.can_ack_pings)
-                .prober(self.is_prober)
-                .server_public_key(self.server_public_key)
-                .build()
-                .await
-                .map_err(|e| ClientError::Build(e.to_string()))?;
-
-        if self.is_preferred && derp_client.note_preferred(true).await.is_err() {
-            derp_client.close().await;
-            return Err(ClientError::Send);
-        }
-
-        trace!("connect_0 done");
-        Ok((derp_client, receiver))
+        debug!(websocket, "connection upgraded");
+        let (reader, writer) = if websocket {
+            let io = hyper_util::rt::TokioIo::new(upgraded);
+            let io = WsStream::new(io, Bytes::new(), Role::Client).await;
+            let (reader, writer) = tokio::io::split(io);
+            let reader: Box<dyn AsyncRead + Unpin + Send + 'static> = Box::new(reader);
+            let writer: Box<dyn AsyncWrite + Unpin + Send + 'static> = Box::new(writer);
+            (reader, writer)
+        } else {
+            downcast_upgrade(upgraded).map_err(|e| ClientError::Upgrade(e.to_string()))?
+        };
+        Ok((reader, writer, local_addr))
     }
 
     /// Sends the HTTP upgrade request to the derper.
-    async fn start_upgrade<T>(io: T) -> Result<hyper::Response<Incoming>, ClientError>
+    ///
+    /// Asks for a websocket if a `websocket_key` is given.
+    async fn start_upgrade<T>(
+        io: T,
+        host: Option<String>,
+        websocket_key: Option<&str>,
+    ) -> Result<hyper::Response<Incoming>, ClientError>
     where
         T: AsyncRead + AsyncWrite + Send + Unpin + 'static,
     {
@@ -605,10 +729,23 @@ impl Actor {
             .instrument(info_span!("http-driver")),
         );
         debug!("Sending upgrade request");
-        let req = Request::builder()
-            .uri("/derp")
-            .header(UPGRADE, super::HTTP_UPGRADE_PROTOCOL)
-            .body(http_body_util::Empty::<hyper::body::Bytes>::new())?;
+        let mut req = Request::builder().uri("/derp");
+        if let Some(host) = host {
+            req = req.header(HOST, host);
+        }
+        let req = match websocket_key {
+            Some(key) => req
+                .header(UPGRADE, "websocket")
+                .header(CONNECTION, "Upgrade")
+                .header(websocket::SEC_WEBSOCKET_KEY, key)
+                .header(websocket::SEC_WEBSOCKET_VERSION, "13")
+                .header(
+                    websocket::SEC_WEBSOCKET_PROTOCOL,
+                    websocket::WEBSOCKET_PROTOCOL,
+                ),
+            None => req.header(UPGRADE, super::HTTP_UPGRADE_PROTOCOL),
+        };
+        let req = req.body(http_body_util::Empty::<hyper::body::Bytes>::new())?;
         request_sender.send_request(req).await.map_err(From::from)
     }
 
@@ -726,6 +863,15 @@ impl Actor {
             .and_then(|s| rustls::ServerName::try_from(s).ok())
     }
 
+    /// The value of the `Host` header of the upgrade request.
+    fn host_header(&self) -> Option<String> {
+        let host = self.url.host_str()?;
+        Some(match self.url.port() {
+            Some(port) => format!("{host}:{port}"),
+            None => host.to_string(),
+        })
+    }
+
     fn url_port(&self) -> Option<u16> {
         if let Some(port) = self.url.port() {
             return Some(port);
@@ -749,6 +895,24 @@ impl Actor {
     async fn dial_url(&self) -> Result<TcpStream, ClientError> {
         debug!(%self.url, "dial url");
 
+        if let Some(proxy) = &self.proxy {
+            let host = self
+                .url
+                .host_str()
+                .ok_or_else(|| ClientError::InvalidUrl("missing url host".into()))?;
+            if !proxy.bypasses(host) {
+                let port = self
+                    .url_port()
+                    .ok_or_else(|| ClientError::InvalidUrl("missing url port".into()))?;
+                debug!(?proxy, "connecting through proxy");
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                return proxy
+                    .connect(host, port)
+                    .await
+                    .map_err(|e| ClientError::Proxy(format!("{e:#}")));
+            }
+        }
+
         let prefer_ipv6 = self.prefer_ipv6().await;
         let dst_ip = resolve_host(&self.url, prefer_ipv6).await?;
 
@@ -854,8 +1018,8 @@ async fn resolve_host(url: &Url, prefer_ipv6: bool) -> Result<IpAddr, ClientErro
 fn downcast_upgrade(
     upgraded: Upgraded,
 ) -> anyhow::Result<(
-    Box<dyn AsyncRead + Unpin + Send + Sync + 'static>,
-    Box<dyn AsyncWrite + Unpin + Send + Sync + 'static>,
+    Box<dyn AsyncRead + Unpin + Send + 'static>,
+    Box<dyn AsyncWrite + Unpin + Send + 'static>,
 )> {
     match upgraded.downcast::<hyper_util::rt::TokioIo<tokio::net::TcpStream>>() {
         Ok(Parts { read_buf, io, .. }) => {
@@ -16,11 +16,15 @@ use hyper::{HeaderMap, Method, Request, Response, StatusCode};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::task::JoinHandle;
 use tokio_rustls_acme::AcmeAcceptor;
+use tokio_tungstenite::tungstenite::protocol::Role;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, info_span, warn, Instrument};
 
-use crate::derp::http::HTTP_UPGRADE_PROTOCOL;
-use crate::derp::server::{ClientConnHandler, MaybeTlsStream};
+use crate::derp::auth::ClientAuthenticator;
+use crate::derp::http::websocket::{accept_headers, is_websocket_upgrade};
+use crate::derp::http::{WsStream, HTTP_UPGRADE_PROTOCOL};
+use crate::derp::server::{ClientConnHandler, MaybeTlsStream, MeshConfig};
+use crate::derp::types::ClientLimits;
 use crate::derp::MaybeTlsStreamServer;
 use crate::key::SecretKey;
 
@@ -57,9 +61,16 @@ fn downcast_upgrade(upgraded: Upgraded) -> Result<(MaybeTlsStream, Bytes)> {
 async fn derp_connection_handler(
     conn_handler: &ClientConnHandler,
     upgraded: Upgraded,
+    websocket: bool,
 ) -> Result<()> {
-    debug!("derp_connection upgraded");
+    debug!(websocket, "derp_connection upgraded");
     let (io, read_buf) = downcast_upgrade(upgraded)?;
+    if websocket {
+        let io = WsStream::new(io, read_buf, Role::Server).await;
+        return conn_handler
+            .accept(MaybeTlsStream::WebSocket(Box::new(io)))
+            .await;
+    }
     ensure!(
         read_buf.is_empty(),
         "can not deal with buffered data yet: {:?}",
@@ -143,6 +154,15 @@ pub struct ServerBuilder {
     /// When `None`, a default is provided.
     #[debug("{}", not_found_fn.as_ref().map_or("None", |_| "Some(Box<Fn(ResponseBuilder) -> Result<Response<Body>> + Send + Sync + 'static>)"))]
     not_found_fn: Option<HyperHandler>,
+    /// Mesh the derp server with other derp servers.
+    ///
+    /// When `None`, the server does not forward packets to other servers.
+    #[debug(skip)]
+    mesh: Option<MeshConfig>,
+    /// Limits and abuse controls applied to every client of the derp server.
+    client_limits: ClientLimits,
+    /// Makes the derp server private, only relaying for the clients it allows.
+    authenticator: Option<Arc<dyn ClientAuthenticator>>,
 }
 
 impl ServerBuilder {
@@ -157,6 +177,9 @@ impl ServerBuilder {
             derp_override: None,
             headers: HeaderMap::new(),
             not_found_fn: None,
+            mesh: None,
+            client_limits: ClientLimits::default(),
+            authenticator: None,
         }
     }
 
@@ -203,6 +226,27 @@ impl ServerBuilder {
         self
     }
 
+    /// Mesh the derp server with the other derp servers of a cluster, forwarding packets for
+    /// clients that are connected to another server of the mesh.
+    pub fn mesh(mut self, mesh: Option<MeshConfig>) -> Self {
+        self.mesh = mesh;
+        self
+    }
+
+    /// Rate limit the clients of the derp server and restrict which nodes may connect.
+    pub fn client_limits(mut self, limits: ClientLimits) -> Self {
+        self.client_limits = limits;
+        self
+    }
+
+    /// Only relay for the clients allowed by `authenticator`.
+    ///
+    /// See [`crate::derp::auth`] for details.
+    pub fn authenticator(mut self, authenticator: Option<Arc<dyn ClientAuthenticator>>) -> Self {
+        self.authenticator = authenticator;
+        self
+    }
+
     /// Add http headers.
     pub fn headers(mut self, headers: HeaderMap) -> Self {
         for (k, v) in headers.iter() {
@@ -215,7 +259,11 @@ impl ServerBuilder {
     pub async fn spawn(self) -> Result<Server> {
         ensure!(self.secret_key.is_some() || self.derp_override.is_some(), "Must provide a `SecretKey` for the derp server OR pass in an override function for the 'derp' endpoint");
         let (derp_handler, derp_server) = if let Some(secret_key) = self.secret_key {
-            let server = crate::derp::server::Server::new(secret_key.clone());
+            let mut server = crate::derp::server::Server::with_mesh(secret_key.clone(), self.mesh);
+            server.set_client_limits(self.client_limits);
+            if let Some(authenticator) = self.authenticator {
+                server.set_authenticator(authenticator);
+            }
             (
                 DerpHandler::ConnHandler(server.client_conn_handler(self.headers.clone())),
                 Some(server),
@@ -347,6 +395,10 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                     *res.status_mut() = StatusCode::BAD_REQUEST;
                     return Ok(res);
                 }
+                // Clients behind proxies that break the plain upgrade use a WebSocket instead.
+                let websocket_headers =
+                    is_websocket_upgrade(req.headers()).then(|| accept_headers(req.headers()));
+                let websocket = websocket_headers.is_some();
 
                 // Setup a future that will eventually receive the upgraded
                 // connection and talk a new protocol, and spawn the future
@@ -359,8 +411,12 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                     async move {
                         match hyper::upgrade::on(&mut req).await {
                             Ok(upgraded) => {
-                                if let Err(e) =
-                                    derp_connection_handler(&closure_conn_handler, upgraded).await
+                                if let Err(e) = derp_connection_handler(
+                                    &closure_conn_handler,
+                                    upgraded,
+                                    websocket,
+                                )
+                                .await
                                 {
                                     tracing::warn!(
                                         "upgrade to \"{HTTP_UPGRADE_PROTOCOL}\": io error: {:?}",
@@ -379,10 +435,15 @@ impl Service<Request<Incoming>> for ClientConnHandler {
                 );
 
                 // Now return a 101 Response saying we agree to the upgrade to the
-                // HTTP_UPGRADE_PROTOCOL
+                // HTTP_UPGRADE_PROTOCOL, or to the WebSocket
                 *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
-                res.headers_mut()
-                    .insert(UPGRADE, HeaderValue::from_static(HTTP_UPGRADE_PROTOCOL));
+                match websocket_headers {
+                    Some(headers) => res.headers_mut().extend(headers),
+                    None => {
+                        res.headers_mut()
+                            .insert(UPGRADE, HeaderValue::from_static(HTTP_UPGRADE_PROTOCOL));
+                    }
+                }
                 Ok(res)
             }
         }
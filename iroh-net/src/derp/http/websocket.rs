@@ -0,0 +1,211 @@
+//! WebSocket framing of the derp protocol.
+//!
+//! Some proxies and load balancers only let WebSocket upgrades through, breaking the plain
+//! [`HTTP_UPGRADE_PROTOCOL`](super::HTTP_UPGRADE_PROTOCOL) upgrade. Over a WebSocket the derp
+//! byte stream is carried in binary messages, and read back as a stream on the other side, so
+//! the derp protocol itself is unchanged.
+
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use bytes::{Buf, Bytes};
+use futures::{Sink, Stream};
+use hyper::header::{HeaderValue, CONNECTION, UPGRADE};
+use hyper::HeaderMap;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+
+/// The WebSocket subprotocol of derp connections.
+pub(crate) const WEBSOCKET_PROTOCOL: &str = "derp";
+
+/// The `Sec-WebSocket-Key` request header.
+pub(crate) const SEC_WEBSOCKET_KEY: &str = "sec-websocket-key";
+/// The `Sec-WebSocket-Accept` response header.
+pub(crate) const SEC_WEBSOCKET_ACCEPT: &str = "sec-websocket-accept";
+/// The `Sec-WebSocket-Protocol` header.
+pub(crate) const SEC_WEBSOCKET_PROTOCOL: &str = "sec-websocket-protocol";
+/// The `Sec-WebSocket-Version` request header.
+pub(crate) const SEC_WEBSOCKET_VERSION: &str = "sec-websocket-version";
+
+/// Returns whether the request headers ask for a WebSocket upgrade.
+pub(crate) fn is_websocket_upgrade(headers: &HeaderMap) -> bool {
+    headers
+        .get(UPGRADE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.eq_ignore_ascii_case("websocket"))
+        && headers.contains_key(SEC_WEBSOCKET_KEY)
+}
+
+/// Returns the headers of the response accepting the WebSocket upgrade requested with
+/// `headers`.
+pub(crate) fn accept_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut response = HeaderMap::new();
+    response.insert(UPGRADE, HeaderValue::from_static("websocket"));
+    response.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+    if let Some(key) = headers.get(SEC_WEBSOCKET_KEY) {
+        let accept = tungstenite::handshake::derive_accept_key(key.as_bytes());
+        response.insert(
+            SEC_WEBSOCKET_ACCEPT,
+            HeaderValue::from_str(&accept).expect("base64 is a valid header value"),
+        );
+    }
+    if headers.get(SEC_WEBSOCKET_PROTOCOL).is_some() {
+        response.insert(
+            SEC_WEBSOCKET_PROTOCOL,
+            HeaderValue::from_static(WEBSOCKET_PROTOCOL),
+        );
+    }
+    response
+}
+
+/// Generates a `Sec-WebSocket-Key` for a WebSocket upgrade request.
+pub(crate) fn generate_key() -> String {
+    tungstenite::handshake::client::generate_key()
+}
+
+/// Returns whether the response headers accept the WebSocket upgrade requested with `key`.
+pub(crate) fn is_accepted(headers: &HeaderMap, key: &str) -> bool {
+    let expected = tungstenite::handshake::derive_accept_key(key.as_bytes());
+    headers
+        .get(SEC_WEBSOCKET_ACCEPT)
+        .is_some_and(|accept| accept.as_bytes() == expected.as_bytes())
+}
+
+/// A derp byte stream carried over a WebSocket.
+///
+/// Writes are sent as binary messages, reads return the content of the received binary
+/// messages. A close message ends the stream.
+#[derive(derive_more::Debug)]
+pub struct WsStream<S> {
+    #[debug("WebSocketStream")]
+    inner: WebSocketStream<S>,
+    /// The part of the last received message not read yet.
+    read_buf: Bytes,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> WsStream<S> {
+    /// Wraps an upgraded connection, where `read_buf` holds the data already read from `io`.
+    pub(crate) async fn new(io: S, read_buf: Bytes, role: Role) -> Self {
+        let inner = WebSocketStream::from_partially_read(io, read_buf.to_vec(), role, None).await;
+        Self {
+            inner,
+            read_buf: Bytes::new(),
+        }
+    }
+}
+
+fn to_io_error(err: tungstenite::Error) -> std::io::Error {
+    match err {
+        tungstenite::Error::Io(err) => err,
+        err => std::io::Error::new(std::io::ErrorKind::Other, err),
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for WsStream<S> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        while self.read_buf.is_empty() {
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(Message::Binary(data))) => self.read_buf = data.into(),
+                // pings are answered by the websocket itself
+                Some(Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_))) => {}
+                Some(Ok(Message::Text(_))) => {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "unexpected text message on derp websocket",
+                    )));
+                }
+                Some(Ok(Message::Close(_))) | None => return Poll::Ready(Ok(())),
+                Some(Err(err)) => return Poll::Ready(Err(to_io_error(err))),
+            }
+        }
+        let len = self.read_buf.len().min(buf.remaining());
+        buf.put_slice(&self.read_buf[..len]);
+        self.read_buf.advance(len);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for WsStream<S> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        ready!(Pin::new(&mut self.inner).poll_ready(cx)).map_err(to_io_error)?;
+        Pin::new(&mut self.inner)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(to_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_flush(cx)
+            .map_err(to_io_error)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner)
+            .poll_close(cx)
+            .map_err(to_io_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ws_stream() -> anyhow::Result<()> {
+        let (client, server) = tokio::io::duplex(1024);
+        let (mut client, mut server) = tokio::join!(
+            WsStream::new(client, Bytes::new(), Role::Client),
+            WsStream::new(server, Bytes::new(), Role::Server),
+        );
+
+        client.write_all(b"hello ").await?;
+        client.write_all(b"world").await?;
+        client.flush().await?;
+        let mut buf = [0u8; 11];
+        server.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"hello world");
+
+        // reads may split a message
+        server.write_all(b"derp").await?;
+        server.flush().await?;
+        let mut buf = [0u8; 2];
+        client.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"de");
+        client.read_exact(&mut buf).await?;
+        assert_eq!(&buf, b"rp");
+
+        // closing the websocket ends the stream
+        server.shutdown().await?;
+        let mut rest = Vec::new();
+        client.read_to_end(&mut rest).await?;
+        assert!(rest.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_handshake_headers() {
+        let key = generate_key();
+        let mut request = HeaderMap::new();
+        request.insert(UPGRADE, HeaderValue::from_static("WebSocket"));
+        assert!(!is_websocket_upgrade(&request));
+        request.insert(SEC_WEBSOCKET_KEY, key.parse().unwrap());
+        assert!(is_websocket_upgrade(&request));
+
+        let response = accept_headers(&request);
+        assert!(is_accepted(&response, &key));
+        assert!(!is_accepted(&response, &generate_key()));
+    }
+}
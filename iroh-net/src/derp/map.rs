@@ -77,6 +77,8 @@ impl DerpMap {
                 url,
                 stun_only: false,
                 stun_port,
+                discovery: false,
+                auth_token: None,
             }
             .into(),
         );
@@ -114,7 +116,7 @@ impl fmt::Display for DerpMap {
 /// Information on a specific derp server.
 ///
 /// Includes the Url where it can be dialed.
-#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
+#[derive(derive_more::Debug, Clone, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct DerpNode {
     /// The [`DerpUrl`] where this derp server can be dialed.
     pub url: DerpUrl,
@@ -127,6 +129,17 @@ pub struct DerpNode {
     ///
     /// Setting this to `0` means the default STUN port is used.
     pub stun_port: u16,
+    /// Whether this derp server hosts a store for node address records.
+    ///
+    /// See [`crate::discovery::http`] for details.
+    #[serde(default)]
+    pub discovery: bool,
+    /// The token to present to the derp server, if it only relays for authorized clients.
+    ///
+    /// See [`crate::derp::auth`] for details.
+    #[serde(default)]
+    #[debug(skip)]
+    pub auth_token: Option<String>,
 }
 
 impl fmt::Display for DerpNode {
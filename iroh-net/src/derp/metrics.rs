@@ -36,6 +36,11 @@ pub struct Metrics {
     /// Packets of other `FrameType`s dropped
     pub other_packets_dropped: Counter,
 
+    /// `FrameType::SendPacket` dropped because the client exceeded its rate limit
+    pub packets_rate_limited: Counter,
+    /// Bytes dropped because the client exceeded its rate limit
+    pub bytes_rate_limited: Counter,
+
     /// Number of packets we have forwarded out to another packet forwarder
     pub packets_forwarded_out: Counter,
     /// Number of packets we have been asked to forward
@@ -60,6 +65,8 @@ pub struct Metrics {
     pub accepts: Counter,
     /// Number of connections we have removed because of an error
     pub disconnects: Counter,
+    /// Number of connections we have rejected because of the allow or deny list
+    pub rejected_clients: Counter,
     // TODO: enable when we can have multiple connections for one node id
     // pub duplicate_client_keys: Counter,
     // pub duplicate_client_conns: Counter,
@@ -92,6 +99,13 @@ impl Default for Metrics {
                 "Number of times a non-disco, non-'send; packet was dropped.",
             ),
 
+            packets_rate_limited: Counter::new(
+                "Number of packets dropped because a client exceeded its rate limit.",
+            ),
+            bytes_rate_limited: Counter::new(
+                "Number of bytes dropped because a client exceeded its rate limit.",
+            ),
+
             packets_forwarded_out: Counter::new(
                 "Number of times the server has sent a forwarded packet",
             ),
@@ -115,6 +129,9 @@ impl Default for Metrics {
 
             accepts: Counter::new("Number of times this server has accepted a connection."),
             disconnects: Counter::new("Number of clients that have then disconnected."),
+            rejected_clients: Counter::new(
+                "Number of connections rejected because of the allow or deny list.",
+            ),
             // TODO: enable when we can have multiple connections for one node id
             // pub duplicate_client_keys: Counter::new("Number of duplicate client keys."),
             // pub duplicate_client_conns: Counter::new("Number of duplicate client connections."),
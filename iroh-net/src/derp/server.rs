@@ -1,11 +1,12 @@
 //! based on tailscale/derp/derp_server.go
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
 use std::time::Duration;
 
-use anyhow::{Context as _, Result};
+use anyhow::{ensure, Context as _, Result};
 use futures::SinkExt;
 use hyper::HeaderMap;
 use iroh_metrics::core::UsageStatsReport;
@@ -15,20 +16,26 @@ use tokio::sync::mpsc;
 use tokio::task::JoinHandle;
 use tokio_util::codec::Framed;
 use tokio_util::sync::CancellationToken;
-use tracing::{info_span, trace, Instrument};
+use tracing::{info_span, trace, warn, Instrument};
 
+use crate::disco::looks_like_disco_wrapper;
 use crate::key::{PublicKey, SecretKey, SharedSecret};
+use crate::util::AbortingJoinHandle;
 
 use super::{
+    auth::{constant_time_eq, ClientAuthenticator, AUTH_TIMEOUT},
+    client::Client as DerpClient,
     client_conn::ClientConnBuilder,
     clients::Clients,
     codec::{
         recv_client_key, write_frame, DerpCodec, Frame, PER_CLIENT_SEND_QUEUE_DEPTH,
         PROTOCOL_VERSION, SERVER_CHANNEL_SIZE,
     },
+    http::{ClientBuilder as HttpClientBuilder, WsStream},
     metrics::Metrics,
     types::ServerInfo,
-    types::ServerMessage,
+    types::{ClientLimits, MeshKey, PacketRateLimiter, ServerMessage},
+    DerpUrl, ReceivedMessage,
 };
 
 // TODO: skipping `verboseDropKeys` for now
@@ -40,6 +47,21 @@ fn new_conn_num() -> usize {
 
 pub(crate) const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
 
+/// How long to wait before reconnecting to another server of the mesh.
+const MESH_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// Configuration to mesh a [`Server`] with the other derp servers of a cluster.
+///
+/// Packets for clients that are connected to another server of the mesh are forwarded to that
+/// server, so that two clients homed on different servers can reach each other.
+#[derive(Debug, Clone)]
+pub struct MeshConfig {
+    /// The key shared by all servers of the mesh.
+    pub mesh_key: MeshKey,
+    /// The urls of the other servers of the mesh.
+    pub peers: Vec<DerpUrl>,
+}
+
 /// A DERP server.
 ///
 /// Responsible for managing connections to derp [`super::client::Client`]s, sending packets from one client to another.
@@ -63,12 +85,26 @@ pub struct Server {
     loop_handler: JoinHandle<Result<()>>,
     /// Done token, forces a hard shutdown. To gracefully shutdown, use [`Server::close`]
     cancel: CancellationToken,
+    /// The key shared with the other servers of the mesh, if any
+    mesh_key: Option<MeshKey>,
+    /// Tasks watching the connections of the other servers of the mesh
+    mesh_tasks: Vec<AbortingJoinHandle<()>>,
+    /// Limits and abuse controls applied to every client
+    client_limits: Arc<ClientLimits>,
+    /// Decides which clients may connect, if the server is private
+    authenticator: Option<Arc<dyn ClientAuthenticator>>,
     // TODO: stats collection
 }
 
 impl Server {
     /// TODO: replace with builder
     pub fn new(key: SecretKey) -> Self {
+        Self::with_mesh(key, None)
+    }
+
+    /// Creates a server that, if `mesh` is set, forwards packets to and from the other servers
+    /// of the mesh.
+    pub fn with_mesh(key: SecretKey, mesh: Option<MeshConfig>) -> Self {
         let (server_channel_s, server_channel_r) = mpsc::channel(SERVER_CHANNEL_SIZE);
         let server_actor = ServerActor::new(key.public(), server_channel_r);
         let cancel_token = CancellationToken::new();
@@ -78,6 +114,25 @@ impl Server {
                 .instrument(info_span!("derp.server", me = %key.public().fmt_short())),
         );
         let meta_cert = init_meta_cert(&key.public());
+        let mesh_key = mesh.as_ref().map(|mesh| mesh.mesh_key);
+        let mesh_tasks: Vec<AbortingJoinHandle<()>> = mesh
+            .into_iter()
+            .flat_map(|mesh| {
+                let MeshConfig { mesh_key, peers } = mesh;
+                peers
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(peer, url)| (mesh_key, peer, url))
+            })
+            .map(|(mesh_key, peer, url)| {
+                let span = info_span!("derp.mesh", me = %key.public().fmt_short(), %url);
+                tokio::spawn(
+                    run_mesh_client(key.clone(), mesh_key, peer, url, server_channel_s.clone())
+                        .instrument(span),
+                )
+                .into()
+            })
+            .collect();
         Self {
             write_timeout: Some(WRITE_TIMEOUT),
             secret_key: key,
@@ -88,9 +143,28 @@ impl Server {
             server_info: ServerInfo::no_rate_limit(),
             loop_handler: server_task,
             cancel: cancel_token,
+            mesh_key,
+            mesh_tasks,
+            client_limits: Default::default(),
+            authenticator: None,
         }
     }
 
+    /// Sets the limits and abuse controls applied to every client.
+    ///
+    /// Only affects [`ClientConnHandler`]s created afterwards.
+    pub fn set_client_limits(&mut self, limits: ClientLimits) {
+        self.server_info = ServerInfo::with_limits(&limits);
+        self.client_limits = Arc::new(limits);
+    }
+
+    /// Makes this a private server, only relaying for the clients allowed by `authenticator`.
+    ///
+    /// Only affects [`ClientConnHandler`]s created afterwards.
+    pub fn set_authenticator(&mut self, authenticator: Arc<dyn ClientAuthenticator>) {
+        self.authenticator = Some(authenticator);
+    }
+
     /// Returns the server's secret key.
     pub fn secret_key(&self) -> &SecretKey {
         &self.secret_key
@@ -104,6 +178,8 @@ impl Server {
     /// Closes the server and waits for the connections to disconnect.
     pub async fn close(mut self) {
         if !self.closed {
+            // stop watching the other servers of the mesh
+            self.mesh_tasks.clear();
             if let Err(err) = self.server_channel.send(ServerMessage::Shutdown).await {
                 tracing::warn!(
                     "could not shutdown the server gracefully, doing a forced shutdown: {:?}",
@@ -134,6 +210,9 @@ impl Server {
             write_timeout: self.write_timeout,
             server_info: self.server_info.clone(),
             default_headers: Arc::new(default_headers),
+            mesh_key: self.mesh_key,
+            client_limits: self.client_limits.clone(),
+            authenticator: self.authenticator.clone(),
         }
     }
 
@@ -156,6 +235,9 @@ pub struct ClientConnHandler {
     write_timeout: Option<Duration>,
     server_info: ServerInfo,
     pub(super) default_headers: Arc<HeaderMap>,
+    mesh_key: Option<MeshKey>,
+    client_limits: Arc<ClientLimits>,
+    authenticator: Option<Arc<dyn ClientAuthenticator>>,
 }
 
 impl Clone for ClientConnHandler {
@@ -166,6 +248,9 @@ impl Clone for ClientConnHandler {
             write_timeout: self.write_timeout,
             server_info: self.server_info.clone(),
             default_headers: Arc::clone(&self.default_headers),
+            mesh_key: self.mesh_key,
+            client_limits: Arc::clone(&self.client_limits),
+            authenticator: self.authenticator.clone(),
         }
     }
 }
@@ -175,7 +260,10 @@ impl ClientConnHandler {
     ///
     /// Will error if it takes too long (10 sec) to write or read to the connection, if there is
     /// some read or write error to the connection,  if the server is meant to verify clients,
-    /// and is unable to verify this one, or if there is some issue communicating with the server.
+    /// and is unable to verify this one, if the client presents a mesh key that does not match
+    /// the server's, if the client is not allowed to connect by the allow and deny lists or by
+    /// the authenticator of a private server, or if there is some issue communicating with the
+    /// server.
     ///
     /// The provided [`AsyncRead`] and [`AsyncWrite`] must be already connected to the connection.
     pub async fn accept(&self, io: MaybeTlsStream) -> Result<()> {
@@ -185,9 +273,53 @@ impl ClientConnHandler {
             .await
             .context("unable to send server key to client")?;
         trace!("accept: recv client key");
-        let (client_key, _, shared_secret) = recv_client_key(self.secret_key.clone(), &mut io)
-            .await
-            .context("unable to receive client information")?;
+        let (client_key, client_info, shared_secret) =
+            recv_client_key(self.secret_key.clone(), &mut io)
+                .await
+                .context("unable to receive client information")?;
+        let can_mesh = match client_info.mesh_key {
+            Some(mesh_key) => {
+                ensure!(
+                    self.mesh_key
+                        .is_some_and(|key| constant_time_eq(&key, &mesh_key)),
+                    "invalid mesh key from client {client_key:?}"
+                );
+                true
+            }
+            None => false,
+        };
+        // other servers of the mesh are not subject to the client limits
+        if !can_mesh && !self.client_limits.is_allowed(&client_key) {
+            inc!(Metrics, rejected_clients);
+            anyhow::bail!("client {client_key:?} is not allowed to connect");
+        }
+        if let (false, Some(authenticator)) = (can_mesh, &self.authenticator) {
+            trace!("accept: authenticate client");
+            let token = client_info.auth_token.as_deref();
+            let allowed =
+                tokio::time::timeout(AUTH_TIMEOUT, authenticator.authenticate(client_key, token))
+                    .await;
+            match allowed {
+                Ok(Ok(true)) => {}
+                Ok(Ok(false)) => {
+                    inc!(Metrics, rejected_clients);
+                    anyhow::bail!("client {client_key:?} is not authorized");
+                }
+                Ok(Err(err)) => {
+                    inc!(Metrics, rejected_clients);
+                    anyhow::bail!("unable to authenticate client {client_key:?}: {err:#}");
+                }
+                Err(_) => {
+                    inc!(Metrics, rejected_clients);
+                    anyhow::bail!("timed out authenticating client {client_key:?}");
+                }
+            }
+        }
+        let rate_limiter = if can_mesh {
+            None
+        } else {
+            PacketRateLimiter::new(&self.server_info)?
+        };
         trace!("accept: send server info");
         self.send_server_info(&mut io, &shared_secret)
             .await
@@ -200,6 +332,8 @@ impl ClientConnHandler {
             write_timeout: self.write_timeout,
             channel_capacity: PER_CLIENT_SEND_QUEUE_DEPTH,
             server_channel: self.server_channel.clone(),
+            can_mesh,
+            rate_limiter,
         };
         trace!("accept: create client");
         self.server_channel
@@ -255,6 +389,9 @@ pub(crate) struct ServerActor {
     receiver: mpsc::Receiver<ServerMessage>,
     /// All clients connected to this server
     clients: Clients,
+    /// Clients connected to other servers of the mesh, with the index of the server and the
+    /// connection to forward packets over
+    mesh_routes: HashMap<PublicKey, (usize, DerpClient)>,
 }
 
 impl ServerActor {
@@ -263,6 +400,7 @@ impl ServerActor {
             key,
             receiver,
             clients: Clients::new(),
+            mesh_routes: HashMap::new(),
         }
     }
 
@@ -296,6 +434,14 @@ impl ServerActor {
                                 if self.clients.send_packet(&key, packet).is_ok() {
                                     self.clients.record_send(&src, key);
                                 }
+                            } else if let Some((_, forwarder)) = self.mesh_routes.get(&key) {
+                                // the client is connected to another server of the mesh
+                                if forwarder.forward_packet(src, key, packet.bytes).is_ok() {
+                                    inc!(Metrics, packets_forwarded_out);
+                                } else {
+                                    tracing::warn!("send packet: unable to forward to {key:?}, dropped packet");
+                                    inc!(Metrics, send_packets_dropped);
+                                }
                             } else {
                                 tracing::warn!("send packet: no way to reach client {key:?}, dropped packet");
                                 inc!(Metrics, send_packets_dropped);
@@ -311,11 +457,54 @@ impl ServerActor {
 
                                     self.clients.record_send(&src, key);
                                 }
+                            } else if let Some((_, forwarder)) = self.mesh_routes.get(&key) {
+                                // the client is connected to another server of the mesh
+                                if forwarder.forward_packet(src, key, packet.bytes).is_ok() {
+                                    inc!(Metrics, packets_forwarded_out);
+                                } else {
+                                    tracing::warn!("send disco packet: unable to forward to {key:?}, dropped packet");
+                                    inc!(Metrics, disco_packets_dropped);
+                                }
                             } else {
                                 tracing::warn!("send disco packet: no way to reach client {key:?}, dropped packet");
                                 inc!(Metrics, disco_packets_dropped);
                             }
                        }
+                       ServerMessage::ForwardedPacket((key, packet)) => {
+                           tracing::trace!("forwarded packet from: {:?} to: {:?} ({}b)", packet.src, key, packet.bytes.len());
+                            // never forward again, to avoid loops between servers of the mesh
+                            if self.clients.contains_key(&key) {
+                                if looks_like_disco_wrapper(&packet.bytes) {
+                                    let _ = self.clients.send_disco_packet(&key, packet);
+                                } else {
+                                    let _ = self.clients.send_packet(&key, packet);
+                                }
+                            } else {
+                                tracing::warn!("forwarded packet: client {key:?} is not connected, dropped packet");
+                                inc!(Metrics, send_packets_dropped);
+                            }
+                       }
+                       ServerMessage::WatchConns(key) => {
+                           tracing::trace!("watch conns: {:?}", key);
+                           self.clients.watch(key);
+                       }
+                       ServerMessage::AddMeshRoute((key, peer, forwarder)) => {
+                           tracing::trace!("add mesh route: {:?} via mesh server {}", key, peer);
+                           inc!(Metrics, added_pkt_fwder);
+                           self.mesh_routes.insert(key, (peer, forwarder));
+                       }
+                       ServerMessage::RemoveMeshRoute((key, peer)) => {
+                           // only remove the route if the client did not move to another server
+                           if matches!(self.mesh_routes.get(&key), Some((p, _)) if *p == peer) {
+                               tracing::trace!("remove mesh route: {:?} via mesh server {}", key, peer);
+                               inc!(Metrics, removed_pkt_fwder);
+                               self.mesh_routes.remove(&key);
+                           }
+                       }
+                       ServerMessage::ClearMeshRoutes(peer) => {
+                           tracing::trace!("clear mesh routes via mesh server {}", peer);
+                           self.mesh_routes.retain(|_, (p, _)| *p != peer);
+                       }
                        ServerMessage::CreateClient(client_builder) => {
                            inc!(Metrics, accepts);
 
@@ -358,6 +547,75 @@ impl ServerActor {
     }
 }
 
+/// Connects to the mesh server at `url`, watches the clients connected to it and reports them
+/// to the [`ServerActor`], which forwards packets for those clients over the connection.
+async fn run_mesh_client(
+    secret_key: SecretKey,
+    mesh_key: MeshKey,
+    peer: usize,
+    url: DerpUrl,
+    server_channel: mpsc::Sender<ServerMessage>,
+) {
+    let (client, mut receiver) = HttpClientBuilder::new(url)
+        .mesh_key(Some(mesh_key))
+        .build(secret_key);
+    let mut current: Option<(DerpClient, usize)> = None;
+    loop {
+        let (forwarder, conn_gen) = match &current {
+            Some(current) => current.clone(),
+            None => {
+                // routes learned over a previous connection may be stale
+                if server_channel
+                    .send(ServerMessage::ClearMeshRoutes(peer))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+                let res = match client.connect().await {
+                    Ok((derp_client, conn_gen)) => derp_client
+                        .watch_connection_changes()
+                        .await
+                        .map(|_| (derp_client, conn_gen)),
+                    Err(err) => Err(err.into()),
+                };
+                match res {
+                    Ok(res) => current.insert(res).clone(),
+                    Err(err) => {
+                        warn!("failed to watch mesh server: {err:?}");
+                        tokio::time::sleep(MESH_RECONNECT_DELAY).await;
+                        continue;
+                    }
+                }
+            }
+        };
+        let msg = match receiver.recv().await {
+            Some(Ok((_, gen))) if gen != conn_gen => {
+                // the client reconnected, so we need to watch the new connection
+                current = None;
+                continue;
+            }
+            Some(Ok((msg, _))) => match msg {
+                ReceivedMessage::PeerPresent(key) => {
+                    ServerMessage::AddMeshRoute((key, peer, forwarder))
+                }
+                ReceivedMessage::PeerGone(key) => ServerMessage::RemoveMeshRoute((key, peer)),
+                _ => continue,
+            },
+            Some(Err(err)) => {
+                warn!("connection to mesh server failed: {err:?}");
+                current = None;
+                tokio::time::sleep(MESH_RECONNECT_DELAY).await;
+                continue;
+            }
+            None => return,
+        };
+        if server_channel.send(msg).await.is_err() {
+            return;
+        }
+    }
+}
+
 /// Initializes the [`Server`] with a self-signed x509 cert
 /// encoding this server's public key and protocol version. "cmd/derp_server
 /// then sends this after the Let's Encrypt leaf + intermediate certs after
@@ -395,6 +653,8 @@ pub enum MaybeTlsStream {
     Plain(tokio::net::TcpStream),
     /// A Tls wrapped [`tokio::net::TcpStream`]
     Tls(tokio_rustls::server::TlsStream<tokio::net::TcpStream>),
+    /// A derp connection carried over a WebSocket
+    WebSocket(Box<WsStream<MaybeTlsStream>>),
     #[cfg(test)]
     Test(tokio::io::DuplexStream),
 }
@@ -408,6 +668,7 @@ impl AsyncRead for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_read(cx, buf),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_read(cx, buf),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_read(cx, buf),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_read(cx, buf),
         }
@@ -422,6 +683,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_flush(cx),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_flush(cx),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_flush(cx),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_flush(cx),
         }
@@ -434,6 +696,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_shutdown(cx),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_shutdown(cx),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_shutdown(cx),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_shutdown(cx),
         }
@@ -447,6 +710,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_write(cx, buf),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_write(cx, buf),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_write(cx, buf),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_write(cx, buf),
         }
@@ -460,6 +724,7 @@ impl AsyncWrite for MaybeTlsStream {
         match &mut *self {
             MaybeTlsStream::Plain(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             MaybeTlsStream::Tls(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
+            MaybeTlsStream::WebSocket(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
             #[cfg(test)]
             MaybeTlsStream::Test(ref mut s) => Pin::new(s).poll_write_vectored(cx, bufs),
         }
@@ -498,6 +763,8 @@ mod tests {
                 write_timeout: None,
                 channel_capacity: 10,
                 server_channel,
+                can_mesh: false,
+                rate_limiter: None,
             },
             Framed::new(test_io, DerpCodec),
         )
@@ -581,6 +848,9 @@ mod tests {
             server_info: ServerInfo::no_rate_limit(),
             server_channel: server_channel_s,
             default_headers: Default::default(),
+            mesh_key: None,
+            client_limits: Default::default(),
+            authenticator: None,
         };
 
         // create the parts needed for a client
@@ -603,6 +873,7 @@ mod tests {
                 can_ack_pings: true,
                 is_prober: true,
                 mesh_key: None,
+                auth_token: None,
             };
             let shared_secret = client_key.shared(&got_server_key);
             crate::derp::codec::send_client_key(
@@ -621,7 +892,7 @@ mod tests {
             };
             let mut buf = encrypted_message.to_vec();
             shared_secret.open(&mut buf)?;
-            let _info: ServerInfo = postcard::from_bytes(&buf)?;
+            let _info = ServerInfo::from_bytes(&buf)?;
             Ok(())
         });
 
@@ -639,6 +910,33 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_client_conn_handler_deny_list() -> Result<()> {
+        let (server_channel_s, _server_channel_r) = mpsc::channel(10);
+        let denied_key = SecretKey::generate();
+        let handler = ClientConnHandler {
+            secret_key: SecretKey::generate(),
+            write_timeout: None,
+            server_info: ServerInfo::no_rate_limit(),
+            server_channel: server_channel_s,
+            default_headers: Default::default(),
+            mesh_key: None,
+            client_limits: Arc::new(ClientLimits {
+                deny: [denied_key.public()].into_iter().collect(),
+                ..Default::default()
+            }),
+            authenticator: None,
+        };
+
+        // the denied client is rejected after identifying itself
+        let (rw, client_builder) = make_test_client(denied_key);
+        let handler_task =
+            tokio::spawn(async move { handler.accept(MaybeTlsStream::Test(rw)).await });
+        assert!(client_builder.build().await.is_err());
+        assert!(handler_task.await?.is_err());
+        Ok(())
+    }
+
     fn make_test_client(secret_key: SecretKey) -> (tokio::io::DuplexStream, ClientBuilder) {
         let (client, server) = tokio::io::duplex(10);
         let (client_reader, client_writer) = tokio::io::split(client);
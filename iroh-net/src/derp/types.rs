@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::num::NonZeroU32;
 
 use anyhow::{bail, Context, Result};
@@ -5,7 +6,9 @@ use bytes::Bytes;
 use postcard::experimental::max_size::MaxSize;
 use serde::{Deserialize, Serialize};
 
-use super::{client_conn::ClientConnBuilder, codec::PROTOCOL_VERSION};
+use super::{
+    client::Client as DerpClient, client_conn::ClientConnBuilder, codec::PROTOCOL_VERSION,
+};
 use crate::key::PublicKey;
 
 pub(crate) struct RateLimiter {
@@ -36,12 +39,57 @@ impl RateLimiter {
     pub(crate) fn check_n(&self, n: usize) -> Result<()> {
         let n = NonZeroU32::new(u32::try_from(n)?).context("n not non-zero")?;
         match self.inner.check_n(n) {
-            Ok(_) => Ok(()),
+            Ok(Ok(_)) => Ok(()),
+            Ok(Err(_)) => bail!("rate limit exceeded"),
             Err(_) => bail!("batch cannot go through"),
         }
     }
 }
 
+/// Limits the bytes and packets a client sends, as advertised in the [`ServerInfo`].
+pub(crate) struct PacketRateLimiter {
+    bytes: Option<RateLimiter>,
+    packets: Option<RateLimiter>,
+}
+
+impl std::fmt::Debug for PacketRateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PacketRateLimiter").finish_non_exhaustive()
+    }
+}
+
+impl PacketRateLimiter {
+    /// Creates a rate limiter for the limits of `info`, or `None` if there are no limits.
+    pub(crate) fn new(info: &ServerInfo) -> Result<Option<Self>> {
+        let bytes = RateLimiter::new(
+            info.token_bucket_bytes_per_second,
+            info.token_bucket_bytes_burst,
+        )?;
+        let packets = RateLimiter::new(
+            info.token_bucket_packets_per_second,
+            info.token_bucket_packets_burst,
+        )?;
+        if bytes.is_none() && packets.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { bytes, packets }))
+    }
+
+    /// Checks whether a frame of `len` bytes may be sent.
+    ///
+    /// The byte limit is checked first, so that a frame rejected by it does not spend a
+    /// packet token. A limit that rejects a frame does not spend its own tokens either.
+    pub(crate) fn check(&self, len: usize) -> Result<()> {
+        if let Some(bytes) = &self.bytes {
+            bytes.check_n(len)?;
+        }
+        if let Some(packets) = &self.packets {
+            packets.check_n(1)?;
+        }
+        Ok(())
+    }
+}
+
 /// A request to write a dataframe to a Client
 #[derive(Debug, Clone)]
 pub(crate) struct Packet {
@@ -51,29 +99,71 @@ pub(crate) struct Packet {
     pub(crate) bytes: Bytes,
 }
 
-#[derive(Debug, Serialize, Deserialize, MaxSize, PartialEq, Eq)]
+/// The key shared by all derp servers of a mesh.
+///
+/// Clients that present this key in their [`ClientInfo`] are trusted to watch the
+/// connections of the server and to forward packets on behalf of other clients.
+pub type MeshKey = [u8; 32];
+
+#[derive(derive_more::Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub(crate) struct ClientInfo {
     /// The DERP protocol version that the client was built with.
     /// See [`PROTOCOL_VERSION`].
     pub(crate) version: usize,
-    /// Unused field, ignored by the relay server.
-    pub(crate) mesh_key: Option<[u8; 32]>,
+    /// The mesh key, if this client is another server of the mesh.
+    pub(crate) mesh_key: Option<MeshKey>,
     /// Whether the client declares it's able to ack pings
     pub(crate) can_ack_pings: bool,
     /// Whether this client is a prober.
     pub(crate) is_prober: bool,
+    /// The token presented to a private derp server, see [`super::auth`].
+    #[debug(skip)]
+    pub(crate) auth_token: Option<String>,
+}
+
+impl ClientInfo {
+    /// Decodes the [`ClientInfo`] sent by a client.
+    ///
+    /// Clients that do not support authentication send the shorter [`ClientInfoV1`], which
+    /// is decoded without a token. Older servers ignore the trailing token of this encoding.
+    pub(crate) fn from_bytes(buf: &[u8]) -> Result<Self> {
+        match postcard::from_bytes(buf) {
+            Ok(info) => Ok(info),
+            Err(_) => {
+                let info: ClientInfoV1 = postcard::from_bytes(buf)?;
+                Ok(Self {
+                    version: info.version,
+                    mesh_key: info.mesh_key,
+                    can_ack_pings: info.can_ack_pings,
+                    is_prober: info.is_prober,
+                    auth_token: None,
+                })
+            }
+        }
+    }
+}
+
+/// The [`ClientInfo`] of clients that do not support authentication.
+#[derive(Debug, Serialize, Deserialize)]
+struct ClientInfoV1 {
+    version: usize,
+    mesh_key: Option<MeshKey>,
+    can_ack_pings: bool,
+    is_prober: bool,
 }
 
 /// The information we send to the [`super::client::Client`] about the [`super::server::Server`]'s
 /// protocol version & rate limiting
 ///
 /// If either `token_bucket_bytes_per_second` or `token_bucket_bytes_burst` is 0, there is no rate
-/// limit.
+/// limit on bytes, likewise for packets.
 #[derive(Debug, Clone, Serialize, Deserialize, MaxSize)]
 pub(crate) struct ServerInfo {
     pub(crate) version: usize,
     pub(crate) token_bucket_bytes_per_second: usize,
     pub(crate) token_bucket_bytes_burst: usize,
+    pub(crate) token_bucket_packets_per_second: usize,
+    pub(crate) token_bucket_packets_burst: usize,
 }
 
 impl ServerInfo {
@@ -83,16 +173,196 @@ impl ServerInfo {
             version: PROTOCOL_VERSION,
             token_bucket_bytes_burst: 0,
             token_bucket_bytes_per_second: 0,
+            token_bucket_packets_per_second: 0,
+            token_bucket_packets_burst: 0,
+        }
+    }
+
+    /// Specifies the rate limit of the given [`ClientLimits`]
+    pub fn with_limits(limits: &ClientLimits) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            token_bucket_bytes_per_second: limits.bytes_per_second,
+            token_bucket_bytes_burst: limits.bytes_burst,
+            token_bucket_packets_per_second: limits.packets_per_second,
+            token_bucket_packets_burst: limits.packets_burst,
         }
     }
+
+    /// Decodes the [`ServerInfo`] sent by a server.
+    ///
+    /// Servers that do not advertise packet rate limits send the shorter [`ServerInfoV1`],
+    /// which is decoded without a packet rate limit. Older clients ignore the trailing packet
+    /// rate limits of this encoding.
+    pub(crate) fn from_bytes(buf: &[u8]) -> Result<Self> {
+        match postcard::from_bytes(buf) {
+            Ok(info) => Ok(info),
+            Err(_) => {
+                let info: ServerInfoV1 = postcard::from_bytes(buf)?;
+                Ok(Self {
+                    version: info.version,
+                    token_bucket_bytes_per_second: info.token_bucket_bytes_per_second,
+                    token_bucket_bytes_burst: info.token_bucket_bytes_burst,
+                    token_bucket_packets_per_second: 0,
+                    token_bucket_packets_burst: 0,
+                })
+            }
+        }
+    }
+}
+
+/// The [`ServerInfo`] of servers that only advertise a rate limit on bytes.
+#[derive(Debug, Serialize, Deserialize)]
+struct ServerInfoV1 {
+    version: usize,
+    token_bucket_bytes_per_second: usize,
+    token_bucket_bytes_burst: usize,
+}
+
+/// Limits and abuse controls applied to every client of a [`super::server::Server`].
+///
+/// The rate limits are advertised to the clients, and packets of clients that exceed them are
+/// dropped. A rate limit is disabled if either its rate or its burst is zero.
+#[derive(Debug, Clone, Default)]
+pub struct ClientLimits {
+    /// Sustained number of bytes per second a client may send.
+    pub bytes_per_second: usize,
+    /// Number of bytes a client may send in one burst.
+    pub bytes_burst: usize,
+    /// Sustained number of packets per second a client may send.
+    pub packets_per_second: usize,
+    /// Number of packets a client may send in one burst.
+    pub packets_burst: usize,
+    /// If set, only these nodes may connect.
+    pub allow: Option<HashSet<PublicKey>>,
+    /// Nodes that may not connect.
+    pub deny: HashSet<PublicKey>,
+}
+
+impl ClientLimits {
+    /// Whether the node is allowed to connect by the allow and deny lists.
+    pub fn is_allowed(&self, node: &PublicKey) -> bool {
+        !self.deny.contains(node)
+            && self
+                .allow
+                .as_ref()
+                .map_or(true, |allow| allow.contains(node))
+    }
+}
+
+/// A change in the presence of a client, sent to the mesh clients watching a server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PeerStatus {
+    /// The client connected to the server.
+    Present(PublicKey),
+    /// The client disconnected from the server.
+    Gone(PublicKey),
 }
 
 #[derive(derive_more::Debug)]
 pub(crate) enum ServerMessage {
     SendPacket((PublicKey, Packet)),
     SendDiscoPacket((PublicKey, Packet)),
+    /// A packet forwarded by another server of the mesh, only delivered to local clients.
+    ForwardedPacket((PublicKey, Packet)),
     #[debug("CreateClient")]
     CreateClient(ClientConnBuilder),
     RemoveClient((PublicKey, usize)),
+    /// A mesh client wants to be notified about clients connecting and disconnecting.
+    WatchConns(PublicKey),
+    /// A client is reachable through the mesh server with the given index.
+    AddMeshRoute((PublicKey, usize, DerpClient)),
+    /// A client is no longer reachable through the mesh server with the given index.
+    RemoveMeshRoute((PublicKey, usize)),
+    /// The connection to the mesh server with the given index was reset.
+    ClearMeshRoutes(usize),
     Shutdown,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_info_v1_compat() -> Result<()> {
+        let old = ServerInfoV1 {
+            version: PROTOCOL_VERSION,
+            token_bucket_bytes_per_second: 10,
+            token_bucket_bytes_burst: 20,
+        };
+        let info = ServerInfo::from_bytes(&postcard::to_stdvec(&old)?)?;
+        assert_eq!(info.version, PROTOCOL_VERSION);
+        assert_eq!(info.token_bucket_bytes_per_second, 10);
+        assert_eq!(info.token_bucket_bytes_burst, 20);
+        assert_eq!(info.token_bucket_packets_per_second, 0);
+        assert_eq!(info.token_bucket_packets_burst, 0);
+
+        // old clients read the current encoding
+        let limits = ClientLimits {
+            bytes_per_second: 10,
+            bytes_burst: 20,
+            packets_per_second: 30,
+            packets_burst: 40,
+            ..Default::default()
+        };
+        let bytes = postcard::to_stdvec(&ServerInfo::with_limits(&limits))?;
+        let old: ServerInfoV1 = postcard::from_bytes(&bytes)?;
+        assert_eq!(old.token_bucket_bytes_per_second, 10);
+        assert_eq!(old.token_bucket_bytes_burst, 20);
+        let info = ServerInfo::from_bytes(&bytes)?;
+        assert_eq!(info.token_bucket_packets_per_second, 30);
+        assert_eq!(info.token_bucket_packets_burst, 40);
+        Ok(())
+    }
+
+    #[test]
+    fn test_packet_rate_limiter_rejected_bytes() -> Result<()> {
+        let limits = ClientLimits {
+            bytes_per_second: 1,
+            bytes_burst: 10,
+            packets_per_second: 1,
+            packets_burst: 2,
+            ..Default::default()
+        };
+        let limiter = PacketRateLimiter::new(&ServerInfo::with_limits(&limits))?.unwrap();
+        // frames rejected by the byte limit do not spend packet tokens
+        assert!(limiter.check(20).is_err());
+        limiter.check(8)?;
+        assert!(limiter.check(5).is_err());
+        limiter.check(2)?;
+        // both packet tokens are spent now
+        assert!(limiter.check(1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_info_v1_compat() -> Result<()> {
+        let old = ClientInfoV1 {
+            version: PROTOCOL_VERSION,
+            mesh_key: Some([1u8; 32]),
+            can_ack_pings: true,
+            is_prober: false,
+        };
+        let info = ClientInfo::from_bytes(&postcard::to_stdvec(&old)?)?;
+        let expected = ClientInfo {
+            version: PROTOCOL_VERSION,
+            mesh_key: Some([1u8; 32]),
+            can_ack_pings: true,
+            is_prober: false,
+            auth_token: None,
+        };
+        assert_eq!(info, expected);
+
+        // old servers read the current encoding
+        let current = ClientInfo {
+            auth_token: Some("secret".to_string()),
+            ..expected
+        };
+        let bytes = postcard::to_stdvec(&current)?;
+        let old: ClientInfoV1 = postcard::from_bytes(&bytes)?;
+        assert_eq!(old.mesh_key, Some([1u8; 32]));
+        assert!(old.can_ack_pings);
+        assert_eq!(ClientInfo::from_bytes(&bytes)?, current);
+        Ok(())
+    }
+}
@@ -26,7 +26,7 @@ use std::{
 use anyhow::{anyhow, bail, ensure, Context, Result};
 use url::Url;
 
-use crate::{derp::DerpUrl, key, net::ip::to_canonical};
+use crate::{derp::DerpUrl, key, magicsock::TransportAddr, net::ip::to_canonical};
 
 use super::{key::PublicKey, stun};
 
@@ -132,13 +132,17 @@ pub struct Pong {
     pub src: SendAddr,
 }
 
-/// Addresses to which we can send. This is either a UDP or a derp address.
+/// Addresses to which we can send. This is either a UDP, a derp or a transport address.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum SendAddr {
     /// UDP, the ip addr.
     Udp(SocketAddr),
     /// Derp Url.
     Derp(DerpUrl),
+    /// Address on one of the additional [`Transport`]s.
+    ///
+    /// [`Transport`]: crate::magicsock::Transport
+    Transport(TransportAddr),
 }
 
 impl SendAddr {
@@ -151,7 +155,7 @@ impl SendAddr {
     pub fn derp_url(&self) -> Option<DerpUrl> {
         match self {
             Self::Derp(url) => Some(url.clone()),
-            Self::Udp(_) => None,
+            Self::Udp(_) | Self::Transport(_) => None,
         }
     }
 }
@@ -159,7 +163,7 @@ impl SendAddr {
 impl PartialEq<SocketAddr> for SendAddr {
     fn eq(&self, other: &SocketAddr) -> bool {
         match self {
-            Self::Derp(_) => false,
+            Self::Derp(_) | Self::Transport(_) => false,
             Self::Udp(addr) => addr.eq(other),
         }
     }
@@ -170,6 +174,7 @@ impl Display for SendAddr {
         match self {
             SendAddr::Derp(id) => write!(f, "Derp({})", id),
             SendAddr::Udp(addr) => write!(f, "UDP({})", addr),
+            SendAddr::Transport(addr) => write!(f, "Transport({})", addr),
         }
     }
 }
@@ -226,6 +231,11 @@ fn send_addr_from_bytes(p: &[u8]) -> Result<SendAddr> {
             let u: Url = s.parse()?;
             Ok(SendAddr::Derp(u.into()))
         }
+        2u8 => {
+            let s = std::str::from_utf8(&p[1..])?;
+            let u: Url = s.parse()?;
+            Ok(SendAddr::Transport(u.into()))
+        }
         _ => {
             bail!("invalid addr type {}", p[0]);
         }
@@ -244,6 +254,11 @@ fn send_addr_to_vec(addr: &SendAddr) -> Vec<u8> {
             out.extend_from_slice(&socket_addr_as_bytes(ip));
             out
         }
+        SendAddr::Transport(addr) => {
+            let mut out = vec![2u8];
+            out.extend_from_slice(addr.to_string().as_bytes());
+            out
+        }
     }
 }
 
@@ -423,6 +438,14 @@ mod tests {
                 }),
                 want: "02 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 00 fe d0 00 00 00 00 00 00 00 00 00 00 00 00 00 12 0a 1a",
             },
+            Test {
+                name: "pong_transport",
+                m: Message::Pong(Pong {
+                    tx_id: [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12].into(),
+                    src: SendAddr::Transport("mem://3".parse().unwrap()),
+                }),
+                want: "02 00 01 02 03 04 05 06 07 08 09 0a 0b 0c 02 6d 65 6d 3a 2f 2f 33",
+            },
             Test {
                 name: "call_me_maybe",
                 m: Message::CallMeMaybe(CallMeMaybe { my_numbers: Vec::new() }),
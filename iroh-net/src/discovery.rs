@@ -10,6 +10,10 @@ use tracing::{debug, error_span, warn, Instrument};
 
 use crate::{AddrInfo, MagicEndpoint, NodeId};
 
+pub mod dns;
+pub mod http;
+pub mod mdns;
+
 /// Node discovery for [`super::MagicEndpoint`].
 ///
 /// The purpose of this trait is to hook up a node discovery mechanism that
@@ -337,6 +341,7 @@ mod tests {
                     let addr_info = AddrInfo {
                         derp_url: None,
                         direct_addresses: BTreeSet::from([addr]),
+                        transport_addrs: Default::default(),
                     };
                     Some((addr_info, ts))
                 }
@@ -508,6 +513,7 @@ mod tests {
             info: AddrInfo {
                 derp_url: None,
                 direct_addresses: BTreeSet::from(["240.0.0.1:1000".parse().unwrap()]),
+                transport_addrs: Default::default(),
             },
         };
         let _conn = ep2.connect(ep1_wrong_addr, TEST_ALPN).await?;
@@ -0,0 +1,564 @@
+//! DNS based node discovery.
+//!
+//! Nodes publish their [`AddrInfo`] as a signed TXT record for the domain
+//! `<node-id>.<origin>`, where `<node-id>` is the base32 encoded [`NodeId`]. The TXT record
+//! consists of [RFC 1464] style attributes:
+//!
+//! ```text
+//! "v=1" "ts=<microseconds since the unix epoch>" "derp=<derp url>" "addr=<socket addr>" ... "transport=<transport addr>" ... "sig=<signature>"
+//! ```
+//!
+//! The `sig` attribute is always last and holds the base32 encoded ed25519 signature of the
+//! node's secret key over all preceding attributes, so that resolvers can verify the record
+//! against the [`NodeId`] in the domain name without trusting the DNS server.
+//!
+//! Records are published with a DNS UPDATE message to an authoritative server for the
+//! origin, e.g. the [`DnsServer`] contained in this module, and resolved through regular
+//! DNS lookups.
+//!
+//! [RFC 1464]: https://www.rfc-editor.org/rfc/rfc1464
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use futures::{stream::BoxStream, StreamExt};
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, Query, ResponseCode},
+    rr::{rdata::TXT, Name, RData, Record, RecordType},
+};
+use hickory_resolver::{
+    config::{NameServerConfig, Protocol, ResolverConfig, ResolverOpts},
+    error::ResolveErrorKind,
+    TokioAsyncResolver,
+};
+use iroh_base::base32;
+use parking_lot::Mutex;
+use tokio::{net::UdpSocket, task::JoinHandle};
+use tracing::{debug, warn};
+
+use crate::{
+    discovery::{Discovery, DiscoveryItem},
+    key::{SecretKey, Signature},
+    AddrInfo, MagicEndpoint, NodeId,
+};
+
+mod server;
+
+pub use self::server::DnsServer;
+
+/// The provenance of [`DiscoveryItem`]s produced by [`DnsDiscovery`].
+pub const DNS_PROVENANCE: &str = "dns";
+
+/// The default time to live of published records, in seconds.
+pub const DEFAULT_TTL: u32 = 30;
+
+/// The version of the TXT record format.
+const RECORD_VERSION: &str = "1";
+
+/// Domain separation prefix for the signed payload of a record.
+const SIGNATURE_DOMAIN: &[u8] = b"iroh-net/discovery/dns/v1";
+
+/// Timeout for a DNS UPDATE round trip.
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Timeout for resolving a node record.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The [`AddrInfo`] of a node, signed by the node's secret key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedAddrInfo {
+    node_id: NodeId,
+    timestamp: u64,
+    info: AddrInfo,
+    signature: Signature,
+}
+
+impl SignedAddrInfo {
+    /// Sign the [`AddrInfo`] with the node's secret key.
+    ///
+    /// `timestamp` must be microseconds since the unix epoch. It is used to discard outdated
+    /// records.
+    pub fn sign(secret_key: &SecretKey, info: AddrInfo, timestamp: u64) -> Self {
+        let node_id = secret_key.public();
+        let attrs = attributes(timestamp, &info);
+        let signature = secret_key.sign(&signing_payload(&node_id, &attrs));
+        Self {
+            node_id,
+            timestamp,
+            info,
+            signature,
+        }
+    }
+
+    /// Get the [`NodeId`] of the node which signed this record.
+    pub fn node_id(&self) -> NodeId {
+        self.node_id
+    }
+
+    /// Get the timestamp of this record, in microseconds since the unix epoch.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Get the signed [`AddrInfo`].
+    pub fn info(&self) -> &AddrInfo {
+        &self.info
+    }
+
+    /// Convert into the signed [`AddrInfo`].
+    pub fn into_info(self) -> AddrInfo {
+        self.info
+    }
+
+    /// Encode as the character strings of a TXT record.
+    pub fn to_txt_strings(&self) -> Vec<String> {
+        let mut strings = attributes(self.timestamp, &self.info);
+        strings.push(format!("sig={}", base32::fmt(self.signature.to_bytes())));
+        strings
+    }
+
+    /// Decode from the character strings of a TXT record and verify the signature against
+    /// `node_id`.
+    pub fn from_txt_strings<S: AsRef<[u8]>>(node_id: NodeId, strings: &[S]) -> Result<Self> {
+        let (sig, attrs) = strings.split_last().context("empty record")?;
+        let sig = std::str::from_utf8(sig.as_ref())?
+            .strip_prefix("sig=")
+            .context("missing signature")?;
+        let signature = Signature::from_bytes(&base32::parse_array(sig)?);
+        let attrs = attrs
+            .iter()
+            .map(|s| Ok(std::str::from_utf8(s.as_ref())?.to_string()))
+            .collect::<Result<Vec<_>>>()?;
+        node_id
+            .verify(&signing_payload(&node_id, &attrs), &signature)
+            .map_err(|_| anyhow!("invalid signature"))?;
+
+        let mut version = None;
+        let mut timestamp = None;
+        let mut info = AddrInfo::default();
+        for attr in &attrs {
+            let (key, value) = attr
+                .split_once('=')
+                .with_context(|| format!("invalid attribute {attr:?}"))?;
+            match key {
+                "v" => version = Some(value),
+                "ts" => timestamp = Some(value.parse()?),
+                "derp" => {
+                    ensure!(info.derp_url.is_none(), "duplicate derp attribute");
+                    info.derp_url = Some(value.parse()?);
+                }
+                "addr" => {
+                    info.direct_addresses.insert(value.parse()?);
+                }
+                "transport" => {
+                    info.transport_addrs.insert(value.parse()?);
+                }
+                // Unknown attributes are covered by the signature, skip them to allow
+                // extending the format.
+                _ => {}
+            }
+        }
+        ensure!(
+            version == Some(RECORD_VERSION),
+            "unsupported record version {version:?}"
+        );
+        Ok(Self {
+            node_id,
+            timestamp: timestamp.context("missing timestamp")?,
+            info,
+            signature,
+        })
+    }
+
+    /// Create the TXT [`Record`] for this node below `origin`.
+    pub(crate) fn to_record(&self, origin: &Name, ttl: u32) -> Result<Record> {
+        let name = node_domain(&self.node_id, origin)?;
+        let txt = TXT::new(self.to_txt_strings());
+        Ok(Record::from_rdata(name, ttl, RData::TXT(txt)))
+    }
+
+    /// Parse and verify a TXT [`Record`] for a node below `origin`.
+    pub(crate) fn from_record(origin: &Name, record: &Record) -> Result<Self> {
+        let node_id = node_id_from_domain(record.name(), origin)?;
+        match record.data() {
+            Some(RData::TXT(txt)) => Self::from_txt_strings(node_id, txt.txt_data()),
+            _ => bail!("not a TXT record"),
+        }
+    }
+}
+
+/// Get the domain name under which the record for `node_id` is published.
+pub(crate) fn node_domain(node_id: &NodeId, origin: &Name) -> Result<Name> {
+    let name = Name::from_ascii(node_id.to_string())?.append_domain(origin)?;
+    Ok(name)
+}
+
+/// Parse the [`NodeId`] from a domain name of the form `<node-id>.<origin>`.
+pub(crate) fn node_id_from_domain(name: &Name, origin: &Name) -> Result<NodeId> {
+    ensure!(
+        origin.zone_of(name) && name.num_labels() == origin.num_labels() + 1,
+        "{name} is not a node domain of {origin}"
+    );
+    let label = name.iter().next().context("missing node label")?;
+    let label = std::str::from_utf8(label)?.to_ascii_lowercase();
+    let node_id = label.parse()?;
+    Ok(node_id)
+}
+
+/// Parse a domain name for use as origin.
+pub(crate) fn parse_origin(origin: &str) -> Result<Name> {
+    let mut origin = Name::from_ascii(origin)?;
+    origin.set_fqdn(true);
+    Ok(origin)
+}
+
+/// The unsigned attributes of a record, in order.
+fn attributes(timestamp: u64, info: &AddrInfo) -> Vec<String> {
+    let mut attrs = vec![format!("v={RECORD_VERSION}"), format!("ts={timestamp}")];
+    if let Some(url) = &info.derp_url {
+        attrs.push(format!("derp={url}"));
+    }
+    for addr in &info.direct_addresses {
+        attrs.push(format!("addr={addr}"));
+    }
+    for addr in &info.transport_addrs {
+        attrs.push(format!("transport={addr}"));
+    }
+    attrs
+}
+
+/// The payload signed by a node: the domain separator, the node id and the length prefixed
+/// attributes.
+fn signing_payload(node_id: &NodeId, attrs: &[String]) -> Vec<u8> {
+    let mut payload = SIGNATURE_DOMAIN.to_vec();
+    payload.extend_from_slice(node_id.as_bytes());
+    for attr in attrs {
+        payload.extend_from_slice(&(attr.len() as u16).to_be_bytes());
+        payload.extend_from_slice(attr.as_bytes());
+    }
+    payload
+}
+
+/// Node discovery through signed DNS TXT records.
+///
+/// Resolving looks up the TXT record of `<node-id>.<origin>` and verifies its signature.
+/// If configured with [`DnsDiscovery::with_publisher`], the node's own [`AddrInfo`] is
+/// published to an authoritative server for the origin whenever it changes.
+#[derive(Debug)]
+pub struct DnsDiscovery {
+    origin: Name,
+    resolver: TokioAsyncResolver,
+    publisher: Option<Publisher>,
+}
+
+#[derive(Debug)]
+struct Publisher {
+    secret_key: SecretKey,
+    server: SocketAddr,
+    ttl: u32,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl DnsDiscovery {
+    /// Create a [`DnsDiscovery`] for `origin`, resolving through the system's resolver.
+    pub fn new(origin: &str) -> Result<Self> {
+        Ok(Self {
+            origin: parse_origin(origin)?,
+            resolver: crate::dns::DNS_RESOLVER.clone(),
+            publisher: None,
+        })
+    }
+
+    /// Resolve through the nameserver at `addr` instead of the system's resolver.
+    ///
+    /// This is useful for private deployments and tests.
+    pub fn with_nameserver(mut self, addr: SocketAddr) -> Self {
+        let mut config = ResolverConfig::new();
+        config.add_name_server(NameServerConfig::new(addr, Protocol::Udp));
+        let mut opts = ResolverOpts::default();
+        // Don't cache missing records for long, nodes might publish at any time.
+        opts.negative_max_ttl = Some(Duration::from_secs(1));
+        self.resolver = TokioAsyncResolver::tokio(config, opts);
+        self
+    }
+
+    /// Publish the node's [`AddrInfo`], signed with `secret_key`, to the authoritative DNS
+    /// server at `server`.
+    pub fn with_publisher(mut self, secret_key: SecretKey, server: SocketAddr) -> Self {
+        self.publisher = Some(Publisher {
+            secret_key,
+            server,
+            ttl: DEFAULT_TTL,
+            task: Default::default(),
+        });
+        self
+    }
+
+    /// Set the time to live of published records, in seconds.
+    ///
+    /// Defaults to [`DEFAULT_TTL`]. Has no effect without [`DnsDiscovery::with_publisher`].
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        if let Some(publisher) = self.publisher.as_mut() {
+            publisher.ttl = ttl;
+        }
+        self
+    }
+
+    /// Look up and verify the record of `node_id`.
+    ///
+    /// Returns `None` if no record exists. If several valid records exist, the newest one is
+    /// returned.
+    pub async fn lookup(&self, node_id: NodeId) -> Result<Option<SignedAddrInfo>> {
+        lookup(&self.resolver, &self.origin, node_id).await
+    }
+}
+
+impl Discovery for DnsDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        let Some(publisher) = &self.publisher else {
+            return;
+        };
+        let signed = SignedAddrInfo::sign(&publisher.secret_key, info.clone(), now());
+        let record = match signed.to_record(&self.origin, publisher.ttl) {
+            Ok(record) => record,
+            Err(err) => {
+                warn!(?err, "failed to create dns record");
+                return;
+            }
+        };
+        let origin = self.origin.clone();
+        let server = publisher.server;
+        let task = tokio::task::spawn(async move {
+            match publish_record(server, origin, record).await {
+                Ok(()) => debug!(%server, "published node record"),
+                Err(err) => warn!(%server, ?err, "failed to publish node record"),
+            }
+        });
+        // A newer record supersedes any publish still in flight.
+        if let Some(task) = publisher.task.lock().replace(task) {
+            task.abort();
+        }
+    }
+
+    fn resolve(
+        &self,
+        _endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, Result<DiscoveryItem>>> {
+        let fut = async move {
+            let signed = self.lookup(node_id).await?;
+            Ok(signed.map(|signed| DiscoveryItem {
+                provenance: DNS_PROVENANCE,
+                last_updated: Some(signed.timestamp),
+                addr_info: signed.into_info(),
+            }))
+        };
+        let stream = futures::stream::once(fut).filter_map(|res| async move { res.transpose() });
+        Some(stream.boxed())
+    }
+}
+
+impl Drop for DnsDiscovery {
+    fn drop(&mut self) {
+        if let Some(task) = self.publisher.as_ref().and_then(|p| p.task.lock().take()) {
+            task.abort();
+        }
+    }
+}
+
+async fn lookup(
+    resolver: &TokioAsyncResolver,
+    origin: &Name,
+    node_id: NodeId,
+) -> Result<Option<SignedAddrInfo>> {
+    let name = node_domain(&node_id, origin)?;
+    let lookup = match tokio::time::timeout(RESOLVE_TIMEOUT, resolver.txt_lookup(name)).await? {
+        Ok(lookup) => lookup,
+        Err(err) => match err.kind() {
+            ResolveErrorKind::NoRecordsFound { .. } => {
+                debug!(node = %node_id.fmt_short(), "no dns record found");
+                return Ok(None);
+            }
+            _ => return Err(err.into()),
+        },
+    };
+    let mut newest: Option<SignedAddrInfo> = None;
+    for txt in lookup.iter() {
+        match SignedAddrInfo::from_txt_strings(node_id, txt.txt_data()) {
+            Ok(signed) => {
+                if newest
+                    .as_ref()
+                    .map_or(true, |newest| signed.timestamp > newest.timestamp)
+                {
+                    newest = Some(signed);
+                }
+            }
+            Err(err) => debug!(node = %node_id.fmt_short(), ?err, "skipping invalid dns record"),
+        }
+    }
+    Ok(newest)
+}
+
+/// Send a DNS UPDATE adding `record` to the zone `origin` at `server`.
+async fn publish_record(server: SocketAddr, origin: Name, record: Record) -> Result<()> {
+    let mut msg = Message::new();
+    msg.set_id(rand::random())
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Update)
+        .add_query(Query::query(origin, RecordType::SOA))
+        .add_name_server(record);
+    let request = msg.to_vec()?;
+
+    let bind_addr: SocketAddr = match server {
+        SocketAddr::V4(_) => (std::net::Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(server).await?;
+    socket.send(&request).await?;
+    let response = tokio::time::timeout(PUBLISH_TIMEOUT, async {
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let len = socket.recv(&mut buf).await?;
+            match Message::from_vec(&buf[..len]) {
+                Ok(response) if response.id() == msg.id() => return anyhow::Ok(response),
+                _ => continue,
+            }
+        }
+    })
+    .await
+    .context("timeout")??;
+    ensure!(
+        response.response_code() == ResponseCode::NoError,
+        "update rejected: {}",
+        response.response_code()
+    );
+    Ok(())
+}
+
+/// Microseconds since the unix epoch.
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("time drift")
+        .as_micros() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, time::Instant};
+
+    use crate::{derp::DerpMode, key::SecretKey, NodeAddr};
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+    const ORIGIN: &str = "nodes.iroh.test";
+
+    fn addr_info() -> AddrInfo {
+        AddrInfo {
+            derp_url: Some("https://derp.example.com".parse().unwrap()),
+            direct_addresses: BTreeSet::from([
+                "127.0.0.1:1234".parse().unwrap(),
+                "[::1]:1234".parse().unwrap(),
+            ]),
+            transport_addrs: BTreeSet::from(["unix:///run/iroh.sock".parse().unwrap()]),
+        }
+    }
+
+    #[test]
+    fn test_signed_addr_info_roundtrip() -> Result<()> {
+        let origin = parse_origin(ORIGIN)?;
+        let secret_key = SecretKey::generate();
+        let signed = SignedAddrInfo::sign(&secret_key, addr_info(), now());
+        let record = signed.to_record(&origin, DEFAULT_TTL)?;
+        assert_eq!(
+            record.name().to_ascii(),
+            format!("{}.{ORIGIN}.", secret_key.public())
+        );
+        assert_eq!(SignedAddrInfo::from_record(&origin, &record)?, signed);
+
+        // records signed by another key are rejected
+        let other = SecretKey::generate().public();
+        let strings = signed.to_txt_strings();
+        assert!(SignedAddrInfo::from_txt_strings(other, &strings).is_err());
+
+        // tampered records are rejected
+        let mut tampered = strings.clone();
+        tampered[2] = "addr=10.0.0.1:1234".to_string();
+        assert!(SignedAddrInfo::from_txt_strings(signed.node_id(), &tampered).is_err());
+        let mut tampered = strings;
+        tampered.insert(2, "addr=10.0.0.1:1234".to_string());
+        assert!(SignedAddrInfo::from_txt_strings(signed.node_id(), &tampered).is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dns_publish_resolve() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let server = DnsServer::spawn("127.0.0.1:0".parse()?, ORIGIN).await?;
+        let secret_key = SecretKey::generate();
+        let node_id = secret_key.public();
+        let publisher = DnsDiscovery::new(ORIGIN)?
+            .with_nameserver(server.local_addr())
+            .with_publisher(secret_key, server.local_addr());
+        let resolver = DnsDiscovery::new(ORIGIN)?.with_nameserver(server.local_addr());
+
+        let unknown = SecretKey::generate().public();
+        assert!(resolver.lookup(unknown).await?.is_none());
+
+        publisher.publish(&addr_info());
+        wait_published(&server, node_id).await?;
+        let signed = resolver.lookup(node_id).await?.context("missing record")?;
+        assert_eq!(signed.info(), &addr_info());
+
+        // updates with a bad signature are refused
+        let other = SignedAddrInfo::sign(&SecretKey::generate(), AddrInfo::default(), now());
+        let mut record = other.to_record(server.origin(), DEFAULT_TTL)?;
+        record.set_name(node_domain(&node_id, server.origin())?);
+        let res = publish_record(server.local_addr(), server.origin().clone(), record).await;
+        assert!(res.is_err());
+        assert_eq!(server.get(&node_id).unwrap().info(), &addr_info());
+        Ok(())
+    }
+
+    /// Connect to a node knowing nothing but its node id.
+    #[tokio::test]
+    async fn magic_endpoint_discovery_dns() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let server = DnsServer::spawn("127.0.0.1:0".parse()?, ORIGIN).await?;
+        let ep1 = new_endpoint(&server).await?;
+        let ep2 = new_endpoint(&server).await?;
+        // wait for our address to be updated and thus published at least once
+        ep1.my_addr().await?;
+        wait_published(&server, ep1.node_id()).await?;
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        Ok(())
+    }
+
+    async fn new_endpoint(server: &DnsServer) -> Result<MagicEndpoint> {
+        let secret_key = SecretKey::generate();
+        let disco = DnsDiscovery::new(ORIGIN)?
+            .with_nameserver(server.local_addr())
+            .with_publisher(secret_key.clone(), server.local_addr());
+        MagicEndpoint::builder()
+            .secret_key(secret_key)
+            .discovery(Box::new(disco))
+            .derp_mode(DerpMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind(0)
+            .await
+    }
+
+    async fn wait_published(server: &DnsServer, node_id: NodeId) -> Result<()> {
+        let start = Instant::now();
+        while server.get(&node_id).is_none() {
+            ensure!(start.elapsed() < Duration::from_secs(5), "not published");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! A small authoritative DNS server for signed node records.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc};
+
+use anyhow::Result;
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, ResponseCode},
+    rr::{DNSClass, Name, RecordType},
+};
+use parking_lot::Mutex;
+use tokio::{net::UdpSocket, task::JoinHandle};
+use tracing::{debug, error_span, trace, Instrument};
+
+use super::{node_id_from_domain, parse_origin, SignedAddrInfo};
+use crate::NodeId;
+
+/// An authoritative DNS server for the node records of a single origin.
+///
+/// Nodes publish their [`SignedAddrInfo`] with DNS UPDATE messages, which are only accepted
+/// if the record is correctly signed by the node it is published for. A record replaces the
+/// previous record of the node if it is newer. Records are kept in memory and served over
+/// UDP as TXT records.
+///
+/// This is intended for tests and small private deployments.
+#[derive(Debug)]
+pub struct DnsServer {
+    local_addr: SocketAddr,
+    state: Arc<State>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+struct State {
+    origin: Name,
+    records: Mutex<HashMap<NodeId, (SignedAddrInfo, u32)>>,
+}
+
+impl DnsServer {
+    /// Bind to `addr` and serve the node records for `origin`.
+    pub async fn spawn(addr: SocketAddr, origin: &str) -> Result<Self> {
+        let state = Arc::new(State {
+            origin: parse_origin(origin)?,
+            records: Default::default(),
+        });
+        let socket = UdpSocket::bind(addr).await?;
+        let local_addr = socket.local_addr()?;
+        let task = tokio::task::spawn(
+            run(socket, state.clone()).instrument(error_span!("dns-server", %local_addr)),
+        );
+        Ok(Self {
+            local_addr,
+            state,
+            task,
+        })
+    }
+
+    /// Get the address the server is listening on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Get the origin the server is authoritative for.
+    #[cfg(test)]
+    pub(crate) fn origin(&self) -> &Name {
+        &self.state.origin
+    }
+
+    /// Get the current record of a node.
+    pub fn get(&self, node_id: &NodeId) -> Option<SignedAddrInfo> {
+        let records = self.state.records.lock();
+        records.get(node_id).map(|(signed, _ttl)| signed.clone())
+    }
+
+    /// Insert a record, unless a newer record for the node exists.
+    ///
+    /// Returns `true` if the record was inserted.
+    pub fn insert(&self, signed: SignedAddrInfo, ttl: u32) -> bool {
+        self.state.insert(signed, ttl)
+    }
+}
+
+impl Drop for DnsServer {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl State {
+    fn insert(&self, signed: SignedAddrInfo, ttl: u32) -> bool {
+        let mut records = self.records.lock();
+        match records.get(&signed.node_id()) {
+            Some((existing, _)) if existing.timestamp() >= signed.timestamp() => false,
+            _ => {
+                records.insert(signed.node_id(), (signed, ttl));
+                true
+            }
+        }
+    }
+
+    fn handle(&self, request: &Message) -> Message {
+        let mut response = Message::new();
+        response
+            .set_id(request.id())
+            .set_message_type(MessageType::Response)
+            .set_op_code(request.op_code())
+            .set_recursion_desired(request.recursion_desired())
+            .add_queries(request.queries().to_vec());
+        let code = match request.op_code() {
+            OpCode::Query => self.handle_query(request, &mut response),
+            OpCode::Update => self.handle_update(request),
+            _ => ResponseCode::NotImp,
+        };
+        response.set_response_code(code);
+        response
+    }
+
+    fn handle_query(&self, request: &Message, response: &mut Message) -> ResponseCode {
+        let [query] = request.queries() else {
+            return ResponseCode::FormErr;
+        };
+        if !self.origin.zone_of(query.name()) {
+            return ResponseCode::Refused;
+        }
+        response.set_authoritative(true);
+        if query.name() == &self.origin {
+            return ResponseCode::NoError;
+        }
+        let Ok(node_id) = node_id_from_domain(query.name(), &self.origin) else {
+            return ResponseCode::NXDomain;
+        };
+        let Some((signed, ttl)) = self.records.lock().get(&node_id).cloned() else {
+            return ResponseCode::NXDomain;
+        };
+        if matches!(query.query_type(), RecordType::TXT | RecordType::ANY) {
+            match signed.to_record(&self.origin, ttl) {
+                Ok(mut record) => {
+                    // Answer with the name as queried to preserve its case.
+                    record.set_name(query.name().clone());
+                    response.add_answer(record);
+                }
+                Err(_) => return ResponseCode::ServFail,
+            }
+        }
+        ResponseCode::NoError
+    }
+
+    /// Handle an update. Updates are atomic: if any record is invalid, none are applied.
+    fn handle_update(&self, request: &Message) -> ResponseCode {
+        let [zone] = request.queries() else {
+            return ResponseCode::FormErr;
+        };
+        if zone.query_type() != RecordType::SOA || zone.name() != &self.origin {
+            return ResponseCode::NotAuth;
+        }
+        let mut updates = Vec::new();
+        for record in request.name_servers() {
+            if record.dns_class() != DNSClass::IN || record.record_type() != RecordType::TXT {
+                return ResponseCode::Refused;
+            }
+            match SignedAddrInfo::from_record(&self.origin, record) {
+                Ok(signed) => updates.push((signed, record.ttl())),
+                Err(err) => {
+                    debug!(name = %record.name(), ?err, "refusing invalid record");
+                    return ResponseCode::Refused;
+                }
+            }
+        }
+        for (signed, ttl) in updates {
+            let node = signed.node_id();
+            if self.insert(signed, ttl) {
+                debug!(node = %node.fmt_short(), "record updated");
+            }
+        }
+        ResponseCode::NoError
+    }
+}
+
+async fn run(socket: UdpSocket, state: Arc<State>) {
+    let mut buf = vec![0u8; 4096];
+    loop {
+        let (len, from) = match socket.recv_from(&mut buf).await {
+            Ok(res) => res,
+            Err(err) => {
+                debug!(?err, "recv failed");
+                continue;
+            }
+        };
+        let request = match Message::from_vec(&buf[..len]) {
+            Ok(request) => request,
+            Err(err) => {
+                trace!(%from, ?err, "ignoring invalid message");
+                continue;
+            }
+        };
+        let response = state.handle(&request);
+        match response.to_vec() {
+            Ok(response) => {
+                if let Err(err) = socket.send_to(&response, from).await {
+                    debug!(%from, ?err, "send failed");
+                }
+            }
+            Err(err) => debug!(?err, "failed to encode response"),
+        }
+    }
+}
@@ -0,0 +1,632 @@
+//! HTTP based node discovery.
+//!
+//! Nodes publish their [`SignedAddrInfo`] to a [`RecordStore`] served over HTTP, usually
+//! hosted by a derper next to its derp endpoint, and resolve other nodes from the same
+//! store. Records use the attributes of the [DNS record format](super::dns), so the store
+//! verifies them against the [`NodeId`] and resolvers do not need to trust the store.
+//!
+//! The protocol consists of two requests on [`ANNOUNCE_PATH`]:
+//!
+//! - `PUT /discovery?node=<node-id>&v=1&ts=<timestamp>&addr=<socket addr>&...&sig=<signature>`
+//!   stores a record. The query parameters after `node` are the attributes of the record, in
+//!   order. Answers `204 No Content` on success and `409 Conflict` if a newer record is
+//!   stored already. Answers `429 Too Many Requests` if the store is rate limited and
+//!   `503 Service Unavailable` if the store is full.
+//! - `GET /discovery?node=<node-id>` resolves a record. Answers with one attribute per line,
+//!   or `404 Not Found` if no unexpired record is stored.
+//!
+//! Stored records expire after a time to live, so nodes republish their record periodically.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use futures::{stream::BoxStream, StreamExt};
+use http::{response::Builder as ResponseBuilder, Method, Request, Response, StatusCode};
+use parking_lot::Mutex;
+use tokio::{task::JoinHandle, time::Instant};
+use tracing::{debug, warn};
+use url::Url;
+
+use super::dns::{now, SignedAddrInfo};
+use crate::{
+    derp::{types::RateLimiter, DerpMap},
+    discovery::{Discovery, DiscoveryItem},
+    key::SecretKey,
+    AddrInfo, MagicEndpoint, NodeId,
+};
+
+/// The provenance of [`DiscoveryItem`]s produced by [`HttpDiscovery`].
+pub const HTTP_PROVENANCE: &str = "http";
+
+/// The path on which a [`RecordStore`] is served.
+pub const ANNOUNCE_PATH: &str = "/discovery";
+
+/// The default time after which a stored record expires.
+pub const DEFAULT_RECORD_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// The default interval in which [`HttpDiscovery`] republishes the node's record.
+pub const DEFAULT_REPUBLISH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Timeout for a single request to a [`RecordStore`].
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The default maximum number of records kept by a [`RecordStore`].
+pub const DEFAULT_MAX_RECORDS: usize = 100_000;
+
+/// The default number of `PUT` requests per second accepted by a [`RecordStore`].
+pub const DEFAULT_PUTS_PER_SECOND: usize = 200;
+
+/// The default burst of `PUT` requests accepted by a [`RecordStore`].
+pub const DEFAULT_PUTS_BURST: usize = 1_000;
+
+/// An in-memory store of signed node records with expiry, to be served over HTTP.
+///
+/// A record is only accepted if it is correctly signed by the node it is published for, and
+/// replaces the previous record of the node if it is newer. The store holds at most
+/// [`RecordStore::with_max_records`] records and limits the rate of `PUT` requests.
+#[derive(Debug)]
+pub struct RecordStore {
+    ttl: Duration,
+    max_records: usize,
+    records: Mutex<Records>,
+    put_limiter: Option<PutRateLimiter>,
+}
+
+/// The outcome of [`RecordStore::insert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InsertOutcome {
+    /// The record was stored.
+    Inserted,
+    /// A record with the same or a newer timestamp is stored already.
+    Outdated,
+    /// The store is full and the node has no record in it.
+    Full,
+}
+
+#[derive(Debug, Default)]
+struct Records {
+    by_node: HashMap<NodeId, (SignedAddrInfo, Instant)>,
+    /// Expiry times in insertion order. Since all records have the same time to live, the
+    /// front of the queue expires first. Entries of replaced records are skipped on removal.
+    expiry: VecDeque<(Instant, NodeId)>,
+}
+
+impl Records {
+    /// Remove all records which expired before `now`.
+    ///
+    /// Only walks the expired front of the expiry queue.
+    fn expire(&mut self, now: Instant) {
+        while let Some((expires, node_id)) = self.expiry.front().copied() {
+            if expires > now {
+                break;
+            }
+            self.expiry.pop_front();
+            if matches!(self.by_node.get(&node_id), Some((_, e)) if *e == expires) {
+                self.by_node.remove(&node_id);
+            }
+        }
+    }
+}
+
+struct PutRateLimiter(RateLimiter);
+
+impl std::fmt::Debug for PutRateLimiter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PutRateLimiter").finish_non_exhaustive()
+    }
+}
+
+impl Default for RecordStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_RECORD_TTL)
+    }
+}
+
+impl RecordStore {
+    /// Create an empty store in which records expire after `ttl`.
+    ///
+    /// The store holds at most [`DEFAULT_MAX_RECORDS`] records and accepts
+    /// [`DEFAULT_PUTS_PER_SECOND`] `PUT` requests per second, with a burst of
+    /// [`DEFAULT_PUTS_BURST`].
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            max_records: DEFAULT_MAX_RECORDS,
+            records: Default::default(),
+            put_limiter: RateLimiter::new(DEFAULT_PUTS_PER_SECOND, DEFAULT_PUTS_BURST)
+                .expect("valid default limits")
+                .map(PutRateLimiter),
+        }
+    }
+
+    /// Set the maximum number of records held by the store.
+    ///
+    /// Records of new nodes are refused while the store is full of unexpired records.
+    pub fn with_max_records(mut self, max_records: usize) -> Self {
+        self.max_records = max_records;
+        self
+    }
+
+    /// Set the rate of `PUT` requests accepted by [`RecordStore::handle_request`].
+    ///
+    /// A rate or burst of `0` disables the limit.
+    pub fn with_put_rate_limit(mut self, per_second: usize, burst: usize) -> Result<Self> {
+        self.put_limiter = RateLimiter::new(per_second, burst)?.map(PutRateLimiter);
+        Ok(self)
+    }
+
+    /// Get the unexpired record of a node.
+    pub fn get(&self, node_id: &NodeId) -> Option<SignedAddrInfo> {
+        let mut records = self.records.lock();
+        records.expire(Instant::now());
+        records
+            .by_node
+            .get(node_id)
+            .map(|(signed, _)| signed.clone())
+    }
+
+    /// Insert a record, unless a newer unexpired record for the node exists or the store is
+    /// full.
+    pub fn insert(&self, signed: SignedAddrInfo) -> InsertOutcome {
+        let inserted_at = Instant::now();
+        let mut records = self.records.lock();
+        records.expire(inserted_at);
+        match records.by_node.get(&signed.node_id()) {
+            Some((existing, _)) if existing.timestamp() >= signed.timestamp() => {
+                return InsertOutcome::Outdated;
+            }
+            Some(_) => {}
+            None if records.by_node.len() >= self.max_records => return InsertOutcome::Full,
+            None => {}
+        }
+        let node_id = signed.node_id();
+        let expires = inserted_at + self.ttl;
+        records.by_node.insert(node_id, (signed, expires));
+        records.expiry.push_back((expires, node_id));
+        InsertOutcome::Inserted
+    }
+
+    /// Handle a `GET` or `PUT` request on [`ANNOUNCE_PATH`].
+    pub fn handle_request<B>(
+        &self,
+        req: &Request<B>,
+        response: ResponseBuilder,
+    ) -> http::Result<Response<http_body_util::Full<bytes::Bytes>>> {
+        let query = req.uri().query().unwrap_or_default();
+        let (status, body) = match *req.method() {
+            Method::GET => match parse_get_query(query) {
+                Ok(node_id) => match self.get(&node_id) {
+                    Some(signed) => (StatusCode::OK, signed.to_txt_strings().join("\n")),
+                    None => (StatusCode::NOT_FOUND, "record not found".to_string()),
+                },
+                Err(err) => (StatusCode::BAD_REQUEST, err.to_string()),
+            },
+            Method::PUT if self.put_rate_limited() => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests".to_string(),
+            ),
+            Method::PUT => match parse_put_query(query) {
+                Ok(signed) => {
+                    let node_id = signed.node_id();
+                    match self.insert(signed) {
+                        InsertOutcome::Inserted => {
+                            debug!(node = %node_id.fmt_short(), "stored node record");
+                            (StatusCode::NO_CONTENT, String::new())
+                        }
+                        InsertOutcome::Outdated => {
+                            (StatusCode::CONFLICT, "newer record exists".to_string())
+                        }
+                        InsertOutcome::Full => {
+                            warn!(node = %node_id.fmt_short(), "record store full");
+                            (StatusCode::SERVICE_UNAVAILABLE, "store full".to_string())
+                        }
+                    }
+                }
+                Err(err) => (StatusCode::BAD_REQUEST, err.to_string()),
+            },
+            _ => (StatusCode::METHOD_NOT_ALLOWED, String::new()),
+        };
+        response
+            .status(status)
+            .header("Content-Type", "text/plain; charset=utf-8")
+            .body(body.into())
+    }
+
+    fn put_rate_limited(&self) -> bool {
+        self.put_limiter
+            .as_ref()
+            .is_some_and(|limiter| limiter.0.check_n(1).is_err())
+    }
+}
+
+fn parse_get_query(query: &str) -> Result<NodeId> {
+    let mut pairs = url::form_urlencoded::parse(query.as_bytes());
+    match pairs.next() {
+        Some((key, value)) if key == "node" => Ok(value.parse()?),
+        _ => bail!("missing node parameter"),
+    }
+}
+
+fn parse_put_query(query: &str) -> Result<SignedAddrInfo> {
+    let mut pairs = url::form_urlencoded::parse(query.as_bytes());
+    let node_id = match pairs.next() {
+        Some((key, value)) if key == "node" => value.parse()?,
+        _ => bail!("missing node parameter"),
+    };
+    let attrs: Vec<_> = pairs.map(|(key, value)| format!("{key}={value}")).collect();
+    SignedAddrInfo::from_txt_strings(node_id, &attrs)
+}
+
+/// Node discovery through [`RecordStore`]s served over HTTP.
+///
+/// Resolving queries all configured stores concurrently. If configured with
+/// [`HttpDiscovery::with_publisher`], the node's own [`AddrInfo`] is published to all stores
+/// whenever it changes, and republished periodically so it does not expire.
+#[derive(Debug)]
+pub struct HttpDiscovery {
+    urls: Vec<Url>,
+    client: reqwest::Client,
+    publisher: Option<Publisher>,
+}
+
+#[derive(Debug)]
+struct Publisher {
+    secret_key: SecretKey,
+    interval: Duration,
+    task: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl HttpDiscovery {
+    /// Create a [`HttpDiscovery`] using the store at `url`.
+    ///
+    /// The url must include the path of the store, usually [`ANNOUNCE_PATH`].
+    pub fn new(url: Url) -> Self {
+        Self::from_urls([url])
+    }
+
+    /// Create a [`HttpDiscovery`] using the stores of all nodes in the [`DerpMap`] which
+    /// have [`DerpNode::discovery`] set.
+    ///
+    /// [`DerpNode::discovery`]: crate::derp::DerpNode::discovery
+    pub fn from_derp_map(derp_map: &DerpMap) -> Self {
+        let urls = derp_map
+            .nodes()
+            .filter(|node| node.discovery && !node.stun_only)
+            .map(|node| {
+                let mut url = Url::clone(&node.url);
+                url.set_path(ANNOUNCE_PATH);
+                url
+            });
+        Self::from_urls(urls)
+    }
+
+    fn from_urls(urls: impl IntoIterator<Item = Url>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("valid client config");
+        Self {
+            urls: urls.into_iter().collect(),
+            client,
+            publisher: None,
+        }
+    }
+
+    /// Publish the node's [`AddrInfo`], signed with `secret_key`, to all stores.
+    pub fn with_publisher(mut self, secret_key: SecretKey) -> Self {
+        self.publisher = Some(Publisher {
+            secret_key,
+            interval: DEFAULT_REPUBLISH_INTERVAL,
+            task: Default::default(),
+        });
+        self
+    }
+
+    /// Set the interval in which the node's record is republished.
+    ///
+    /// Must be shorter than the time to live of the stores. Defaults to
+    /// [`DEFAULT_REPUBLISH_INTERVAL`]. Has no effect without [`HttpDiscovery::with_publisher`].
+    pub fn with_republish_interval(mut self, interval: Duration) -> Self {
+        if let Some(publisher) = self.publisher.as_mut() {
+            publisher.interval = interval;
+        }
+        self
+    }
+
+    /// Look up and verify the record of `node_id` in the store at `url`.
+    ///
+    /// Returns `None` if the store has no record for the node.
+    pub async fn lookup(&self, url: &Url, node_id: NodeId) -> Result<Option<SignedAddrInfo>> {
+        lookup(&self.client, url, node_id).await
+    }
+}
+
+impl Discovery for HttpDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        let Some(publisher) = &self.publisher else {
+            return;
+        };
+        let client = self.client.clone();
+        let urls = self.urls.clone();
+        let secret_key = publisher.secret_key.clone();
+        let interval = publisher.interval;
+        let info = info.clone();
+        let task = tokio::task::spawn(async move {
+            loop {
+                let signed = SignedAddrInfo::sign(&secret_key, info.clone(), now());
+                let requests = urls.iter().map(|url| {
+                    let client = &client;
+                    let signed = &signed;
+                    async move {
+                        match publish(client, url, signed).await {
+                            Ok(()) => debug!(%url, "published node record"),
+                            Err(err) => warn!(%url, ?err, "failed to publish node record"),
+                        }
+                    }
+                });
+                futures::future::join_all(requests).await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+        // A newer record supersedes the republishing of the previous one.
+        if let Some(task) = publisher.task.lock().replace(task) {
+            task.abort();
+        }
+    }
+
+    fn resolve(
+        &self,
+        _endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, Result<DiscoveryItem>>> {
+        let lookups = self.urls.iter().map(move |url| async move {
+            match self.lookup(url, node_id).await {
+                Ok(signed) => signed.map(|signed| {
+                    Ok(DiscoveryItem {
+                        provenance: HTTP_PROVENANCE,
+                        last_updated: Some(signed.timestamp()),
+                        addr_info: signed.into_info(),
+                    })
+                }),
+                // A single failing store must not end the discovery.
+                Err(err) => {
+                    debug!(%url, node = %node_id.fmt_short(), ?err, "lookup failed");
+                    None
+                }
+            }
+        });
+        let stream = futures::stream::iter(lookups)
+            .buffer_unordered(self.urls.len().max(1))
+            .filter_map(|item| async move { item });
+        Some(stream.boxed())
+    }
+}
+
+impl Drop for HttpDiscovery {
+    fn drop(&mut self) {
+        if let Some(task) = self.publisher.as_ref().and_then(|p| p.task.lock().take()) {
+            task.abort();
+        }
+    }
+}
+
+async fn publish(client: &reqwest::Client, url: &Url, signed: &SignedAddrInfo) -> Result<()> {
+    let mut url = url.clone();
+    {
+        let mut query = url.query_pairs_mut();
+        query
+            .clear()
+            .append_pair("node", &signed.node_id().to_string());
+        for attr in signed.to_txt_strings() {
+            let (key, value) = attr.split_once('=').context("invalid attribute")?;
+            query.append_pair(key, value);
+        }
+    }
+    let response = client.put(url).send().await?;
+    match response.status() {
+        // A newer record from this node is already stored, nothing to do.
+        reqwest::StatusCode::NO_CONTENT | reqwest::StatusCode::CONFLICT => Ok(()),
+        status => bail!("store responded with {status}"),
+    }
+}
+
+async fn lookup(
+    client: &reqwest::Client,
+    url: &Url,
+    node_id: NodeId,
+) -> Result<Option<SignedAddrInfo>> {
+    let mut url = url.clone();
+    url.query_pairs_mut()
+        .clear()
+        .append_pair("node", &node_id.to_string());
+    let response = client.get(url).send().await?;
+    match response.status() {
+        reqwest::StatusCode::OK => {
+            let body = response.text().await?;
+            let strings: Vec<_> = body.lines().collect();
+            let signed = SignedAddrInfo::from_txt_strings(node_id, &strings)?;
+            Ok(Some(signed))
+        }
+        reqwest::StatusCode::NOT_FOUND => Ok(None),
+        status => bail!("store responded with {status}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, net::SocketAddr, sync::Arc};
+
+    use anyhow::ensure;
+    use hyper::body::Incoming;
+
+    use crate::{
+        derp::{self, DerpMode},
+        NodeAddr,
+    };
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    fn addr_info() -> AddrInfo {
+        AddrInfo {
+            derp_url: None,
+            direct_addresses: BTreeSet::from(["127.0.0.1:1234".parse().unwrap()]),
+            transport_addrs: Default::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_record_store_expiry() -> Result<()> {
+        let store = RecordStore::new(Duration::from_millis(100));
+        let secret_key = SecretKey::generate();
+        let node_id = secret_key.public();
+        let old = SignedAddrInfo::sign(&secret_key, AddrInfo::default(), now());
+        let new = SignedAddrInfo::sign(&secret_key, addr_info(), now() + 1);
+        assert_eq!(store.insert(new.clone()), InsertOutcome::Inserted);
+        assert_eq!(store.insert(old), InsertOutcome::Outdated);
+        assert_eq!(store.get(&node_id), Some(new));
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(store.get(&node_id), None);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_record_store_bounded() -> Result<()> {
+        let store = RecordStore::new(Duration::from_millis(100)).with_max_records(2);
+        let sign = |key: &SecretKey, ts| SignedAddrInfo::sign(key, addr_info(), ts);
+        let keys: Vec<_> = (0..3).map(|_| SecretKey::generate()).collect();
+        assert_eq!(store.insert(sign(&keys[0], 1)), InsertOutcome::Inserted);
+        assert_eq!(store.insert(sign(&keys[1], 1)), InsertOutcome::Inserted);
+        assert_eq!(store.insert(sign(&keys[2], 1)), InsertOutcome::Full);
+        // nodes with a record can still update it
+        assert_eq!(store.insert(sign(&keys[0], 2)), InsertOutcome::Inserted);
+
+        // expired records make room for new nodes
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(store.insert(sign(&keys[0], 3)), InsertOutcome::Inserted);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(store.insert(sign(&keys[2], 1)), InsertOutcome::Inserted);
+        assert!(store.get(&keys[0].public()).is_some());
+        assert!(store.get(&keys[1].public()).is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_store_put_rate_limit() -> Result<()> {
+        let store = RecordStore::default().with_put_rate_limit(1, 2)?;
+        let put = || {
+            let signed = SignedAddrInfo::sign(&SecretKey::generate(), addr_info(), now());
+            let uri = format!("{ANNOUNCE_PATH}?node={}", signed.node_id());
+            let uri = signed
+                .to_txt_strings()
+                .iter()
+                .fold(uri, |uri, attr| format!("{uri}&{attr}"));
+            let req = Request::put(uri).body(())?;
+            anyhow::Ok(store.handle_request(&req, Response::builder())?.status())
+        };
+        assert_eq!(put()?, StatusCode::NO_CONTENT);
+        assert_eq!(put()?, StatusCode::NO_CONTENT);
+        assert_eq!(put()?, StatusCode::TOO_MANY_REQUESTS);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_publish_resolve() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let (server, store) = spawn_store().await?;
+        let url = store_url(server.addr());
+        let secret_key = SecretKey::generate();
+        let node_id = secret_key.public();
+        let disco = HttpDiscovery::new(url.clone()).with_publisher(secret_key);
+
+        assert!(disco.lookup(&url, node_id).await?.is_none());
+        disco.publish(&addr_info());
+        wait_published(&store, node_id).await?;
+        let signed = disco
+            .lookup(&url, node_id)
+            .await?
+            .context("missing record")?;
+        assert_eq!(signed.info(), &addr_info());
+
+        // records signed by another key are refused
+        let other = SignedAddrInfo::sign(&SecretKey::generate(), AddrInfo::default(), now());
+        let mut forged = url.clone();
+        forged
+            .query_pairs_mut()
+            .append_pair("node", &node_id.to_string());
+        for attr in other.to_txt_strings() {
+            let (key, value) = attr.split_once('=').unwrap();
+            forged.query_pairs_mut().append_pair(key, value);
+        }
+        let response = reqwest::Client::new().put(forged).send().await?;
+        assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+        assert_eq!(store.get(&node_id).unwrap().info(), &addr_info());
+
+        server.shutdown().await;
+        Ok(())
+    }
+
+    /// Connect to a node knowing nothing but its node id.
+    #[tokio::test]
+    async fn magic_endpoint_discovery_http() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let (server, store) = spawn_store().await?;
+        let url = store_url(server.addr());
+        let ep1 = new_endpoint(url.clone()).await?;
+        let ep2 = new_endpoint(url).await?;
+        // wait for our address to be updated and thus published at least once
+        ep1.my_addr().await?;
+        wait_published(&store, ep1.node_id()).await?;
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        server.shutdown().await;
+        Ok(())
+    }
+
+    /// Serve a [`RecordStore`] the way the derper does.
+    async fn spawn_store() -> Result<(derp::http::Server, Arc<RecordStore>)> {
+        let store = Arc::new(RecordStore::default());
+        let mut builder = derp::http::ServerBuilder::new("127.0.0.1:0".parse()?)
+            .secret_key(Some(SecretKey::generate()));
+        for method in [Method::GET, Method::PUT] {
+            let store = store.clone();
+            builder = builder.request_handler(
+                method,
+                ANNOUNCE_PATH,
+                Box::new(move |req: Request<Incoming>, response: ResponseBuilder| {
+                    store.handle_request(&req, response).map_err(Into::into)
+                }),
+            );
+        }
+        let server = builder.spawn().await?;
+        Ok((server, store))
+    }
+
+    fn store_url(addr: SocketAddr) -> Url {
+        format!("http://{addr}{ANNOUNCE_PATH}").parse().unwrap()
+    }
+
+    async fn new_endpoint(url: Url) -> Result<MagicEndpoint> {
+        let secret_key = SecretKey::generate();
+        let disco = HttpDiscovery::new(url).with_publisher(secret_key.clone());
+        MagicEndpoint::builder()
+            .secret_key(secret_key)
+            .discovery(Box::new(disco))
+            .derp_mode(DerpMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind(0)
+            .await
+    }
+
+    async fn wait_published(store: &RecordStore, node_id: NodeId) -> Result<()> {
+        let start = Instant::now();
+        while store.get(&node_id).is_none() {
+            ensure!(start.elapsed() < Duration::from_secs(5), "not published");
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,602 @@
+//! Local network node discovery over multicast DNS.
+//!
+//! Nodes announce themselves as [DNS-SD] instances of the `_iroh._udp.local.` service. The
+//! instance name is the [`NodeId`] and the instance's TXT record is the node's
+//! [`SignedAddrInfo`], in the same format used by [`DnsDiscovery`]. Records are thus
+//! verified against the [`NodeId`] and can not be spoofed by other hosts on the network.
+//!
+//! Only direct addresses which belong to one of the local network interfaces are announced.
+//! Resolving a node sends [mDNS] queries for its instance name and yields all valid answers
+//! until a timeout is reached, so two nodes on the same network can connect without any
+//! internet connectivity.
+//!
+//! [`DnsDiscovery`]: super::dns::DnsDiscovery
+//! [DNS-SD]: https://www.rfc-editor.org/rfc/rfc6763
+//! [mDNS]: https://www.rfc-editor.org/rfc/rfc6762
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::{ensure, Context, Result};
+use futures::{stream::BoxStream, StreamExt};
+use hickory_proto::{
+    op::{Message, MessageType, OpCode, Query},
+    rr::{rdata::PTR, Name, RData, Record, RecordType},
+};
+use parking_lot::Mutex;
+use tokio::{
+    net::UdpSocket,
+    sync::{broadcast, mpsc},
+    task::JoinHandle,
+    time::Instant,
+};
+use tracing::{debug, error_span, trace, warn, Instrument};
+
+use super::dns::{node_domain, now, parse_origin, SignedAddrInfo};
+use crate::{
+    discovery::{Discovery, DiscoveryItem},
+    key::SecretKey,
+    net::interfaces,
+    AddrInfo, MagicEndpoint, NodeId,
+};
+
+/// The provenance of [`DiscoveryItem`]s produced by [`MdnsDiscovery`].
+pub const MDNS_PROVENANCE: &str = "mdns";
+
+/// The well-known mDNS port.
+pub const MDNS_PORT: u16 = 5353;
+
+/// The IPv4 mDNS multicast group.
+pub const MDNS_ADDR_V4: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// The DNS-SD service name under which nodes announce themselves.
+const SERVICE_NAME: &str = "_iroh._udp.local.";
+
+/// The time to live of announced records, in seconds.
+const TTL: u32 = 120;
+
+/// Interval for unsolicited re-announcements of our own record.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Interval between repeated queries while resolving a node.
+const QUERY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long to wait for answers when resolving a node.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum size of an mDNS packet we accept.
+const MAX_PACKET_SIZE: usize = 9000;
+
+/// Maximum number of records of other nodes that are cached.
+const MAX_CACHED_NODES: usize = 1024;
+
+/// How long a record of another node is cached after it was last received.
+const CACHE_TTL: Duration = Duration::from_secs(TTL as u64);
+
+/// Node discovery on the local network via multicast DNS.
+///
+/// The node's own [`AddrInfo`] is announced whenever it is published and periodically
+/// afterwards, and whenever another node queries for it. Records of other nodes seen on the
+/// network are cached for the TTL of the records, up to a fixed number of nodes.
+///
+/// Dropping the [`MdnsDiscovery`] stops the announcements.
+#[derive(Debug)]
+pub struct MdnsDiscovery {
+    to_actor: mpsc::Sender<ToActor>,
+    found: broadcast::Sender<SignedAddrInfo>,
+    cache: Arc<Mutex<Cache>>,
+    task: JoinHandle<()>,
+}
+
+#[derive(Debug)]
+enum ToActor {
+    Publish(AddrInfo),
+    Query(NodeId),
+}
+
+impl MdnsDiscovery {
+    /// Start mDNS discovery on [`MDNS_PORT`], announcing the node of `secret_key`.
+    pub async fn new(secret_key: SecretKey) -> Result<Self> {
+        Self::with_port(secret_key, MDNS_PORT).await
+    }
+
+    /// Start mDNS discovery on a non-standard port.
+    ///
+    /// Nodes only find each other if they use the same port. This is useful for tests which
+    /// should not interfere with other mDNS responders on the host.
+    pub async fn with_port(secret_key: SecretKey, port: u16) -> Result<Self> {
+        let socket = bind(port)?;
+        let (to_actor, rx) = mpsc::channel(32);
+        let (found, _) = broadcast::channel(64);
+        let cache: Arc<Mutex<Cache>> = Default::default();
+        let me = secret_key.public();
+        let mut actor = Actor {
+            origin: parse_origin(SERVICE_NAME)?,
+            socket,
+            port,
+            interfaces: Default::default(),
+            secret_key,
+            own: None,
+            cache: cache.clone(),
+            found: found.clone(),
+            rx,
+        };
+        actor
+            .update_interfaces(&interfaces::State::new().await)
+            .context("no interface joined the mDNS group")?;
+        let task = tokio::task::spawn(
+            actor
+                .run()
+                .instrument(error_span!("mdns", me = %me.fmt_short())),
+        );
+        Ok(Self {
+            to_actor,
+            found,
+            cache,
+            task,
+        })
+    }
+
+    /// Get the most recent record of a node seen on the local network.
+    pub fn get(&self, node_id: &NodeId) -> Option<SignedAddrInfo> {
+        self.cache.lock().get(node_id)
+    }
+}
+
+impl Discovery for MdnsDiscovery {
+    fn publish(&self, info: &AddrInfo) {
+        if let Err(err) = self.to_actor.try_send(ToActor::Publish(info.clone())) {
+            warn!(?err, "failed to publish to mdns");
+        }
+    }
+
+    fn resolve(
+        &self,
+        _endpoint: MagicEndpoint,
+        node_id: NodeId,
+    ) -> Option<BoxStream<'_, Result<DiscoveryItem>>> {
+        let resolving = Resolving {
+            node_id,
+            cached: self.get(&node_id),
+            found: self.found.subscribe(),
+            to_actor: self.to_actor.clone(),
+            next_query: Instant::now(),
+            deadline: Instant::now() + RESOLVE_TIMEOUT,
+            last_timestamp: None,
+        };
+        let stream = futures::stream::unfold(resolving, |mut resolving| async move {
+            let signed = resolving.next().await?;
+            let item = DiscoveryItem {
+                provenance: MDNS_PROVENANCE,
+                last_updated: Some(signed.timestamp()),
+                addr_info: signed.into_info(),
+            };
+            Some((Ok(item), resolving))
+        });
+        Some(stream.boxed())
+    }
+}
+
+impl Drop for MdnsDiscovery {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// State of a single [`Discovery::resolve`] stream.
+#[derive(Debug)]
+struct Resolving {
+    node_id: NodeId,
+    cached: Option<SignedAddrInfo>,
+    found: broadcast::Receiver<SignedAddrInfo>,
+    to_actor: mpsc::Sender<ToActor>,
+    next_query: Instant,
+    deadline: Instant,
+    last_timestamp: Option<u64>,
+}
+
+impl Resolving {
+    /// Wait for the next record of the node which is newer than the last one returned.
+    ///
+    /// Returns `None` once the deadline is reached.
+    async fn next(&mut self) -> Option<SignedAddrInfo> {
+        if let Some(signed) = self.cached.take() {
+            self.last_timestamp = Some(signed.timestamp());
+            return Some(signed);
+        }
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(self.deadline) => return None,
+                _ = tokio::time::sleep_until(self.next_query) => {
+                    self.next_query += QUERY_INTERVAL;
+                    self.to_actor.send(ToActor::Query(self.node_id)).await.ok()?;
+                }
+                res = self.found.recv() => match res {
+                    Ok(signed) => {
+                        let is_newer = self
+                            .last_timestamp
+                            .map_or(true, |last| signed.timestamp() > last);
+                        if signed.node_id() == self.node_id && is_newer {
+                            self.last_timestamp = Some(signed.timestamp());
+                            return Some(signed);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        debug!("missed {n} mdns records");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Actor {
+    origin: Name,
+    socket: UdpSocket,
+    port: u16,
+    /// The interface addresses on which we joined the multicast group.
+    interfaces: BTreeSet<Ipv4Addr>,
+    secret_key: SecretKey,
+    own: Option<SignedAddrInfo>,
+    cache: Arc<Mutex<Cache>>,
+    found: broadcast::Sender<SignedAddrInfo>,
+    rx: mpsc::Receiver<ToActor>,
+}
+
+impl Actor {
+    async fn run(mut self) {
+        let start = Instant::now() + ANNOUNCE_INTERVAL;
+        let mut announce = tokio::time::interval_at(start, ANNOUNCE_INTERVAL);
+        let mut buf = vec![0u8; MAX_PACKET_SIZE];
+        loop {
+            tokio::select! {
+                msg = self.rx.recv() => match msg {
+                    None => break,
+                    Some(ToActor::Publish(info)) => {
+                        let state = interfaces::State::new().await;
+                        if let Err(err) = self.update_interfaces(&state) {
+                            warn!(?err, "failed to join mdns group");
+                        }
+                        self.own = Some(sign(&self.secret_key, &state, info));
+                        self.announce().await;
+                    }
+                    Some(ToActor::Query(node_id)) => self.query(node_id).await,
+                },
+                _ = announce.tick() => self.announce().await,
+                res = self.socket.recv_from(&mut buf) => match res {
+                    Ok((len, from)) => self.handle_packet(&buf[..len], from).await,
+                    Err(err) => warn!(?err, "failed to receive mdns packet"),
+                }
+            }
+        }
+    }
+
+    /// Join the multicast group on all IPv4 addresses of interfaces which are up and which
+    /// we did not join yet.
+    ///
+    /// Fails if the group is not joined on any interface.
+    fn update_interfaces(&mut self, state: &interfaces::State) -> Result<()> {
+        let addrs = state
+            .interfaces
+            .values()
+            .filter(|iface| iface.is_up())
+            .flat_map(|iface| iface.addrs())
+            .filter_map(|net| match net.addr() {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            });
+        let socket = socket2::SockRef::from(&self.socket);
+        for addr in addrs {
+            if self.interfaces.contains(&addr) {
+                continue;
+            }
+            match socket.join_multicast_v4(&MDNS_ADDR_V4, &addr) {
+                Ok(()) => {
+                    debug!(%addr, "joined mdns group");
+                    self.interfaces.insert(addr);
+                }
+                Err(err) => debug!(%addr, ?err, "failed to join mdns group"),
+            }
+        }
+        ensure!(!self.interfaces.is_empty(), "no usable interface");
+        Ok(())
+    }
+
+    async fn handle_packet(&mut self, packet: &[u8], from: SocketAddr) {
+        let msg = match Message::from_vec(packet) {
+            Ok(msg) => msg,
+            Err(err) => {
+                trace!(%from, ?err, "ignoring invalid mdns packet");
+                return;
+            }
+        };
+        match msg.message_type() {
+            MessageType::Query => {
+                let me = self.secret_key.public();
+                if is_queried(&self.origin, &me, &msg) {
+                    trace!(%from, "answering mdns query");
+                    self.announce().await;
+                }
+            }
+            MessageType::Response => {
+                for signed in parse_response(&self.origin, &msg) {
+                    self.insert(signed);
+                }
+            }
+        }
+    }
+
+    /// Cache a record of another node, unless a newer record is already known.
+    fn insert(&self, signed: SignedAddrInfo) {
+        if signed.node_id() == self.secret_key.public() {
+            return;
+        }
+        if !self.cache.lock().insert(signed.clone()) {
+            return;
+        }
+        debug!(node = %signed.node_id().fmt_short(), info = ?signed.info(), "found node via mdns");
+        // No receivers means nobody is resolving right now.
+        self.found.send(signed).ok();
+    }
+
+    async fn announce(&self) {
+        let Some(own) = &self.own else {
+            return;
+        };
+        match announcement(&self.origin, own) {
+            Ok(msg) => self.send(msg).await,
+            Err(err) => warn!(?err, "failed to create mdns announcement"),
+        }
+    }
+
+    async fn query(&self, node_id: NodeId) {
+        match query(&self.origin, &node_id) {
+            Ok(msg) => self.send(msg).await,
+            Err(err) => warn!(?err, "failed to create mdns query"),
+        }
+    }
+
+    /// Send a message to the multicast group on all joined interfaces.
+    async fn send(&self, msg: Message) {
+        let packet = match msg.to_vec() {
+            Ok(packet) => packet,
+            Err(err) => {
+                warn!(?err, "failed to encode mdns message");
+                return;
+            }
+        };
+        let dest = SocketAddr::from((MDNS_ADDR_V4, self.port));
+        let socket = socket2::SockRef::from(&self.socket);
+        for addr in &self.interfaces {
+            if let Err(err) = socket.set_multicast_if_v4(addr) {
+                debug!(%addr, ?err, "failed to set multicast interface");
+                continue;
+            }
+            if let Err(err) = self.socket.send_to(&packet, dest).await {
+                debug!(%addr, ?err, "failed to send mdns packet");
+            }
+        }
+    }
+}
+
+/// Records of other nodes seen on the local network.
+///
+/// Records expire after [`CACHE_TTL`]. If more than [`MAX_CACHED_NODES`] nodes are seen, the
+/// expired records and then the least recently received record are evicted.
+#[derive(Debug, Default)]
+struct Cache {
+    records: HashMap<NodeId, (SignedAddrInfo, Instant)>,
+}
+
+impl Cache {
+    /// Get the record of a node, unless it expired.
+    fn get(&mut self, node_id: &NodeId) -> Option<SignedAddrInfo> {
+        let (signed, received) = self.records.get(node_id)?;
+        if received.elapsed() < CACHE_TTL {
+            return Some(signed.clone());
+        }
+        self.records.remove(node_id);
+        None
+    }
+
+    /// Insert a record, unless a newer record of the node is already known.
+    ///
+    /// Returns whether the record was inserted.
+    fn insert(&mut self, signed: SignedAddrInfo) -> bool {
+        let node_id = signed.node_id();
+        match self.records.get(&node_id) {
+            Some((existing, _)) if existing.timestamp() >= signed.timestamp() => return false,
+            Some(_) => {}
+            None if self.records.len() >= MAX_CACHED_NODES => {
+                self.records
+                    .retain(|_, (_, received)| received.elapsed() < CACHE_TTL);
+                if self.records.len() >= MAX_CACHED_NODES {
+                    let oldest = self
+                        .records
+                        .iter()
+                        .min_by_key(|(_, (_, received))| *received)
+                        .map(|(node_id, _)| *node_id);
+                    if let Some(oldest) = oldest {
+                        self.records.remove(&oldest);
+                    }
+                }
+            }
+            None => {}
+        }
+        self.records.insert(node_id, (signed, Instant::now()));
+        true
+    }
+}
+
+/// Bind a socket for sending to and receiving from the mDNS multicast group on `port`.
+///
+/// The port is shared with other mDNS responders on the host.
+fn bind(port: u16) -> Result<UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::IPV4,
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )
+    .context("socket create")?;
+    socket.set_reuse_address(true).context("reuse address")?;
+    #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+    socket.set_reuse_port(true).context("reuse port")?;
+    socket
+        .set_multicast_loop_v4(true)
+        .context("multicast loop")?;
+    socket.set_multicast_ttl_v4(255).context("multicast ttl")?;
+    let addr = SocketAddr::from((Ipv4Addr::UNSPECIFIED, port));
+    socket.bind(&addr.into()).context("binding")?;
+    socket.set_nonblocking(true).context("nonblocking: true")?;
+    let socket = UdpSocket::from_std(socket.into()).context("conversion to tokio")?;
+    Ok(socket)
+}
+
+/// Sign `info`, keeping only the direct addresses of local interfaces.
+fn sign(secret_key: &SecretKey, state: &interfaces::State, mut info: AddrInfo) -> SignedAddrInfo {
+    info.direct_addresses
+        .retain(|addr| state.has_ip(&addr.ip()));
+    SignedAddrInfo::sign(secret_key, info, now())
+}
+
+/// Create the response announcing our service instance and its TXT record.
+fn announcement(origin: &Name, own: &SignedAddrInfo) -> Result<Message> {
+    let instance = node_domain(&own.node_id(), origin)?;
+    let ptr = Record::from_rdata(origin.clone(), TTL, RData::PTR(PTR(instance)));
+    let mut msg = Message::new();
+    msg.set_id(0)
+        .set_message_type(MessageType::Response)
+        .set_op_code(OpCode::Query)
+        .set_authoritative(true)
+        .add_answer(ptr)
+        .add_answer(own.to_record(origin, TTL)?);
+    Ok(msg)
+}
+
+/// Create a query for the TXT record of `node_id`.
+fn query(origin: &Name, node_id: &NodeId) -> Result<Message> {
+    let instance = node_domain(node_id, origin)?;
+    let mut msg = Message::new();
+    msg.set_id(0)
+        .set_message_type(MessageType::Query)
+        .set_op_code(OpCode::Query)
+        .add_query(Query::query(instance, RecordType::TXT));
+    Ok(msg)
+}
+
+/// Whether `msg` queries for our service or our instance.
+fn is_queried(origin: &Name, me: &NodeId, msg: &Message) -> bool {
+    let Ok(instance) = node_domain(me, origin) else {
+        return false;
+    };
+    msg.queries().iter().any(|query| {
+        let ty = query.query_type();
+        (query.name() == origin && matches!(ty, RecordType::PTR | RecordType::ANY))
+            || (query.name() == &instance && matches!(ty, RecordType::TXT | RecordType::ANY))
+    })
+}
+
+/// Parse and verify all node records in a response.
+fn parse_response(origin: &Name, msg: &Message) -> Vec<SignedAddrInfo> {
+    msg.answers()
+        .iter()
+        .chain(msg.additionals())
+        .filter(|record| record.record_type() == RecordType::TXT)
+        .filter(|record| origin.zone_of(record.name()))
+        .filter_map(|record| match SignedAddrInfo::from_record(origin, record) {
+            Ok(signed) => Some(signed),
+            Err(err) => {
+                debug!(name = %record.name(), ?err, "skipping invalid mdns record");
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{derp::DerpMode, NodeAddr};
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    #[test]
+    fn test_announcement_roundtrip() -> Result<()> {
+        let origin = parse_origin(SERVICE_NAME)?;
+        let secret_key = SecretKey::generate();
+        let info = AddrInfo {
+            derp_url: None,
+            direct_addresses: BTreeSet::from(["192.168.1.2:1234".parse()?]),
+            transport_addrs: Default::default(),
+        };
+        let signed = SignedAddrInfo::sign(&secret_key, info, now());
+
+        let msg = Message::from_vec(&announcement(&origin, &signed)?.to_vec()?)?;
+        assert_eq!(parse_response(&origin, &msg), vec![signed]);
+
+        let msg = Message::from_vec(&query(&origin, &secret_key.public())?.to_vec()?)?;
+        assert!(is_queried(&origin, &secret_key.public(), &msg));
+        assert!(!is_queried(&origin, &SecretKey::generate().public(), &msg));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_cache_bounded() {
+        let info = AddrInfo::default();
+        let mut cache = Cache::default();
+        let first = SignedAddrInfo::sign(&SecretKey::generate(), info.clone(), now());
+        assert!(cache.insert(first.clone()));
+        assert!(!cache.insert(first.clone()));
+        for _ in 1..MAX_CACHED_NODES {
+            tokio::time::advance(Duration::from_millis(1)).await;
+            let signed = SignedAddrInfo::sign(&SecretKey::generate(), info.clone(), now());
+            assert!(cache.insert(signed));
+        }
+        assert_eq!(cache.get(&first.node_id()), Some(first.clone()));
+
+        // the least recently received record is evicted
+        let signed = SignedAddrInfo::sign(&SecretKey::generate(), info.clone(), now());
+        assert!(cache.insert(signed.clone()));
+        assert_eq!(cache.records.len(), MAX_CACHED_NODES);
+        assert_eq!(cache.get(&first.node_id()), None);
+
+        // records expire
+        tokio::time::advance(CACHE_TTL).await;
+        assert_eq!(cache.get(&signed.node_id()), None);
+    }
+
+    /// Connect to a node on the local network knowing nothing but its node id.
+    #[tokio::test]
+    async fn magic_endpoint_discovery_mdns() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        // Stay clear of real mDNS responders and of concurrently running tests.
+        let port = rand::thread_rng().gen_range(40_000..50_000);
+        let ep1 = new_endpoint(port).await?;
+        let ep2 = new_endpoint(port).await?;
+        // wait for our address to be updated and thus announced at least once
+        ep1.my_addr().await?;
+        let _conn = ep2.connect(NodeAddr::new(ep1.node_id()), TEST_ALPN).await?;
+        Ok(())
+    }
+
+    async fn new_endpoint(port: u16) -> Result<MagicEndpoint> {
+        let secret_key = SecretKey::generate();
+        let disco = MdnsDiscovery::with_port(secret_key.clone(), port).await?;
+        MagicEndpoint::builder()
+            .secret_key(secret_key)
+            .discovery(Box::new(disco))
+            .derp_mode(DerpMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .bind(0)
+            .await
+    }
+}
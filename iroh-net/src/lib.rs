@@ -24,6 +24,8 @@ pub mod net;
 pub mod netcheck;
 pub mod ping;
 pub mod portmapper;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod sim;
 pub mod stun;
 pub mod ticket;
 pub mod tls;
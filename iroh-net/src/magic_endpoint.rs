@@ -12,10 +12,10 @@ use tracing::{debug, trace};
 use crate::{
     config,
     defaults::default_derp_map,
-    derp::{DerpMap, DerpMode, DerpUrl},
+    derp::{http::Proxy, DerpMap, DerpMode, DerpUrl},
     discovery::{Discovery, DiscoveryTask},
     key::{PublicKey, SecretKey},
-    magicsock::{self, MagicSock},
+    magicsock::{self, MagicSock, Transport},
     tls, NodeId,
 };
 
@@ -39,6 +39,8 @@ pub struct MagicEndpointBuilder {
     discovery: Option<Box<dyn Discovery>>,
     /// Path for known peers. See [`MagicEndpointBuilder::peers_data_path`].
     peers_path: Option<PathBuf>,
+    proxy: Option<Proxy>,
+    transports: Vec<Arc<dyn Transport>>,
 }
 
 impl Default for MagicEndpointBuilder {
@@ -52,6 +54,8 @@ impl Default for MagicEndpointBuilder {
             keylog: Default::default(),
             discovery: Default::default(),
             peers_path: None,
+            proxy: None,
+            transports: Vec::new(),
         }
     }
 }
@@ -141,6 +145,32 @@ impl MagicEndpointBuilder {
         self
     }
 
+    /// Connect to the DERP servers through an HTTP or SOCKS5 proxy.
+    ///
+    /// Direct connections to other peers are unaffected by the proxy.
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Connect to the DERP servers through the proxy configured in the environment, if any.
+    ///
+    /// See [`Proxy::from_env`] for the environment variables that are read.
+    pub fn proxy_from_env(mut self) -> Self {
+        self.proxy = Proxy::from_env();
+        self
+    }
+
+    /// Add a transport to reach other nodes on, besides UDP and DERP.
+    ///
+    /// The addresses of this node on the transport are included in [`MagicEndpoint::my_addr`],
+    /// and paths to other nodes over the transport are used just like direct UDP paths.
+    /// Only one transport per [`TransportAddr`](magicsock::TransportAddr) scheme is used.
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transports.push(transport);
+        self
+    }
+
     /// Bind the magic endpoint on the specified socket address.
     ///
     /// The *bind_port* is the port that should be bound locally.
@@ -172,6 +202,8 @@ impl MagicEndpointBuilder {
             derp_map,
             nodes_path: self.peers_path,
             discovery: self.discovery,
+            proxy: self.proxy,
+            transports: self.transports,
         };
         MagicEndpoint::bind(Some(server_config), msock_opts, self.keylog).await
     }
@@ -321,14 +353,16 @@ impl MagicEndpoint {
             .ok_or(anyhow!("No endpoints found"))?;
         let derp = self.my_derp();
         let addrs = addrs.into_iter().map(|x| x.addr).collect();
-        Ok(NodeAddr::from_parts(self.node_id(), derp, addrs))
+        Ok(NodeAddr::from_parts(self.node_id(), derp, addrs)
+            .with_transport_addrs(self.msock.local_transport_addrs()))
     }
 
     /// Get the [`NodeAddr`] for this endpoint, while providing the endpoints.
     pub fn my_addr_with_endpoints(&self, eps: Vec<config::Endpoint>) -> Result<NodeAddr> {
         let derp = self.my_derp();
         let addrs = eps.into_iter().map(|x| x.addr).collect();
-        Ok(NodeAddr::from_parts(self.node_id(), derp, addrs))
+        Ok(NodeAddr::from_parts(self.node_id(), derp, addrs)
+            .with_transport_addrs(self.msock.local_transport_addrs()))
     }
 
     /// Get information on all the nodes we have connection information about.
@@ -376,8 +410,8 @@ impl MagicEndpoint {
     /// The `alpn`, or application-level protocol identifier, is also required. The remote endpoint
     /// must support this `alpn`, otherwise the connection attempt will fail with an error.
     ///
-    /// If the [`NodeAddr`] contains only [`NodeId`] and no direct addresses and no Derp servers,
-    /// a discovery service will be invoked, if configured, to try and discover the node's
+    /// If the [`NodeAddr`] contains only [`NodeId`] and no direct addresses, transport addresses
+    /// and no Derp servers, a discovery service will be invoked, if configured, to try and discover the node's
     /// addressing information. The discovery services must be configured globally per [`MagicEndpoint`]
     /// with [`MagicEndpointBuilder::discovery`]. The discovery service will also be invoked if
     /// none of the existing or provided direct addresses are reachable.
@@ -593,10 +627,11 @@ mod tests {
             direct_addresses: vec![SocketAddr::from(([1, 2, 3, 4], 1234))]
                 .into_iter()
                 .collect(),
+            transport_addrs: Default::default(),
         };
         assert_eq!(
             format!("{:?}", info),
-            r#"AddrInfo { derp_url: Some(DerpUrl("https://derp.example.com./")), direct_addresses: {1.2.3.4:1234} }"#
+            r#"AddrInfo { derp_url: Some(DerpUrl("https://derp.example.com./")), direct_addresses: {1.2.3.4:1234}, transport_addrs: {} }"#
         );
     }
 
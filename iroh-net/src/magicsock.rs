@@ -53,7 +53,7 @@ use watchable::Watchable;
 
 use crate::{
     config,
-    derp::{DerpMap, DerpUrl},
+    derp::{http::Proxy, DerpMap, DerpUrl},
     disco::{self, SendAddr},
     discovery::Discovery,
     dns::DNS_RESOLVER,
@@ -74,6 +74,7 @@ mod derp_actor;
 mod metrics;
 mod node_map;
 mod timer;
+mod transport;
 mod udp_conn;
 
 pub use crate::net::UdpSocket;
@@ -81,6 +82,9 @@ pub use crate::net::UdpSocket;
 pub use self::metrics::Metrics;
 pub use self::node_map::{ConnectionType, ControlMsg, DirectAddrInfo, EndpointInfo};
 pub use self::timer::Timer;
+#[cfg(unix)]
+pub use self::transport::UnixTransport;
+pub use self::transport::{Transport, TransportAddr};
 
 /// How long we consider a STUN-derived endpoint valid for. UDP NAT mappings typically
 /// expire at 30 seconds, so this is a few seconds shy of that.
@@ -112,6 +116,12 @@ pub struct Options {
 
     /// Optional node discovery mechanism.
     pub discovery: Option<Box<dyn Discovery>>,
+
+    /// Proxy to connect to the DERP servers through.
+    pub proxy: Option<Proxy>,
+
+    /// Additional transports to reach other nodes on, besides UDP and DERP.
+    pub transports: Vec<Arc<dyn Transport>>,
 }
 
 impl Default for Options {
@@ -122,6 +132,8 @@ impl Default for Options {
             derp_map: DerpMap::empty(),
             nodes_path: None,
             discovery: None,
+            proxy: None,
+            transports: Vec::new(),
         }
     }
 }
@@ -200,6 +212,12 @@ struct Inner {
     /// Optional discovery service
     discovery: Option<Box<dyn Discovery>>,
 
+    /// Proxy to connect to the DERP servers through
+    proxy: Option<Proxy>,
+
+    /// Additional transports
+    transports: Vec<Arc<dyn Transport>>,
+
     /// Our discovered endpoints
     endpoints: Watchable<DiscoveredEndpoints>,
 
@@ -248,6 +266,28 @@ impl Inner {
         Ok(addr)
     }
 
+    /// Get the addresses of this node on the additional transports.
+    fn local_transport_addrs(&self) -> Vec<TransportAddr> {
+        self.transports
+            .iter()
+            .flat_map(|transport| transport.local_addrs())
+            .collect()
+    }
+
+    /// Get the transport handling `addr`.
+    fn transport_for_addr(&self, addr: &TransportAddr) -> io::Result<&dyn Transport> {
+        self.transports
+            .iter()
+            .find(|transport| transport.scheme() == addr.scheme())
+            .map(|transport| transport.as_ref())
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::Other,
+                    format!("no transport for {}", addr.scheme()),
+                )
+            })
+    }
+
     #[instrument(skip_all, fields(me = %self.me))]
     fn poll_send(
         &self,
@@ -314,7 +354,15 @@ impl Inner {
             .node_map
             .get_send_addrs_for_quic_mapped_addr(&dest, self.ipv6_reported.load(Ordering::Relaxed))
         {
-            Some((public_key, udp_addr, derp_url, mut msgs)) => {
+            Some((public_key, udp_addr, derp_url, transport_addr, mut msgs)) => {
+                // Wait for the transport before sending anything, so that nothing is sent
+                // twice when polled again.
+                if let Some(ref addr) = transport_addr {
+                    if let Err(err) = ready!(self.poll_transport_send_ready(addr, cx)) {
+                        warn!(node = %public_key.fmt_short(), %addr, "transport not ready: {err:?}");
+                    }
+                }
+
                 let mut pings_sent = false;
                 // If we have pings to send, we *have* to send them out first.
                 if !msgs.is_empty() {
@@ -326,6 +374,7 @@ impl Inner {
 
                 let mut udp_sent = false;
                 let mut derp_sent = false;
+                let mut transport_sent = false;
                 let mut udp_error = None;
 
                 // send udp
@@ -362,8 +411,23 @@ impl Inner {
                     derp_sent = true;
                 }
 
-                if !derp_sent && !udp_sent && !pings_sent {
-                    warn!(node = %public_key.fmt_short(), "failed to send: no UDP or DERP addr");
+                // send over transport
+                if let Some(ref addr) = transport_addr {
+                    match self.send_transport(addr, split_packets(&transmits)) {
+                        Ok(_all_sent) => {
+                            trace!(node = %public_key.fmt_short(), dst = %addr, transmit_count = transmits.len(), "sent transmits over transport");
+                            transmits_sent = transmits.len();
+                            transport_sent = true;
+                        }
+                        Err(err) => {
+                            error!(node = %public_key.fmt_short(), %addr, "failed to send over transport: {err:?}");
+                            udp_error.get_or_insert(err);
+                        }
+                    }
+                }
+
+                if !derp_sent && !udp_sent && !transport_sent && !pings_sent {
+                    warn!(node = %public_key.fmt_short(), "failed to send: no UDP, DERP or transport addr");
                     let err = udp_error.unwrap_or_else(|| {
                         io::Error::new(
                             io::ErrorKind::NotConnected,
@@ -377,6 +441,7 @@ impl Inner {
                         transmit_count = %transmits_sent,
                         send_udp = ?udp_addr,
                         send_derp = ?derp_url,
+                        send_transport = ?transport_addr,
                         "sent transmits"
                     );
                     Poll::Ready(Ok(transmits_sent))
@@ -413,6 +478,36 @@ impl Inner {
         Poll::Ready(Ok(n))
     }
 
+    fn poll_transport_send_ready(
+        &self,
+        addr: &TransportAddr,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.transport_for_addr(addr)?.poll_send_ready(cx)
+    }
+
+    /// Sends datagrams over the transport handling `addr`.
+    ///
+    /// Datagrams the transport is not ready for are dropped like lost UDP datagrams, and
+    /// counted. Returns whether all datagrams were sent.
+    fn send_transport(&self, addr: &TransportAddr, datagrams: DerpContents) -> io::Result<bool> {
+        let transport = self.transport_for_addr(addr)?;
+        let mut all_sent = true;
+        for datagram in datagrams {
+            let len = datagram.len() as u64;
+            match transport.send(addr, datagram) {
+                Ok(()) => inc_by!(MagicsockMetrics, send_transport, len),
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    trace!(%addr, "transport not ready, dropping datagram");
+                    inc!(MagicsockMetrics, send_transport_dropped);
+                    all_sent = false;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(all_sent)
+    }
+
     fn conn_for_addr(&self, addr: SocketAddr) -> io::Result<&UdpConn> {
         let sock = match addr {
             SocketAddr::V4(_) => &self.pconn4,
@@ -440,17 +535,17 @@ impl Inner {
             )));
         }
 
-        // order of polling is: UDPv4, UDPv6, Derp
+        // order of polling is: UDPv4, UDPv6, transports, Derp
         let msgs = match self.pconn4.poll_recv(cx, bufs, metas)? {
             Poll::Pending | Poll::Ready(0) => match &self.pconn6 {
                 Some(conn) => match conn.poll_recv(cx, bufs, metas)? {
                     Poll::Pending | Poll::Ready(0) => {
-                        return self.poll_recv_derp(cx, bufs, metas);
+                        return self.poll_recv_transports(cx, bufs, metas);
                     }
                     Poll::Ready(n) => n,
                 },
                 None => {
-                    return self.poll_recv_derp(cx, bufs, metas);
+                    return self.poll_recv_transports(cx, bufs, metas);
                 }
             },
             Poll::Ready(n) => n,
@@ -534,6 +629,64 @@ impl Inner {
         Poll::Ready(Ok(msgs))
     }
 
+    /// Receives QUIC packets from the additional transports, falling back to DERP if there
+    /// are none.
+    ///
+    /// Disco packets are handled right away and do not take up a buffer.
+    fn poll_recv_transports(
+        &self,
+        cx: &mut Context,
+        bufs: &mut [io::IoSliceMut<'_>],
+        metas: &mut [quinn_udp::RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let dst_ip = self.normalized_local_addr().ok().map(|addr| addr.ip());
+        let mut num_msgs = 0;
+        for transport in &self.transports {
+            while num_msgs < bufs.len() {
+                let (src, datagram) = match transport.poll_recv(cx) {
+                    Poll::Pending => break,
+                    Poll::Ready(Err(err)) => {
+                        warn!(scheme = %transport.scheme(), "transport recv failed: {err:?}");
+                        break;
+                    }
+                    Poll::Ready(Ok(res)) => res,
+                };
+                if let Some((sender, sealed_box)) = disco::source_and_box(&datagram) {
+                    trace!(%src, len = datagram.len(), "transport recv: disco packet");
+                    self.handle_disco_message(
+                        sender,
+                        sealed_box,
+                        DiscoMessageSource::Transport(src),
+                    );
+                    continue;
+                }
+                let Some((node_id, quic_mapped_addr)) = self.node_map.receive_transport(&src)
+                else {
+                    warn!(%src, len = datagram.len(), "transport recv quic packet: no node state found, skipping");
+                    continue;
+                };
+                trace!(%src, node = %node_id.fmt_short(), len = datagram.len(), "transport recv quic packet");
+                inc_by!(MagicsockMetrics, recv_data_transport, datagram.len() as _);
+                bufs[num_msgs][..datagram.len()].copy_from_slice(&datagram);
+                metas[num_msgs] = quinn_udp::RecvMeta {
+                    addr: quic_mapped_addr.0,
+                    len: datagram.len(),
+                    stride: datagram.len(),
+                    ecn: None,
+                    dst_ip,
+                };
+                num_msgs += 1;
+            }
+        }
+
+        if num_msgs > 0 {
+            inc_by!(MagicsockMetrics, recv_datagrams, num_msgs as _);
+            Poll::Ready(Ok(num_msgs))
+        } else {
+            self.poll_recv_derp(cx, bufs, metas)
+        }
+    }
+
     #[instrument(skip_all, fields(name = %self.me))]
     fn poll_recv_derp(
         &self,
@@ -611,10 +764,10 @@ impl Inner {
             }
         };
 
-        if src.is_derp() {
-            inc!(MagicsockMetrics, recv_disco_derp);
-        } else {
-            inc!(MagicsockMetrics, recv_disco_udp);
+        match src {
+            DiscoMessageSource::Udp(_) => inc!(MagicsockMetrics, recv_disco_udp),
+            DiscoMessageSource::Derp { .. } => inc!(MagicsockMetrics, recv_disco_derp),
+            DiscoMessageSource::Transport(_) => inc!(MagicsockMetrics, recv_disco_transport),
         }
 
         let span = trace_span!("handle_disco", ?dm);
@@ -716,6 +869,7 @@ impl Inner {
                 .try_send((addr, dst_node, msg))
                 .is_ok(),
             SendAddr::Derp(ref url) => self.send_disco_message_derp(url, dst_node, msg),
+            SendAddr::Transport(ref addr) => self.send_disco_message_transport(addr, dst_node, msg),
         };
         if sent {
             let msg_sender = self.actor_sender.clone();
@@ -751,6 +905,7 @@ impl Inner {
     ///
     /// If `dst` is [`SendAddr::Derp`], the message will be pushed into the derp client channel.
     /// If `dst` is [`SendAddr::Udp`], the message will be pushed into the udp disco send channel.
+    /// If `dst` is [`SendAddr::Transport`], the message will be sent over the transport.
     ///
     /// Returns true if the channel had capacity for the message, and false if the message was
     /// dropped.
@@ -763,6 +918,7 @@ impl Inner {
         match dst {
             SendAddr::Udp(addr) => self.udp_disco_sender.try_send((addr, dst_key, msg)).is_ok(),
             SendAddr::Derp(ref url) => self.send_disco_message_derp(url, dst_key, msg),
+            SendAddr::Transport(ref addr) => self.send_disco_message_transport(addr, dst_key, msg),
         }
     }
 
@@ -781,6 +937,9 @@ impl Inner {
             SendAddr::Derp(ref url) => {
                 self.send_disco_message_derp(url, dst_key, msg);
             }
+            SendAddr::Transport(ref addr) => {
+                self.send_disco_message_transport(addr, dst_key, msg);
+            }
         }
         Poll::Ready(Ok(()))
     }
@@ -803,6 +962,31 @@ impl Inner {
         }
     }
 
+    fn send_disco_message_transport(
+        &self,
+        addr: &TransportAddr,
+        dst_key: PublicKey,
+        msg: disco::Message,
+    ) -> bool {
+        debug!(node = %dst_key.fmt_short(), %addr, %msg, "send disco message (transport)");
+        let pkt = self.encode_disco_message(dst_key, &msg);
+        match self.send_transport(addr, smallvec![pkt]) {
+            Ok(true) => {
+                inc!(MagicsockMetrics, sent_disco_transport);
+                disco_message_sent(&msg);
+                true
+            }
+            Ok(false) => {
+                debug!(node = %dst_key.fmt_short(), %addr, ?msg, "transport not ready, dropped disco message");
+                false
+            }
+            Err(err) => {
+                warn!(node = %dst_key.fmt_short(), %addr, ?msg, ?err, "failed to send disco message");
+                false
+            }
+        }
+    }
+
     async fn send_disco_message_udp(
         &self,
         dst: SocketAddr,
@@ -974,6 +1158,7 @@ impl Inner {
             let info = AddrInfo {
                 derp_url,
                 direct_addresses,
+                transport_addrs: self.local_transport_addrs().into_iter().collect(),
             };
             discovery.publish(&info);
         }
@@ -984,6 +1169,7 @@ impl Inner {
 enum DiscoMessageSource {
     Udp(SocketAddr),
     Derp { url: DerpUrl, key: PublicKey },
+    Transport(TransportAddr),
 }
 
 impl Display for DiscoMessageSource {
@@ -991,6 +1177,7 @@ impl Display for DiscoMessageSource {
         match self {
             Self::Udp(addr) => write!(f, "Udp({addr})"),
             Self::Derp { ref url, key } => write!(f, "Derp({url}, {})", key.fmt_short()),
+            Self::Transport(addr) => write!(f, "Transport({addr})"),
         }
     }
 }
@@ -1000,6 +1187,7 @@ impl From<DiscoMessageSource> for SendAddr {
         match value {
             DiscoMessageSource::Udp(addr) => SendAddr::Udp(addr),
             DiscoMessageSource::Derp { url, .. } => SendAddr::Derp(url),
+            DiscoMessageSource::Transport(addr) => SendAddr::Transport(addr),
         }
     }
 }
@@ -1009,16 +1197,11 @@ impl From<&DiscoMessageSource> for SendAddr {
         match value {
             DiscoMessageSource::Udp(addr) => SendAddr::Udp(*addr),
             DiscoMessageSource::Derp { url, .. } => SendAddr::Derp(url.clone()),
+            DiscoMessageSource::Transport(addr) => SendAddr::Transport(addr.clone()),
         }
     }
 }
 
-impl DiscoMessageSource {
-    fn is_derp(&self) -> bool {
-        matches!(self, DiscoMessageSource::Derp { .. })
-    }
-}
-
 /// Manages currently running endpoint updates, aka netcheck runs.
 ///
 /// Invariants:
@@ -1096,6 +1279,8 @@ impl MagicSock {
             derp_map,
             discovery,
             nodes_path,
+            proxy,
+            transports,
         } = opts;
 
         let nodes_path = match nodes_path {
@@ -1172,6 +1357,8 @@ impl MagicSock {
             send_buffer: Default::default(),
             udp_disco_sender,
             discovery,
+            proxy,
+            transports,
             endpoints: Watchable::new(Default::default()),
             pending_call_me_maybes: Default::default(),
             endpoints_update_state: EndpointUpdateState::new(),
@@ -1281,6 +1468,11 @@ impl MagicSock {
         Ok(self.inner.local_addr())
     }
 
+    /// Get the addresses of this node on the additional transports.
+    pub fn local_transport_addrs(&self) -> Vec<TransportAddr> {
+        self.inner.local_transport_addrs()
+    }
+
     /// Triggers an address discovery. The provided why string is for debug logging only.
     #[instrument(skip_all, fields(me = %self.inner.me))]
     pub fn re_stun(&self, why: &'static str) {
@@ -2547,6 +2739,7 @@ pub(crate) mod tests {
                     info: crate::AddrInfo {
                         derp_url: Some(derp_url.clone()),
                         direct_addresses: new_eps.iter().map(|ep| ep.addr).collect(),
+                        transport_addrs: Default::default(),
                     },
                 };
                 m.endpoint.magic_sock().add_node_addr(addr);
@@ -477,6 +477,11 @@ impl DerpActor {
         let ipv6_reported = self.conn.ipv6_reported.clone();
         let url = url.clone();
         let url1 = url.clone();
+        let auth_token = self
+            .conn
+            .derp_map
+            .get_node(&url)
+            .and_then(|node| node.auth_token.clone());
 
         // building a client does not dial
         let (dc, dc_receiver) = derp::http::ClientBuilder::new(url1.clone())
@@ -486,6 +491,8 @@ impl DerpActor {
             })
             .can_ack_pings(true)
             .is_preferred(my_derp.as_ref() == Some(&url1))
+            .auth_token(auth_token)
+            .proxy(self.conn.proxy.clone())
             .build(self.conn.secret_key.clone());
 
         let (s, r) = mpsc::channel(64);
@@ -25,6 +25,9 @@ pub struct Metrics {
     pub send_ipv6_error: Counter,
     pub send_derp: Counter,
     pub send_derp_error: Counter,
+    pub send_transport: Counter,
+    /// Number of datagrams dropped because a transport was not ready to send them.
+    pub send_transport_dropped: Counter,
 
     // Data packets (non-disco)
     pub send_data: Counter,
@@ -32,6 +35,7 @@ pub struct Metrics {
     pub recv_data_derp: Counter,
     pub recv_data_ipv4: Counter,
     pub recv_data_ipv6: Counter,
+    pub recv_data_transport: Counter,
     /// Number of QUIC datagrams received.
     pub recv_datagrams: Counter,
 
@@ -40,6 +44,7 @@ pub struct Metrics {
     pub send_disco_derp: Counter,
     pub sent_disco_udp: Counter,
     pub sent_disco_derp: Counter,
+    pub sent_disco_transport: Counter,
     pub sent_disco_ping: Counter,
     pub sent_disco_pong: Counter,
     pub sent_disco_call_me_maybe: Counter,
@@ -49,6 +54,7 @@ pub struct Metrics {
 
     pub recv_disco_udp: Counter,
     pub recv_disco_derp: Counter,
+    pub recv_disco_transport: Counter,
     pub recv_disco_ping: Counter,
     pub recv_disco_pong: Counter,
     pub recv_disco_call_me_maybe: Counter,
@@ -92,6 +98,8 @@ impl Default for Metrics {
             send_ipv6_error: Counter::new("send_ipv6_error"),
             send_derp: Counter::new("send_derp"),
             send_derp_error: Counter::new("send_derp_error"),
+            send_transport: Counter::new("send_transport"),
+            send_transport_dropped: Counter::new("send_transport_dropped"),
 
             // Data packets (non-disco)
             send_data: Counter::new("send_data"),
@@ -99,6 +107,7 @@ impl Default for Metrics {
             recv_data_derp: Counter::new("recv_data_derp"),
             recv_data_ipv4: Counter::new("recv_data_ipv4"),
             recv_data_ipv6: Counter::new("recv_data_ipv6"),
+            recv_data_transport: Counter::new("recv_data_transport"),
             recv_datagrams: Counter::new("recv_datagrams"),
 
             // Disco packets
@@ -106,6 +115,7 @@ impl Default for Metrics {
             send_disco_derp: Counter::new("disco_send_derp"),
             sent_disco_udp: Counter::new("disco_sent_udp"),
             sent_disco_derp: Counter::new("disco_sent_derp"),
+            sent_disco_transport: Counter::new("disco_sent_transport"),
             sent_disco_ping: Counter::new("disco_sent_ping"),
             sent_disco_pong: Counter::new("disco_sent_pong"),
             sent_disco_call_me_maybe: Counter::new("disco_sent_callmemaybe"),
@@ -115,6 +125,7 @@ impl Default for Metrics {
 
             recv_disco_udp: Counter::new("disco_recv_udp"),
             recv_disco_derp: Counter::new("disco_recv_derp"),
+            recv_disco_transport: Counter::new("disco_recv_transport"),
             recv_disco_ping: Counter::new("disco_recv_ping"),
             recv_disco_pong: Counter::new("disco_recv_pong"),
             recv_disco_call_me_maybe: Counter::new("disco_recv_callmemaybe"),
@@ -7,6 +7,7 @@ use std::{
 };
 
 use anyhow::{ensure, Context};
+use iroh_base::node_addr::NodeAddrV0;
 use iroh_metrics::inc;
 use parking_lot::Mutex;
 use stun_rs::TransactionId;
@@ -16,6 +17,7 @@ use tracing::{debug, info, instrument, trace, warn};
 use self::endpoint::{Endpoint, Options, PingHandled};
 use super::{
     metrics::Metrics as MagicsockMetrics, ActorMessage, DiscoMessageSource, QuicMappedAddr,
+    TransportAddr,
 };
 use crate::{
     derp::DerpUrl,
@@ -34,6 +36,12 @@ pub(super) use endpoint::{DiscoPingPurpose, PingAction, PingRole, SendPing};
 /// periodically via [`NodeMap::prune_inactive`].
 const MAX_INACTIVE_NODES: usize = 30;
 
+/// Header of files written by [`NodeMap::save_to_file`].
+///
+/// Files without it were written before transport addresses were added, and contain
+/// [`NodeAddrV0`]s.
+const NODES_FILE_HEADER: &[u8] = b"iroh-nodes/1\n";
+
 /// Map of the [`Endpoint`] information for all the known nodes.
 ///
 /// Each endpoint is also known as a "Node" in the "(iroh) network", but this is a bit of a
@@ -54,6 +62,8 @@ const MAX_INACTIVE_NODES: usize = 30;
 /// - A public socket address on which they are reachable on the internet, known as ip-port.
 ///   These come and go as the node moves around on the internet
 ///
+/// - A [`TransportAddr`] on which they are reachable over one of the additional transports.
+///
 /// An index of nodeInfos by node key, QuicMappedAddr, and discovered ip:port endpoints.
 #[derive(Default, Debug)]
 pub(super) struct NodeMap {
@@ -64,6 +74,7 @@ pub(super) struct NodeMap {
 pub(super) struct NodeMapInner {
     by_node_key: HashMap<PublicKey, usize>,
     by_ip_port: HashMap<IpPort, usize>,
+    by_transport_addr: HashMap<TransportAddr, usize>,
     by_quic_mapped_addr: HashMap<QuicMappedAddr, usize>,
     by_id: HashMap<usize, Endpoint>,
     next_id: usize,
@@ -75,6 +86,7 @@ enum EndpointId<'a> {
     NodeKey(&'a PublicKey),
     QuicMappedAddr(&'a QuicMappedAddr),
     IpPort(&'a IpPort),
+    TransportAddr(&'a TransportAddr),
 }
 
 impl NodeMap {
@@ -110,6 +122,10 @@ impl NodeMap {
         self.inner.lock().receive_udp(udp_addr)
     }
 
+    pub fn receive_transport(&self, addr: &TransportAddr) -> Option<(PublicKey, QuicMappedAddr)> {
+        self.inner.lock().receive_transport(addr)
+    }
+
     pub fn receive_derp(&self, derp_url: &DerpUrl, src: PublicKey) -> QuicMappedAddr {
         self.inner.lock().receive_derp(derp_url, &src)
     }
@@ -172,13 +188,14 @@ impl NodeMap {
         PublicKey,
         Option<SocketAddr>,
         Option<DerpUrl>,
+        Option<TransportAddr>,
         Vec<PingAction>,
     )> {
         let mut inner = self.inner.lock();
         let ep = inner.get_mut(EndpointId::QuicMappedAddr(addr))?;
         let public_key = *ep.public_key();
-        let (udp_addr, derp_url, msgs) = ep.get_send_addrs(have_ipv6);
-        Some((public_key, udp_addr, derp_url, msgs))
+        let (udp_addr, derp_url, transport_addr, msgs) = ep.get_send_addrs(have_ipv6);
+        Some((public_key, udp_addr, derp_url, transport_addr, msgs))
     }
 
     pub fn notify_shutdown(&self) {
@@ -247,6 +264,9 @@ impl NodeMap {
             .await
             .context("failed creating tmp file")?;
 
+        tmp.write_all(NODES_FILE_HEADER)
+            .await
+            .context("failed to persist node data")?;
         let mut count = 0;
         for node_addr in known_nodes {
             let ser = postcard::to_stdvec(&node_addr).context("failed to serialize node data")?;
@@ -287,12 +307,25 @@ impl NodeMapInner {
         ensure!(path.is_file(), "{} is not a file", path.display());
         let mut me = NodeMapInner::default();
         let contents = std::fs::read(path)?;
-        let mut slice: &[u8] = &contents;
-        while !slice.is_empty() {
-            let (node_addr, next_contents) =
-                postcard::take_from_bytes(slice).context("failed to load node data")?;
-            me.add_node_addr(node_addr);
-            slice = next_contents;
+        match contents.strip_prefix(NODES_FILE_HEADER) {
+            Some(mut slice) => {
+                while !slice.is_empty() {
+                    let (node_addr, next_contents) =
+                        postcard::take_from_bytes(slice).context("failed to load node data")?;
+                    me.add_node_addr(node_addr);
+                    slice = next_contents;
+                }
+            }
+            // files written before transport addresses were added have no header
+            None => {
+                let mut slice: &[u8] = &contents;
+                while !slice.is_empty() {
+                    let (node_addr, next_contents) = postcard::take_from_bytes::<NodeAddrV0>(slice)
+                        .context("failed to load node data")?;
+                    me.add_node_addr(node_addr.into());
+                    slice = next_contents;
+                }
+            }
         }
         Ok(me)
     }
@@ -313,6 +346,9 @@ impl NodeMapInner {
         for endpoint in &info.direct_addresses {
             self.set_endpoint_for_ip_port(*endpoint, id);
         }
+        for addr in &info.transport_addrs {
+            self.by_transport_addr.insert(addr.clone(), id);
+        }
     }
 
     fn get_id(&self, id: EndpointId) -> Option<usize> {
@@ -321,6 +357,7 @@ impl NodeMapInner {
             EndpointId::NodeKey(node_key) => self.by_node_key.get(node_key).copied(),
             EndpointId::QuicMappedAddr(addr) => self.by_quic_mapped_addr.get(addr).copied(),
             EndpointId::IpPort(ipp) => self.by_ip_port.get(ipp).copied(),
+            EndpointId::TransportAddr(addr) => self.by_transport_addr.get(addr).copied(),
         }
     }
 
@@ -356,6 +393,17 @@ impl NodeMapInner {
         Some((*endpoint.public_key(), *endpoint.quic_mapped_addr()))
     }
 
+    /// Marks the node we believe to be at `addr` as recently used, returning the [`Endpoint`] if
+    /// found.
+    fn receive_transport(&mut self, addr: &TransportAddr) -> Option<(PublicKey, QuicMappedAddr)> {
+        let Some(endpoint) = self.get_mut(EndpointId::TransportAddr(addr)) else {
+            info!(src=%addr, "receive_transport: no node_map state found for addr, ignore");
+            return None;
+        };
+        endpoint.receive_transport(addr, Instant::now());
+        Some((*endpoint.public_key(), *endpoint.quic_mapped_addr()))
+    }
+
     #[instrument(skip_all, fields(src = %src.fmt_short()))]
     fn receive_derp(&mut self, derp_url: &DerpUrl, src: &PublicKey) -> QuicMappedAddr {
         let endpoint = self.get_or_insert_with(EndpointId::NodeKey(src), || {
@@ -439,10 +487,15 @@ impl NodeMapInner {
             }
         });
 
+        let id = endpoint.id();
         let handled = endpoint.handle_ping(src.clone(), tx_id);
-        if let SendAddr::Udp(ref addr) = src {
-            if matches!(handled.role, PingRole::NewEndpoint) {
-                self.set_node_key_for_ip_port(*addr, &sender);
+        if matches!(handled.role, PingRole::NewEndpoint) {
+            match src {
+                SendAddr::Udp(addr) => self.set_node_key_for_ip_port(addr, &sender),
+                SendAddr::Transport(addr) => {
+                    self.by_transport_addr.insert(addr, id);
+                }
+                SendAddr::Derp(_) => {}
             }
         }
         handled
@@ -531,6 +584,10 @@ impl NodeMapInner {
                 self.by_ip_port.remove(&ip_port);
             }
 
+            for addr in ep.transport_addrs() {
+                self.by_transport_addr.remove(addr);
+            }
+
             self.by_quic_mapped_addr.remove(ep.quic_mapped_addr());
         }
     }
@@ -606,7 +663,9 @@ mod tests {
             .with_derp_url(derp_x)
             .with_direct_addresses(direct_addresses_a);
         let node_addr_b = NodeAddr::new(node_b).with_derp_url(derp_y);
-        let node_addr_c = NodeAddr::new(node_c).with_direct_addresses(direct_addresses_c);
+        let node_addr_c = NodeAddr::new(node_c)
+            .with_direct_addresses(direct_addresses_c)
+            .with_transport_addrs(["unix:///run/c.sock".parse().unwrap()]);
         let node_addr_d = NodeAddr::new(node_d);
 
         node_map.add_node_addr(node_addr_a);
@@ -634,6 +693,22 @@ mod tests {
         assert_eq!(og, loaded);
     }
 
+    #[test]
+    fn load_node_data_without_header() {
+        let node_addr = NodeAddr::new(SecretKey::generate().public()).with_direct_addresses([(
+            Ipv4Addr::LOCALHOST,
+            4000,
+        )
+            .into()]);
+        let legacy = NodeAddrV0::from(node_addr.clone());
+        let root = testdir::testdir!();
+        let path = root.join("nodes.postcard");
+        std::fs::write(&path, postcard::to_stdvec(&legacy).unwrap()).unwrap();
+
+        let loaded = NodeMap::load_from_file(&path).unwrap();
+        assert_eq!(loaded.known_node_addresses(), vec![node_addr]);
+    }
+
     #[test]
     fn test_prune_direct_addresses() {
         let _guard = iroh_test::logging::setup();
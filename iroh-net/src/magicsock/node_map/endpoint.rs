@@ -16,7 +16,7 @@ use crate::{
     disco::{self, SendAddr},
     key::PublicKey,
     magic_endpoint::AddrInfo,
-    magicsock::{Timer, HEARTBEAT_INTERVAL},
+    magicsock::{Timer, TransportAddr, HEARTBEAT_INTERVAL},
     net::ip::is_unicast_link_local,
     stun,
     util::derp_only_mode,
@@ -56,6 +56,9 @@ const UPGRADE_INTERVAL: Duration = Duration::from_secs(60);
 /// How long until we send a stayin alive ping
 const STAYIN_ALIVE_MIN_ELAPSED: Duration = Duration::from_secs(2);
 
+/// How long we trust a transport address as the exclusive path without having heard a Pong reply.
+const TRUST_TRANSPORT_ADDR_DURATION: Duration = Duration::from_millis(6500);
+
 #[derive(Debug)]
 pub(in crate::magicsock) enum PingAction {
     SendCallMeMaybe { derp_url: DerpUrl, dst_node: NodeId },
@@ -123,6 +126,8 @@ pub(super) struct Endpoint {
     best_addr: BestAddr,
     /// State for each of this node's direct paths.
     direct_addr_state: BTreeMap<IpPort, PathState>,
+    /// State for each of this node's paths over additional transports.
+    transport_addr_state: BTreeMap<TransportAddr, PathState>,
     sent_pings: HashMap<stun::TransactionId, SentPing>,
     /// Last time this node was used.
     ///
@@ -166,6 +171,7 @@ impl Endpoint {
             best_addr: Default::default(),
             sent_pings: HashMap::new(),
             direct_addr_state: BTreeMap::new(),
+            transport_addr_state: BTreeMap::new(),
             last_used: options.active.then(Instant::now),
             last_call_me_maybe: None,
         }
@@ -188,21 +194,25 @@ impl Endpoint {
         use best_addr::State::*;
         // Report our active connection. This replicates the logic of [`Endpoint::addr_for_send`]
         // without choosing a random candidate address if no best_addr is set.
-        let (conn_type, latency) = match (self.best_addr.state(now), self.derp_url.as_ref()) {
-            (Valid(addr), _) | (Outdated(addr), None) => {
-                (ConnectionType::Direct(addr.addr), Some(addr.latency))
-            }
-            (Outdated(addr), Some((url, relay_state))) => {
-                let latency = relay_state
-                    .latency()
-                    .map(|l| l.min(addr.latency))
-                    .unwrap_or(addr.latency);
-                (ConnectionType::Mixed(addr.addr, url.clone()), Some(latency))
-            }
-            (Empty, Some((url, relay_state))) => {
-                (ConnectionType::Relay(url.clone()), relay_state.latency())
+        let (conn_type, latency) = if let Some((addr, latency)) = self.best_transport_addr(now) {
+            (ConnectionType::Transport(addr.clone()), Some(latency))
+        } else {
+            match (self.best_addr.state(now), self.derp_url.as_ref()) {
+                (Valid(addr), _) | (Outdated(addr), None) => {
+                    (ConnectionType::Direct(addr.addr), Some(addr.latency))
+                }
+                (Outdated(addr), Some((url, relay_state))) => {
+                    let latency = relay_state
+                        .latency()
+                        .map(|l| l.min(addr.latency))
+                        .unwrap_or(addr.latency);
+                    (ConnectionType::Mixed(addr.addr, url.clone()), Some(latency))
+                }
+                (Empty, Some((url, relay_state))) => {
+                    (ConnectionType::Relay(url.clone()), relay_state.latency())
+                }
+                (Empty, None) => (ConnectionType::None, None),
             }
-            (Empty, None) => (ConnectionType::None, None),
         };
         let addrs = self
             .direct_addr_state
@@ -236,25 +246,30 @@ impl Endpoint {
 
     /// Returns the address(es) that should be used for sending the next packet.
     ///
-    /// Any or all of the UDP and DERP addrs may be non-zero.
+    /// Any or all of the UDP, DERP and transport addrs may be non-zero.
     fn addr_for_send(
         &mut self,
         now: &Instant,
         have_ipv6: bool,
-    ) -> (Option<SocketAddr>, Option<DerpUrl>) {
+    ) -> (Option<SocketAddr>, Option<DerpUrl>, Option<TransportAddr>) {
         if derp_only_mode() {
             debug!("in `DEV_DERP_ONLY` mode, giving the DERP address as the only viable address for this endpoint");
-            return (None, self.derp_url());
+            return (None, self.derp_url(), None);
         }
         // Update our best addr from candidate addresses (only if it is empty and if we have
         // recent pongs).
         self.assign_best_addr_from_candidates_if_empty();
+        if let Some((addr, latency)) = self.best_transport_addr(*now) {
+            // A confirmed transport path is used exclusively, unless UDP is faster.
+            trace!(%addr, ?latency, "transport addr is valid, use transport addr only");
+            return (None, None, Some(addr.clone()));
+        }
         match self.best_addr.state(*now) {
             best_addr::State::Valid(best_addr) => {
                 // If we have a valid address we use it.
                 trace!(addr = %best_addr.addr, latency = ?best_addr.latency,
                        "best_addr is set and valid, use best_addr only");
-                (Some(best_addr.addr), None)
+                (Some(best_addr.addr), None, None)
             }
             best_addr::State::Outdated(best_addr) => {
                 // If the address is outdated we use it, but send via derp at the same time.
@@ -262,7 +277,7 @@ impl Endpoint {
                 // works (i.e. we don't need to holepunch again).
                 trace!(addr = %best_addr.addr, latency = ?best_addr.latency,
                        "best_addr is set but outdated, use best_addr and derp");
-                (Some(best_addr.addr), self.derp_url())
+                (Some(best_addr.addr), self.derp_url(), None)
             }
             best_addr::State::Empty => {
                 // No direct connection has been used before.  If we know of any possible
@@ -277,12 +292,42 @@ impl Endpoint {
                     })
                     .choose_stable(&mut rand::thread_rng())
                     .map(|ipp| SocketAddr::from(*ipp));
-                trace!(udp_addr = ?addr, "best_addr is unset, use candidate addr and derp");
-                (addr, self.derp_url())
+                let transport_addr = self
+                    .transport_addr_state
+                    .keys()
+                    .choose_stable(&mut rand::thread_rng())
+                    .cloned();
+                trace!(udp_addr = ?addr, ?transport_addr,
+                       "best_addr is unset, use candidate addrs and derp");
+                (addr, self.derp_url(), transport_addr)
             }
         }
     }
 
+    /// Returns the transport path with the lowest latency which recently received a pong.
+    fn valid_transport_addr(&self, now: Instant) -> Option<(&TransportAddr, Duration)> {
+        self.transport_addr_state
+            .iter()
+            .filter_map(|(addr, state)| {
+                let pong = state.recent_pong()?;
+                let confirmed_ago = now.saturating_duration_since(pong.pong_at);
+                (confirmed_ago <= TRUST_TRANSPORT_ADDR_DURATION).then_some((addr, pong.latency))
+            })
+            .min_by_key(|(_addr, latency)| *latency)
+    }
+
+    /// Returns the transport path to send on instead of UDP and DERP, if any.
+    ///
+    /// This is the [`Endpoint::valid_transport_addr`], unless the best UDP address is valid
+    /// and has a lower latency.
+    fn best_transport_addr(&self, now: Instant) -> Option<(&TransportAddr, Duration)> {
+        let (addr, latency) = self.valid_transport_addr(now)?;
+        match self.best_addr.state(now) {
+            best_addr::State::Valid(best_addr) if best_addr.latency < latency => None,
+            _ => Some((addr, latency)),
+        }
+    }
+
     /// Fixup best_addr from candidates.
     ///
     /// If somehow we end up in a state where we failed to set a best_addr, while we do have
@@ -345,6 +390,10 @@ impl Endpoint {
             debug!("no previous full ping: need full ping");
             return true;
         };
+        if self.best_transport_addr(*now).is_some() {
+            trace!("transport addr is valid: not needed");
+            return false;
+        }
         match self.best_addr.state(*now) {
             best_addr::State::Empty => {
                 debug!("best addr not set: need full ping");
@@ -396,6 +445,11 @@ impl Endpoint {
                         }
                     }
                 }
+                SendAddr::Transport(ref addr) => {
+                    if let Some(st) = self.transport_addr_state.get_mut(addr) {
+                        st.last_ping = None;
+                    }
+                }
             }
         }
     }
@@ -446,6 +500,12 @@ impl Endpoint {
                     }
                 }
             }
+            SendAddr::Transport(ref addr) => {
+                if let Some(st) = self.transport_addr_state.get_mut(addr) {
+                    st.last_ping.replace(now);
+                    path_found = true
+                }
+            }
         }
         if !path_found {
             // Shouldn't happen. But don't ping an endpoint that's not active for us.
@@ -527,7 +587,8 @@ impl Endpoint {
     #[must_use = "actions must be handled"]
     fn send_pings(&mut self, now: Instant) -> Vec<PingAction> {
         // We allocate +1 in case the caller wants to add a call-me-maybe message.
-        let mut ping_msgs = Vec::with_capacity(self.direct_addr_state.len() + 1);
+        let mut ping_msgs =
+            Vec::with_capacity(self.direct_addr_state.len() + self.transport_addr_state.len() + 1);
 
         if let Some((url, state)) = self.derp_url.as_ref() {
             if state.needs_ping(&now) {
@@ -558,6 +619,20 @@ impl Endpoint {
                 write!(&mut ping_dsts, " {} ", msg.dst).ok();
                 ping_msgs.push(PingAction::SendPing(msg));
             });
+        self.transport_addr_state
+            .iter()
+            .filter(|(_addr, state)| state.needs_ping(&now))
+            .filter_map(|(addr, _state)| {
+                self.start_ping(
+                    SendAddr::Transport(addr.clone()),
+                    DiscoPingPurpose::Discovery,
+                )
+            })
+            .for_each(|msg| {
+                use std::fmt::Write;
+                write!(&mut ping_dsts, " {} ", msg.dst).ok();
+                ping_msgs.push(PingAction::SendPing(msg));
+            });
         ping_dsts.push(']');
         debug!(
             %ping_dsts,
@@ -599,6 +674,10 @@ impl Endpoint {
         }
         let paths = summarize_endpoint_paths(&self.direct_addr_state);
         debug!(new = ?n.direct_addresses , %paths, "added new direct paths for endpoint");
+
+        for addr in n.transport_addrs.iter() {
+            self.transport_addr_state.entry(addr.clone()).or_default();
+        }
     }
 
     /// Clears all the endpoint's p2p state, reverting it to a DERP-only endpoint.
@@ -608,7 +687,11 @@ impl Endpoint {
         self.best_addr
             .clear(ClearReason::Reset, self.derp_url.is_some());
 
-        for es in self.direct_addr_state.values_mut() {
+        for es in self
+            .direct_addr_state
+            .values_mut()
+            .chain(self.transport_addr_state.values_mut())
+        {
             es.last_ping = None;
         }
     }
@@ -655,18 +738,30 @@ impl Endpoint {
                     }
                 }
             }
+            SendAddr::Transport(ref addr) => match self.transport_addr_state.entry(addr.clone()) {
+                Entry::Occupied(mut occupied) => occupied.get_mut().handle_ping(tx_id, now),
+                Entry::Vacant(vacant) => {
+                    info!(%addr, "new transport addr for node");
+                    vacant.insert(PathState::with_ping(tx_id, now));
+                    PingRole::NewEndpoint
+                }
+            },
         };
 
         if matches!(path, SendAddr::Udp(_)) && matches!(role, PingRole::NewEndpoint) {
             self.prune_direct_addresses();
         }
 
-        // if the endpoint does not yet have a best_addrr
-        let needs_ping_back = if matches!(path, SendAddr::Udp(_))
-            && matches!(
+        // if the endpoint does not yet have a best_addrr, or a valid transport path
+        let needs_ping_back = match path {
+            SendAddr::Udp(_) => matches!(
                 self.best_addr.state(now),
                 best_addr::State::Empty | best_addr::State::Outdated(_)
-            ) {
+            ),
+            SendAddr::Transport(_) => self.valid_transport_addr(now).is_none(),
+            SendAddr::Derp(_) => false,
+        };
+        let needs_ping_back = if needs_ping_back {
             // We also need to send a ping to make this path available to us as well.  This
             // is always sent togehter with a pong.  So in the worst case the pong gets lost
             // and this ping does not.  In that case we ping-pong until both sides have
@@ -832,6 +927,23 @@ impl Endpoint {
                             warn!(stored=?other, received=?url, "disco: ignoring pong via derp for different derper to last one stored");
                         }
                     },
+                    SendAddr::Transport(ref addr) => {
+                        match self.transport_addr_state.get_mut(addr) {
+                            None => {
+                                info!("ignoring pong: no state for src addr");
+                                // This is no longer a transport path we care about.
+                                return node_map_insert;
+                            }
+                            Some(st) => {
+                                st.add_pong_reply(PongReply {
+                                    latency,
+                                    pong_at: now,
+                                    from: src,
+                                    pong_src: m.src.clone(),
+                                });
+                            }
+                        }
+                    }
                 }
 
                 // Promote this pong response to our current best address if it's lower latency.
@@ -924,6 +1036,19 @@ impl Endpoint {
         self.last_used = Some(now);
     }
 
+    /// Marks this endpoint as having received a payload message over a transport.
+    pub(super) fn receive_transport(&mut self, addr: &TransportAddr, now: Instant) {
+        let Some(state) = self.transport_addr_state.get_mut(addr) else {
+            debug_assert!(
+                false,
+                "node map inconsistency by_transport_addr <-> transport addr"
+            );
+            return;
+        };
+        state.last_payload_msg = Some(now);
+        self.last_used = Some(now);
+    }
+
     pub(super) fn receive_derp(&mut self, url: &DerpUrl, _src: &PublicKey, now: Instant) {
         match self.derp_url.as_mut() {
             Some((current_home, state)) if current_home == url => {
@@ -951,6 +1076,10 @@ impl Endpoint {
                 .as_ref()
                 .filter(|(home_url, _state)| home_url == url)
                 .and_then(|(_home_url, state)| state.last_ping),
+            SendAddr::Transport(addr) => self
+                .transport_addr_state
+                .get(addr)
+                .and_then(|state| state.last_ping),
         }
     }
 
@@ -979,6 +1108,24 @@ impl Endpoint {
             return self.send_call_me_maybe(now, SendCallMeMaybe::Always);
         }
 
+        // Send heartbeat ping to keep the current transport path going as long as we need it.
+        if let Some((addr, _latency)) = self.best_transport_addr(now) {
+            let addr = SendAddr::Transport(addr.clone());
+            let elapsed = self.last_ping(&addr).map(|l| now - l);
+            let needs_ping = match elapsed {
+                Some(e) => e >= STAYIN_ALIVE_MIN_ELAPSED,
+                None => false,
+            };
+
+            if needs_ping {
+                debug!(dst = %addr, since_last_ping=?elapsed, "send stayin alive ping");
+                if let Some(msg) = self.start_ping(addr, DiscoPingPurpose::StayinAlive) {
+                    return vec![PingAction::SendPing(msg)];
+                }
+            }
+            return Vec::new();
+        }
+
         // Send heartbeat ping to keep the current addr going as long as we need it.
         if let Some(udp_addr) = self.best_addr.addr() {
             let elapsed = self.last_ping(&SendAddr::Udp(udp_addr)).map(|l| now - l);
@@ -1012,10 +1159,15 @@ impl Endpoint {
     pub(crate) fn get_send_addrs(
         &mut self,
         have_ipv6: bool,
-    ) -> (Option<SocketAddr>, Option<DerpUrl>, Vec<PingAction>) {
+    ) -> (
+        Option<SocketAddr>,
+        Option<DerpUrl>,
+        Option<TransportAddr>,
+        Vec<PingAction>,
+    ) {
         let now = Instant::now();
         self.last_used.replace(now);
-        let (udp_addr, derp_url) = self.addr_for_send(&now, have_ipv6);
+        let (udp_addr, derp_url, transport_addr) = self.addr_for_send(&now, have_ipv6);
         let mut ping_msgs = Vec::new();
 
         if self.want_call_me_maybe(&now) {
@@ -1025,11 +1177,12 @@ impl Endpoint {
         trace!(
             ?udp_addr,
             ?derp_url,
+            ?transport_addr,
             pings = %ping_msgs.len(),
             "found send address",
         );
 
-        (udp_addr, derp_url, ping_msgs)
+        (udp_addr, derp_url, transport_addr, ping_msgs)
     }
 
     /// Get the direct addresses for this endpoint.
@@ -1037,6 +1190,11 @@ impl Endpoint {
         self.direct_addr_state.keys().copied()
     }
 
+    /// Get the transport addresses for this endpoint.
+    pub(super) fn transport_addrs(&self) -> impl Iterator<Item = &TransportAddr> + '_ {
+        self.transport_addr_state.keys()
+    }
+
     /// Get the addressing information of this endpoint.
     pub(super) fn node_addr(&self) -> NodeAddr {
         let direct_addresses = self.direct_addresses().map(SocketAddr::from).collect();
@@ -1045,6 +1203,7 @@ impl Endpoint {
             info: AddrInfo {
                 derp_url: self.derp_url(),
                 direct_addresses,
+                transport_addrs: self.transport_addrs().cloned().collect(),
             },
         }
     }
@@ -1370,6 +1529,9 @@ pub enum ConnectionType {
     /// Relay connection over DERP
     #[display("relay")]
     Relay(DerpUrl),
+    /// Connection over one of the additional transports.
+    #[display("transport")]
+    Transport(TransportAddr),
     /// Both a UDP and a DERP connection are used.
     ///
     /// This is the case if we do have a UDP address, but are missing a recent confirmation that
@@ -1433,6 +1595,7 @@ mod tests {
                         now + Duration::from_secs(100),
                     ),
                     direct_addr_state: endpoint_state,
+                    transport_addr_state: BTreeMap::new(),
                     sent_pings: HashMap::new(),
                     last_used: Some(now),
                     last_call_me_maybe: None,
@@ -1458,6 +1621,7 @@ mod tests {
                 derp_url: Some((send_addr.clone(), relay_state)),
                 best_addr: BestAddr::default(),
                 direct_addr_state: BTreeMap::default(),
+                transport_addr_state: BTreeMap::new(),
                 sent_pings: HashMap::new(),
                 last_used: Some(now),
                 last_call_me_maybe: None,
@@ -1477,6 +1641,7 @@ mod tests {
                 derp_url: new_relay_and_state(Some(send_addr.clone())),
                 best_addr: BestAddr::default(),
                 direct_addr_state: endpoint_state,
+                transport_addr_state: BTreeMap::new(),
                 sent_pings: HashMap::new(),
                 last_used: Some(now),
                 last_call_me_maybe: None,
@@ -1517,6 +1682,7 @@ mod tests {
                         expired,
                     ),
                     direct_addr_state: endpoint_state,
+                    transport_addr_state: BTreeMap::new(),
                     sent_pings: HashMap::new(),
                     last_used: Some(now),
                     last_call_me_maybe: None,
@@ -1590,6 +1756,7 @@ mod tests {
                 (c_endpoint.quic_mapped_addr, c_endpoint.id),
                 (d_endpoint.quic_mapped_addr, d_endpoint.id),
             ]),
+            by_transport_addr: HashMap::new(),
             by_id: HashMap::from([
                 (a_endpoint.id, a_endpoint),
                 (b_endpoint.id, b_endpoint),
@@ -1628,4 +1795,68 @@ mod tests {
         // number of pings as direct addresses in the call-me-maybe.
         assert_eq!(ping_messages.len(), my_numbers_count as usize);
     }
+
+    #[test]
+    fn test_transport_addr_selection() {
+        let key = SecretKey::generate();
+        let opts = Options {
+            public_key: key.public(),
+            derp_url: None,
+            active: true,
+        };
+        let mut ep = Endpoint::new(0, opts);
+        let udp_addr: SocketAddr = (Ipv4Addr::LOCALHOST, 1000).into();
+        let transport_addr: TransportAddr = "unix:///run/iroh.sock".parse().unwrap();
+        ep.update_from_node_addr(&AddrInfo {
+            derp_url: None,
+            direct_addresses: BTreeSet::from([udp_addr]),
+            transport_addrs: BTreeSet::from([transport_addr.clone()]),
+        });
+
+        // Without pongs both paths are candidates.
+        let now = Instant::now();
+        let (udp, derp, transport) = ep.addr_for_send(&now, false);
+        assert_eq!(udp, Some(udp_addr));
+        assert_eq!(derp, None);
+        assert_eq!(transport, Some(transport_addr.clone()));
+
+        // A confirmed transport path is used exclusively.
+        let pong = |from: SendAddr, latency| PongReply {
+            latency,
+            pong_at: now,
+            from: from.clone(),
+            pong_src: from,
+        };
+        ep.transport_addr_state
+            .get_mut(&transport_addr)
+            .unwrap()
+            .add_pong_reply(pong(
+                SendAddr::Transport(transport_addr.clone()),
+                Duration::from_millis(10),
+            ));
+        assert_eq!(
+            ep.addr_for_send(&now, false),
+            (None, None, Some(transport_addr.clone()))
+        );
+        assert_eq!(
+            ep.info(now).conn_type,
+            ConnectionType::Transport(transport_addr.clone())
+        );
+
+        // Unless a valid UDP address is faster.
+        ep.best_addr.insert_if_better_or_reconfirm(
+            udp_addr,
+            Duration::from_millis(5),
+            best_addr::Source::ReceivedPong,
+            now,
+            false,
+        );
+        assert_eq!(ep.addr_for_send(&now, false), (Some(udp_addr), None, None));
+
+        // Once no pong was received for a while, the transport path is no longer trusted.
+        ep.best_addr.clear(ClearReason::Reset, false);
+        let later = now + TRUST_TRANSPORT_ADDR_DURATION + Duration::from_secs(1);
+        assert!(ep.best_transport_addr(later).is_none());
+        assert!(ep.valid_transport_addr(now).is_some());
+    }
 }
@@ -0,0 +1,208 @@
+//! Additional transports for the [`MagicSock`](super::MagicSock), besides UDP and DERP.
+//!
+//! A [`Transport`] carries QUIC and disco datagrams to other nodes over some other medium,
+//! e.g. a Unix domain socket between nodes on the same host.  Nodes are addressed on a
+//! transport by a [`TransportAddr`], whose URL scheme names the transport.  Transport
+//! addresses are part of a node's [`AddrInfo`](crate::AddrInfo), and the paths they offer
+//! are pinged and selected just like direct UDP paths.
+
+use std::{
+    fmt::Debug,
+    io,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+
+pub use iroh_base::node_addr::TransportAddr;
+
+/// A transport carrying datagrams between nodes.
+///
+/// Like UDP, a transport is unreliable: datagrams may be dropped, e.g. when the transport's
+/// buffers are full.  Reliability is provided by QUIC on top.
+pub trait Transport: Debug + Send + Sync + 'static {
+    /// The URL scheme of the [`TransportAddr`]s handled by this transport.
+    fn scheme(&self) -> &str;
+
+    /// The addresses of this node on this transport.
+    ///
+    /// These are advertised to other nodes as part of this node's addressing information.
+    fn local_addrs(&self) -> Vec<TransportAddr>;
+
+    /// Polls until the transport is ready to send datagrams.
+    ///
+    /// The [`MagicSock`](super::MagicSock) waits for this before calling
+    /// [`Transport::send`], like it waits for its UDP sockets to become writable.  The default
+    /// implementation is always ready.
+    fn poll_send_ready(&self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Sends a datagram to `dst`.
+    ///
+    /// This must not block.  If the datagram can not be sent right away, it is dropped and an
+    /// error of kind [`io::ErrorKind::WouldBlock`] is returned.
+    fn send(&self, dst: &TransportAddr, datagram: Bytes) -> io::Result<()>;
+
+    /// Polls for the next received datagram, together with the address of its sender.
+    ///
+    /// The sender address must be the address the sender advertises in its
+    /// [`Transport::local_addrs`], so that replies reach it.
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TransportAddr, Bytes)>>;
+}
+
+#[cfg(unix)]
+pub use self::unix::UnixTransport;
+
+#[cfg(unix)]
+mod unix {
+    use std::{
+        io,
+        path::{Path, PathBuf},
+        task::{ready, Context, Poll},
+    };
+
+    use bytes::Bytes;
+    use percent_encoding::percent_decode_str;
+    use tokio::{io::ReadBuf, net::UnixDatagram};
+    use url::Url;
+
+    use super::{Transport, TransportAddr};
+
+    /// The URL scheme of [`UnixTransport`] addresses.
+    const SCHEME: &str = "unix";
+
+    /// The maximum size of a received datagram.
+    const MAX_DATAGRAM_SIZE: usize = u16::MAX as usize;
+
+    /// A [`Transport`] over a Unix domain datagram socket, for nodes on the same host.
+    ///
+    /// Nodes are addressed by the path of their socket, as `unix:///path/to/socket`.  The
+    /// socket file is removed again when the transport is dropped.
+    #[derive(derive_more::Debug)]
+    pub struct UnixTransport {
+        path: PathBuf,
+        addr: TransportAddr,
+        #[debug(skip)]
+        socket: UnixDatagram,
+        #[debug(skip)]
+        recv_buf: parking_lot::Mutex<Vec<u8>>,
+    }
+
+    impl UnixTransport {
+        /// Binds a Unix domain datagram socket at `path`.
+        pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+            let path = path.as_ref().to_path_buf();
+            let addr = path_to_addr(&path)?;
+            let socket = UnixDatagram::bind(&path)?;
+            Ok(Self {
+                path,
+                addr,
+                socket,
+                recv_buf: parking_lot::Mutex::new(vec![0u8; MAX_DATAGRAM_SIZE]),
+            })
+        }
+
+        /// The path of the socket.
+        pub fn path(&self) -> &Path {
+            &self.path
+        }
+    }
+
+    impl Drop for UnixTransport {
+        fn drop(&mut self) {
+            std::fs::remove_file(&self.path).ok();
+        }
+    }
+
+    impl Transport for UnixTransport {
+        fn scheme(&self) -> &str {
+            SCHEME
+        }
+
+        fn local_addrs(&self) -> Vec<TransportAddr> {
+            vec![self.addr.clone()]
+        }
+
+        fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.socket.poll_send_ready(cx)
+        }
+
+        fn send(&self, dst: &TransportAddr, datagram: Bytes) -> io::Result<()> {
+            let path = addr_to_path(dst)?;
+            self.socket.try_send_to(&datagram, path).map(|_| ())
+        }
+
+        fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TransportAddr, Bytes)>> {
+            let mut recv_buf = self.recv_buf.lock();
+            let mut buf = ReadBuf::new(&mut recv_buf[..]);
+            let src = ready!(self.socket.poll_recv_from(cx, &mut buf))?;
+            let datagram = Bytes::copy_from_slice(buf.filled());
+            let src = src.as_pathname().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "datagram from unbound socket")
+            })?;
+            Poll::Ready(Ok((path_to_addr(src)?, datagram)))
+        }
+    }
+
+    fn path_to_addr(path: &Path) -> io::Result<TransportAddr> {
+        let path = path
+            .to_str()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "non UTF-8 socket path"))?;
+        let mut url = Url::parse("unix:///").expect("valid url");
+        url.set_path(path);
+        Ok(url.into())
+    }
+
+    fn addr_to_path(addr: &TransportAddr) -> io::Result<PathBuf> {
+        if addr.scheme() != SCHEME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a unix transport address: {addr}"),
+            ));
+        }
+        let path = percent_decode_str(addr.path())
+            .decode_utf8()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        Ok(PathBuf::from(path.as_ref()))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use futures::future::poll_fn;
+
+        use super::*;
+
+        /// Returns a socket path in the temp dir, which is short enough for a Unix socket.
+        fn socket_path(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("{name}-{}.sock", rand::random::<u32>()))
+        }
+
+        #[tokio::test]
+        async fn test_unix_transport() -> anyhow::Result<()> {
+            let a_path = socket_path("iroh a");
+            let a = UnixTransport::bind(&a_path)?;
+            let b = UnixTransport::bind(socket_path("iroh-b"))?;
+            let a_addr = a.local_addrs().remove(0);
+            let b_addr = b.local_addrs().remove(0);
+            assert_eq!(a_addr.scheme(), "unix");
+            assert_eq!(addr_to_path(&a_addr)?, a_path);
+
+            poll_fn(|cx| a.poll_send_ready(cx)).await?;
+            a.send(&b_addr, Bytes::from_static(b"hello"))?;
+            let (src, datagram) = poll_fn(|cx| b.poll_recv(cx)).await?;
+            assert_eq!(src, a_addr);
+            assert_eq!(&datagram[..], b"hello");
+
+            poll_fn(|cx| b.poll_send_ready(cx)).await?;
+            b.send(&src, Bytes::from_static(b"world"))?;
+            let (src, datagram) = poll_fn(|cx| a.poll_recv(cx)).await?;
+            assert_eq!(src, b_addr);
+            assert_eq!(&datagram[..], b"world");
+
+            drop(a);
+            assert!(!a_path.exists());
+            Ok(())
+        }
+    }
+}
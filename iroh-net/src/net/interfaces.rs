@@ -252,7 +252,7 @@ impl State {
             have_v6,
             is_expensive: false,
             default_route_interface,
-            http_proxy: None,
+            http_proxy: crate::derp::http::proxy::url_from_env(),
             pac: None,
         }
     }
@@ -837,6 +837,8 @@ mod tests {
             url: url.clone(),
             stun_only: true,
             stun_port: DEFAULT_DERP_STUN_PORT,
+            discovery: false,
+            auth_token: None,
         }])
         .expect("hardcoded");
 
@@ -0,0 +1,575 @@
+//! An in-memory simulated network, for deterministic multi-node tests.
+//!
+//! A [`SimNetwork`] is an in-process router which delivers datagrams between the
+//! [`SimTransport`]s attached to it.  Each link between two nodes has a configurable
+//! latency and loss rate, nodes can sit behind a simulated [`Nat`] and the network can be
+//! partitioned and healed at any time.  Packet loss is drawn from a seeded random number
+//! generator.  Delayed datagrams are delivered by a single task per link, in the order of
+//! their delivery deadline on the [`tokio::time`] clock.  Tests which run all their work on
+//! a current thread runtime with a paused clock therefore behave the same on every run.
+//! Work on other threads, e.g. actors of a node running on their own thread, changes the
+//! order in which datagrams are sent, and with it which datagrams are lost.
+//!
+//! A [`SimTransport`] is a [`Transport`] for the [`MagicEndpoint`], so endpoints exchange
+//! their QUIC and disco packets over the simulated network:
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use iroh_net::{derp::DerpMode, sim::{Nat, SimNetwork}, MagicEndpoint, NodeAddr};
+//! # async fn wrapper() -> anyhow::Result<()> {
+//! let net = SimNetwork::new(42);
+//! let transport = net.add_node(Nat::None);
+//! let addr = transport.addr().clone();
+//! let ep = MagicEndpoint::builder()
+//!     .derp_mode(DerpMode::Disabled)
+//!     .transport(Arc::new(transport))
+//!     .bind(0)
+//!     .await?;
+//! let node_addr = NodeAddr::new(ep.node_id()).with_transport_addrs([addr]);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! DERP servers are not simulated, endpoints on a simulated network should use
+//! [`DerpMode::Disabled`](crate::derp::DerpMode::Disabled) and only be given the
+//! [`SimTransport`] addresses of each other.
+//!
+//! [`MagicEndpoint`]: crate::MagicEndpoint
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::{sync::mpsc, time::Instant};
+use tracing::trace;
+use url::Url;
+
+use crate::{
+    magicsock::{Transport, TransportAddr},
+    util::AbortingJoinHandle,
+};
+
+/// The URL scheme of [`SimTransport`] addresses.
+const SCHEME: &str = "sim";
+
+/// The number of datagrams a node buffers before dropping further received datagrams.
+const INBOX_CAPACITY: usize = 1024;
+
+/// How long a [`Nat::AddressDependent`] keeps a mapping open without outgoing traffic.
+pub const NAT_MAPPING_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The behaviour of the simulated NAT in front of a node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Nat {
+    /// The node is not behind a NAT and receives datagrams from every node.
+    #[default]
+    None,
+    /// The node only receives datagrams from nodes it sent a datagram to within the last
+    /// [`NAT_MAPPING_TIMEOUT`].
+    ///
+    /// Two such nodes can only reach each other by sending to each other at the same
+    /// time, i.e. by holepunching.
+    AddressDependent,
+    /// The node does not receive any datagrams at all.
+    Blocked,
+}
+
+/// The properties of a link between two nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Link {
+    /// The one-way latency of the link.
+    pub latency: Duration,
+    /// The probability with which a datagram is dropped, between `0.0` and `1.0`.
+    pub loss: f64,
+}
+
+/// An in-process network routing datagrams between [`SimTransport`]s.
+///
+/// This is cheaply cloneable, all clones refer to the same network.
+#[derive(Debug, Clone)]
+pub struct SimNetwork {
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(derive_more::Debug)]
+struct Inner {
+    #[debug(skip)]
+    rng: StdRng,
+    next_node: u64,
+    nodes: HashMap<TransportAddr, Node>,
+    default_link: Link,
+    /// Links with non-default properties, keyed by the ordered pair of node addresses.
+    links: HashMap<(TransportAddr, TransportAddr), Link>,
+    /// The partition group of each node, if the network is partitioned.
+    partitions: Option<HashMap<TransportAddr, usize>>,
+    /// The queues of delayed datagrams, keyed by source and destination address.
+    in_flight: HashMap<(TransportAddr, TransportAddr), LinkQueue>,
+}
+
+#[derive(Debug)]
+struct Node {
+    nat: Nat,
+    /// When this node last sent to each destination, for [`Nat::AddressDependent`].
+    nat_mappings: HashMap<TransportAddr, Instant>,
+    inbox: mpsc::Sender<(TransportAddr, Bytes)>,
+}
+
+impl SimNetwork {
+    /// Creates a new network, with packet loss drawn from an RNG seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                rng: StdRng::seed_from_u64(seed),
+                next_node: 1,
+                nodes: Default::default(),
+                default_link: Link::default(),
+                links: Default::default(),
+                partitions: None,
+                in_flight: Default::default(),
+            })),
+        }
+    }
+
+    /// Adds a new node to the network, behind the given [`Nat`].
+    pub fn add_node(&self, nat: Nat) -> SimTransport {
+        let mut inner = self.inner.lock();
+        let addr = node_addr(inner.next_node);
+        inner.next_node += 1;
+        let (inbox, recv) = mpsc::channel(INBOX_CAPACITY);
+        inner.nodes.insert(
+            addr.clone(),
+            Node {
+                nat,
+                nat_mappings: Default::default(),
+                inbox,
+            },
+        );
+        SimTransport {
+            net: self.clone(),
+            addr,
+            recv: Mutex::new(recv),
+        }
+    }
+
+    /// Changes the [`Nat`] in front of a node.
+    pub fn set_nat(&self, addr: &TransportAddr, nat: Nat) {
+        if let Some(node) = self.inner.lock().nodes.get_mut(addr) {
+            node.nat = nat;
+            node.nat_mappings.clear();
+        }
+    }
+
+    /// Sets the properties of all links without explicitly configured properties.
+    pub fn set_default_link(&self, link: Link) {
+        self.inner.lock().default_link = link;
+    }
+
+    /// Sets the properties of the link between nodes `a` and `b`, in both directions.
+    pub fn set_link(&self, a: &TransportAddr, b: &TransportAddr, link: Link) {
+        self.inner.lock().links.insert(link_key(a, b), link);
+    }
+
+    /// Partitions the network into the given groups of nodes.
+    ///
+    /// Afterwards nodes can only reach nodes of the same group.  Nodes not in any group can
+    /// not reach any other node.
+    pub fn partition<G>(&self, groups: impl IntoIterator<Item = G>)
+    where
+        G: IntoIterator<Item = TransportAddr>,
+    {
+        let partitions = groups
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.into_iter().map(move |addr| (addr, i)))
+            .collect();
+        self.inner.lock().partitions = Some(partitions);
+    }
+
+    /// Removes any partitioning of the network.
+    pub fn heal(&self) {
+        self.inner.lock().partitions = None;
+    }
+
+    fn send(&self, src: &TransportAddr, dst: &TransportAddr, datagram: Bytes) {
+        let mut inner = self.inner.lock();
+        let now = Instant::now();
+        if let Some(node) = inner.nodes.get_mut(src) {
+            node.nat_mappings.insert(dst.clone(), now);
+        }
+        if !inner.is_reachable(src, dst) {
+            trace!(%src, %dst, "sim: unreachable, dropping datagram");
+            return;
+        }
+        let link = inner.link(src, dst);
+        if link.loss > 0.0 && inner.rng.gen_bool(link.loss.min(1.0)) {
+            trace!(%src, %dst, "sim: lost datagram");
+            return;
+        }
+        let Some(node) = inner.nodes.get(dst) else {
+            return;
+        };
+        let nat_open = match node.nat {
+            Nat::None => true,
+            Nat::AddressDependent => node
+                .nat_mappings
+                .get(src)
+                .is_some_and(|sent| now.duration_since(*sent) < NAT_MAPPING_TIMEOUT),
+            Nat::Blocked => false,
+        };
+        if !nat_open {
+            trace!(%src, %dst, "sim: blocked by NAT, dropping datagram");
+            return;
+        }
+        if link.latency.is_zero() {
+            node.inbox.try_send((src.clone(), datagram)).ok();
+        } else {
+            let inbox = node.inbox.clone();
+            inner
+                .in_flight
+                .entry((src.clone(), dst.clone()))
+                .or_insert_with(|| LinkQueue::new(src.clone(), inbox))
+                .send
+                .send((now + link.latency, datagram))
+                .ok();
+        }
+    }
+}
+
+/// The delayed datagrams on the link from one node to another.
+///
+/// A single task delivers the datagrams of the link in the order of their deadline, and in
+/// the order they were sent for equal deadlines.
+#[derive(Debug)]
+struct LinkQueue {
+    send: mpsc::UnboundedSender<(Instant, Bytes)>,
+    _task: AbortingJoinHandle<()>,
+}
+
+impl LinkQueue {
+    fn new(src: TransportAddr, inbox: mpsc::Sender<(TransportAddr, Bytes)>) -> Self {
+        let (send, recv) = mpsc::unbounded_channel();
+        let task = tokio::spawn(deliver_delayed(src, recv, inbox));
+        Self {
+            send,
+            _task: task.into(),
+        }
+    }
+}
+
+async fn deliver_delayed(
+    src: TransportAddr,
+    mut recv: mpsc::UnboundedReceiver<(Instant, Bytes)>,
+    inbox: mpsc::Sender<(TransportAddr, Bytes)>,
+) {
+    let mut queue: BTreeMap<(Instant, u64), Bytes> = BTreeMap::new();
+    let mut next_seq = 0u64;
+    loop {
+        let next_deadline = queue.keys().next().map(|(deadline, _seq)| *deadline);
+        let sleep = tokio::time::sleep_until(next_deadline.unwrap_or_else(Instant::now));
+        tokio::select! {
+            biased;
+            item = recv.recv() => {
+                let Some((deadline, datagram)) = item else {
+                    break;
+                };
+                queue.insert((deadline, next_seq), datagram);
+                next_seq += 1;
+            }
+            _ = sleep, if next_deadline.is_some() => {
+                let now = Instant::now();
+                while let Some(entry) = queue.first_entry() {
+                    if entry.key().0 > now {
+                        break;
+                    }
+                    inbox.try_send((src.clone(), entry.remove())).ok();
+                }
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn is_reachable(&self, src: &TransportAddr, dst: &TransportAddr) -> bool {
+        match self.partitions {
+            Some(ref partitions) => match (partitions.get(src), partitions.get(dst)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+            None => true,
+        }
+    }
+
+    fn link(&self, a: &TransportAddr, b: &TransportAddr) -> Link {
+        self.links
+            .get(&link_key(a, b))
+            .copied()
+            .unwrap_or(self.default_link)
+    }
+}
+
+/// A node on a [`SimNetwork`].
+///
+/// Nodes are addressed as `sim://<n>`, where `n` numbers the nodes in the order they were
+/// added to the network.
+#[derive(derive_more::Debug)]
+pub struct SimTransport {
+    #[debug(skip)]
+    net: SimNetwork,
+    addr: TransportAddr,
+    #[debug(skip)]
+    recv: Mutex<mpsc::Receiver<(TransportAddr, Bytes)>>,
+}
+
+impl SimTransport {
+    /// The address of this node on the network.
+    pub fn addr(&self) -> &TransportAddr {
+        &self.addr
+    }
+
+    /// The network this node is attached to.
+    pub fn network(&self) -> &SimNetwork {
+        &self.net
+    }
+}
+
+impl Transport for SimTransport {
+    fn scheme(&self) -> &str {
+        SCHEME
+    }
+
+    fn local_addrs(&self) -> Vec<TransportAddr> {
+        vec![self.addr.clone()]
+    }
+
+    fn send(&self, dst: &TransportAddr, datagram: Bytes) -> io::Result<()> {
+        if dst.scheme() != SCHEME {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a sim transport address: {dst}"),
+            ));
+        }
+        self.net.send(&self.addr, dst, datagram);
+        Ok(())
+    }
+
+    fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<(TransportAddr, Bytes)>> {
+        // The network holds on to the sender for as long as this node exists.
+        match self.recv.lock().poll_recv(cx) {
+            Poll::Ready(Some(item)) => Poll::Ready(Ok(item)),
+            Poll::Ready(None) => Poll::Ready(Err(io::ErrorKind::NotConnected.into())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for SimTransport {
+    fn drop(&mut self) {
+        let mut inner = self.net.inner.lock();
+        inner.nodes.remove(&self.addr);
+        inner
+            .links
+            .retain(|(a, b), _| a != &self.addr && b != &self.addr);
+        inner
+            .in_flight
+            .retain(|(a, b), _| a != &self.addr && b != &self.addr);
+    }
+}
+
+fn node_addr(n: u64) -> TransportAddr {
+    let url = Url::parse(&format!("{SCHEME}://{n}")).expect("valid url");
+    url.into()
+}
+
+fn link_key(a: &TransportAddr, b: &TransportAddr) -> (TransportAddr, TransportAddr) {
+    if a <= b {
+        (a.clone(), b.clone())
+    } else {
+        (b.clone(), a.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures::{future::poll_fn, FutureExt};
+
+    use crate::{derp::DerpMode, magicsock::ConnectionType, MagicEndpoint, NodeAddr};
+
+    use super::*;
+
+    const TEST_ALPN: &[u8] = b"n0/iroh/test";
+
+    async fn recv(node: &SimTransport) -> (TransportAddr, Bytes) {
+        poll_fn(|cx| node.poll_recv(cx)).await.unwrap()
+    }
+
+    fn try_recv(node: &SimTransport) -> Option<(TransportAddr, Bytes)> {
+        poll_fn(|cx| node.poll_recv(cx))
+            .now_or_never()
+            .map(|res| res.unwrap())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_sim_latency() {
+        let net = SimNetwork::new(0);
+        let a = net.add_node(Nat::None);
+        let b = net.add_node(Nat::None);
+        let latency = Duration::from_millis(50);
+        net.set_link(a.addr(), b.addr(), Link { latency, loss: 0.0 });
+
+        let start = Instant::now();
+        a.send(b.addr(), Bytes::from_static(b"hello")).unwrap();
+        assert!(try_recv(&b).is_none());
+        let (src, datagram) = recv(&b).await;
+        assert_eq!(&src, a.addr());
+        assert_eq!(&datagram[..], b"hello");
+        assert_eq!(start.elapsed(), latency);
+    }
+
+    #[tokio::test(flavor = "current_thread", start_paused = true)]
+    async fn test_sim_latency_change_keeps_deadline_order() {
+        let net = SimNetwork::new(0);
+        let a = net.add_node(Nat::None);
+        let b = net.add_node(Nat::None);
+        let link = |ms| Link {
+            latency: Duration::from_millis(ms),
+            loss: 0.0,
+        };
+        net.set_link(a.addr(), b.addr(), link(50));
+        a.send(b.addr(), Bytes::from_static(b"slow")).unwrap();
+        a.send(b.addr(), Bytes::from_static(b"slow again")).unwrap();
+        net.set_link(a.addr(), b.addr(), link(10));
+        a.send(b.addr(), Bytes::from_static(b"fast")).unwrap();
+
+        let start = Instant::now();
+        let mut received = vec![];
+        for _ in 0..3 {
+            let (_src, datagram) = recv(&b).await;
+            received.push((datagram, start.elapsed().as_millis()));
+        }
+        assert_eq!(
+            received,
+            vec![
+                (Bytes::from_static(b"fast"), 10),
+                (Bytes::from_static(b"slow"), 50),
+                (Bytes::from_static(b"slow again"), 50),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sim_loss_is_deterministic() {
+        fn received(seed: u64) -> usize {
+            let net = SimNetwork::new(seed);
+            net.set_default_link(Link {
+                latency: Duration::ZERO,
+                loss: 0.5,
+            });
+            let a = net.add_node(Nat::None);
+            let b = net.add_node(Nat::None);
+            for _ in 0..100 {
+                a.send(b.addr(), Bytes::from_static(b"ping")).unwrap();
+            }
+            std::iter::from_fn(|| try_recv(&b)).count()
+        }
+
+        let count = received(7);
+        assert!(count > 0 && count < 100, "received {count}");
+        assert_eq!(count, received(7));
+    }
+
+    #[test]
+    fn test_sim_nat() {
+        let net = SimNetwork::new(0);
+        let a = net.add_node(Nat::None);
+        let b = net.add_node(Nat::AddressDependent);
+
+        // Unsolicited datagrams are dropped by b's NAT.
+        a.send(b.addr(), Bytes::from_static(b"one")).unwrap();
+        assert!(try_recv(&b).is_none());
+
+        // Once b sent to a, a can reach b.
+        b.send(a.addr(), Bytes::from_static(b"two")).unwrap();
+        assert_eq!(&try_recv(&a).unwrap().1[..], b"two");
+        a.send(b.addr(), Bytes::from_static(b"three")).unwrap();
+        assert_eq!(&try_recv(&b).unwrap().1[..], b"three");
+
+        net.set_nat(b.addr(), Nat::Blocked);
+        a.send(b.addr(), Bytes::from_static(b"four")).unwrap();
+        assert!(try_recv(&b).is_none());
+    }
+
+    #[test]
+    fn test_sim_partition() {
+        let net = SimNetwork::new(0);
+        let a = net.add_node(Nat::None);
+        let b = net.add_node(Nat::None);
+        let c = net.add_node(Nat::None);
+
+        net.partition([vec![a.addr().clone(), b.addr().clone()]]);
+        a.send(b.addr(), Bytes::from_static(b"ab")).unwrap();
+        a.send(c.addr(), Bytes::from_static(b"ac")).unwrap();
+        assert_eq!(&try_recv(&b).unwrap().1[..], b"ab");
+        assert!(try_recv(&c).is_none());
+
+        net.heal();
+        a.send(c.addr(), Bytes::from_static(b"ac")).unwrap();
+        assert_eq!(&try_recv(&c).unwrap().1[..], b"ac");
+    }
+
+    /// Two endpoints behind [`Nat::AddressDependent`] can only connect by dialing each
+    /// other at the same time.
+    #[tokio::test]
+    async fn test_sim_magic_endpoint_holepunching() -> anyhow::Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let net = SimNetwork::new(0);
+        net.set_default_link(Link {
+            latency: Duration::from_millis(10),
+            loss: 0.0,
+        });
+
+        let mut eps = Vec::new();
+        for _ in 0..2 {
+            let transport = net.add_node(Nat::AddressDependent);
+            let addr = transport.addr().clone();
+            let ep = MagicEndpoint::builder()
+                .alpns(vec![TEST_ALPN.to_vec()])
+                .derp_mode(DerpMode::Disabled)
+                .transport(Arc::new(transport))
+                .bind(0)
+                .await?;
+            eps.push((ep, addr));
+        }
+        let (ep1, addr1) = &eps[0];
+        let (ep2, addr2) = &eps[1];
+        let node1 = NodeAddr::new(ep1.node_id()).with_transport_addrs([addr1.clone()]);
+        let node2 = NodeAddr::new(ep2.node_id()).with_transport_addrs([addr2.clone()]);
+
+        let (conn1, conn2) =
+            tokio::try_join!(ep1.connect(node2, TEST_ALPN), ep2.connect(node1, TEST_ALPN))?;
+
+        let mut send = conn1.open_uni().await?;
+        send.write_all(b"hello").await?;
+        send.finish().await?;
+        let accepted = ep2.accept().await.expect("endpoint open").await?;
+        let mut recv = accepted.accept_uni().await?;
+        assert_eq!(recv.read_to_end(100).await?, b"hello");
+
+        let info = ep1.connection_info(ep2.node_id()).expect("known node");
+        assert_eq!(info.conn_type, ConnectionType::Transport(addr2.clone()));
+
+        drop(conn2);
+        for (ep, _) in &eps {
+            ep.close(0u32.into(), b"done").await?;
+        }
+        Ok(())
+    }
+}
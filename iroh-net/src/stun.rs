@@ -194,6 +194,8 @@ pub mod test {
                 url,
                 stun_port: port,
                 stun_only,
+                discovery: false,
+                auth_token: None,
             }
         });
         DerpMap::from_nodes(nodes).expect("generated invalid nodes")
@@ -45,6 +45,8 @@ pub(crate) async fn run_derper() -> Result<(DerpMap, DerpUrl, CleanupDropGuard)>
         url: url.clone(),
         stun_only: false,
         stun_port: stun_addr.port(),
+        discovery: false,
+        auth_token: None,
     }])
     .expect("hardcoded");
 
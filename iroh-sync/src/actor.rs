@@ -15,11 +15,15 @@ use tokio::sync::oneshot;
 use tracing::{debug, error, error_span, trace, warn};
 
 use crate::{
+    metadata,
     ranger::Message,
-    store::{self, DownloadPolicy, ImportNamespaceOutcome, Query},
+    store::{
+        self, AreaOfInterest, DownloadPolicy, HistoryPolicy, ImportNamespaceOutcome, IndexSpec,
+        KeyFilter, Query, Quota,
+    },
     Author, AuthorHeads, AuthorId, Capability, CapabilityKind, ContentStatus,
-    ContentStatusCallback, Event, NamespaceId, NamespaceSecret, PeerIdBytes, Replica, SignedEntry,
-    SyncOutcome,
+    ContentStatusCallback, Event, InsertError, NamespaceId, NamespaceSecret, PeerIdBytes,
+    PendingRanges, Replica, SignedEntry, SyncOutcome, Validator,
 };
 
 #[derive(derive_more::Debug, derive_more::Display)]
@@ -30,6 +34,18 @@ enum Action {
         #[debug("reply")]
         reply: oneshot::Sender<Result<AuthorId>>,
     },
+    #[display("ExportAuthor")]
+    ExportAuthor {
+        author: AuthorId,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Option<Author>>>,
+    },
+    #[display("DeleteAuthor")]
+    DeleteAuthor {
+        author: AuthorId,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<bool>>,
+    },
     #[display("NewReplica")]
     ImportNamespace {
         capability: Capability,
@@ -93,12 +109,23 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<()>>,
     },
+    InsertMetadata {
+        hash: Hash,
+        len: u64,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
     DeletePrefix {
         author: AuthorId,
         key: Bytes,
         #[debug("reply")]
         reply: oneshot::Sender<Result<usize>>,
     },
+    InsertImported {
+        entry: SignedEntry,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<bool>>,
+    },
     InsertRemote {
         entry: SignedEntry,
         from: PeerIdBytes,
@@ -107,6 +134,12 @@ enum ReplicaAction {
         reply: oneshot::Sender<Result<()>>,
     },
     SyncInitialMessage {
+        area: AreaOfInterest,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Message<SignedEntry>>>,
+    },
+    SyncResumeMessage {
+        pending: PendingRanges,
         #[debug("reply")]
         reply: oneshot::Sender<Result<Message<SignedEntry>>>,
     },
@@ -136,6 +169,10 @@ enum ReplicaAction {
         query: Query,
         reply: flume::Sender<Result<SignedEntry>>,
     },
+    Count {
+        query: Query,
+        reply: oneshot::Sender<Result<u64>>,
+    },
     DropReplica {
         reply: oneshot::Sender<Result<()>>,
     },
@@ -147,6 +184,10 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<Option<NonZeroU64>>>,
     },
+    GetAuthorHeads {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<AuthorHeads>>,
+    },
     SetDownloadPolicy {
         policy: DownloadPolicy,
         #[debug("reply")]
@@ -156,6 +197,72 @@ enum ReplicaAction {
         #[debug("reply")]
         reply: oneshot::Sender<Result<DownloadPolicy>>,
     },
+    SetSyncArea {
+        area: AreaOfInterest,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetSyncArea {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<AreaOfInterest>>,
+    },
+    SetLabel {
+        label: Option<String>,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetLabel {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Option<String>>>,
+    },
+    GetMetadataEntry {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Option<SignedEntry>>>,
+    },
+    SetHistoryPolicy {
+        policy: HistoryPolicy,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetHistoryPolicy {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<HistoryPolicy>>,
+    },
+    SetQuota {
+        quota: Quota,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    GetQuota {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Quota>>,
+    },
+    CreateIndex {
+        spec: IndexSpec,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<()>>,
+    },
+    RemoveIndex {
+        name: String,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<bool>>,
+    },
+    ListIndexes {
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<Vec<IndexSpec>>>,
+    },
+    IndexContent {
+        hash: Hash,
+        #[debug("content")]
+        content: Bytes,
+        #[debug("reply")]
+        reply: oneshot::Sender<Result<usize>>,
+    },
+    GetByIndex {
+        name: String,
+        filter: KeyFilter,
+        reply: flume::Sender<Result<SignedEntry>>,
+    },
 }
 
 /// The state for an open replica.
@@ -201,6 +308,10 @@ pub struct OpenOpts {
     pub sync: bool,
     /// Optionally subscribe to replica events.
     pub subscribe: Option<flume::Sender<Event>>,
+    /// Optionally set the validator for entries of the replica.
+    ///
+    /// Replaces the validator of a replica that is already open.
+    pub validator: Option<Arc<dyn Validator>>,
 }
 impl OpenOpts {
     /// Set sync state to true.
@@ -213,6 +324,11 @@ impl OpenOpts {
         self.subscribe = Some(subscribe);
         self
     }
+    /// Set the validator for entries of the replica.
+    pub fn validator(mut self, validator: Arc<dyn Validator>) -> Self {
+        self.validator = Some(validator);
+        self
+    }
 }
 
 #[allow(missing_docs)]
@@ -312,6 +428,18 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn insert_metadata(
+        &self,
+        namespace: NamespaceId,
+        hash: Hash,
+        len: u64,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::InsertMetadata { hash, len, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn delete_prefix(
         &self,
         namespace: NamespaceId,
@@ -324,6 +452,20 @@ impl SyncHandle {
         rx.await?
     }
 
+    /// Insert an entry imported from a snapshot or bundle with [`Replica::insert_remote_entry`].
+    ///
+    /// Returns `false` if the entry was not inserted because a newer entry exists.
+    pub async fn insert_imported(
+        &self,
+        namespace: NamespaceId,
+        entry: SignedEntry,
+    ) -> Result<bool> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::InsertImported { entry, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn insert_remote(
         &self,
         namespace: NamespaceId,
@@ -345,9 +487,21 @@ impl SyncHandle {
     pub async fn sync_initial_message(
         &self,
         namespace: NamespaceId,
+        area: AreaOfInterest,
+    ) -> Result<Message<SignedEntry>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SyncInitialMessage { reply, area };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn sync_resume_message(
+        &self,
+        namespace: NamespaceId,
+        pending: PendingRanges,
     ) -> Result<Message<SignedEntry>> {
         let (reply, rx) = oneshot::channel();
-        let action = ReplicaAction::SyncInitialMessage { reply };
+        let action = ReplicaAction::SyncResumeMessage { reply, pending };
         self.send_replica(namespace, action).await?;
         rx.await?
     }
@@ -399,6 +553,13 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn get_author_heads(&self, namespace: NamespaceId) -> Result<AuthorHeads> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetAuthorHeads { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn get_many(
         &self,
         namespace: NamespaceId,
@@ -410,6 +571,13 @@ impl SyncHandle {
         Ok(())
     }
 
+    pub async fn count(&self, namespace: NamespaceId, query: Query) -> Result<u64> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::Count { query, reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
     pub async fn get_exact(
         &self,
         namespace: NamespaceId,
@@ -473,6 +641,18 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn export_author(&self, author: AuthorId) -> Result<Option<Author>> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Action::ExportAuthor { author, reply }).await?;
+        rx.await?
+    }
+
+    pub async fn delete_author(&self, author: AuthorId) -> Result<bool> {
+        let (reply, rx) = oneshot::channel();
+        self.send(Action::DeleteAuthor { author, reply }).await?;
+        rx.await?
+    }
+
     pub async fn import_namespace(&self, capability: Capability) -> Result<NamespaceId> {
         let (reply, rx) = oneshot::channel();
         self.send(Action::ImportNamespace { capability, reply })
@@ -498,6 +678,128 @@ impl SyncHandle {
         rx.await?
     }
 
+    pub async fn get_sync_area(&self, namespace: NamespaceId) -> Result<AreaOfInterest> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetSyncArea { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_sync_area(&self, namespace: NamespaceId, area: AreaOfInterest) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetSyncArea { reply, area };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn get_label(&self, namespace: NamespaceId) -> Result<Option<String>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetLabel { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_label(&self, namespace: NamespaceId, label: Option<String>) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetLabel { reply, label };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    /// Get the entry that contains the metadata of a document, without opening the replica.
+    pub async fn get_metadata_entry(&self, namespace: NamespaceId) -> Result<Option<SignedEntry>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetMetadataEntry { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn get_history_policy(&self, namespace: NamespaceId) -> Result<HistoryPolicy> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetHistoryPolicy { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_history_policy(
+        &self,
+        namespace: NamespaceId,
+        policy: HistoryPolicy,
+    ) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetHistoryPolicy { reply, policy };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn get_quota(&self, namespace: NamespaceId) -> Result<Quota> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::GetQuota { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn set_quota(&self, namespace: NamespaceId, quota: Quota) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::SetQuota { reply, quota };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn create_index(&self, namespace: NamespaceId, spec: IndexSpec) -> Result<()> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::CreateIndex { reply, spec };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn remove_index(&self, namespace: NamespaceId, name: String) -> Result<bool> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::RemoveIndex { reply, name };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn list_indexes(&self, namespace: NamespaceId) -> Result<Vec<IndexSpec>> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::ListIndexes { reply };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    /// Update the content based indexes of a document for all entries with content `hash`.
+    pub async fn index_content(
+        &self,
+        namespace: NamespaceId,
+        hash: Hash,
+        content: Bytes,
+    ) -> Result<usize> {
+        let (reply, rx) = oneshot::channel();
+        let action = ReplicaAction::IndexContent {
+            reply,
+            hash,
+            content,
+        };
+        self.send_replica(namespace, action).await?;
+        rx.await?
+    }
+
+    pub async fn get_by_index(
+        &self,
+        namespace: NamespaceId,
+        name: String,
+        filter: KeyFilter,
+        reply: flume::Sender<Result<SignedEntry>>,
+    ) -> Result<()> {
+        let action = ReplicaAction::GetByIndex {
+            name,
+            filter,
+            reply,
+        };
+        self.send_replica(namespace, action).await?;
+        Ok(())
+    }
+
     async fn send(&self, action: Action) -> Result<()> {
         self.tx
             .send_async(action)
@@ -561,6 +863,12 @@ impl<S: store::Store> Actor<S> {
                 let id = author.id();
                 send_reply(reply, self.store.import_author(author).map(|_| id))
             }
+            Action::ExportAuthor { author, reply } => {
+                send_reply(reply, self.store.get_author(&author))
+            }
+            Action::DeleteAuthor { author, reply } => {
+                send_reply(reply, self.store.delete_author(&author))
+            }
             Action::ImportNamespace { capability, reply } => send_reply_with(reply, self, |this| {
                 let id = capability.id();
                 let outcome = this.store.import_namespace(capability.clone())?;
@@ -626,6 +934,13 @@ impl<S: store::Store> Actor<S> {
                 replica.insert(&key, &author, hash, len)?;
                 Ok(())
             }),
+            ReplicaAction::InsertMetadata { hash, len, reply } => {
+                send_reply_with(reply, self, move |this| {
+                    let replica = this.states.replica(&namespace)?;
+                    replica.insert_metadata(hash, len)?;
+                    Ok(())
+                })
+            }
             ReplicaAction::DeletePrefix { author, key, reply } => {
                 send_reply_with(reply, self, |this| {
                     let author = get_author(&this.store, &author)?;
@@ -634,6 +949,16 @@ impl<S: store::Store> Actor<S> {
                     Ok(res)
                 })
             }
+            ReplicaAction::InsertImported { entry, reply } => {
+                send_reply_with(reply, self, move |this| {
+                    let replica = this.states.replica(&namespace)?;
+                    match replica.insert_remote_entry(entry, None, ContentStatus::Complete) {
+                        Ok(_) => Ok(true),
+                        Err(InsertError::NewerEntryExists) => Ok(false),
+                        Err(err) => Err(err.into()),
+                    }
+                })
+            }
             ReplicaAction::InsertRemote {
                 entry,
                 from,
@@ -641,14 +966,21 @@ impl<S: store::Store> Actor<S> {
                 reply,
             } => send_reply_with(reply, self, move |this| {
                 let replica = this.states.replica_if_syncing(&namespace)?;
-                replica.insert_remote_entry(entry, from, content_status)?;
+                replica.insert_remote_entry(entry, Some(from), content_status)?;
                 Ok(())
             }),
 
-            ReplicaAction::SyncInitialMessage { reply } => {
+            ReplicaAction::SyncInitialMessage { area, reply } => {
                 send_reply_with(reply, self, move |this| {
                     let replica = this.states.replica_if_syncing(&namespace)?;
-                    let res = replica.sync_initial_message()?;
+                    let res = replica.sync_initial_message(&area)?;
+                    Ok(res)
+                })
+            }
+            ReplicaAction::SyncResumeMessage { pending, reply } => {
+                send_reply_with(reply, self, move |this| {
+                    let replica = this.states.replica_if_syncing(&namespace)?;
+                    let res = replica.sync_resume_message(&pending)?;
                     Ok(res)
                 })
             }
@@ -687,6 +1019,10 @@ impl<S: store::Store> Actor<S> {
                     .and_then(|_| self.store.get_many(namespace, query));
                 iter_to_channel(reply, iter)
             }
+            ReplicaAction::Count { query, reply } => send_reply_with(reply, self, move |this| {
+                this.states.ensure_open(&namespace)?;
+                this.store.count(namespace, query)
+            }),
             ReplicaAction::DropReplica { reply } => send_reply_with(reply, self, |this| {
                 this.close(namespace);
                 this.store.remove_replica(&namespace)
@@ -710,12 +1046,75 @@ impl<S: store::Store> Actor<S> {
                 let res = self.store.has_news_for_us(namespace, &heads);
                 send_reply(reply, res)
             }
+            ReplicaAction::GetAuthorHeads { reply } => {
+                send_reply(reply, self.store.get_author_heads(namespace))
+            }
             ReplicaAction::SetDownloadPolicy { policy, reply } => {
                 send_reply(reply, self.store.set_download_policy(&namespace, policy))
             }
             ReplicaAction::GetDownloadPolicy { reply } => {
                 send_reply(reply, self.store.get_download_policy(&namespace))
             }
+            ReplicaAction::SetSyncArea { area, reply } => {
+                send_reply(reply, self.store.set_sync_area(&namespace, area))
+            }
+            ReplicaAction::GetSyncArea { reply } => {
+                send_reply(reply, self.store.get_sync_area(&namespace))
+            }
+            ReplicaAction::SetLabel { label, reply } => {
+                send_reply(reply, self.store.set_label(&namespace, label))
+            }
+            ReplicaAction::GetLabel { reply } => {
+                send_reply(reply, self.store.get_label(&namespace))
+            }
+            ReplicaAction::GetMetadataEntry { reply } => {
+                let query = Query::single_latest_per_key()
+                    .key_exact(metadata::METADATA_KEY)
+                    .build();
+                let res = self
+                    .store
+                    .get_many(namespace, query)
+                    .and_then(|mut iter| iter.next().transpose());
+                send_reply(reply, res)
+            }
+            ReplicaAction::SetHistoryPolicy { policy, reply } => {
+                send_reply(reply, self.store.set_history_policy(&namespace, policy))
+            }
+            ReplicaAction::GetHistoryPolicy { reply } => {
+                send_reply(reply, self.store.get_history_policy(&namespace))
+            }
+            ReplicaAction::SetQuota { quota, reply } => {
+                send_reply(reply, self.store.set_quota(&namespace, quota))
+            }
+            ReplicaAction::GetQuota { reply } => {
+                send_reply(reply, self.store.get_quota(&namespace))
+            }
+            ReplicaAction::CreateIndex { spec, reply } => {
+                send_reply(reply, self.store.create_index(&namespace, spec))
+            }
+            ReplicaAction::RemoveIndex { name, reply } => {
+                send_reply(reply, self.store.remove_index(&namespace, &name))
+            }
+            ReplicaAction::ListIndexes { reply } => {
+                send_reply(reply, self.store.list_indexes(&namespace))
+            }
+            ReplicaAction::IndexContent {
+                hash,
+                content,
+                reply,
+            } => send_reply(reply, self.store.index_content(&namespace, hash, &content)),
+            ReplicaAction::GetByIndex {
+                name,
+                filter,
+                reply,
+            } => {
+                let iter = self
+                    .states
+                    .ensure_open(&namespace)
+                    .and_then(|_| self.store.get_by_index(namespace, &name, filter))
+                    .map(|entries| entries.into_iter().map(Ok));
+                iter_to_channel(reply, iter)
+            }
         }
     }
 
@@ -789,6 +1188,9 @@ impl<S: store::Store> OpenReplicas<S> {
                 if let Some(sender) = opts.subscribe {
                     replica.subscribe(sender);
                 }
+                if let Some(validator) = opts.validator {
+                    replica.set_validator(Some(validator));
+                }
                 debug!(namespace = %namespace.fmt_short(), "open");
                 let state = OpenReplica {
                     replica,
@@ -804,6 +1206,9 @@ impl<S: store::Store> OpenReplicas<S> {
                 if let Some(sender) = opts.subscribe {
                     state.replica.subscribe(sender);
                 }
+                if let Some(validator) = opts.validator {
+                    state.replica.set_validator(Some(validator));
+                }
             }
         }
         Ok(())
@@ -895,4 +1300,36 @@ mod tests {
         assert!(rx.recv_async().await.is_err());
         Ok(())
     }
+
+    #[derive(Debug)]
+    struct RejectAll;
+
+    impl Validator for RejectAll {
+        fn validate(
+            &self,
+            _entry: &SignedEntry,
+            _origin: &crate::InsertOrigin,
+        ) -> Result<(), String> {
+            Err("read only".to_string())
+        }
+    }
+
+    #[tokio::test]
+    async fn open_with_validator() -> anyhow::Result<()> {
+        let store = store::memory::Store::default();
+        let sync = SyncHandle::spawn(store, None, "foo".into());
+        let namespace = NamespaceSecret::new(&mut rand::rngs::OsRng {});
+        let id = namespace.id();
+        let author = sync
+            .import_author(Author::new(&mut rand::rngs::OsRng {}))
+            .await?;
+        sync.import_namespace(namespace.into()).await?;
+        sync.open(id, OpenOpts::default().validator(Arc::new(RejectAll)))
+            .await?;
+        let res = sync
+            .insert_local(id, author, "foo".into(), Hash::new("bar"), 3)
+            .await;
+        assert!(res.is_err());
+        Ok(())
+    }
 }
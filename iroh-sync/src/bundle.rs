@@ -0,0 +1,181 @@
+//! Bundles of entries for offline sync.
+//!
+//! A [`SyncBundle`] contains the signed entries of a replica that a recipient is missing,
+//! relative to the [`AuthorHeads`] the recipient reported, and optionally the content of these
+//! entries. It can be carried to a node without a live connection to the sender, e.g. on
+//! removable media, and imported there. All entries are signed and all content is verified
+//! against its hash, so the recipient does not need to trust the carrier.
+
+use std::collections::BTreeSet;
+
+use bytes::Bytes;
+use iroh_base::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{AuthorHeads, NamespaceId, SignedEntry, ValidationFailure};
+
+/// A bundle of entries and their content for offline sync.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SyncBundle {
+    namespace: NamespaceId,
+    entries: Vec<SignedEntry>,
+    content: Vec<(Hash, Bytes)>,
+}
+
+impl SyncBundle {
+    /// Create a new bundle with the entries of a replica that are missing for the recipient.
+    ///
+    /// An entry is missing if it is newer than the recipient's latest entry of the same author
+    /// in `heads`. Pass empty heads to include all entries.
+    pub fn new(
+        namespace: NamespaceId,
+        entries: impl IntoIterator<Item = SignedEntry>,
+        heads: &AuthorHeads,
+    ) -> Self {
+        let entries = entries
+            .into_iter()
+            .filter(|entry| is_missing(heads, entry))
+            .collect();
+        Self {
+            namespace,
+            entries,
+            content: Vec::new(),
+        }
+    }
+
+    /// Add the content for one of the entries in this bundle.
+    pub fn add_content(&mut self, hash: Hash, content: Bytes) {
+        self.content.push((hash, content));
+    }
+
+    /// Get the namespace of the replica this bundle was created from.
+    pub fn namespace(&self) -> NamespaceId {
+        self.namespace
+    }
+
+    /// Get the entries contained in this bundle.
+    pub fn entries(&self) -> &[SignedEntry] {
+        &self.entries
+    }
+
+    /// Get the content blobs contained in this bundle.
+    pub fn content(&self) -> &[(Hash, Bytes)] {
+        &self.content
+    }
+
+    /// Convert into the entries and content blobs contained in this bundle.
+    pub fn into_parts(self) -> (Vec<SignedEntry>, Vec<(Hash, Bytes)>) {
+        (self.entries, self.content)
+    }
+
+    /// Serialize this bundle to bytes.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(postcard::to_stdvec(self)?)
+    }
+
+    /// Deserialize a bundle from bytes.
+    ///
+    /// This does not verify the bundle, use [`Self::verify`] for that.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+
+    /// Verify that all entries belong to the bundle's namespace and have valid signatures, and
+    /// that all content blobs match their hash and belong to one of the entries.
+    pub fn verify(&self) -> Result<(), InvalidBundle> {
+        let mut hashes = BTreeSet::new();
+        for entry in &self.entries {
+            if entry.namespace() != self.namespace {
+                return Err(InvalidBundle::Entry(ValidationFailure::InvalidNamespace));
+            }
+            if entry.verify(&()).is_err() {
+                return Err(InvalidBundle::Entry(ValidationFailure::BadSignature));
+            }
+            hashes.insert(entry.content_hash());
+        }
+        for (hash, content) in &self.content {
+            if !hashes.contains(hash) {
+                return Err(InvalidBundle::UnknownContent(*hash));
+            }
+            if Hash::new(content) != *hash {
+                return Err(InvalidBundle::ContentMismatch(*hash));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether `entry` is newer than the latest entry of its author in `heads`.
+fn is_missing(heads: &AuthorHeads, entry: &SignedEntry) -> bool {
+    heads
+        .get(&entry.author())
+        .map(|timestamp| entry.timestamp() > timestamp)
+        .unwrap_or(true)
+}
+
+/// Reason why a [`SyncBundle`] failed to verify.
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidBundle {
+    /// An entry is invalid.
+    #[error("Invalid entry: {0}")]
+    Entry(ValidationFailure),
+    /// A content blob is not referenced by any entry of the bundle.
+    #[error("Content {0} does not belong to any entry")]
+    UnknownContent(Hash),
+    /// A content blob does not match its hash.
+    #[error("Content does not match hash {0}")]
+    ContentMismatch(Hash),
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{Author, NamespaceSecret, Record};
+
+    #[test]
+    fn test_bundle_missing_entries_and_verify() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+
+        let entry = |author: &Author, key: &str, data: &[u8], timestamp: u64| {
+            let record = Record::from_data(data, timestamp);
+            SignedEntry::from_parts(&namespace, author, key, record)
+        };
+        let entries = vec![
+            entry(&alice, "a1", b"a1", 1),
+            entry(&alice, "a2", b"a2", 2),
+            entry(&bob, "b1", b"b1", 1),
+        ];
+
+        // the recipient has alice's first entry and nothing of bob
+        let heads = AuthorHeads::from_iter([(alice.id(), 1u64)]);
+        let mut bundle = SyncBundle::new(namespace.id(), entries.clone(), &heads);
+        assert_eq!(bundle.entries(), &entries[1..]);
+        bundle.add_content(Hash::new(b"a2"), Bytes::from_static(b"a2"));
+        bundle.verify()?;
+
+        let decoded = SyncBundle::from_bytes(&bundle.to_bytes()?)?;
+        assert_eq!(decoded, bundle);
+
+        // content that does not match its hash
+        let mut tampered = bundle.clone();
+        tampered.add_content(Hash::new(b"b1"), Bytes::from_static(b"xx"));
+        assert!(matches!(
+            tampered.verify(),
+            Err(InvalidBundle::ContentMismatch(_))
+        ));
+
+        // content that no entry refers to
+        let mut unknown = bundle.clone();
+        unknown.add_content(Hash::new(b"a1"), Bytes::from_static(b"a1"));
+        assert!(matches!(
+            unknown.verify(),
+            Err(InvalidBundle::UnknownContent(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,489 @@
+//! Conflict-free replicated data types layered on documents.
+//!
+//! Entries of a document are last-writer-wins registers per author and key. The types in this
+//! module store a richer value under a key by giving each author its own contribution: the entry
+//! of an author for the key contains the serialized state of that author only, so authors never
+//! overwrite each other. The merged value is computed on read from the [`Contributions`] of all
+//! authors.
+//!
+//! The following types are provided:
+//!
+//! * [`GCounter`], a counter that can only be incremented.
+//! * [`PnCounter`], a counter that can be incremented and decremented.
+//! * [`OrSet`], an observed-remove set in which an add wins over a concurrent remove.
+//! * [`LwwMap`], a map in which the latest write to each key wins.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use bytes::Bytes;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{sync::system_time_now, AuthorId};
+
+/// A conflict-free replicated data type whose state is split into one contribution per author.
+pub trait Crdt: Default + Serialize + DeserializeOwned {
+    /// The merged value.
+    type Value;
+
+    /// Compute the merged value from the contributions of all authors.
+    fn merge<'a>(contributions: impl Iterator<Item = (&'a AuthorId, &'a Self)>) -> Self::Value
+    where
+        Self: 'a;
+}
+
+/// The contributions of all authors to a CRDT value stored under a document key.
+#[derive(Debug, Clone)]
+pub struct Contributions<C> {
+    contributions: BTreeMap<AuthorId, C>,
+}
+
+impl<C> Default for Contributions<C> {
+    fn default() -> Self {
+        Self {
+            contributions: Default::default(),
+        }
+    }
+}
+
+impl<C: Crdt> Contributions<C> {
+    /// Decode the contributions from the content of the entries of each author.
+    pub fn decode<T: AsRef<[u8]>>(
+        entries: impl IntoIterator<Item = (AuthorId, T)>,
+    ) -> anyhow::Result<Self> {
+        let mut contributions = BTreeMap::new();
+        for (author, content) in entries {
+            contributions.insert(author, postcard::from_bytes(content.as_ref())?);
+        }
+        Ok(Self { contributions })
+    }
+
+    /// Encode the contribution of `author`, to be stored as the content of its entry.
+    pub fn encode(&self, author: &AuthorId) -> anyhow::Result<Bytes> {
+        let bytes = match self.contributions.get(author) {
+            Some(contribution) => postcard::to_stdvec(contribution)?,
+            None => postcard::to_stdvec(&C::default())?,
+        };
+        Ok(bytes.into())
+    }
+
+    /// Get the contribution of `author`, if any.
+    pub fn get(&self, author: &AuthorId) -> Option<&C> {
+        self.contributions.get(author)
+    }
+
+    /// Iterate over the authors that contributed.
+    pub fn authors(&self) -> impl Iterator<Item = &AuthorId> {
+        self.contributions.keys()
+    }
+
+    /// Compute the merged value.
+    pub fn value(&self) -> C::Value {
+        C::merge(self.contributions.iter())
+    }
+
+    fn contribution_mut(&mut self, author: AuthorId) -> &mut C {
+        self.contributions.entry(author).or_default()
+    }
+}
+
+/// A grow-only counter.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct GCounter(u64);
+
+impl Crdt for GCounter {
+    type Value = u64;
+
+    fn merge<'a>(contributions: impl Iterator<Item = (&'a AuthorId, &'a Self)>) -> u64 {
+        contributions.fold(0, |sum, (_, counter)| sum.saturating_add(counter.0))
+    }
+}
+
+impl Contributions<GCounter> {
+    /// Increment the counter by `n` as `author`.
+    pub fn increment(&mut self, author: AuthorId, n: u64) {
+        let counter = self.contribution_mut(author);
+        counter.0 = counter.0.saturating_add(n);
+    }
+}
+
+/// A counter that can be incremented and decremented.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PnCounter {
+    increments: u64,
+    decrements: u64,
+}
+
+impl Crdt for PnCounter {
+    type Value = i64;
+
+    fn merge<'a>(contributions: impl Iterator<Item = (&'a AuthorId, &'a Self)>) -> i64 {
+        let (increments, decrements) =
+            contributions.fold((0i128, 0i128), |(inc, dec), (_, counter)| {
+                (
+                    inc + counter.increments as i128,
+                    dec + counter.decrements as i128,
+                )
+            });
+        (increments - decrements).clamp(i64::MIN as i128, i64::MAX as i128) as i64
+    }
+}
+
+impl Contributions<PnCounter> {
+    /// Increment the counter by `n` as `author`.
+    pub fn increment(&mut self, author: AuthorId, n: u64) {
+        let counter = self.contribution_mut(author);
+        counter.increments = counter.increments.saturating_add(n);
+    }
+
+    /// Decrement the counter by `n` as `author`.
+    pub fn decrement(&mut self, author: AuthorId, n: u64) {
+        let counter = self.contribution_mut(author);
+        counter.decrements = counter.decrements.saturating_add(n);
+    }
+}
+
+/// Unique tag of an add operation in an [`OrSet`].
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+struct Tag {
+    author: AuthorId,
+    seq: u64,
+}
+
+/// An observed-remove set.
+///
+/// Each add is tagged uniquely. A remove only removes the adds it observed, so an element that
+/// is concurrently added and removed stays in the set.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "E: Ord + Serialize + DeserializeOwned")]
+pub struct OrSet<E> {
+    /// Sequence numbers of the adds of this author, per element.
+    added: BTreeMap<E, BTreeSet<u64>>,
+    /// Tags of the adds of any author that were removed by this author.
+    removed: BTreeSet<Tag>,
+}
+
+impl<E> Default for OrSet<E> {
+    fn default() -> Self {
+        Self {
+            added: Default::default(),
+            removed: Default::default(),
+        }
+    }
+}
+
+impl<E: Ord + Clone + Serialize + DeserializeOwned> Crdt for OrSet<E> {
+    type Value = BTreeSet<E>;
+
+    fn merge<'a>(contributions: impl Iterator<Item = (&'a AuthorId, &'a Self)>) -> BTreeSet<E>
+    where
+        Self: 'a,
+    {
+        let contributions: Vec<_> = contributions.collect();
+        let removed: BTreeSet<&Tag> = contributions
+            .iter()
+            .flat_map(|(_, set)| set.removed.iter())
+            .collect();
+        let mut value = BTreeSet::new();
+        for (author, set) in contributions {
+            for (element, seqs) in &set.added {
+                let alive = seqs.iter().any(|&seq| {
+                    !removed.contains(&Tag {
+                        author: *author,
+                        seq,
+                    })
+                });
+                if alive {
+                    value.insert(element.clone());
+                }
+            }
+        }
+        value
+    }
+}
+
+impl<E: Ord + Clone + Serialize + DeserializeOwned> Contributions<OrSet<E>> {
+    /// Add an element to the set as `author`.
+    pub fn insert(&mut self, author: AuthorId, element: E) {
+        let set = self.contribution_mut(author);
+        let seq = set
+            .added
+            .values()
+            .flat_map(|seqs| seqs.last())
+            .max()
+            .map_or(0, |seq| seq + 1);
+        set.added.entry(element).or_default().insert(seq);
+    }
+
+    /// Remove an element from the set as `author`.
+    ///
+    /// This removes the adds of the element by all authors that are part of these contributions.
+    pub fn remove(&mut self, author: AuthorId, element: &E) {
+        let observed: Vec<Tag> = self
+            .contributions
+            .iter()
+            .filter_map(|(author, set)| Some((author, set.added.get(element)?)))
+            .flat_map(|(author, seqs)| {
+                seqs.iter().map(|&seq| Tag {
+                    author: *author,
+                    seq,
+                })
+            })
+            .collect();
+        self.contribution_mut(author).removed.extend(observed);
+    }
+}
+
+/// A map in which the latest write to each key wins.
+///
+/// Writes are ordered by timestamp, and by author for equal timestamps. A write always gets a
+/// timestamp greater than the writes to the key it observed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(bound = "K: Ord + Serialize + DeserializeOwned, V: Serialize + DeserializeOwned")]
+pub struct LwwMap<K, V> {
+    /// Latest write of this author per key, with `None` for removals.
+    entries: BTreeMap<K, (u64, Option<V>)>,
+}
+
+impl<K, V> Default for LwwMap<K, V> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+        }
+    }
+}
+
+impl<K, V> Crdt for LwwMap<K, V>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    type Value = BTreeMap<K, V>;
+
+    fn merge<'a>(contributions: impl Iterator<Item = (&'a AuthorId, &'a Self)>) -> BTreeMap<K, V>
+    where
+        Self: 'a,
+    {
+        let mut latest: BTreeMap<&K, ((u64, &AuthorId), &Option<V>)> = BTreeMap::new();
+        for (author, map) in contributions {
+            for (key, (timestamp, value)) in &map.entries {
+                let order = (*timestamp, author);
+                match latest.get(key) {
+                    Some((existing, _)) if *existing >= order => {}
+                    _ => {
+                        latest.insert(key, (order, value));
+                    }
+                }
+            }
+        }
+        latest
+            .into_iter()
+            .filter_map(|(key, (_, value))| Some((key.clone(), value.clone()?)))
+            .collect()
+    }
+}
+
+impl<K, V> Contributions<LwwMap<K, V>>
+where
+    K: Ord + Clone + Serialize + DeserializeOwned,
+    V: Clone + Serialize + DeserializeOwned,
+{
+    /// Set the value for `key` as `author`.
+    pub fn insert(&mut self, author: AuthorId, key: K, value: V) {
+        self.write(author, key, Some(value))
+    }
+
+    /// Remove the value for `key` as `author`.
+    pub fn remove(&mut self, author: AuthorId, key: K) {
+        self.write(author, key, None)
+    }
+
+    fn write(&mut self, author: AuthorId, key: K, value: Option<V>) {
+        let observed = self
+            .contributions
+            .values()
+            .filter_map(|map| map.entries.get(&key))
+            .map(|(timestamp, _)| timestamp + 1)
+            .max()
+            .unwrap_or_default();
+        let timestamp = system_time_now().max(observed);
+        self.contribution_mut(author)
+            .entries
+            .insert(key, (timestamp, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use iroh_base::hash::Hash;
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{
+        store::{self, Query, Store},
+        Author, NamespaceSecret,
+    };
+
+    /// Load the contributions for `key` from a store, with the content looked up in `blobs`.
+    fn load<C: Crdt>(
+        store: &store::memory::Store,
+        namespace: &NamespaceSecret,
+        key: &[u8],
+        blobs: &HashMap<Hash, Bytes>,
+    ) -> anyhow::Result<Contributions<C>> {
+        let entries = store
+            .get_many(namespace.id(), Query::key_exact(key))?
+            .map(|entry| {
+                let entry = entry?;
+                let content = blobs[&entry.content_hash()].clone();
+                anyhow::Ok((entry.author(), content))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Contributions::decode(entries)
+    }
+
+    /// Store the contribution of `author` for `key`.
+    fn save<C: Crdt>(
+        store: &mut store::memory::Store,
+        namespace: &NamespaceSecret,
+        author: &Author,
+        key: &[u8],
+        contributions: &Contributions<C>,
+        blobs: &mut HashMap<Hash, Bytes>,
+    ) -> anyhow::Result<()> {
+        let content = contributions.encode(&author.id())?;
+        let mut replica = store.open_replica(&namespace.id())?;
+        let hash = replica.hash_and_insert(key, author, &content)?;
+        store.close_replica(replica);
+        blobs.insert(hash, content);
+        Ok(())
+    }
+
+    #[test]
+    fn test_counters() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let mut store = store::memory::Store::default();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+        let replica = store.new_replica(namespace.clone())?;
+        store.close_replica(replica);
+        let mut blobs = HashMap::new();
+
+        // each author reads the merged value, updates its own contribution and writes it back
+        let mut counter = load::<GCounter>(&store, &namespace, b"views", &blobs)?;
+        counter.increment(alice.id(), 2);
+        save(
+            &mut store, &namespace, &alice, b"views", &counter, &mut blobs,
+        )?;
+        let mut counter = load::<GCounter>(&store, &namespace, b"views", &blobs)?;
+        counter.increment(bob.id(), 3);
+        save(&mut store, &namespace, &bob, b"views", &counter, &mut blobs)?;
+        let mut counter = load::<GCounter>(&store, &namespace, b"views", &blobs)?;
+        assert_eq!(counter.value(), 5);
+        counter.increment(alice.id(), 1);
+        save(
+            &mut store, &namespace, &alice, b"views", &counter, &mut blobs,
+        )?;
+        let counter = load::<GCounter>(&store, &namespace, b"views", &blobs)?;
+        assert_eq!(counter.value(), 6);
+        assert_eq!(counter.authors().count(), 2);
+
+        let mut counter = load::<PnCounter>(&store, &namespace, b"stock", &blobs)?;
+        counter.increment(alice.id(), 10);
+        counter.decrement(alice.id(), 3);
+        save(
+            &mut store, &namespace, &alice, b"stock", &counter, &mut blobs,
+        )?;
+        // bob decrements concurrently, without having seen alice's contribution
+        let mut bob_counter = Contributions::<PnCounter>::default();
+        bob_counter.decrement(bob.id(), 9);
+        save(
+            &mut store,
+            &namespace,
+            &bob,
+            b"stock",
+            &bob_counter,
+            &mut blobs,
+        )?;
+        let counter = load::<PnCounter>(&store, &namespace, b"stock", &blobs)?;
+        assert_eq!(counter.value(), -2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_or_set() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let mut store = store::memory::Store::default();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+        let replica = store.new_replica(namespace.clone())?;
+        store.close_replica(replica);
+        let mut blobs = HashMap::new();
+
+        let mut set = load::<OrSet<String>>(&store, &namespace, b"tags", &blobs)?;
+        set.insert(alice.id(), "a".to_string());
+        set.insert(alice.id(), "b".to_string());
+        save(&mut store, &namespace, &alice, b"tags", &set, &mut blobs)?;
+
+        // bob removes "a" after observing alice's add
+        let mut bob_set = load::<OrSet<String>>(&store, &namespace, b"tags", &blobs)?;
+        bob_set.remove(bob.id(), &"a".to_string());
+        bob_set.insert(bob.id(), "c".to_string());
+        // concurrently, alice removes and re-adds "b", and adds "a" again
+        set.remove(alice.id(), &"b".to_string());
+        set.insert(alice.id(), "b".to_string());
+        set.insert(alice.id(), "a".to_string());
+        save(&mut store, &namespace, &alice, b"tags", &set, &mut blobs)?;
+        save(&mut store, &namespace, &bob, b"tags", &bob_set, &mut blobs)?;
+
+        // the concurrent add of "a" wins over bob's remove
+        let mut set = load::<OrSet<String>>(&store, &namespace, b"tags", &blobs)?;
+        let expected: BTreeSet<String> = ["a", "b", "c"].map(String::from).into();
+        assert_eq!(set.value(), expected);
+
+        set.remove(bob.id(), &"a".to_string());
+        save(&mut store, &namespace, &bob, b"tags", &set, &mut blobs)?;
+        let set = load::<OrSet<String>>(&store, &namespace, b"tags", &blobs)?;
+        let expected: BTreeSet<String> = ["b", "c"].map(String::from).into();
+        assert_eq!(set.value(), expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lww_map() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let mut store = store::memory::Store::default();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+        let replica = store.new_replica(namespace.clone())?;
+        store.close_replica(replica);
+        let mut blobs = HashMap::new();
+
+        let mut map = load::<LwwMap<String, u32>>(&store, &namespace, b"config", &blobs)?;
+        map.insert(alice.id(), "x".to_string(), 1);
+        map.insert(alice.id(), "y".to_string(), 2);
+        save(&mut store, &namespace, &alice, b"config", &map, &mut blobs)?;
+
+        // bob's writes after observing alice's win, even with a lagging clock
+        let mut map = load::<LwwMap<String, u32>>(&store, &namespace, b"config", &blobs)?;
+        map.contributions
+            .get_mut(&alice.id())
+            .unwrap()
+            .entries
+            .values_mut()
+            .for_each(|(timestamp, _)| *timestamp += 60 * 1_000_000);
+        map.insert(bob.id(), "x".to_string(), 10);
+        map.remove(bob.id(), "y".to_string());
+        save(&mut store, &namespace, &alice, b"config", &map, &mut blobs)?;
+        save(&mut store, &namespace, &bob, b"config", &map, &mut blobs)?;
+
+        let map = load::<LwwMap<String, u32>>(&store, &namespace, b"config", &blobs)?;
+        let expected: BTreeMap<String, u32> = [("x".to_string(), 10)].into();
+        assert_eq!(map.value(), expected);
+        Ok(())
+    }
+}
@@ -3,13 +3,14 @@
 use std::{collections::BTreeMap, num::NonZeroU64};
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
 use crate::AuthorId;
 
 type Timestamp = u64;
 
 /// Timestamps of the latest entry for each author.
-#[derive(Debug, Clone, Eq, PartialEq, Default)]
+#[derive(Debug, Clone, Eq, PartialEq, Default, Serialize, Deserialize)]
 pub struct AuthorHeads {
     heads: BTreeMap<AuthorId, Timestamp>,
 }
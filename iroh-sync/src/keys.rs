@@ -3,7 +3,7 @@
 use std::{cmp::Ordering, fmt, str::FromStr};
 
 use ed25519_dalek::{Signature, SignatureError, Signer, SigningKey, VerifyingKey};
-use iroh_base::base32;
+use iroh_base::{base32, key};
 use rand_core::CryptoRngCore;
 use serde::{Deserialize, Serialize};
 
@@ -33,6 +33,22 @@ impl Author {
         self.signing_key.to_bytes()
     }
 
+    /// Encrypt this [`Author`] with a passphrase, e.g. for a backup.
+    ///
+    /// Use [`Self::from_encrypted_bytes`] with the same passphrase to restore it.
+    pub fn to_encrypted_bytes(&self, passphrase: &str) -> Vec<u8> {
+        key::seal_with_passphrase(passphrase, &self.to_bytes())
+    }
+
+    /// Decrypt a [`Author`] created with [`Self::to_encrypted_bytes`].
+    pub fn from_encrypted_bytes(passphrase: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let bytes = key::open_with_passphrase(passphrase, bytes)?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid key length"))?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
     /// Get the [`AuthorPublicKey`] for this author.
     pub fn public_key(&self) -> AuthorPublicKey {
         AuthorPublicKey(self.signing_key.verifying_key())
@@ -110,6 +126,22 @@ impl NamespaceSecret {
         self.signing_key.to_bytes()
     }
 
+    /// Encrypt this [`NamespaceSecret`] with a passphrase, e.g. for a backup.
+    ///
+    /// Use [`Self::from_encrypted_bytes`] with the same passphrase to restore it.
+    pub fn to_encrypted_bytes(&self, passphrase: &str) -> Vec<u8> {
+        key::seal_with_passphrase(passphrase, &self.to_bytes())
+    }
+
+    /// Decrypt a [`NamespaceSecret`] created with [`Self::to_encrypted_bytes`].
+    pub fn from_encrypted_bytes(passphrase: &str, bytes: &[u8]) -> anyhow::Result<Self> {
+        let bytes = key::open_with_passphrase(passphrase, bytes)?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("invalid key length"))?;
+        Ok(Self::from_bytes(&bytes))
+    }
+
     /// Get the [`NamespacePublicKey`] for this namespace.
     pub fn public_key(&self) -> NamespacePublicKey {
         NamespacePublicKey(self.signing_key.verifying_key())
@@ -26,17 +26,27 @@
 //! implementations. The latter makes use of [`redb`], an embedded key-value store, and persists
 //! the whole store with all replicas to a single file.
 //!
+//! Values richer than a single entry, such as counters and sets that all authors write to, can be
+//! stored with the [conflict-free replicated data types](crdt) of this crate.
+//!
+//! Without a live connection, replicas can be synchronized by carrying a [bundle](bundle) of the
+//! entries the other peer is missing.
+//!
 //! [paper]: https://arxiv.org/abs/2212.13567
 #![deny(missing_docs, rustdoc::broken_intra_doc_links)]
 
 pub mod actor;
+pub mod bundle;
+pub mod crdt;
 mod heads;
 mod keys;
+pub mod metadata;
 #[cfg(feature = "metrics")]
 pub mod metrics;
 #[cfg(feature = "net")]
 pub mod net;
 mod ranger;
+pub mod snapshot;
 pub mod store;
 pub mod sync;
 
@@ -0,0 +1,179 @@
+//! Document metadata.
+//!
+//! The [`DocMetadata`] of a document is stored as a regular entry under [`METADATA_KEY`], so it
+//! is synced along with the document.
+//!
+//! The owner of a namespace is anyone holding its [`NamespaceSecret`], which is every peer with
+//! write access. Owners write the keys starting with [`RESERVED_KEY_PREFIX`] with the
+//! [`owner_author`] derived from the secret, and [`Replica::insert`](crate::Replica::insert)
+//! rejects other authors for these keys. Entries received from other peers are not checked: every
+//! entry is signed with the namespace secret already, and read-only replicas cannot derive the
+//! owner author.
+//!
+//! Documents created before the keys were reserved may contain reserved keys written by other
+//! authors. These entries are kept and synced as before, but can no longer be updated.
+
+use iroh_base::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::{sync::system_time_now, Author, NamespaceSecret};
+
+/// Prefix of the keys reserved for entries written by the namespace owner.
+pub const RESERVED_KEY_PREFIX: &[u8] = b"\0iroh/";
+
+/// Key of the entry that contains the [`DocMetadata`] of a document.
+///
+/// The latest entry for this key is the metadata of the document, whichever author wrote it.
+pub const METADATA_KEY: &[u8] = b"\0iroh/metadata";
+
+/// Human-readable metadata of a document.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DocMetadata {
+    /// Name of the document.
+    pub name: String,
+    /// Description of the document.
+    pub description: Option<String>,
+    /// Time the document was created, in microseconds since the unix epoch.
+    pub created: u64,
+    /// Hash of an icon for the document.
+    pub icon: Option<Hash>,
+}
+
+impl DocMetadata {
+    /// Create new metadata with a name, created now.
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: None,
+            created: system_time_now(),
+            icon: None,
+        }
+    }
+
+    /// Set the description.
+    pub fn with_description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    /// Set the icon.
+    pub fn with_icon(mut self, icon: Hash) -> Self {
+        self.icon = Some(icon);
+        self
+    }
+
+    /// Serialize the metadata to bytes.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(postcard::to_stdvec(self)?)
+    }
+
+    /// Deserialize metadata from bytes.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+}
+
+/// Whether `key` is reserved for entries written by the namespace owner.
+pub fn is_reserved_key(key: &[u8]) -> bool {
+    key.starts_with(RESERVED_KEY_PREFIX)
+}
+
+/// Context for deriving the [`owner_author`] key from the namespace secret.
+const OWNER_AUTHOR_CONTEXT: &str = "iroh-sync 2024-03-01 namespace owner author";
+
+/// The author that writes the reserved entries of a namespace.
+///
+/// Its key is derived from the [`NamespaceSecret`] with a keyed hash, so the namespace key is
+/// never used as an author key, and only owners can sign as this author.
+pub fn owner_author(secret: &NamespaceSecret) -> Author {
+    let key = blake3::derive_key(OWNER_AUTHOR_CONTEXT, &secret.to_bytes());
+    Author::from_bytes(&key)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{
+        store::{self, Query, Store},
+        sync::system_time_now,
+        ContentStatus, Record, SignedEntry,
+    };
+
+    #[test]
+    fn test_metadata_owner_only() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let store = store::memory::Store::default();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+        let mut replica = store.new_replica(namespace.clone())?;
+
+        let owner = owner_author(&namespace);
+        let same_secret = NamespaceSecret::from_bytes(&namespace.to_bytes());
+        assert_eq!(owner.id(), owner_author(&same_secret).id());
+        assert_ne!(owner.to_bytes(), namespace.to_bytes());
+
+        let metadata = DocMetadata::new("notes").with_description("my notes");
+        let decoded = DocMetadata::from_bytes(&metadata.to_bytes()?)?;
+        assert_eq!(decoded, metadata);
+
+        // the owner may write reserved keys
+        replica.hash_and_insert(METADATA_KEY, &owner, metadata.to_bytes()?)?;
+        let entry = store
+            .get_exact(namespace.id(), owner.id(), METADATA_KEY, false)?
+            .expect("metadata entry");
+        assert_eq!(entry.content_hash(), Hash::new(metadata.to_bytes()?));
+
+        // other authors may not
+        assert!(replica
+            .hash_and_insert(METADATA_KEY, &author, b"fake")
+            .is_err());
+        assert!(replica
+            .hash_and_insert(b"\0iroh/other", &author, b"fake")
+            .is_err());
+        replica.hash_and_insert(b"regular", &author, b"value")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_preexisting_reserved_keys() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let store = store::memory::Store::default();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+        let mut replica = store.new_replica(namespace.clone())?;
+
+        // a document created before the keys were reserved, with reserved keys written by a
+        // regular author
+        let record = Record::from_data(b"legacy", system_time_now());
+        let legacy = SignedEntry::from_parts(&namespace, &author, METADATA_KEY, record);
+        replica.insert_remote_entry(legacy, Some([0u8; 32]), ContentStatus::Complete)?;
+        let record = Record::from_data(b"legacy", system_time_now());
+        let legacy = SignedEntry::from_parts(&namespace, &author, b"\0iroh/other", record);
+        replica.insert_remote_entry(legacy, Some([0u8; 32]), ContentStatus::Complete)?;
+        assert!(store
+            .get_exact(namespace.id(), author.id(), b"\0iroh/other", false)?
+            .is_some());
+
+        // the entries can no longer be updated by their author
+        assert!(replica
+            .hash_and_insert(b"\0iroh/other", &author, b"update")
+            .is_err());
+
+        // the metadata written by the owner supersedes the legacy entry
+        let metadata = DocMetadata::new("notes");
+        replica.hash_and_insert(
+            METADATA_KEY,
+            &owner_author(&namespace),
+            metadata.to_bytes()?,
+        )?;
+        let query = Query::single_latest_per_key().key_exact(METADATA_KEY);
+        let entries = store
+            .get_many(namespace.id(), query)?
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content_hash(), Hash::new(metadata.to_bytes()?));
+        Ok(())
+    }
+}
@@ -13,6 +13,8 @@ pub struct Metrics {
     pub new_entries_remote: Counter,
     pub new_entries_local_size: Counter,
     pub new_entries_remote_size: Counter,
+    pub rejected_entries_local: Counter,
+    pub rejected_entries_remote: Counter,
     pub sync_via_connect_success: Counter,
     pub sync_via_connect_failure: Counter,
     pub sync_via_accept_success: Counter,
@@ -26,6 +28,12 @@ impl Default for Metrics {
             new_entries_remote: Counter::new("Number of document entries added by peers"),
             new_entries_local_size: Counter::new("Total size of entry contents added locally"),
             new_entries_remote_size: Counter::new("Total size of entry contents added by peers"),
+            rejected_entries_local: Counter::new(
+                "Number of local document entries rejected by a validator",
+            ),
+            rejected_entries_remote: Counter::new(
+                "Number of document entries from peers rejected by a validator",
+            ),
             sync_via_accept_success: Counter::new("Number of successful syncs (via accept)"),
             sync_via_accept_failure: Counter::new("Number of failed syncs (via accept)"),
             sync_via_connect_success: Counter::new("Number of successful syncs (via connect)"),
@@ -12,7 +12,7 @@ use tracing::{debug, error_span, trace, Instrument};
 use crate::{
     actor::SyncHandle,
     net::codec::{run_alice, BobState},
-    NamespaceId, SyncOutcome,
+    NamespaceId, PendingRanges, SyncOutcome,
 };
 
 #[cfg(feature = "metrics")]
@@ -21,16 +21,23 @@ use crate::metrics::Metrics;
 use iroh_metrics::inc;
 
 /// The ALPN identifier for the iroh-sync protocol
-pub const SYNC_ALPN: &[u8] = b"/iroh-sync/1";
+pub const SYNC_ALPN: &[u8] = b"/iroh-sync/2";
 
 mod codec;
 
 /// Connect to a peer and sync a replica
+///
+/// If `resume` is set to the [`SyncProgress::pending`] ranges of an interrupted sync with this
+/// peer, only those ranges are reconciled again. Ranges that were already reconciled are not
+/// checked for changes that happened in the meantime, so a full sync should follow eventually.
+/// `progress` is called after each round of the reconciliation.
 pub async fn connect_and_sync(
     endpoint: &MagicEndpoint,
     sync: &SyncHandle,
     namespace: NamespaceId,
     peer: NodeAddr,
+    resume: Option<PendingRanges>,
+    progress: impl Fn(SyncProgress),
 ) -> Result<SyncFinished, ConnectError> {
     let t_start = Instant::now();
     let peer_id = peer.node_id;
@@ -46,7 +53,16 @@ pub async fn connect_and_sync(
     let t_connect = t_start.elapsed();
     debug!(?t_connect, "connected");
 
-    let res = run_alice(&mut send_stream, &mut recv_stream, sync, namespace, peer_id).await;
+    let res = run_alice(
+        &mut send_stream,
+        &mut recv_stream,
+        sync,
+        namespace,
+        peer_id,
+        resume,
+        progress,
+    )
+    .await;
 
     send_stream.finish().await.map_err(ConnectError::close)?;
     recv_stream
@@ -69,6 +85,7 @@ pub async fn connect_and_sync(
                 ?t_process,
                 sent = %res.num_sent,
                 recv = %res.num_recv,
+                rejected = %res.num_rejected,
                 "done, ok"
             );
         }
@@ -104,14 +121,18 @@ pub enum AcceptOutcome {
 }
 
 /// Handle an iroh-sync connection and sync all shared documents in the replica store.
-pub async fn handle_connection<F, Fut>(
+///
+/// `progress` is called after each round of the reconciliation.
+pub async fn handle_connection<F, Fut, P>(
     sync: SyncHandle,
     connecting: quinn::Connecting,
     accept_cb: F,
+    progress: P,
 ) -> Result<SyncFinished, AcceptError>
 where
     F: Fn(NamespaceId, PublicKey) -> Fut,
     Fut: Future<Output = AcceptOutcome>,
+    P: Fn(SyncProgress),
 {
     let t_start = Instant::now();
     let connection = connecting.await.map_err(AcceptError::connect)?;
@@ -129,7 +150,13 @@ where
 
     let mut state = BobState::new(peer);
     let res = state
-        .run(&mut send_stream, &mut recv_stream, sync, accept_cb)
+        .run(
+            &mut send_stream,
+            &mut recv_stream,
+            sync,
+            accept_cb,
+            progress,
+        )
         .instrument(span.clone())
         .await;
 
@@ -160,6 +187,7 @@ where
                 ?t_process,
                 sent = %outcome.num_sent,
                 recv = %outcome.num_recv,
+                rejected = %outcome.num_rejected,
                 "done, ok"
             );
         }
@@ -197,6 +225,39 @@ pub struct SyncFinished {
     pub timings: Timings,
 }
 
+/// Progress of a running sync operation.
+#[derive(Debug, Clone)]
+pub struct SyncProgress {
+    /// The namespace that is synced.
+    pub namespace: NamespaceId,
+    /// The peer we sync with.
+    pub peer: PublicKey,
+    /// Number of entries sent so far.
+    pub num_sent: usize,
+    /// Number of entries received so far.
+    pub num_recv: usize,
+    /// Number of ranges that were reconciled so far.
+    pub num_ranges_reconciled: usize,
+    /// Ranges that still need to be reconciled.
+    ///
+    /// Its length is an estimate of the remaining work. If the sync is interrupted, it can be
+    /// resumed from these ranges.
+    pub pending: PendingRanges,
+}
+
+impl SyncProgress {
+    pub(crate) fn new(namespace: NamespaceId, peer: PublicKey, outcome: &SyncOutcome) -> Self {
+        Self {
+            namespace,
+            peer,
+            num_sent: outcome.num_sent,
+            num_recv: outcome.num_recv,
+            num_ranges_reconciled: outcome.num_ranges_reconciled,
+            pending: outcome.pending.clone(),
+        }
+    }
+}
+
 /// Time a sync operation took
 #[derive(Debug, Default, Clone)]
 pub struct Timings {
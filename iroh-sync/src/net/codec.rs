@@ -12,8 +12,9 @@ use tracing::{debug, trace, Span};
 
 use crate::{
     actor::SyncHandle,
-    net::{AbortReason, AcceptError, AcceptOutcome, ConnectError},
-    NamespaceId, SyncOutcome,
+    net::{AbortReason, AcceptError, AcceptOutcome, ConnectError, SyncProgress},
+    store::AreaOfInterest,
+    NamespaceId, PendingRanges, SyncOutcome,
 };
 
 #[derive(Debug, Default)]
@@ -69,7 +70,8 @@ impl Encoder<Message> for SyncCodec {
 
 /// Sync Protocol
 ///
-/// - Init message: signals which namespace is being synced
+/// - Init message: signals which namespace and area of interest is being synced
+/// - Optionally a Restrict message, if the accepting peer only syncs a smaller area
 /// - N Sync messages
 ///
 /// On any error and on success the substream is closed.
@@ -79,9 +81,19 @@ enum Message {
     Init {
         /// Namespace to sync
         namespace: NamespaceId,
+        /// Area of interest of the dialing peer
+        area: AreaOfInterest,
         /// Initial message
         message: crate::sync::ProtocolMessage,
     },
+    /// Restrict message (sent by the accepting peer in reply to the init message, if the
+    /// intersection of both areas of interest is smaller than the area of the dialing peer)
+    Restrict {
+        /// Area of interest to sync
+        area: AreaOfInterest,
+        /// Initial message for the restricted area
+        message: crate::sync::ProtocolMessage,
+    },
     /// Sync messages (sent by both peers)
     Sync(crate::sync::ProtocolMessage),
     /// Abort message (sent by the accepting peer to decline a request)
@@ -89,26 +101,49 @@ enum Message {
 }
 
 /// Runs the initiator side of the sync protocol.
+///
+/// If `resume` contains the pending ranges of an interrupted sync within the current area of
+/// interest, only these ranges are reconciled again.
 pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     writer: &mut W,
     reader: &mut R,
     handle: &SyncHandle,
     namespace: NamespaceId,
     peer: PublicKey,
+    resume: Option<PendingRanges>,
+    progress_cb: impl Fn(SyncProgress),
 ) -> Result<SyncOutcome, ConnectError> {
     let peer_bytes = *peer.as_bytes();
     let mut reader = FramedRead::new(reader, SyncCodec);
     let mut writer = FramedWrite::new(writer, SyncCodec);
 
-    let mut progress = Some(SyncOutcome::default());
+    let area = handle
+        .get_sync_area(namespace)
+        .await
+        .map_err(ConnectError::sync)?;
+    let mut progress = Some(SyncOutcome {
+        area: area.clone(),
+        ..Default::default()
+    });
 
     // Init message
 
-    let message = handle
-        .sync_initial_message(namespace)
-        .await
-        .map_err(ConnectError::sync)?;
-    let init_message = Message::Init { namespace, message };
+    let message = match resume {
+        Some(pending) if pending.area() == &area && !pending.is_empty() => {
+            debug!(ranges = pending.len(), "resume sync");
+            handle.sync_resume_message(namespace, pending).await
+        }
+        _ => handle.sync_initial_message(namespace, area.clone()).await,
+    }
+    .map_err(ConnectError::sync)?;
+    if let Some(progress) = progress.as_mut() {
+        progress.pending = PendingRanges::new(area.clone(), &message);
+    }
+    let init_message = Message::Init {
+        namespace,
+        area,
+        message,
+    };
     trace!("send init message");
     writer
         .send(init_message)
@@ -116,12 +151,26 @@ pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
         .map_err(ConnectError::sync)?;
 
     // Sync message loop
+    let mut is_first_reply = true;
     while let Some(msg) = reader.next().await {
         let msg = msg.map_err(ConnectError::sync)?;
+        let msg = match msg {
+            Message::Restrict { area, message } if is_first_reply => {
+                trace!("recv restrict message");
+                let progress = progress.as_mut().unwrap();
+                progress.area = progress.area.intersection(&area);
+                Message::Sync(message)
+            }
+            msg => msg,
+        };
+        is_first_reply = false;
         match msg {
             Message::Init { .. } => {
                 return Err(ConnectError::sync(anyhow!("unexpected init message")));
             }
+            Message::Restrict { .. } => {
+                return Err(ConnectError::sync(anyhow!("unexpected restrict message")));
+            }
             Message::Sync(msg) => {
                 trace!("recv process message");
                 let current_progress = progress.take().unwrap();
@@ -129,6 +178,7 @@ pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
                     .sync_process_message(namespace, msg, peer_bytes, current_progress)
                     .await
                     .map_err(ConnectError::sync)?;
+                progress_cb(SyncProgress::new(namespace, peer, &next_progress));
                 progress = Some(next_progress);
                 if let Some(msg) = reply {
                     trace!("send process message");
@@ -147,7 +197,10 @@ pub(super) async fn run_alice<R: AsyncRead + Unpin, W: AsyncWrite + Unpin>(
     }
 
     trace!("done");
-    Ok(progress.unwrap())
+    // The remote closed the stream after processing our last message, so nothing is pending.
+    let mut progress = progress.unwrap();
+    progress.pending = PendingRanges::default();
+    Ok(progress)
 }
 
 /// Runs the receiver side of the sync protocol.
@@ -166,7 +219,7 @@ where
     Fut: Future<Output = AcceptOutcome>,
 {
     let mut state = BobState::new(peer);
-    let namespace = state.run(writer, reader, handle, accept_cb).await?;
+    let namespace = state.run(writer, reader, handle, accept_cb, |_| {}).await?;
     Ok((namespace, state.into_outcome()))
 }
 
@@ -192,25 +245,36 @@ impl BobState {
     }
 
     /// Handle connection and run to end.
-    pub async fn run<R, W, F, Fut>(
+    ///
+    /// `progress_cb` is called after each processed sync message.
+    pub async fn run<R, W, F, Fut, P>(
         &mut self,
         writer: W,
         reader: R,
         sync: SyncHandle,
         accept_cb: F,
+        progress_cb: P,
     ) -> Result<NamespaceId, AcceptError>
     where
         R: AsyncRead + Unpin,
         W: AsyncWrite + Unpin,
         F: Fn(NamespaceId, PublicKey) -> Fut,
         Fut: Future<Output = AcceptOutcome>,
+        P: Fn(SyncProgress),
     {
         let mut reader = FramedRead::new(reader, SyncCodec);
         let mut writer = FramedWrite::new(writer, SyncCodec);
         while let Some(msg) = reader.next().await {
             let msg = msg.map_err(|e| self.fail(e))?;
             let next = match (msg, self.namespace.as_ref()) {
-                (Message::Init { namespace, message }, None) => {
+                (
+                    Message::Init {
+                        namespace,
+                        area,
+                        message,
+                    },
+                    None,
+                ) => {
                     Span::current()
                         .record("namespace", tracing::field::display(&namespace.fmt_short()));
                     trace!("recv init message");
@@ -232,17 +296,36 @@ impl BobState {
                             });
                         }
                     }
-                    let last_progress = self.progress.take().unwrap();
-                    let next = sync
-                        .sync_process_message(
-                            namespace,
-                            message,
-                            *self.peer.as_bytes(),
-                            last_progress,
-                        )
-                        .await;
                     self.namespace = Some(namespace);
-                    next
+                    let our_area = sync
+                        .get_sync_area(namespace)
+                        .await
+                        .map_err(|e| self.fail(e))?;
+                    let mut last_progress = self.progress.take().unwrap();
+                    last_progress.area = our_area.intersection(&area);
+                    if last_progress.area != area {
+                        // The dialing peer wants to sync entries outside of our area of interest,
+                        // so restart the reconciliation within the intersection of both areas.
+                        trace!("send restrict message");
+                        let area = last_progress.area.clone();
+                        self.progress = Some(last_progress);
+                        let message = sync
+                            .sync_initial_message(namespace, area.clone())
+                            .await
+                            .map_err(|e| self.fail(e))?;
+                        writer
+                            .send(Message::Restrict { area, message })
+                            .await
+                            .map_err(|e| self.fail(e))?;
+                        continue;
+                    }
+                    sync.sync_process_message(
+                        namespace,
+                        message,
+                        *self.peer.as_bytes(),
+                        last_progress,
+                    )
+                    .await
                 }
                 (Message::Sync(msg), Some(namespace)) => {
                     trace!("recv process message");
@@ -256,11 +339,17 @@ impl BobState {
                 (Message::Sync(_), None) => {
                     return Err(self.fail(anyhow!("unexpected sync message before init")))
                 }
+                (Message::Restrict { .. }, _) => {
+                    return Err(self.fail(anyhow!("unexpected restrict message")))
+                }
                 (Message::Abort { .. }, _) => {
                     return Err(self.fail(anyhow!("unexpected sync abort message")))
                 }
             };
             let (reply, progress) = next.map_err(|e| self.fail(e))?;
+            if let Some(namespace) = self.namespace {
+                progress_cb(SyncProgress::new(namespace, self.peer, &progress));
+            }
             self.progress = Some(progress);
             match reply {
                 Some(msg) => {
@@ -296,7 +385,7 @@ mod tests {
     use crate::{
         actor::OpenOpts,
         store::{self, Query, Store},
-        AuthorId, NamespaceSecret,
+        Author, AuthorId, NamespaceSecret,
     };
     use anyhow::Result;
     use iroh_base::hash::Hash;
@@ -367,6 +456,8 @@ mod tests {
                 &alice_handle2,
                 namespace_id,
                 bob_peer_id,
+                None,
+                |_| {},
             )
             .await
         });
@@ -592,6 +683,8 @@ mod tests {
                 &alice_handle,
                 namespace,
                 bob_node_pubkey,
+                None,
+                |_| {},
             )
             .await
         });
@@ -613,6 +706,98 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_sync_area_memory() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_sync_area(alice_store, bob_store).await
+    }
+
+    #[tokio::test]
+    async fn test_sync_area_fs() -> Result<()> {
+        let _guard = iroh_test::logging::setup();
+        let tmpdir = tempfile::tempdir()?;
+        let alice_store = store::fs::Store::new(tmpdir.path().join("a.db"))?;
+        let bob_store = store::fs::Store::new(tmpdir.path().join("b.db"))?;
+        test_sync_area(alice_store, bob_store).await
+    }
+
+    async fn test_sync_area<S: Store>(alice_store: S, bob_store: S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(99);
+        let alice_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let bob_node_pubkey = SecretKey::generate_with_rng(&mut rng).public();
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+        let mut alice_replica = alice_store.new_replica(namespace.clone())?;
+        let mut bob_replica = bob_store.new_replica(namespace.clone())?;
+
+        let keys = |store: &S| -> Vec<String> {
+            get_messages(store, namespace.id())
+                .into_iter()
+                .map(|(_author, key, _hash)| String::from_utf8(key).unwrap())
+                .collect()
+        };
+
+        // the dialing peer has the smaller area
+        alice_replica.hash_and_insert("photos/a", &author, "a")?;
+        alice_replica.hash_and_insert("docs/a", &author, "a")?;
+        bob_replica.hash_and_insert("photos/b", &author, "b")?;
+        bob_replica.hash_and_insert("docs/b", &author, "b")?;
+        alice_store.set_sync_area(
+            &namespace.id(),
+            AreaOfInterest::full().with_prefix("photos/"),
+        )?;
+        alice_store.close_replica(alice_replica);
+        bob_store.close_replica(bob_replica);
+
+        let alice_handle = SyncHandle::spawn(alice_store.clone(), None, "alice".to_string());
+        let bob_handle = SyncHandle::spawn(bob_store.clone(), None, "bob".to_string());
+        run_sync(
+            alice_handle.clone(),
+            alice_node_pubkey,
+            bob_handle.clone(),
+            bob_node_pubkey,
+            namespace.id(),
+        )
+        .await?;
+        assert_eq!(keys(&alice_store), ["docs/a", "photos/a", "photos/b"]);
+        assert_eq!(keys(&bob_store), ["docs/b", "photos/a", "photos/b"]);
+
+        // the accepting peer has the smaller area
+        alice_handle
+            .set_sync_area(namespace.id(), AreaOfInterest::full())
+            .await?;
+        bob_handle
+            .set_sync_area(
+                namespace.id(),
+                AreaOfInterest::full().with_prefix("photos/2026/"),
+            )
+            .await?;
+        alice_store.import_author(author.clone())?;
+        for key in ["photos/2026/a", "photos/2025/a"] {
+            alice_handle
+                .insert_local(namespace.id(), author.id(), key.into(), Hash::new("a"), 1)
+                .await?;
+        }
+        run_sync(
+            alice_handle.clone(),
+            alice_node_pubkey,
+            bob_handle.clone(),
+            bob_node_pubkey,
+            namespace.id(),
+        )
+        .await?;
+        assert_eq!(
+            keys(&bob_store),
+            ["docs/b", "photos/2026/a", "photos/a", "photos/b"]
+        );
+
+        alice_handle.shutdown().await;
+        bob_handle.shutdown().await;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_sync_timestamps_memory() -> Result<()> {
         let _guard = iroh_test::logging::setup();
@@ -188,6 +188,13 @@ impl<E: RangeEntry> MessagePart<E> {
             MessagePart::RangeItem(RangeItem { values, .. }) => Some(values),
         }
     }
+
+    pub fn range(&self) -> &Range<E::Key> {
+        match self {
+            MessagePart::RangeFingerprint(RangeFingerprint { range, .. }) => range,
+            MessagePart::RangeItem(RangeItem { range, .. }) => range,
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -201,14 +208,33 @@ pub struct Message<E: RangeEntry> {
 
 impl<E: RangeEntry> Message<E> {
     /// Construct the initial message.
-    fn init<S: Store<E>>(store: &S) -> Result<Self, S::Error> {
+    fn init<S: Store<E>>(store: &S, filter: &impl Filter<E>) -> Result<Self, S::Error> {
         let x = store.get_first()?;
         let range = Range::new(x.clone(), x);
-        let fingerprint = store.get_fingerprint(&range)?;
+        let fingerprint = filtered_fingerprint(store, &range, filter)?;
         let part = MessagePart::RangeFingerprint(RangeFingerprint { range, fingerprint });
         Ok(Message { parts: vec![part] })
     }
 
+    /// Construct a message to resume the reconciliation of `ranges`.
+    fn resume<S: Store<E>>(
+        store: &S,
+        ranges: &[Range<E::Key>],
+        filter: &impl Filter<E>,
+    ) -> Result<Self, S::Error> {
+        let parts = ranges
+            .iter()
+            .map(|range| {
+                let fingerprint = filtered_fingerprint(store, range, filter)?;
+                Ok(MessagePart::RangeFingerprint(RangeFingerprint {
+                    range: range.clone(),
+                    fingerprint,
+                }))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Message { parts })
+    }
+
     pub fn parts(&self) -> &[MessagePart<E>] {
         &self.parts
     }
@@ -220,6 +246,11 @@ impl<E: RangeEntry> Message<E> {
     pub fn value_count(&self) -> usize {
         self.values().count()
     }
+
+    /// The ranges of all parts of this message.
+    pub fn ranges(&self) -> impl Iterator<Item = &Range<E::Key>> {
+        self.parts().iter().map(|p| p.range())
+    }
 }
 
 pub trait Store<E: RangeEntry>: Sized {
@@ -281,6 +312,48 @@ pub trait Store<E: RangeEntry>: Sized {
     ) -> Result<usize, Self::Error>;
 }
 
+/// Restricts set reconciliation to a subset of the entries of a store.
+///
+/// Both peers must use the same filter, otherwise their fingerprints will never match.
+pub trait Filter<E: RangeEntry> {
+    /// Returns `true` if the entry is part of the reconciled set.
+    fn includes(&self, entry: &E) -> bool;
+
+    /// Returns `true` if all entries are part of the reconciled set.
+    ///
+    /// This allows to use the fingerprints computed by the store directly.
+    fn includes_all(&self) -> bool;
+}
+
+/// Compute the fingerprint of the entries of `range` that are included in `filter`.
+fn filtered_fingerprint<E: RangeEntry, S: Store<E>>(
+    store: &S,
+    range: &Range<E::Key>,
+    filter: &impl Filter<E>,
+) -> Result<Fingerprint, S::Error> {
+    if filter.includes_all() {
+        return store.get_fingerprint(range);
+    }
+    let mut fingerprint = Fingerprint::empty();
+    for entry in filtered_range(store, range.clone(), filter)? {
+        fingerprint ^= entry?.as_fingerprint();
+    }
+    Ok(fingerprint)
+}
+
+/// Get the entries of `range` that are included in `filter`.
+fn filtered_range<'a, E: RangeEntry + 'a, S: Store<E>>(
+    store: &'a S,
+    range: Range<E::Key>,
+    filter: &'a impl Filter<E>,
+) -> Result<impl Iterator<Item = Result<E, S::Error>> + 'a, S::Error> {
+    let iter = store.get_range(range)?;
+    Ok(iter.filter(move |entry| match entry {
+        Ok(entry) => filter.includes(entry),
+        Err(_) => true,
+    }))
+}
+
 #[derive(Debug)]
 pub struct Peer<E: RangeEntry, S: Store<E>> {
     pub(crate) store: S,
@@ -323,14 +396,33 @@ where
         }
     }
 
-    /// Generates the initial message.
-    pub fn initial_message(&self) -> Result<Message<E>, S::Error> {
-        Message::init(&self.store)
+    /// Generates the initial message, reconciling the entries included in `filter`.
+    pub fn initial_message(&self, filter: &impl Filter<E>) -> Result<Message<E>, S::Error> {
+        Message::init(&self.store, filter)
+    }
+
+    /// Generates a message that resumes an interrupted reconciliation of `ranges`.
+    ///
+    /// The ranges should be the ranges of the last message sent in the interrupted
+    /// reconciliation. If empty, the initial message is generated.
+    pub fn resume_message(
+        &self,
+        ranges: &[Range<E::Key>],
+        filter: &impl Filter<E>,
+    ) -> Result<Message<E>, S::Error> {
+        if ranges.is_empty() {
+            Message::init(&self.store, filter)
+        } else {
+            Message::resume(&self.store, ranges, filter)
+        }
     }
 
     /// Processes an incoming message and produces a response.
     /// If terminated, returns `None`
     ///
+    /// Only entries included in `filter` are reconciled. Incoming entries that are not included
+    /// are dropped.
+    ///
     /// `validate_cb` is called for each incoming entry received from the remote.
     /// It must return true if the entry is valid and should be stored, and false otherwise
     /// (which means the entry will be dropped and not stored).
@@ -344,6 +436,7 @@ where
     pub fn process_message<F, F2, F3>(
         &mut self,
         message: Message<E>,
+        filter: &impl Filter<E>,
         validate_cb: F,
         mut on_insert_cb: F2,
         content_status_cb: F3,
@@ -383,8 +476,7 @@ where
                     // we get the range of the item form our store. from this set, we remove all
                     // entries that whose key is contained in the peer's set and where our value is
                     // lower than the peer entry's value.
-                    self.store
-                        .get_range(range.clone())?
+                    filtered_range(&self.store, range.clone(), filter)?
                         .filter_map(|our_entry| match our_entry {
                             Ok(our_entry) => {
                                 if !values.iter().any(|(their_entry, _)| {
@@ -410,7 +502,7 @@ where
 
             // Store incoming values
             for (entry, content_status) in values {
-                if validate_cb(&self.store, &entry, content_status) {
+                if filter.includes(&entry) && validate_cb(&self.store, &entry, content_status) {
                     // TODO: Get rid of the clone?
                     let outcome = self.put(entry.clone())?;
                     if let InsertOutcome::Inserted { .. } = outcome {
@@ -432,7 +524,7 @@ where
 
         // Process fingerprint messages
         for RangeFingerprint { range, fingerprint } in fingerprints {
-            let local_fingerprint = self.store.get_fingerprint(&range)?;
+            let local_fingerprint = filtered_fingerprint(&self.store, &range, filter)?;
             // Case1 Match, nothing to do
             if local_fingerprint == fingerprint {
                 continue;
@@ -442,10 +534,8 @@ where
             // TODO: This is hugely inefficient and needs to be optimized
             // For an identity range that includes everything we allocate a vec with all entries of
             // the replica here.
-            let local_values: Vec<_> = self
-                .store
-                .get_range(range.clone())?
-                .collect::<Result<_, _>>()?;
+            let local_values: Vec<_> =
+                filtered_range(&self.store, range.clone(), filter)?.collect::<Result<_, _>>()?;
             if local_values.len() <= 1 || fingerprint == Fingerprint::empty() {
                 let values = local_values
                     .into_iter()
@@ -530,12 +620,13 @@ where
 
                 let mut non_empty = 0;
                 for range in ranges {
-                    let chunk: Vec<_> = self.store.get_range(range.clone())?.collect();
+                    let chunk: Vec<_> =
+                        filtered_range(&self.store, range.clone(), filter)?.collect();
                     if !chunk.is_empty() {
                         non_empty += 1;
                     }
                     // Add either the fingerprint or the item set
-                    let fingerprint = self.store.get_fingerprint(&range)?;
+                    let fingerprint = filtered_fingerprint(&self.store, &range, filter)?;
                     if chunk.len() > self.max_set_size {
                         out.push(MessagePart::RangeFingerprint(RangeFingerprint {
                             range: range.clone(),
@@ -642,6 +733,32 @@ mod tests {
 
     use super::*;
 
+    /// Filter that includes all entries.
+    struct All;
+
+    impl<E: RangeEntry> Filter<E> for All {
+        fn includes(&self, _entry: &E) -> bool {
+            true
+        }
+
+        fn includes_all(&self) -> bool {
+            true
+        }
+    }
+
+    /// Filter that includes all entries whose key starts with a prefix.
+    struct KeyPrefix(&'static str);
+
+    impl<V: RangeValue> Filter<(&'static str, V)> for KeyPrefix {
+        fn includes(&self, entry: &(&'static str, V)) -> bool {
+            entry.0.starts_with(self.0)
+        }
+
+        fn includes_all(&self) -> bool {
+            false
+        }
+    }
+
     #[derive(Debug)]
     struct SimpleStore<K, V> {
         data: BTreeMap<K, V>,
@@ -741,8 +858,11 @@ mod tests {
             Ok(())
         }
 
-        type RangeIterator<'a> = SimpleRangeIterator<'a, K, V>
-        where K: 'a, V: 'a;
+        type RangeIterator<'a>
+            = SimpleRangeIterator<'a, K, V>
+        where
+            K: 'a,
+            V: 'a;
         /// Returns all items in the given range
         fn get_range(&self, range: Range<K>) -> Result<Self::RangeIterator<'_>, Self::Error> {
             // TODO: this is not very efficient, optimize depending on data structure
@@ -876,6 +996,36 @@ mod tests {
         assert!(res.bob_to_alice[1].parts[1].is_range_item());
     }
 
+    #[test]
+    fn test_filtered() {
+        let alice_set = [("ape", 1), ("bee", 1), ("cat", 1), ("bog", 1)];
+        let bob_set = [("bat", 1), ("bee", 2), ("cow", 1), ("dog", 1)];
+
+        let mut alice = Peer::default();
+        for e in alice_set {
+            alice.put(e).unwrap();
+        }
+        let mut bob = Peer::default();
+        for e in bob_set {
+            bob.put(e).unwrap();
+        }
+
+        let validate = |_: &SimpleStore<_, _>, _: &_, _| true;
+        let res = sync_exchange_messages(alice, bob, &KeyPrefix("b"), validate, validate, 100);
+        res.assert_alice_set(
+            "filtered",
+            &[("ape", 1), ("bat", 1), ("bee", 2), ("bog", 1), ("cat", 1)],
+        );
+        res.assert_bob_set(
+            "filtered",
+            &[("bat", 1), ("bee", 2), ("bog", 1), ("cow", 1), ("dog", 1)],
+        );
+        // only entries in the filter were sent
+        for msg in res.alice_to_bob.iter().chain(res.bob_to_alice.iter()) {
+            assert!(msg.values().all(|(e, _)| e.0.starts_with('b')));
+        }
+    }
+
     #[test]
     fn test_paper_2() {
         let alice_set = [
@@ -1089,7 +1239,7 @@ mod tests {
         }
 
         // run sync with a validate callback returning false, so no new entries are stored on either side
-        let res = sync_exchange_messages(alice, bob, &validate_alice, &validate_bob, 100);
+        let res = sync_exchange_messages(alice, bob, &All, &validate_alice, &validate_bob, 100);
         res.assert_alice_set("unchanged", &alice_set);
         res.assert_bob_set("unchanged", &bob_set);
 
@@ -1271,7 +1421,7 @@ mod tests {
             expected_set.into_iter().collect::<Vec<_>>()
         };
 
-        let res = sync_exchange_messages(alice, bob, alice_validate_cb, bob_validate_cb, 100);
+        let res = sync_exchange_messages(alice, bob, &All, alice_validate_cb, bob_validate_cb, 100);
 
         let alice_now: Vec<_> = res.alice.all().unwrap().collect::<Result<_, _>>().unwrap();
         if alice_now != expected_set {
@@ -1330,6 +1480,7 @@ mod tests {
     fn sync_exchange_messages<K, V, F1, F2>(
         mut alice: Peer<(K, V), SimpleStore<K, V>>,
         mut bob: Peer<(K, V), SimpleStore<K, V>>,
+        filter: &impl Filter<(K, V)>,
         alice_validate_cb: F1,
         bob_validate_cb: F2,
         max_rounds: usize,
@@ -1342,7 +1493,7 @@ mod tests {
     {
         let mut alice_to_bob = Vec::new();
         let mut bob_to_alice = Vec::new();
-        let initial_message = alice.initial_message().unwrap();
+        let initial_message = alice.initial_message(filter).unwrap();
 
         let mut next_to_bob = Some(initial_message);
         let mut rounds = 0;
@@ -1354,6 +1505,7 @@ mod tests {
             if let Some(msg) = bob
                 .process_message(
                     msg,
+                    filter,
                     &bob_validate_cb,
                     |_, _, _| (),
                     |_, _| ContentStatus::Complete,
@@ -1364,6 +1516,7 @@ mod tests {
                 next_to_bob = alice
                     .process_message(
                         msg,
+                        filter,
                         &alice_validate_cb,
                         |_, _, _| (),
                         |_, _| ContentStatus::Complete,
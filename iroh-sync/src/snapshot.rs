@@ -0,0 +1,124 @@
+//! Snapshots of all entries of a replica.
+//!
+//! A [`Snapshot`] contains the signed entries of a replica at a point in time. It can be
+//! serialized to bytes to archive a document or to transfer it without live sync. Because all
+//! entries are signed, a snapshot can be verified by the receiver without trusting the sender.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{NamespaceId, SignedEntry, ValidationFailure};
+
+/// Name of the snapshot blob in a collection that contains a snapshot and its content.
+pub const SNAPSHOT_BLOB_NAME: &str = "snapshot.iroh-sync";
+
+/// A snapshot of all entries of a replica.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Snapshot {
+    namespace: NamespaceId,
+    entries: Vec<SignedEntry>,
+}
+
+impl Snapshot {
+    /// Create a new snapshot from the entries of a replica.
+    pub fn new(namespace: NamespaceId, entries: Vec<SignedEntry>) -> Self {
+        Self { namespace, entries }
+    }
+
+    /// Get the namespace of the replica this snapshot was taken from.
+    pub fn namespace(&self) -> NamespaceId {
+        self.namespace
+    }
+
+    /// Get the entries contained in this snapshot.
+    pub fn entries(&self) -> &[SignedEntry] {
+        &self.entries
+    }
+
+    /// Convert into the entries contained in this snapshot.
+    pub fn into_entries(self) -> Vec<SignedEntry> {
+        self.entries
+    }
+
+    /// Serialize this snapshot to bytes.
+    pub fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        Ok(postcard::to_stdvec(self)?)
+    }
+
+    /// Deserialize a snapshot from bytes.
+    ///
+    /// This does not verify the entries, use [`Self::verify`] for that.
+    pub fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        Ok(postcard::from_bytes(bytes)?)
+    }
+
+    /// Verify that all entries belong to the snapshot's namespace and have valid signatures.
+    pub fn verify(&self) -> Result<(), ValidationFailure> {
+        for entry in &self.entries {
+            if entry.namespace() != self.namespace {
+                return Err(ValidationFailure::InvalidNamespace);
+            }
+            if entry.verify(&()).is_err() {
+                return Err(ValidationFailure::BadSignature);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_core::SeedableRng;
+
+    use super::*;
+    use crate::{Author, Entry, NamespaceSecret, Record, RecordIdentifier};
+
+    #[test]
+    fn test_snapshot_roundtrip_and_verify() -> anyhow::Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let other_namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+
+        let entries = (0..3u64)
+            .map(|i| {
+                let record = Record::from_data(i.to_be_bytes(), i + 1);
+                SignedEntry::from_parts(&namespace, &author, format!("key{i}"), record)
+            })
+            .collect::<Vec<_>>();
+        let snapshot = Snapshot::new(namespace.id(), entries.clone());
+        snapshot.verify()?;
+
+        let bytes = snapshot.to_bytes()?;
+        let decoded = Snapshot::from_bytes(&bytes)?;
+        assert_eq!(decoded, snapshot);
+        assert_eq!(decoded.namespace(), namespace.id());
+        assert_eq!(decoded.into_entries(), entries);
+
+        // entry from another namespace
+        let foreign = SignedEntry::from_parts(
+            &other_namespace,
+            &author,
+            "foreign",
+            Record::from_data(b"x", 1),
+        );
+        let snapshot = Snapshot::new(namespace.id(), vec![foreign]);
+        assert!(matches!(
+            snapshot.verify(),
+            Err(ValidationFailure::InvalidNamespace)
+        ));
+
+        // entry with a signature that does not match its content
+        let signed = entries[0].clone();
+        let id = RecordIdentifier::new(namespace.id(), author.id(), "tampered");
+        let tampered = SignedEntry::new(
+            signed.signature().clone(),
+            Entry::new(id, signed.entry().record().clone()),
+        );
+        let snapshot = Snapshot::new(namespace.id(), vec![tampered]);
+        assert!(matches!(
+            snapshot.verify(),
+            Err(ValidationFailure::BadSignature)
+        ));
+        Ok(())
+    }
+}
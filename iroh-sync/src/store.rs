@@ -1,6 +1,11 @@
 //! Storage trait and implementation for iroh-sync documents
 
-use std::num::{NonZeroU64, NonZeroUsize};
+use std::{
+    collections::BTreeSet,
+    num::{NonZeroU64, NonZeroUsize},
+    ops::{Bound, RangeBounds},
+    time::Duration,
+};
 
 use anyhow::Result;
 use bytes::Bytes;
@@ -49,6 +54,8 @@ pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
     type Instance: ranger::Store<SignedEntry>
         + PublicKeyStore
         + DownloadPolicyStore
+        + HistoryPolicyStore
+        + QuotaStore
         + Send
         + Sync
         + 'static
@@ -127,6 +134,11 @@ pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
     /// Import an author key pair.
     fn import_author(&self, author: Author) -> Result<()>;
 
+    /// Delete an author key pair.
+    ///
+    /// Entries signed by the author are kept. Returns `false` if the author was not found.
+    fn delete_author(&self, author: &AuthorId) -> Result<bool>;
+
     /// List all author keys in this store.
     fn list_authors(&self) -> Result<Self::AuthorsIter<'_>>;
 
@@ -149,12 +161,36 @@ pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
         include_empty: bool,
     ) -> Result<Option<SignedEntry>>;
 
+    /// Count the entries of a replica that match a query.
+    ///
+    /// The limit and offset of the query are applied, so this returns the number of entries that
+    /// [`Self::get_many`] would return for the same query.
+    fn count(&self, namespace: NamespaceId, query: impl Into<Query>) -> Result<u64> {
+        let mut count = 0;
+        for entry in self.get_many(namespace, query)? {
+            entry?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
     /// Get all content hashes of all replicas in the store.
     fn content_hashes(&self) -> Result<Self::ContentHashesIter<'_>>;
 
     /// Get the latest entry for each author in a namespace.
     fn get_latest_for_each_author(&self, namespace: NamespaceId) -> Result<Self::LatestIter<'_>>;
 
+    /// Get the timestamp of the latest entry for each author in a namespace.
+    fn get_author_heads(&self, namespace: NamespaceId) -> Result<AuthorHeads> {
+        let latest = self.get_latest_for_each_author(namespace)?;
+        let mut heads = AuthorHeads::default();
+        for e in latest {
+            let (author, timestamp, _key) = e?;
+            heads.insert(author, timestamp);
+        }
+        Ok(heads)
+    }
+
     /// Check if a [`AuthorHeads`] contains entry timestamps that we do not have locally.
     ///
     /// Returns the number of authors that the other peer has updates for.
@@ -163,15 +199,7 @@ pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
         namespace: NamespaceId,
         heads: &AuthorHeads,
     ) -> Result<Option<NonZeroU64>> {
-        let our_heads = {
-            let latest = self.get_latest_for_each_author(namespace)?;
-            let mut heads = AuthorHeads::default();
-            for e in latest {
-                let (author, timestamp, _key) = e?;
-                heads.insert(author, timestamp);
-            }
-            heads
-        };
+        let our_heads = self.get_author_heads(namespace)?;
         let has_news_for_us = heads.has_news_for(&our_heads);
         Ok(has_news_for_us)
     }
@@ -186,6 +214,66 @@ pub trait Store: std::fmt::Debug + Clone + Send + Sync + 'static {
     fn set_download_policy(&self, namespace: &NamespaceId, policy: DownloadPolicy) -> Result<()>;
     /// Get the download policy for a document.
     fn get_download_policy(&self, namespace: &NamespaceId) -> Result<DownloadPolicy>;
+
+    /// Set the area of interest that is reconciled when syncing a document.
+    fn set_sync_area(&self, namespace: &NamespaceId, area: AreaOfInterest) -> Result<()>;
+    /// Get the area of interest that is reconciled when syncing a document.
+    fn get_sync_area(&self, namespace: &NamespaceId) -> Result<AreaOfInterest>;
+
+    /// Set a label for a document, or remove it with `None`.
+    ///
+    /// Labels are only stored locally and are not synced.
+    fn set_label(&self, namespace: &NamespaceId, label: Option<String>) -> Result<()>;
+    /// Get the label of a document.
+    fn get_label(&self, namespace: &NamespaceId) -> Result<Option<String>>;
+
+    /// Set the history policy for a document.
+    ///
+    /// The policy only applies to entries superseded after it was set. Existing history is pruned
+    /// the next time a new version for the same key and author is stored.
+    fn set_history_policy(&self, namespace: &NamespaceId, policy: HistoryPolicy) -> Result<()>;
+    /// Get the history policy for a document.
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy>;
+
+    /// Set the quota for a document.
+    ///
+    /// The quota only applies to entries inserted after it was set. Existing entries are never
+    /// removed because of a quota.
+    fn set_quota(&self, namespace: &NamespaceId, quota: Quota) -> Result<()>;
+    /// Get the quota for a document.
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota>;
+    /// Get the number and total content length of the non-empty entries in a document.
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage>;
+
+    /// Create a secondary index on a document.
+    ///
+    /// Values extracted from keys are indexed for all existing entries right away. Values
+    /// extracted from content are only indexed once the content is passed to
+    /// [`Store::index_content`]. Creating an index with the name of an existing index replaces it.
+    fn create_index(&self, namespace: &NamespaceId, spec: IndexSpec) -> Result<()>;
+
+    /// Remove a secondary index from a document.
+    ///
+    /// Returns `true` if the index existed.
+    fn remove_index(&self, namespace: &NamespaceId, name: &str) -> Result<bool>;
+
+    /// List the secondary indexes of a document.
+    fn list_indexes(&self, namespace: &NamespaceId) -> Result<Vec<IndexSpec>>;
+
+    /// Update the content based indexes of a document for all entries with content `hash`.
+    ///
+    /// Returns the number of entries for which values were indexed.
+    fn index_content(&self, namespace: &NamespaceId, hash: Hash, content: &[u8]) -> Result<usize>;
+
+    /// Get the entries whose value in the index `name` matches `filter`.
+    ///
+    /// Entries are returned ordered by indexed value, then author, then key.
+    fn get_by_index(
+        &self,
+        namespace: NamespaceId,
+        name: &str,
+        filter: KeyFilter,
+    ) -> Result<Vec<SignedEntry>>;
 }
 
 /// Store that gives read access to download policies for a document.
@@ -200,6 +288,35 @@ impl<T: Store> DownloadPolicyStore for T {
     }
 }
 
+/// Store that gives read access to history policies for a document.
+pub trait HistoryPolicyStore {
+    /// Get the history policy for a document.
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy>;
+}
+
+impl<T: Store> HistoryPolicyStore for T {
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy> {
+        <T as Store>::get_history_policy(self, namespace)
+    }
+}
+
+/// Store that gives read access to quotas and usage for a document.
+pub trait QuotaStore {
+    /// Get the quota for a document.
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota>;
+    /// Get the number and total content length of the non-empty entries in a document.
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage>;
+}
+
+impl<T: Store> QuotaStore for T {
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota> {
+        <T as Store>::get_quota(self, namespace)
+    }
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage> {
+        <T as Store>::get_usage(self, namespace)
+    }
+}
+
 /// Outcome of [`Store::import_namespace`]
 #[derive(Debug, Clone, Copy)]
 pub enum ImportNamespaceOutcome {
@@ -226,6 +343,106 @@ impl Default for DownloadPolicy {
     }
 }
 
+/// Area of interest of a document, which restricts the entries that are reconciled during sync.
+///
+/// Only entries whose key starts with one of the prefixes and whose author is one of the authors
+/// are synced. Entries whose key is itself a prefix of one of the prefixes are synced as well, so
+/// that deleting a parent key is applied within the area.
+///
+/// When two peers sync, they reconcile the intersection of their areas.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AreaOfInterest {
+    /// Key prefixes to sync. `None` means all keys.
+    pub prefixes: Option<BTreeSet<Bytes>>,
+    /// Authors to sync. `None` means all authors.
+    pub authors: Option<BTreeSet<AuthorId>>,
+}
+
+impl AreaOfInterest {
+    /// The area that includes all entries of a document.
+    pub fn full() -> Self {
+        Self::default()
+    }
+
+    /// Add a key prefix to the area.
+    pub fn with_prefix(mut self, prefix: impl Into<Bytes>) -> Self {
+        self.prefixes
+            .get_or_insert_with(Default::default)
+            .insert(prefix.into());
+        self
+    }
+
+    /// Add an author to the area.
+    pub fn with_author(mut self, author: AuthorId) -> Self {
+        self.authors
+            .get_or_insert_with(Default::default)
+            .insert(author);
+        self
+    }
+
+    /// Returns `true` if the area includes all entries.
+    pub fn is_full(&self) -> bool {
+        self.prefixes.is_none() && self.authors.is_none()
+    }
+
+    /// Returns `true` if the entry is within this area.
+    pub fn includes(&self, entry: &Entry) -> bool {
+        if let Some(authors) = &self.authors {
+            if !authors.contains(&entry.author()) {
+                return false;
+            }
+        }
+        match &self.prefixes {
+            None => true,
+            Some(prefixes) => {
+                let key = entry.key();
+                prefixes
+                    .iter()
+                    .any(|prefix| key.starts_with(prefix) || prefix.starts_with(key))
+            }
+        }
+    }
+
+    /// Get the area that is included in both `self` and `other`.
+    pub fn intersection(&self, other: &Self) -> Self {
+        let authors = match (&self.authors, &other.authors) {
+            (None, None) => None,
+            (Some(authors), None) | (None, Some(authors)) => Some(authors.clone()),
+            (Some(a), Some(b)) => Some(a.intersection(b).copied().collect()),
+        };
+        let prefixes = match (&self.prefixes, &other.prefixes) {
+            (None, None) => None,
+            (Some(prefixes), None) | (None, Some(prefixes)) => Some(prefixes.clone()),
+            (Some(a), Some(b)) => {
+                // For each pair of prefixes where one extends the other, the longer prefix is
+                // within both areas.
+                let mut prefixes = BTreeSet::new();
+                for x in a {
+                    for y in b {
+                        if x.starts_with(y) {
+                            prefixes.insert(x.clone());
+                        } else if y.starts_with(x) {
+                            prefixes.insert(y.clone());
+                        }
+                    }
+                }
+                Some(prefixes)
+            }
+        };
+        Self { prefixes, authors }
+    }
+}
+
+impl ranger::Filter<SignedEntry> for AreaOfInterest {
+    fn includes(&self, entry: &SignedEntry) -> bool {
+        AreaOfInterest::includes(self, entry.entry())
+    }
+
+    fn includes_all(&self) -> bool {
+        self.is_full()
+    }
+}
+
 /// Filter strategy used in download policies.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum FilterKind {
@@ -313,12 +530,203 @@ impl DownloadPolicy {
     }
 }
 
+/// History policy to decide which superseded entries of a document are kept.
+///
+/// Superseded entries are entries that were replaced by a newer entry for the same key and author,
+/// or removed by a newer entry for a prefix of their key. The history is stored locally only and
+/// never synced to other peers.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum HistoryPolicy {
+    /// Do not keep any superseded entries.
+    #[default]
+    Disabled,
+    /// Keep the last `n` superseded entries for each key and author.
+    KeepLast(u64),
+    /// Keep superseded entries whose timestamp is not older than the given duration.
+    KeepNewerThan(Duration),
+}
+
+impl HistoryPolicy {
+    /// Returns `true` if superseded entries should be kept at all.
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            HistoryPolicy::Disabled => false,
+            HistoryPolicy::KeepLast(n) => *n > 0,
+            HistoryPolicy::KeepNewerThan(_) => true,
+        }
+    }
+
+    /// Check if a superseded entry should be kept according to this policy.
+    ///
+    /// `index` is the position of the entry among the superseded entries for its key and author,
+    /// counted from the most recent one, which has index 0. `timestamp` is the timestamp of the
+    /// entry and `now` the current time, both in microseconds since the Unix epoch.
+    pub fn retains(&self, index: u64, timestamp: u64, now: u64) -> bool {
+        match self {
+            HistoryPolicy::Disabled => false,
+            HistoryPolicy::KeepLast(n) => index < *n,
+            HistoryPolicy::KeepNewerThan(max_age) => {
+                let max_age = max_age.as_micros().try_into().unwrap_or(u64::MAX);
+                timestamp >= now.saturating_sub(max_age)
+            }
+        }
+    }
+}
+
+/// Limits on how much can be written into a document.
+///
+/// Quotas are enforced for local and remote inserts. Entries that exceed a quota are rejected
+/// with [`crate::ValidationFailure::QuotaExceeded`]. Empty entries, which delete other entries,
+/// are never rejected.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Quota {
+    /// Maximum number of non-empty entries in the document.
+    pub max_entries: Option<u64>,
+    /// Maximum sum of the content lengths of all entries in the document.
+    pub max_content_len: Option<u64>,
+    /// Maximum rate at which a single author may insert entries.
+    ///
+    /// The rate is tracked in memory while the document is open, by the local time at which
+    /// entries are inserted.
+    pub max_author_rate: Option<RateLimit>,
+}
+
+impl Quota {
+    /// Returns `true` if the document or entry size are limited by this quota.
+    pub fn limits_size(&self) -> bool {
+        self.max_entries.is_some() || self.max_content_len.is_some()
+    }
+}
+
+/// The number and total content length of the non-empty entries in a document.
+///
+/// Stores keep this up to date on every insert and removal, so that [`Quota`]s can be checked
+/// without iterating the document.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct Usage {
+    /// Number of non-empty entries.
+    pub entries: u64,
+    /// Sum of the content lengths of all entries.
+    pub content_len: u64,
+}
+
+impl Usage {
+    /// Count an entry which was added to the document.
+    pub(crate) fn add(&mut self, entry: &SignedEntry) {
+        if !entry.is_empty() {
+            self.entries += 1;
+            self.content_len += entry.content_len();
+        }
+    }
+
+    /// Stop counting an entry which was removed from the document.
+    pub(crate) fn remove(&mut self, entry: &SignedEntry) {
+        if !entry.is_empty() {
+            self.entries = self.entries.saturating_sub(1);
+            self.content_len = self.content_len.saturating_sub(entry.content_len());
+        }
+    }
+}
+
+/// A maximum number of entries within a time interval.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Maximum number of entries within `interval`.
+    pub entries: u64,
+    /// Length of the sliding time window.
+    pub interval: Duration,
+}
+
+/// Maximum length of a value in a secondary index.
+///
+/// Longer values are not indexed.
+pub const MAX_INDEX_VALUE_LEN: usize = 1024;
+
+/// Definition of a secondary index on a document.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IndexSpec {
+    /// Name of the index, unique within a document.
+    pub name: String,
+    /// How to extract the indexed value from an entry.
+    pub extractor: IndexExtractor,
+}
+
+impl IndexSpec {
+    /// Create a new index definition.
+    pub fn new(name: impl Into<String>, extractor: IndexExtractor) -> Self {
+        Self {
+            name: name.into(),
+            extractor,
+        }
+    }
+}
+
+/// Extracts the value to index from the key or content of an entry.
+///
+/// Entries for which no value can be extracted, and empty entries, are not part of the index.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum IndexExtractor {
+    /// The segment at `position` of the key split at `separator`.
+    KeySegment {
+        /// Byte that separates the segments of the key.
+        separator: u8,
+        /// Position of the segment, starting at zero.
+        position: u32,
+    },
+    /// The full content of the entry.
+    Content,
+    /// A field of JSON content, addressed by a path of object keys.
+    ///
+    /// Strings are indexed as their UTF-8 bytes, all other values as their JSON text.
+    JsonField(Vec<String>),
+}
+
+impl IndexExtractor {
+    /// Returns `true` if values are extracted from the content of entries.
+    pub fn uses_content(&self) -> bool {
+        match self {
+            IndexExtractor::KeySegment { .. } => false,
+            IndexExtractor::Content | IndexExtractor::JsonField(_) => true,
+        }
+    }
+
+    /// Extract the value to index from an entry's key, or from its content if available.
+    pub fn extract(&self, key: &[u8], content: Option<&[u8]>) -> Option<Bytes> {
+        let value = match self {
+            IndexExtractor::KeySegment {
+                separator,
+                position,
+            } => {
+                let segment = key
+                    .split(|b| b == separator)
+                    .nth(usize::try_from(*position).ok()?)?;
+                Bytes::copy_from_slice(segment)
+            }
+            IndexExtractor::Content => Bytes::copy_from_slice(content?),
+            IndexExtractor::JsonField(path) => {
+                let json: serde_json::Value = serde_json::from_slice(content?).ok()?;
+                let value = path
+                    .iter()
+                    .try_fold(&json, |value, field| value.get(field))?;
+                match value {
+                    serde_json::Value::String(s) => Bytes::from(s.clone()),
+                    value => Bytes::from(value.to_string()),
+                }
+            }
+        };
+        (value.len() <= MAX_INDEX_VALUE_LEN).then_some(value)
+    }
+}
+
 /// A query builder for document queries.
 #[derive(Debug, Default)]
 pub struct QueryBuilder<K> {
     kind: K,
     filter_author: AuthorFilter,
     filter_key: KeyFilter,
+    filter_timestamp: RangeFilter,
+    filter_content_len: RangeFilter,
+    cursor: Option<Cursor>,
     limit: Option<u64>,
     offset: u64,
     include_empty: bool,
@@ -341,6 +749,30 @@ impl<K> QueryBuilder<K> {
         self.filter_key = KeyFilter::Prefix(key.as_ref().to_vec().into());
         self
     }
+    /// Filter by a range of keys, e.g. `"a".."c"`.
+    pub fn key_range<T: AsRef<[u8]>>(mut self, range: impl RangeBounds<T>) -> Self {
+        self.filter_key = KeyFilter::range(range);
+        self
+    }
+    /// Filter by a range of entry timestamps (in microseconds since the Unix epoch).
+    pub fn timestamp_range(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.filter_timestamp = RangeFilter::new(range);
+        self
+    }
+    /// Filter by a range of content lengths (in bytes).
+    pub fn content_len_range(mut self, range: impl RangeBounds<u64>) -> Self {
+        self.filter_content_len = RangeFilter::new(range);
+        self
+    }
+    /// Only return entries that come after the cursor in the order of the query.
+    ///
+    /// Create the cursor from the last entry of the previous page to paginate through the
+    /// results. Unlike [`Self::offset`], this does not skip or repeat entries if entries are
+    /// inserted or removed between fetching two pages.
+    pub fn after(mut self, cursor: Cursor) -> Self {
+        self.cursor = Some(cursor);
+        self
+    }
     /// Filter by author.
     pub fn author(mut self, author: AuthorId) -> Self {
         self.filter_author = AuthorFilter::Exact(author);
@@ -368,6 +800,12 @@ pub struct FlatQuery {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct SingleLatestPerKeyQuery {}
 
+/// Query that returns the current and all superseded entries for a key.
+///
+/// Superseded entries are only available if a [`HistoryPolicy`] is set for the document.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryQuery {}
+
 impl QueryBuilder<FlatQuery> {
     /// Set the sort for the query.
     ///
@@ -400,12 +838,48 @@ impl QueryBuilder<SingleLatestPerKeyQuery> {
     }
 }
 
+impl QueryBuilder<HistoryQuery> {
+    /// Set the order direction for the query.
+    ///
+    /// Ordering is always by key, then by author, then by timestamp for this query type.
+    /// Default direction is ascending.
+    pub fn sort_direction(mut self, direction: SortDirection) -> Self {
+        self.sort_direction = direction;
+        self
+    }
+
+    /// Build the query.
+    pub fn build(self) -> Query {
+        Query::from(self)
+    }
+}
+
+impl From<QueryBuilder<HistoryQuery>> for Query {
+    fn from(builder: QueryBuilder<HistoryQuery>) -> Query {
+        Query {
+            kind: QueryKind::History(builder.kind),
+            filter_author: builder.filter_author,
+            filter_key: builder.filter_key,
+            filter_timestamp: builder.filter_timestamp,
+            filter_content_len: builder.filter_content_len,
+            cursor: builder.cursor,
+            limit: builder.limit,
+            offset: builder.offset,
+            include_empty: builder.include_empty,
+            sort_direction: builder.sort_direction,
+        }
+    }
+}
+
 impl From<QueryBuilder<SingleLatestPerKeyQuery>> for Query {
     fn from(builder: QueryBuilder<SingleLatestPerKeyQuery>) -> Query {
         Query {
             kind: QueryKind::SingleLatestPerKey(builder.kind),
             filter_author: builder.filter_author,
             filter_key: builder.filter_key,
+            filter_timestamp: builder.filter_timestamp,
+            filter_content_len: builder.filter_content_len,
+            cursor: builder.cursor,
             limit: builder.limit,
             offset: builder.offset,
             include_empty: builder.include_empty,
@@ -420,6 +894,9 @@ impl From<QueryBuilder<FlatQuery>> for Query {
             kind: QueryKind::Flat(builder.kind),
             filter_author: builder.filter_author,
             filter_key: builder.filter_key,
+            filter_timestamp: builder.filter_timestamp,
+            filter_content_len: builder.filter_content_len,
+            cursor: builder.cursor,
             limit: builder.limit,
             offset: builder.offset,
             include_empty: builder.include_empty,
@@ -435,6 +912,9 @@ pub struct Query {
     kind: QueryKind,
     filter_author: AuthorFilter,
     filter_key: KeyFilter,
+    filter_timestamp: RangeFilter,
+    filter_content_len: RangeFilter,
+    cursor: Option<Cursor>,
     limit: Option<u64>,
     offset: u64,
     include_empty: bool,
@@ -452,6 +932,15 @@ impl Query {
         Default::default()
     }
 
+    /// Query the current and all superseded entries for a key.
+    ///
+    /// Superseded entries are only kept if a [`HistoryPolicy`] is set for the document. Entries
+    /// are ordered by key, then by author, then by timestamp. The key filter can be widened with
+    /// [`QueryBuilder::key_prefix`] to query the history of all keys under a prefix.
+    pub fn history(key: impl AsRef<[u8]>) -> QueryBuilder<HistoryQuery> {
+        QueryBuilder::<HistoryQuery>::default().key_exact(key)
+    }
+
     /// Create a [`Query::all`] query filtered by a single author.
     pub fn author(author: AuthorId) -> QueryBuilder<FlatQuery> {
         Self::all().author(author)
@@ -467,6 +956,11 @@ impl Query {
         Self::all().key_prefix(prefix)
     }
 
+    /// Create a [`Query::all`] query filtered by a range of keys.
+    pub fn key_range<T: AsRef<[u8]>>(range: impl RangeBounds<T>) -> QueryBuilder<FlatQuery> {
+        Self::all().key_range(range)
+    }
+
     /// Get the limit for this query (max. number of entries to emit).
     pub fn limit(&self) -> Option<u64> {
         self.limit
@@ -476,6 +970,25 @@ impl Query {
     pub fn offset(&self) -> u64 {
         self.offset
     }
+
+    /// Get the cursor after which entries are returned for this query.
+    pub fn cursor(&self) -> Option<&Cursor> {
+        self.cursor.as_ref()
+    }
+
+    /// Apply the offset and limit of this query to the number of entries that match it.
+    pub(crate) fn limit_count(&self, matching: u64) -> u64 {
+        let count = matching.saturating_sub(self.offset);
+        match self.limit {
+            Some(limit) => count.min(limit),
+            None => count,
+        }
+    }
+
+    /// Test if an entry is matched by the timestamp and content length filters of this query.
+    pub(crate) fn matches_value(&self, timestamp: u64, content_len: u64) -> bool {
+        self.filter_timestamp.matches(timestamp) && self.filter_content_len.matches(content_len)
+    }
 }
 
 /// Sort direction
@@ -494,6 +1007,8 @@ enum QueryKind {
     Flat(FlatQuery),
     #[debug("SingleLatestPerKey")]
     SingleLatestPerKey(SingleLatestPerKeyQuery),
+    #[debug("History")]
+    History(HistoryQuery),
 }
 
 /// Fields by which the query can be sorted
@@ -516,6 +1031,13 @@ pub enum KeyFilter {
     Exact(Bytes),
     /// All keys that start with the provided value.
     Prefix(Bytes),
+    /// All keys within the provided range.
+    Range {
+        /// Start of the range.
+        start: Bound<Bytes>,
+        /// End of the range.
+        end: Bound<Bytes>,
+    },
 }
 
 impl<T: AsRef<[u8]>> From<T> for KeyFilter {
@@ -525,16 +1047,119 @@ impl<T: AsRef<[u8]>> From<T> for KeyFilter {
 }
 
 impl KeyFilter {
+    /// Create a [`KeyFilter`] that matches all keys within `range`.
+    pub fn range<T: AsRef<[u8]>>(range: impl RangeBounds<T>) -> Self {
+        let map = |bound: Bound<&T>| match bound {
+            Bound::Included(key) => Bound::Included(Bytes::copy_from_slice(key.as_ref())),
+            Bound::Excluded(key) => Bound::Excluded(Bytes::copy_from_slice(key.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        Self::Range {
+            start: map(range.start_bound()),
+            end: map(range.end_bound()),
+        }
+    }
+
     /// Test if a key is matched by this [`KeyFilter`].
     pub fn matches(&self, key: &[u8]) -> bool {
         match self {
             Self::Any => true,
             Self::Exact(k) => &k[..] == key,
             Self::Prefix(p) => key.starts_with(p),
+            Self::Range { start, end } => {
+                let after_start = match start {
+                    Bound::Included(start) => key >= &start[..],
+                    Bound::Excluded(start) => key > &start[..],
+                    Bound::Unbounded => true,
+                };
+                let before_end = match end {
+                    Bound::Included(end) => key <= &end[..],
+                    Bound::Excluded(end) => key < &end[..],
+                    Bound::Unbounded => true,
+                };
+                after_start && before_end
+            }
         }
     }
 }
 
+/// Matching of a range of integer values, used to filter by timestamp or content length.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Eq, PartialEq)]
+pub struct RangeFilter {
+    start: Bound<u64>,
+    end: Bound<u64>,
+}
+
+impl Default for RangeFilter {
+    fn default() -> Self {
+        Self::new(..)
+    }
+}
+
+impl RangeFilter {
+    /// Create a [`RangeFilter`] that matches all values within `range`.
+    pub fn new(range: impl RangeBounds<u64>) -> Self {
+        Self {
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+
+    /// Test if a value is matched by this [`RangeFilter`].
+    pub fn matches(&self, value: u64) -> bool {
+        (self.start, self.end).contains(&value)
+    }
+}
+
+/// Position in the results of a query, used for cursor-based pagination.
+///
+/// A cursor points at an entry. A query with [`QueryBuilder::after`] returns only entries that
+/// are sorted after this entry, according to the sort order and direction of the query.
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub struct Cursor {
+    key: Bytes,
+    author: AuthorId,
+    timestamp: u64,
+}
+
+impl Cursor {
+    /// Create a cursor that points at an entry.
+    pub fn new(entry: &Entry) -> Self {
+        Self {
+            key: Bytes::copy_from_slice(entry.key()),
+            author: entry.author(),
+            timestamp: entry.timestamp(),
+        }
+    }
+
+    /// Get the key of the entry this cursor points at.
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+
+    /// Get the author of the entry this cursor points at.
+    pub fn author(&self) -> AuthorId {
+        self.author
+    }
+
+    /// Get the timestamp of the entry this cursor points at.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+impl From<&Entry> for Cursor {
+    fn from(entry: &Entry) -> Self {
+        Self::new(entry)
+    }
+}
+
+impl From<&SignedEntry> for Cursor {
+    fn from(entry: &SignedEntry) -> Self {
+        Self::new(entry.entry())
+    }
+}
+
 /// Author matching.
 #[derive(Debug, Serialize, Deserialize, Clone, Default, Eq, PartialEq)]
 pub enum AuthorFilter {
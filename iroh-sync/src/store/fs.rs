@@ -2,7 +2,7 @@
 
 use std::{
     cmp::Ordering,
-    collections::HashSet,
+    collections::{BTreeSet, HashSet},
     iter::{Chain, Flatten},
     ops::Bound,
     path::Path,
@@ -16,21 +16,23 @@ use ed25519_dalek::{SignatureError, VerifyingKey};
 use iroh_base::hash::Hash;
 use parking_lot::RwLock;
 use redb::{
-    Database, MultimapTableDefinition, ReadOnlyTable, ReadableMultimapTable, ReadableTable,
-    TableDefinition,
+    Database, MultimapTableDefinition, ReadOnlyTable, ReadableMultimapTable, ReadableTable, Table,
+    TableDefinition, WriteTransaction,
 };
 
 use crate::{
     keys::Author,
     ranger::{Fingerprint, Range, RangeEntry},
     store::Store as _,
-    sync::{Entry, EntrySignature, Record, RecordIdentifier, Replica, SignedEntry},
+    sync::{
+        system_time_now, Entry, EntrySignature, Record, RecordIdentifier, Replica, SignedEntry,
+    },
     AuthorId, Capability, CapabilityKind, NamespaceId, PeerIdBytes,
 };
 
 use super::{
-    pubkeys::MemPublicKeyStore, DownloadPolicy, ImportNamespaceOutcome, OpenError, PublicKeyStore,
-    Query,
+    pubkeys::MemPublicKeyStore, AreaOfInterest, DownloadPolicy, HistoryPolicy,
+    ImportNamespaceOutcome, IndexSpec, KeyFilter, OpenError, PublicKeyStore, Query, Quota, Usage,
 };
 
 mod bounds;
@@ -38,8 +40,8 @@ mod migrations;
 mod query;
 mod ranges;
 
-use self::bounds::{ByKeyBounds, RecordsBounds};
-use self::query::QueryIterator;
+use self::bounds::{ByKeyBounds, HistoryBounds, RecordsBounds};
+use self::query::{count_entries, QueryIterator};
 use self::ranges::{TableRange, TableReader};
 
 pub use self::ranges::RecordsRange;
@@ -107,6 +109,77 @@ type Nanos = u64;
 const DOWNLOAD_POLICY_TABLE: TableDefinition<&[u8; 32], &[u8]> =
     TableDefinition::new("download-policy-1");
 
+/// Table: History policy
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `Vec<u8>`         # Postcard encoded history policy
+const HISTORY_POLICY_TABLE: TableDefinition<&[u8; 32], &[u8]> =
+    TableDefinition::new("history-policy-1");
+
+/// Table: Sync area
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `Vec<u8>`         # Postcard encoded area of interest
+const SYNC_AREA_TABLE: TableDefinition<&[u8; 32], &[u8]> = TableDefinition::new("sync-area-1");
+
+/// Table: Labels
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `&str`            # Local label of the document
+const LABEL_TABLE: TableDefinition<&[u8; 32], &str> = TableDefinition::new("label-1");
+
+/// Table: Quota
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `Vec<u8>`         # Postcard encoded quota
+const QUOTA_TABLE: TableDefinition<&[u8; 32], &[u8]> = TableDefinition::new("quota-1");
+
+/// Table: Records history
+/// Key:   `([u8; 32], Vec<u8>, [u8; 32], u64)`
+///      # (NamespaceId, Key, AuthorId, timestamp)
+/// Value: `([u8; 32], [u8; 32], u64, [u8; 32])`
+///      # (signature_namespace, signature_author, len, hash)
+const RECORDS_HISTORY_TABLE: TableDefinition<RecordsHistoryId, RecordsHistoryValue> =
+    TableDefinition::new("records-history-1");
+type RecordsHistoryId<'a> = (&'a [u8; 32], &'a [u8], &'a [u8; 32], u64);
+type RecordsHistoryIdOwned = ([u8; 32], Bytes, [u8; 32], u64);
+type RecordsHistoryValue<'a> = (&'a [u8; 64], &'a [u8; 64], u64, &'a [u8; 32]);
+
+/// Table: Index definitions
+/// Key:   `([u8; 32], &str)` # (NamespaceId, index name)
+/// Value: `Vec<u8>`          # Postcard encoded index spec
+const INDEX_SPECS_TABLE: TableDefinition<(&[u8; 32], &str), &[u8]> =
+    TableDefinition::new("index-specs-1");
+
+/// Table: Index entries
+/// Key:   `([u8; 32], &str, Vec<u8>, [u8; 32], Vec<u8>)`
+///      # (NamespaceId, index name, value, AuthorId, Key)
+/// Value: `()`
+const INDEX_ENTRIES_TABLE: TableDefinition<IndexEntriesId, ()> =
+    TableDefinition::new("index-entries-1");
+type IndexEntriesId<'a> = (&'a [u8; 32], &'a str, &'a [u8], &'a [u8; 32], &'a [u8]);
+
+/// Table: Index values
+/// Key:   `([u8; 32], [u8; 32], Vec<u8>, &str)`
+///      # (NamespaceId, AuthorId, Key, index name)
+/// Value: `Vec<u8>` # value
+const INDEX_VALUES_TABLE: TableDefinition<IndexValuesId, &[u8]> =
+    TableDefinition::new("index-values-1");
+type IndexValuesId<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8], &'a str);
+
+/// Table: Records by content hash
+/// Key:   `([u8; 32], [u8; 32], [u8; 32], Vec<u8>)`
+///      # (NamespaceId, Hash, AuthorId, Key)
+/// Value: `()`
+///
+/// Used to find the entries to update when content for the content based indexes arrives.
+const RECORDS_BY_HASH_TABLE: TableDefinition<RecordsByHashId, ()> =
+    TableDefinition::new("records-by-hash-1");
+type RecordsByHashId<'a> = (&'a [u8; 32], &'a [u8; 32], &'a [u8; 32], &'a [u8]);
+
+/// Table: Usage
+/// Key:   `[u8; 32]`        # NamespaceId
+/// Value: `(u64, u64)`      # (number of non-empty entries, total content length)
+///
+/// Updated together with the records table, used to check the quota of a document.
+const USAGE_TABLE: TableDefinition<&[u8; 32], (u64, u64)> = TableDefinition::new("usage-1");
+
 /// Manages the replicas and authors for an instance.
 #[derive(Debug, Clone)]
 pub struct Store {
@@ -130,6 +203,16 @@ impl Store {
             let _table = write_tx.open_table(LATEST_PER_AUTHOR_TABLE)?;
             let _table = write_tx.open_multimap_table(NAMESPACE_PEERS_TABLE)?;
             let _table = write_tx.open_table(DOWNLOAD_POLICY_TABLE)?;
+            let _table = write_tx.open_table(HISTORY_POLICY_TABLE)?;
+            let _table = write_tx.open_table(QUOTA_TABLE)?;
+            let _table = write_tx.open_table(SYNC_AREA_TABLE)?;
+            let _table = write_tx.open_table(LABEL_TABLE)?;
+            let _table = write_tx.open_table(RECORDS_HISTORY_TABLE)?;
+            let _table = write_tx.open_table(INDEX_SPECS_TABLE)?;
+            let _table = write_tx.open_table(INDEX_ENTRIES_TABLE)?;
+            let _table = write_tx.open_table(INDEX_VALUES_TABLE)?;
+            let _table = write_tx.open_table(RECORDS_BY_HASH_TABLE)?;
+            let _table = write_tx.open_table(USAGE_TABLE)?;
             let _table = write_tx.open_table(AUTHORS_TABLE)?;
         }
         write_tx.commit()?;
@@ -174,7 +257,16 @@ impl super::Store for Store {
         };
         let (raw_kind, raw_bytes) = db_value.value();
         let namespace = Capability::from_raw(raw_kind, raw_bytes)?;
-        let replica = Replica::new(namespace, StoreInstance::new(*namespace_id, self.clone()));
+        let mut replica = Replica::new(namespace, StoreInstance::new(*namespace_id, self.clone()));
+        // start the clock of the replica after the latest entry in the store
+        let mut latest = None;
+        for entry in self.get_latest_for_each_author(*namespace_id)? {
+            let (_author, timestamp, _key) = entry?;
+            latest = latest.max(Some(timestamp));
+        }
+        if let Some(latest) = latest {
+            replica.observe_timestamp(latest);
+        }
         self.open_replicas.write().insert(*namespace_id);
         Ok(replica)
     }
@@ -219,6 +311,17 @@ impl super::Store for Store {
         Ok(())
     }
 
+    fn delete_author(&self, author: &AuthorId) -> Result<bool> {
+        let write_tx = self.db.begin_write()?;
+        let removed = {
+            let mut author_table = write_tx.open_table(AUTHORS_TABLE)?;
+            let removed = author_table.remove(author.as_bytes())?;
+            removed.is_some()
+        };
+        write_tx.commit()?;
+        Ok(removed)
+    }
+
     fn list_authors(&self) -> Result<Self::AuthorsIter<'_>> {
         // TODO: avoid collect
         let read_tx = self.db.begin_read()?;
@@ -286,6 +389,33 @@ impl super::Store for Store {
             let mut dl_policies_table = write_tx.open_table(DOWNLOAD_POLICY_TABLE)?;
             dl_policies_table.remove(namespace.as_bytes())?;
         }
+        {
+            let mut history_policies_table = write_tx.open_table(HISTORY_POLICY_TABLE)?;
+            history_policies_table.remove(namespace.as_bytes())?;
+            let mut history_table = write_tx.open_table(RECORDS_HISTORY_TABLE)?;
+            let bounds = HistoryBounds::namespace(*namespace);
+            history_table.drain(bounds.as_ref())?;
+            let mut quota_table = write_tx.open_table(QUOTA_TABLE)?;
+            quota_table.remove(namespace.as_bytes())?;
+            let mut sync_area_table = write_tx.open_table(SYNC_AREA_TABLE)?;
+            sync_area_table.remove(namespace.as_bytes())?;
+            let mut label_table = write_tx.open_table(LABEL_TABLE)?;
+            label_table.remove(namespace.as_bytes())?;
+            let mut usage_table = write_tx.open_table(USAGE_TABLE)?;
+            usage_table.remove(namespace.as_bytes())?;
+        }
+        {
+            let mut indexes = IndexWriter::new(&write_tx, *namespace)?;
+            let specs = std::mem::take(&mut indexes.specs);
+            for spec in &specs {
+                indexes.remove_index(&spec.name)?;
+            }
+            indexes.remove_namespace_hashes()?;
+            let mut specs_table = write_tx.open_table(INDEX_SPECS_TABLE)?;
+            for spec in &specs {
+                specs_table.remove((namespace.as_bytes(), spec.name.as_str()))?;
+            }
+        }
         write_tx.commit()?;
         Ok(())
     }
@@ -298,6 +428,10 @@ impl super::Store for Store {
         QueryIterator::new(&self.db, namespace, query.into())
     }
 
+    fn count(&self, namespace: NamespaceId, query: impl Into<Query>) -> Result<u64> {
+        count_entries(&self.db, namespace, query.into())
+    }
+
     fn get_exact(
         &self,
         namespace: NamespaceId,
@@ -440,6 +574,466 @@ impl super::Store for Store {
             Some(value) => postcard::from_bytes(value.value())?,
         })
     }
+
+    fn set_history_policy(&self, namespace: &NamespaceId, policy: HistoryPolicy) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(HISTORY_POLICY_TABLE)?;
+            let value = postcard::to_stdvec(&policy)?;
+            table.insert(namespace, value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(HISTORY_POLICY_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(match value {
+            None => HistoryPolicy::default(),
+            Some(value) => postcard::from_bytes(value.value())?,
+        })
+    }
+
+    fn set_sync_area(&self, namespace: &NamespaceId, area: AreaOfInterest) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(SYNC_AREA_TABLE)?;
+            let value = postcard::to_stdvec(&area)?;
+            table.insert(namespace, value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_sync_area(&self, namespace: &NamespaceId) -> Result<AreaOfInterest> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(SYNC_AREA_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(match value {
+            None => AreaOfInterest::default(),
+            Some(value) => postcard::from_bytes(value.value())?,
+        })
+    }
+
+    fn set_label(&self, namespace: &NamespaceId, label: Option<String>) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(LABEL_TABLE)?;
+            match label {
+                Some(label) => table.insert(namespace, label.as_str())?,
+                None => table.remove(namespace)?,
+            };
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_label(&self, namespace: &NamespaceId) -> Result<Option<String>> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(LABEL_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(value.map(|value| value.value().to_string()))
+    }
+
+    fn set_quota(&self, namespace: &NamespaceId, quota: Quota) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            let namespace = namespace.as_bytes();
+
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(&namespace)?.is_some(),
+                "document not created"
+            );
+
+            let mut table = tx.open_table(QUOTA_TABLE)?;
+            let value = postcard::to_stdvec(&quota)?;
+            table.insert(namespace, value.as_slice())?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(QUOTA_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(match value {
+            None => Quota::default(),
+            Some(value) => postcard::from_bytes(value.value())?,
+        })
+    }
+
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(USAGE_TABLE)?;
+        let value = table.get(namespace.as_bytes())?;
+        Ok(match value {
+            None => Usage::default(),
+            Some(value) => {
+                let (entries, content_len) = value.value();
+                Usage {
+                    entries,
+                    content_len,
+                }
+            }
+        })
+    }
+
+    fn create_index(&self, namespace: &NamespaceId, spec: IndexSpec) -> Result<()> {
+        let tx = self.db.begin_write()?;
+        {
+            // ensure the document exists
+            let namespaces = tx.open_table(NAMESPACES_TABLE)?;
+            anyhow::ensure!(
+                namespaces.get(namespace.as_bytes())?.is_some(),
+                "document not created"
+            );
+
+            {
+                let mut specs_table = tx.open_table(INDEX_SPECS_TABLE)?;
+                let value = postcard::to_stdvec(&spec)?;
+                specs_table.insert((namespace.as_bytes(), spec.name.as_str()), value.as_slice())?;
+            }
+
+            let mut indexes = IndexWriter::new(&tx, *namespace)?;
+            indexes.remove_index(&spec.name)?;
+            let record_table = tx.open_table(RECORDS_TABLE)?;
+            let bounds = RecordsBounds::namespace(*namespace);
+            for res in record_table.range(bounds.as_ref())? {
+                let (key, value) = res?;
+                let entry = into_entry(key.value(), value.value());
+                if !entry.is_empty() {
+                    let value = spec.extractor.extract(entry.key(), None);
+                    indexes.set(&spec.name, entry.author(), entry.key(), value.as_deref())?;
+                    // entries written before the hash index existed are added here, so that
+                    // their content can be indexed
+                    if spec.extractor.uses_content() {
+                        indexes.insert_hash(&entry)?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn remove_index(&self, namespace: &NamespaceId, name: &str) -> Result<bool> {
+        let tx = self.db.begin_write()?;
+        let existed = {
+            IndexWriter::new(&tx, *namespace)?.remove_index(name)?;
+            let mut specs_table = tx.open_table(INDEX_SPECS_TABLE)?;
+            let existed = specs_table.remove((namespace.as_bytes(), name))?.is_some();
+            existed
+        };
+        tx.commit()?;
+        Ok(existed)
+    }
+
+    fn list_indexes(&self, namespace: &NamespaceId) -> Result<Vec<IndexSpec>> {
+        let tx = self.db.begin_read()?;
+        let table = tx.open_table(INDEX_SPECS_TABLE)?;
+        read_index_specs(&table, namespace)
+    }
+
+    fn index_content(&self, namespace: &NamespaceId, hash: Hash, content: &[u8]) -> Result<usize> {
+        let tx = self.db.begin_write()?;
+        let count = {
+            let mut indexes = IndexWriter::new(&tx, *namespace)?;
+            let specs = std::mem::take(&mut indexes.specs)
+                .into_iter()
+                .filter(|spec| spec.extractor.uses_content())
+                .collect::<Vec<_>>();
+            if specs.is_empty() {
+                return Ok(0);
+            }
+            let ids = indexes.entries_with_hash(&hash)?;
+            for (author, key) in &ids {
+                for spec in &specs {
+                    let value = spec.extractor.extract(key, Some(content));
+                    indexes.set(&spec.name, *author, key, value.as_deref())?;
+                }
+            }
+            ids.len()
+        };
+        tx.commit()?;
+        Ok(count)
+    }
+
+    fn get_by_index(
+        &self,
+        namespace: NamespaceId,
+        name: &str,
+        filter: KeyFilter,
+    ) -> Result<Vec<SignedEntry>> {
+        let tx = self.db.begin_read()?;
+        let specs_table = tx.open_table(INDEX_SPECS_TABLE)?;
+        anyhow::ensure!(
+            specs_table.get((namespace.as_bytes(), name))?.is_some(),
+            "index not found"
+        );
+        let index_table = tx.open_table(INDEX_ENTRIES_TABLE)?;
+        let record_table = tx.open_table(RECORDS_TABLE)?;
+        let start: &[u8] = match &filter {
+            KeyFilter::Any => &[],
+            KeyFilter::Exact(value) | KeyFilter::Prefix(value) => value,
+            KeyFilter::Range { start, .. } => match start {
+                Bound::Included(value) | Bound::Excluded(value) => value,
+                Bound::Unbounded => &[],
+            },
+        };
+        let start = (namespace.as_bytes(), name, start, &[0u8; 32], &[][..]);
+        let mut entries = vec![];
+        for res in index_table.range(start..)? {
+            let (id, _) = res?;
+            let (ns, index_name, value, author, key) = id.value();
+            if ns != namespace.as_bytes() || index_name != name || is_past_filter(&filter, value) {
+                break;
+            }
+            if !filter.matches(value) {
+                continue;
+            }
+            let author = AuthorId::from(author);
+            if let Some(entry) = get_exact(&record_table, namespace, author, key, false)? {
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+}
+
+fn read_index_specs(
+    table: &impl ReadableTable<(&'static [u8; 32], &'static str), &'static [u8]>,
+    namespace: &NamespaceId,
+) -> Result<Vec<IndexSpec>> {
+    let start = (namespace.as_bytes(), "");
+    let mut specs = vec![];
+    for res in table.range(start..)? {
+        let (key, value) = res?;
+        if key.value().0 != namespace.as_bytes() {
+            break;
+        }
+        specs.push(postcard::from_bytes(value.value())?);
+    }
+    Ok(specs)
+}
+
+/// Returns `true` if `value` and all values sorted after it do not match `filter`.
+fn is_past_filter(filter: &KeyFilter, value: &[u8]) -> bool {
+    match filter {
+        KeyFilter::Any => false,
+        KeyFilter::Exact(expected) => value > &expected[..],
+        KeyFilter::Prefix(prefix) => value > &prefix[..] && !value.starts_with(prefix),
+        KeyFilter::Range { end, .. } => match end {
+            Bound::Included(end) => value > &end[..],
+            Bound::Excluded(end) => value >= &end[..],
+            Bound::Unbounded => false,
+        },
+    }
+}
+
+/// The secondary indexes of a namespace, opened for writing within a transaction.
+struct IndexWriter<'db, 'txn> {
+    namespace: NamespaceId,
+    specs: Vec<IndexSpec>,
+    entries: Table<'db, 'txn, IndexEntriesId<'static>, ()>,
+    values: Table<'db, 'txn, IndexValuesId<'static>, &'static [u8]>,
+    by_hash: Table<'db, 'txn, RecordsByHashId<'static>, ()>,
+}
+
+impl<'db, 'txn> IndexWriter<'db, 'txn> {
+    fn new(tx: &'txn WriteTransaction<'db>, namespace: NamespaceId) -> Result<Self> {
+        let specs = read_index_specs(&tx.open_table(INDEX_SPECS_TABLE)?, &namespace)?;
+        Ok(Self {
+            namespace,
+            specs,
+            entries: tx.open_table(INDEX_ENTRIES_TABLE)?,
+            values: tx.open_table(INDEX_VALUES_TABLE)?,
+            by_hash: tx.open_table(RECORDS_BY_HASH_TABLE)?,
+        })
+    }
+
+    /// Set the value of the index `name` for an entry, replacing the previous value.
+    fn set(
+        &mut self,
+        name: &str,
+        author: AuthorId,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<()> {
+        self.remove(name, author, key)?;
+        if let Some(value) = value {
+            let namespace = self.namespace.as_bytes();
+            let author = author.as_bytes();
+            self.entries
+                .insert((namespace, name, value, author, key), ())?;
+            self.values.insert((namespace, author, key, name), value)?;
+        }
+        Ok(())
+    }
+
+    /// Remove the value of the index `name` for an entry.
+    fn remove(&mut self, name: &str, author: AuthorId, key: &[u8]) -> Result<()> {
+        let namespace = self.namespace.as_bytes();
+        let author = author.as_bytes();
+        let value = self
+            .values
+            .remove((namespace, author, key, name))?
+            .map(|value| value.value().to_vec());
+        if let Some(value) = value {
+            self.entries
+                .remove((namespace, name, value.as_slice(), author, key))?;
+        }
+        Ok(())
+    }
+
+    /// Update all indexes for a new entry which replaced the entry `replaced`.
+    fn insert_entry(&mut self, entry: &SignedEntry, replaced: Option<&SignedEntry>) -> Result<()> {
+        if let Some(replaced) = replaced {
+            self.remove_hash(replaced)?;
+        }
+        self.insert_hash(entry)?;
+        let same_content = replaced.is_some_and(|r| r.content_hash() == entry.content_hash());
+        for spec in std::mem::take(&mut self.specs) {
+            // keep content based values if the content did not change
+            if spec.extractor.uses_content() && same_content && !entry.is_empty() {
+                continue;
+            }
+            let value = match entry.is_empty() {
+                true => None,
+                false => spec.extractor.extract(entry.key(), None),
+            };
+            self.set(&spec.name, entry.author(), entry.key(), value.as_deref())?;
+            self.specs.push(spec);
+        }
+        Ok(())
+    }
+
+    /// Remove an entry from all indexes.
+    fn remove_entry(&mut self, entry: &SignedEntry) -> Result<()> {
+        self.remove_hash(entry)?;
+        for spec in std::mem::take(&mut self.specs) {
+            self.remove(&spec.name, entry.author(), entry.key())?;
+            self.specs.push(spec);
+        }
+        Ok(())
+    }
+
+    /// Add an entry to the records by hash table.
+    fn insert_hash(&mut self, entry: &SignedEntry) -> Result<()> {
+        if !entry.is_empty() {
+            let (hash, author) = (entry.content_hash(), entry.author());
+            let id = (
+                self.namespace.as_bytes(),
+                hash.as_bytes(),
+                author.as_bytes(),
+                entry.key(),
+            );
+            self.by_hash.insert(id, ())?;
+        }
+        Ok(())
+    }
+
+    /// Remove an entry from the records by hash table.
+    fn remove_hash(&mut self, entry: &SignedEntry) -> Result<()> {
+        let (hash, author) = (entry.content_hash(), entry.author());
+        let id = (
+            self.namespace.as_bytes(),
+            hash.as_bytes(),
+            author.as_bytes(),
+            entry.key(),
+        );
+        self.by_hash.remove(id)?;
+        Ok(())
+    }
+
+    /// Get the author and key of all entries with content `hash`.
+    fn entries_with_hash(&self, hash: &Hash) -> Result<Vec<(AuthorId, Vec<u8>)>> {
+        let namespace = self.namespace.as_bytes();
+        let start = (namespace, hash.as_bytes(), &[0u8; 32], &[][..]);
+        let mut ids = vec![];
+        for res in self.by_hash.range(start..)? {
+            let (id, _) = res?;
+            let (ns, entry_hash, author, key) = id.value();
+            if ns != namespace || entry_hash != hash.as_bytes() {
+                break;
+            }
+            ids.push((AuthorId::from(author), key.to_vec()));
+        }
+        Ok(ids)
+    }
+
+    /// Remove all entries of the namespace from the records by hash table.
+    fn remove_namespace_hashes(&mut self) -> Result<()> {
+        let namespace = self.namespace.as_bytes();
+        let start = (namespace, &[0u8; 32], &[0u8; 32], &[][..]);
+        let mut ids = vec![];
+        for res in self.by_hash.range(start..)? {
+            let (id, _) = res?;
+            let (ns, hash, author, key) = id.value();
+            if ns != namespace {
+                break;
+            }
+            ids.push((*hash, *author, key.to_vec()));
+        }
+        for (hash, author, key) in ids {
+            self.by_hash
+                .remove((namespace, &hash, &author, key.as_slice()))?;
+        }
+        Ok(())
+    }
+
+    /// Remove all values of the index `name`.
+    fn remove_index(&mut self, name: &str) -> Result<()> {
+        let namespace = self.namespace.as_bytes();
+        let start = (namespace, name, &[][..], &[0u8; 32], &[][..]);
+        let mut ids = vec![];
+        for res in self.entries.range(start..)? {
+            let (id, _) = res?;
+            let (ns, index_name, value, author, key) = id.value();
+            if ns != namespace || index_name != name {
+                break;
+            }
+            ids.push((value.to_vec(), *author, key.to_vec()));
+        }
+        for (value, author, key) in ids {
+            self.entries
+                .remove((namespace, name, value.as_slice(), &author, key.as_slice()))?;
+            self.values
+                .remove((namespace, &author, key.as_slice(), name))?;
+        }
+        Ok(())
+    }
 }
 
 fn parse_capability((raw_kind, raw_bytes): (u8, &[u8; 32])) -> Result<Capability> {
@@ -485,6 +1079,21 @@ impl super::DownloadPolicyStore for StoreInstance {
     }
 }
 
+impl super::QuotaStore for StoreInstance {
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota> {
+        super::Store::get_quota(&self.store, namespace)
+    }
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage> {
+        super::Store::get_usage(&self.store, namespace)
+    }
+}
+
+impl super::HistoryPolicyStore for StoreInstance {
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy> {
+        super::Store::get_history_policy(&self.store, namespace)
+    }
+}
+
 impl crate::ranger::Store<SignedEntry> for StoreInstance {
     type Error = anyhow::Error;
     type RangeIterator<'a> =
@@ -544,6 +1153,7 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
 
     fn put(&mut self, e: SignedEntry) -> Result<()> {
         let id = e.id();
+        let history_policy = self.store.get_history_policy(&self.namespace)?;
         let write_tx = self.store.db.begin_write()?;
         {
             // insert into record table
@@ -561,7 +1171,29 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
                 e.content_len(),
                 hash.as_bytes(),
             );
-            record_table.insert(key, value)?;
+            let replaced = record_table
+                .insert(key, value)?
+                .map(|old| into_entry(key, old.value()));
+
+            // update the secondary indexes
+            let mut indexes = IndexWriter::new(&write_tx, self.namespace)?;
+            indexes.insert_entry(&e, replaced.as_ref())?;
+
+            // update the usage of the namespace
+            update_usage(&write_tx, &self.namespace, |usage| {
+                usage.add(&e);
+                if let Some(replaced) = &replaced {
+                    usage.remove(replaced);
+                }
+            })?;
+
+            // move the replaced entry into the history table, if requested
+            if let Some(replaced) = replaced {
+                if history_policy.is_enabled() && replaced.timestamp() != e.timestamp() {
+                    let mut history_table = write_tx.open_table(RECORDS_HISTORY_TABLE)?;
+                    insert_history(&mut history_table, &history_policy, [replaced])?;
+                }
+            }
 
             // insert into by key index table
             let mut idx_by_key = write_tx.open_table(RECORDS_BY_KEY_TABLE)?;
@@ -632,6 +1264,10 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
             let value = table.remove(id)?;
             value.map(|value| into_entry(id, value.value()))
         };
+        if let Some(entry) = &entry {
+            IndexWriter::new(&write_tx, self.namespace)?.remove_entry(entry)?;
+            update_usage(&write_tx, &self.namespace, |usage| usage.remove(entry))?;
+        }
         write_tx.commit()?;
         Ok(entry)
     }
@@ -663,6 +1299,7 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
         predicate: impl Fn(&Record) -> bool,
     ) -> Result<usize> {
         let bounds = RecordsBounds::author_prefix(id.namespace(), id.author(), id.key_bytes());
+        let history_policy = self.store.get_history_policy(&self.namespace)?;
         let write_tx = self.store.db.begin_write()?;
         let count = {
             let mut table = write_tx.open_table(RECORDS_TABLE)?;
@@ -672,14 +1309,49 @@ impl crate::ranger::Store<SignedEntry> for StoreInstance {
 
                 predicate(&record)
             };
-            let iter = table.drain_filter(bounds.as_ref(), cb)?;
-            iter.count()
+            let removed = table
+                .drain_filter(bounds.as_ref(), cb)?
+                .map(|res| res.map(|(k, v)| into_entry(k.value(), v.value())))
+                .collect::<Result<Vec<_>, _>>()?;
+            let count = removed.len();
+            let mut indexes = IndexWriter::new(&write_tx, self.namespace)?;
+            for entry in &removed {
+                indexes.remove_entry(entry)?;
+            }
+            update_usage(&write_tx, &self.namespace, |usage| {
+                removed.iter().for_each(|entry| usage.remove(entry))
+            })?;
+            if history_policy.is_enabled() {
+                let mut history_table = write_tx.open_table(RECORDS_HISTORY_TABLE)?;
+                insert_history(&mut history_table, &history_policy, removed)?;
+            }
+            count
         };
         write_tx.commit()?;
         Ok(count)
     }
 }
 
+/// Update the usage counters of a namespace within a write transaction.
+fn update_usage(
+    tx: &WriteTransaction,
+    namespace: &NamespaceId,
+    f: impl FnOnce(&mut Usage),
+) -> Result<()> {
+    let mut table = tx.open_table(USAGE_TABLE)?;
+    let (entries, content_len) = table
+        .get(namespace.as_bytes())?
+        .map(|value| value.value())
+        .unwrap_or_default();
+    let mut usage = Usage {
+        entries,
+        content_len,
+    };
+    f(&mut usage);
+    table.insert(namespace.as_bytes(), (usage.entries, usage.content_len))?;
+    Ok(())
+}
+
 fn chain_none<'a, I: Iterator<Item = T> + 'a, T>(
     iter: I,
 ) -> Chain<I, Flatten<std::option::IntoIter<I>>> {
@@ -785,6 +1457,92 @@ impl Iterator for LatestIterator<'_> {
     }
 }
 
+/// Insert superseded entries into the history table and prune the history of the affected keys
+/// according to the history policy.
+fn insert_history(
+    table: &mut redb::Table<RecordsHistoryId<'static>, RecordsHistoryValue<'static>>,
+    policy: &HistoryPolicy,
+    entries: impl IntoIterator<Item = SignedEntry>,
+) -> Result<()> {
+    let mut touched = BTreeSet::new();
+    for entry in entries {
+        let id = entry.id();
+        let hash = entry.content_hash(); // let binding is needed
+        let key = (
+            &id.namespace().to_bytes(),
+            id.key(),
+            &id.author().to_bytes(),
+            entry.timestamp(),
+        );
+        let value = (
+            &entry.signature().namespace().to_bytes(),
+            &entry.signature().author().to_bytes(),
+            entry.content_len(),
+            hash.as_bytes(),
+        );
+        table.insert(key, value)?;
+        touched.insert((id.namespace(), id.key_bytes(), id.author()));
+    }
+
+    let now = system_time_now();
+    for (namespace, key, author) in touched {
+        let (namespace, author) = (namespace.to_bytes(), author.to_bytes());
+        let start = (&namespace, &key[..], &author, u64::MIN);
+        let end = (&namespace, &key[..], &author, u64::MAX);
+        let mut expired = vec![];
+        for (index, item) in table.range(start..=end)?.rev().enumerate() {
+            let (key, _value) = item?;
+            let (_namespace, _key, _author, timestamp) = key.value();
+            if !policy.retains(index as u64, timestamp, now) {
+                expired.push(timestamp);
+            }
+        }
+        for timestamp in expired {
+            table.remove((&namespace, &key[..], &author, timestamp))?;
+        }
+    }
+    Ok(())
+}
+
+/// Read the superseded entries for the keys matching `range` from the history table.
+fn history_entries(
+    db: &Arc<Database>,
+    namespace: NamespaceId,
+    range: &KeyFilter,
+    filter: impl Fn(&SignedEntry) -> bool,
+) -> Result<Vec<SignedEntry>> {
+    let mut entries = vec![];
+    for_each_history_record(db, namespace, range, |id, value| {
+        let entry = into_entry(id, value);
+        if filter(&entry) {
+            entries.push(entry);
+        }
+    })?;
+    Ok(entries)
+}
+
+/// Call `f` with the id and value of each superseded record in the key `range` of a namespace.
+fn for_each_history_record(
+    db: &Arc<Database>,
+    namespace: NamespaceId,
+    range: &KeyFilter,
+    mut f: impl FnMut(RecordsId<'_>, RecordsValue<'_>),
+) -> Result<()> {
+    let tx = db.begin_read()?;
+    let table = tx.open_table(RECORDS_HISTORY_TABLE)?;
+    let bounds = HistoryBounds::new(namespace, range);
+    for item in table.range(bounds.as_ref())? {
+        let (key, value) = item?;
+        let (ns, key, author, timestamp) = key.value();
+        let (namespace_sig, author_sig, len, hash) = value.value();
+        f(
+            (ns, author, key),
+            (timestamp, namespace_sig, author_sig, len, hash),
+        );
+    }
+    Ok(())
+}
+
 fn into_entry(key: RecordsId, value: RecordsValue) -> SignedEntry {
     let (namespace, author, key) = key;
     let (timestamp, namespace_sig, author_sig, len, hash) = value;
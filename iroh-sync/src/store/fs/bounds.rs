@@ -2,9 +2,15 @@ use std::ops::{Bound, RangeBounds};
 
 use bytes::Bytes;
 
-use crate::{store::KeyFilter, AuthorId, NamespaceId};
+use crate::{
+    store::{KeyFilter, SortDirection},
+    AuthorId, NamespaceId,
+};
 
-use super::{RecordsByKeyId, RecordsByKeyIdOwned, RecordsId, RecordsIdOwned};
+use super::{
+    RecordsByKeyId, RecordsByKeyIdOwned, RecordsHistoryId, RecordsHistoryIdOwned, RecordsId,
+    RecordsIdOwned,
+};
 
 /// Bounds on the records table.
 ///
@@ -17,11 +23,15 @@ impl RecordsBounds {
     }
 
     pub fn author_key(ns: NamespaceId, author: AuthorId, key_matcher: KeyFilter) -> Self {
+        if let KeyFilter::Range { start, end } = key_matcher {
+            return Self::author_key_range(ns, author, start, end);
+        }
         let key_is_exact = matches!(key_matcher, KeyFilter::Exact(_));
         let key = match key_matcher {
             KeyFilter::Any => Bytes::new(),
             KeyFilter::Exact(key) => key,
             KeyFilter::Prefix(prefix) => prefix,
+            KeyFilter::Range { .. } => unreachable!("handled above"),
         };
         let author = author.to_bytes();
         let ns = ns.to_bytes();
@@ -46,6 +56,37 @@ impl RecordsBounds {
         Self(Bound::Included(start), end)
     }
 
+    fn author_key_range(
+        ns: NamespaceId,
+        author: AuthorId,
+        start: Bound<Bytes>,
+        end: Bound<Bytes>,
+    ) -> Self {
+        let author = author.to_bytes();
+        let ns = ns.to_bytes();
+        let start = match start {
+            Bound::Included(key) => Bound::Included((ns, author, key)),
+            Bound::Excluded(key) => Bound::Excluded((ns, author, key)),
+            Bound::Unbounded => Bound::Included((ns, author, Bytes::new())),
+        };
+        let end = match end {
+            Bound::Included(key) => Bound::Included((ns, author, key)),
+            Bound::Excluded(key) => Bound::Excluded((ns, author, key)),
+            Bound::Unbounded => {
+                let mut author_end = author;
+                let mut ns_end = ns;
+                if increment_by_one(&mut author_end) {
+                    Bound::Excluded((ns, author_end, Bytes::new()))
+                } else if increment_by_one(&mut ns_end) {
+                    Bound::Excluded((ns_end, [0u8; 32], Bytes::new()))
+                } else {
+                    Bound::Unbounded
+                }
+            }
+        };
+        Self(start, end)
+    }
+
     pub fn author_prefix(ns: NamespaceId, author: AuthorId, prefix: Bytes) -> Self {
         RecordsBounds::author_key(ns, author, KeyFilter::Prefix(prefix))
     }
@@ -62,6 +103,17 @@ impl RecordsBounds {
         Self::new(start, Self::namespace_end(ns))
     }
 
+    /// Narrow the bounds to the ids that come after `id` in `direction`.
+    pub fn after(self, id: RecordsIdOwned, direction: &SortDirection) -> Self {
+        let (start, end) = after(self.0, self.1, id, direction);
+        Self(start, end)
+    }
+
+    /// Check if no id can be within these bounds.
+    pub fn is_empty(&self) -> bool {
+        is_empty(&self.0, &self.1)
+    }
+
     pub fn as_ref(&self) -> (Bound<RecordsId>, Bound<RecordsId>) {
         fn map(id: &RecordsIdOwned) -> RecordsId {
             (&id.0, &id.1, &id.2[..])
@@ -126,6 +178,27 @@ impl ByKeyBounds {
                 };
                 Self(start, end)
             }
+            KeyFilter::Range { start, end } => {
+                let start = match start {
+                    Bound::Included(key) => {
+                        Bound::Included((ns.to_bytes(), key.clone(), [0u8; 32]))
+                    }
+                    Bound::Excluded(key) => {
+                        Bound::Excluded((ns.to_bytes(), key.clone(), [255u8; 32]))
+                    }
+                    Bound::Unbounded => Bound::Included((ns.to_bytes(), Bytes::new(), [0u8; 32])),
+                };
+                let end = match end {
+                    Bound::Included(key) => {
+                        Bound::Included((ns.to_bytes(), key.clone(), [255u8; 32]))
+                    }
+                    Bound::Excluded(key) => {
+                        Bound::Excluded((ns.to_bytes(), key.clone(), [0u8; 32]))
+                    }
+                    Bound::Unbounded => Self::namespace(ns).1,
+                };
+                Self(start, end)
+            }
         }
     }
 
@@ -140,6 +213,17 @@ impl ByKeyBounds {
         Self(start, end)
     }
 
+    /// Narrow the bounds to the ids that come after `id` in `direction`.
+    pub fn after(self, id: RecordsByKeyIdOwned, direction: &SortDirection) -> Self {
+        let (start, end) = after(self.0, self.1, id, direction);
+        Self(start, end)
+    }
+
+    /// Check if no id can be within these bounds.
+    pub fn is_empty(&self) -> bool {
+        is_empty(&self.0, &self.1)
+    }
+
     pub fn as_ref(&self) -> (Bound<RecordsByKeyId>, Bound<RecordsByKeyId>) {
         fn map(id: &RecordsByKeyIdOwned) -> RecordsByKeyId {
             (&id.0, &id.1[..], &id.2)
@@ -158,6 +242,65 @@ impl RangeBounds<RecordsByKeyIdOwned> for ByKeyBounds {
     }
 }
 
+/// Bounds for the records history table.
+///
+/// Supports bounds by namespace.
+pub struct HistoryBounds(Bound<RecordsHistoryIdOwned>, Bound<RecordsHistoryIdOwned>);
+impl HistoryBounds {
+    pub fn new(ns: NamespaceId, matcher: &KeyFilter) -> Self {
+        let key_start = |key: &Bytes| (ns.to_bytes(), key.clone(), [0u8; 32], u64::MIN);
+        let key_end = |key: &Bytes| (ns.to_bytes(), key.clone(), [255u8; 32], u64::MAX);
+        match matcher {
+            KeyFilter::Any => Self::namespace(ns),
+            KeyFilter::Exact(key) => Self(
+                Bound::Included(key_start(key)),
+                Bound::Included(key_end(key)),
+            ),
+            KeyFilter::Prefix(ref prefix) => {
+                let start = Bound::Included(key_start(prefix));
+                let mut key_end = prefix.to_vec();
+                let end = if increment_by_one(&mut key_end) {
+                    Bound::Excluded((ns.to_bytes(), key_end.into(), [0u8; 32], u64::MIN))
+                } else {
+                    Self::namespace(ns).1
+                };
+                Self(start, end)
+            }
+            KeyFilter::Range { start, end } => {
+                let start = match start {
+                    Bound::Included(key) => Bound::Included(key_start(key)),
+                    Bound::Excluded(key) => Bound::Excluded(key_end(key)),
+                    Bound::Unbounded => Self::namespace(ns).0,
+                };
+                let end = match end {
+                    Bound::Included(key) => Bound::Included(key_end(key)),
+                    Bound::Excluded(key) => Bound::Excluded(key_start(key)),
+                    Bound::Unbounded => Self::namespace(ns).1,
+                };
+                Self(start, end)
+            }
+        }
+    }
+
+    pub fn namespace(ns: NamespaceId) -> Self {
+        let start = Bound::Included((ns.to_bytes(), Bytes::new(), [0u8; 32], u64::MIN));
+        let mut ns_end = ns.to_bytes();
+        let end = if increment_by_one(&mut ns_end) {
+            Bound::Excluded((ns_end, Bytes::new(), [0u8; 32], u64::MIN))
+        } else {
+            Bound::Unbounded
+        };
+        Self(start, end)
+    }
+
+    pub fn as_ref(&self) -> (Bound<RecordsHistoryId<'_>>, Bound<RecordsHistoryId<'_>>) {
+        fn map(id: &RecordsHistoryIdOwned) -> RecordsHistoryId<'_> {
+            (&id.0, &id.1[..], &id.2, id.3)
+        }
+        (map_bound(&self.0, map), map_bound(&self.1, map))
+    }
+}
+
 /// Increment a byte string by one, by incrementing the last byte that is not 255 by one.
 ///
 /// Returns false if all bytes are 255.
@@ -173,6 +316,35 @@ fn increment_by_one(value: &mut [u8]) -> bool {
     false
 }
 
+/// Narrow a range to the values that come after `pivot` when iterating in `direction`.
+fn after<T: Ord>(
+    start: Bound<T>,
+    end: Bound<T>,
+    pivot: T,
+    direction: &SortDirection,
+) -> (Bound<T>, Bound<T>) {
+    match direction {
+        SortDirection::Asc => match start {
+            Bound::Included(ref s) | Bound::Excluded(ref s) if pivot < *s => (start, end),
+            _ => (Bound::Excluded(pivot), end),
+        },
+        SortDirection::Desc => match end {
+            Bound::Included(ref e) | Bound::Excluded(ref e) if pivot > *e => (start, end),
+            _ => (start, Bound::Excluded(pivot)),
+        },
+    }
+}
+
+fn is_empty<T: Ord>(start: &Bound<T>, end: &Bound<T>) -> bool {
+    match (start, end) {
+        (Bound::Included(s), Bound::Included(e)) => s > e,
+        (Bound::Included(s), Bound::Excluded(e))
+        | (Bound::Excluded(s), Bound::Included(e))
+        | (Bound::Excluded(s), Bound::Excluded(e)) => s >= e,
+        _ => false,
+    }
+}
+
 fn map_bound<'a, T, U: 'a>(bound: &'a Bound<T>, f: impl Fn(&'a T) -> U) -> Bound<U> {
     match bound {
         Bound::Unbounded => Bound::Unbounded,
@@ -235,6 +407,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn bounds_key_range_and_after() {
+        let ns = NamespaceId::from(&[2u8; 32]);
+        let a = AuthorId::from(&[0u8; 32]);
+
+        let bounds = RecordsBounds::author_key(ns, a, KeyFilter::range(&b"a"[..]..&b"c"[..]));
+        assert_eq!(
+            bounds.start_bound(),
+            Bound::Included(&(ns.to_bytes(), a.to_bytes(), b"a".to_vec().into()))
+        );
+        assert_eq!(
+            bounds.end_bound(),
+            Bound::Excluded(&(ns.to_bytes(), a.to_bytes(), b"c".to_vec().into()))
+        );
+
+        let id = (ns.to_bytes(), a.to_bytes(), Bytes::from(b"b".to_vec()));
+        let after = bounds.after(id.clone(), &SortDirection::Asc);
+        assert_eq!(after.start_bound(), Bound::Excluded(&id));
+        assert!(!after.is_empty());
+
+        let id = (ns.to_bytes(), a.to_bytes(), Bytes::from(b"c".to_vec()));
+        let after = RecordsBounds::author_key(ns, a, KeyFilter::range(&b"a"[..]..&b"c"[..]))
+            .after(id, &SortDirection::Asc);
+        assert!(after.is_empty());
+
+        let bounds = ByKeyBounds::new(ns, &KeyFilter::range(&b"b"[..]..=&b"c"[..]));
+        assert_eq!(
+            bounds.0,
+            Bound::Included((ns.to_bytes(), b"b".to_vec().into(), [0u8; 32]))
+        );
+        assert_eq!(
+            bounds.1,
+            Bound::Included((ns.to_bytes(), b"c".to_vec().into(), [255u8; 32]))
+        );
+        let id = (ns.to_bytes(), Bytes::from(b"a".to_vec()), [0u8; 32]);
+        let after = bounds.after(id, &SortDirection::Desc);
+        assert!(after.is_empty());
+    }
+
     #[test]
     fn by_key_bounds() {
         let ns = NamespaceId::from(&[255u8; 32]);
@@ -8,7 +8,7 @@ use crate::{Capability, NamespaceSecret};
 
 use super::{
     LATEST_PER_AUTHOR_TABLE, NAMESPACES_TABLE, NAMESPACES_TABLE_V1, RECORDS_BY_KEY_TABLE,
-    RECORDS_TABLE,
+    RECORDS_TABLE, USAGE_TABLE,
 };
 
 /// Run all database migrations, if needed.
@@ -17,6 +17,7 @@ pub fn run_migrations(db: &Database) -> Result<()> {
     run_migration(db, migration_002_namespaces_populate_v2)?;
     run_migration(db, migration_003_namespaces_delete_v1)?;
     run_migration(db, migration_004_populate_by_key_index)?;
+    run_migration(db, migration_005_populate_usage_table)?;
     Ok(())
 }
 
@@ -137,3 +138,30 @@ fn migration_004_populate_by_key_index(tx: &WriteTransaction) -> Result<MigrateO
     }
     Ok(MigrateOutcome::Execute(len))
 }
+
+/// migration 005: populate the usage table (which did not exist before)
+fn migration_005_populate_usage_table(tx: &WriteTransaction) -> Result<MigrateOutcome> {
+    let mut usage_table = tx.open_table(USAGE_TABLE)?;
+    let records_table = tx.open_table(RECORDS_TABLE)?;
+    if !usage_table.is_empty()? || records_table.is_empty()? {
+        return Ok(MigrateOutcome::Skip);
+    }
+
+    let mut usage: HashMap<[u8; 32], (u64, u64)> = HashMap::new();
+    for next in records_table.iter()? {
+        let next = next?;
+        let (namespace, _author, _key) = next.0.value();
+        let (_timestamp, _namespace_sig, _author_sig, len, _hash) = next.1.value();
+        let (entries, content_len) = usage.entry(*namespace).or_default();
+        // empty entries are deletion markers and do not count towards the quota
+        if len > 0 {
+            *entries += 1;
+            *content_len += len;
+        }
+    }
+    let len = usage.len();
+    for (namespace, value) in usage {
+        usage_table.insert(&namespace, value)?;
+    }
+    Ok(MigrateOutcome::Execute(len))
+}
@@ -6,16 +6,17 @@ use redb::Database;
 
 use crate::{
     store::{
-        util::{IndexKind, LatestPerKeySelector, SelectorRes},
-        AuthorFilter, KeyFilter, Query,
+        util::{count_latest_per_key, sort_history, IndexKind, LatestPerKeySelector, SelectorRes},
+        AuthorFilter, KeyFilter, Query, SortDirection,
     },
     AuthorId, NamespaceId, SignedEntry,
 };
 
 use super::{
     bounds::{ByKeyBounds, RecordsBounds},
+    for_each_history_record, history_entries,
     ranges::{RecordsByKeyRange, RecordsRange},
-    RecordsValue,
+    RecordsId, RecordsValue,
 };
 
 /// A query iterator for entry queries.
@@ -38,6 +39,10 @@ enum QueryRange<'a> {
         author_filter: AuthorFilter,
         selector: Option<LatestPerKeySelector>,
     },
+    History {
+        entries: std::vec::IntoIter<SignedEntry>,
+    },
+    Empty,
 }
 
 impl<'a> QueryIterator<'a> {
@@ -55,10 +60,25 @@ impl<'a> QueryIterator<'a> {
                     // no author set => full table scan with the provided key filter
                     AuthorFilter::Any => (RecordsBounds::namespace(namespace), key_filter),
                 };
-                let range = RecordsRange::with_bounds(db, bounds)?;
-                QueryRange::AuthorKey {
-                    range,
-                    key_filter: filter,
+                let bounds = match query.cursor() {
+                    None => bounds,
+                    Some(cursor) => {
+                        let id = (
+                            namespace.to_bytes(),
+                            cursor.author().to_bytes(),
+                            cursor.key().to_vec().into(),
+                        );
+                        bounds.after(id, &query.sort_direction)
+                    }
+                };
+                if bounds.is_empty() {
+                    QueryRange::Empty
+                } else {
+                    let range = RecordsRange::with_bounds(db, bounds)?;
+                    QueryRange::AuthorKey {
+                        range,
+                        key_filter: filter,
+                    }
                 }
             }
             IndexKind::KeyAuthor {
@@ -67,12 +87,63 @@ impl<'a> QueryIterator<'a> {
                 latest_per_key,
             } => {
                 let bounds = ByKeyBounds::new(namespace, &range);
-                let range = RecordsByKeyRange::with_bounds(db, bounds)?;
-                let selector = latest_per_key.then(LatestPerKeySelector::default);
-                QueryRange::KeyAuthor {
-                    author_filter,
-                    range,
-                    selector,
+                let bounds = match query.cursor() {
+                    None => bounds,
+                    Some(cursor) => {
+                        let key = cursor.key().to_vec().into();
+                        // when selecting the latest entry per key, skip all entries for the
+                        // cursor's key, regardless of their author.
+                        let author = match (latest_per_key, &query.sort_direction) {
+                            (false, _) => cursor.author().to_bytes(),
+                            (true, SortDirection::Asc) => [255u8; 32],
+                            (true, SortDirection::Desc) => [0u8; 32],
+                        };
+                        bounds.after((namespace.to_bytes(), key, author), &query.sort_direction)
+                    }
+                };
+                if bounds.is_empty() {
+                    QueryRange::Empty
+                } else {
+                    let range = RecordsByKeyRange::with_bounds(db, bounds)?;
+                    let selector = latest_per_key.then(LatestPerKeySelector::default);
+                    QueryRange::KeyAuthor {
+                        author_filter,
+                        range,
+                        selector,
+                    }
+                }
+            }
+            IndexKind::History {
+                ref range,
+                ref author_filter,
+            } => {
+                // the history table and the records table are separate indexes, so collect
+                // the superseded and the current entries for the matching keys and merge them
+                // in memory.
+                let filter = |entry: &SignedEntry| {
+                    range.matches(entry.key())
+                        && author_filter.matches(&entry.author())
+                        && query.matches_value(entry.timestamp(), entry.content_len())
+                };
+                let mut entries = history_entries(db, namespace, range, filter)?;
+                let bounds = ByKeyBounds::new(namespace, range);
+                let mut current = RecordsByKeyRange::with_bounds(db, bounds)?;
+                while let Some(entry) = current.next_filtered(&SortDirection::Asc, |_| true) {
+                    let entry = entry?;
+                    if filter(&entry) {
+                        entries.push(entry);
+                    }
+                }
+                sort_history(&mut entries);
+                if matches!(query.sort_direction, SortDirection::Desc) {
+                    entries.reverse();
+                }
+                if let Some(cursor) = query.cursor() {
+                    entries
+                        .retain(|entry| index_kind.is_after(cursor, &query.sort_direction, entry));
+                }
+                QueryRange::History {
+                    entries: entries.into_iter(),
                 }
             }
         };
@@ -84,6 +155,134 @@ impl<'a> QueryIterator<'a> {
             count: 0,
         })
     }
+
+    /// Count the entries of the query, without deserializing them.
+    fn count_entries(mut self) -> Result<u64> {
+        let query = &self.query;
+        let matches_value = |record: &CountedRecord| {
+            (query.include_empty || !record.empty)
+                && query.matches_value(record.timestamp, record.len)
+        };
+        let matching = match &mut self.range {
+            QueryRange::AuthorKey { range, key_filter } => {
+                // stop once the entries up to the limit are counted.
+                let max = query
+                    .limit()
+                    .map_or(u64::MAX, |limit| limit.saturating_add(query.offset()));
+                let mut matching = 0;
+                while matching < max {
+                    match range.skip_filtered(&query.sort_direction, |id, value| {
+                        matches_author_key(query, key_filter, id, value)
+                    }) {
+                        None => break,
+                        Some(res) => res?,
+                    }
+                    matching += 1;
+                }
+                matching
+            }
+            QueryRange::KeyAuthor {
+                range,
+                author_filter,
+                selector,
+            } => {
+                let records = std::iter::from_fn(|| {
+                    range.next_filtered_map(
+                        &query.sort_direction,
+                        |(_ns, _key, author)| author_filter.matches(&(AuthorId::from(author))),
+                        CountedRecord::new,
+                    )
+                });
+                if selector.is_some() {
+                    count_latest_per_key(
+                        records,
+                        |record| (&record.key, record.timestamp),
+                        matches_value,
+                    )?
+                } else {
+                    let mut matching = 0;
+                    for record in records {
+                        if matches_value(&record?) {
+                            matching += 1;
+                        }
+                    }
+                    matching
+                }
+            }
+            QueryRange::History { entries } => entries
+                .filter(|entry| query.include_empty || !entry.is_empty())
+                .count() as u64,
+            QueryRange::Empty => 0,
+        };
+        Ok(query.limit_count(matching))
+    }
+}
+
+/// Count the entries of a query, without deserializing them.
+pub fn count_entries(db: &Arc<Database>, namespace: NamespaceId, query: Query) -> Result<u64> {
+    let index_kind = IndexKind::from(&query);
+    let IndexKind::History {
+        range,
+        author_filter,
+    } = &index_kind
+    else {
+        return QueryIterator::new(db, namespace, query)?.count_entries();
+    };
+
+    // count the superseded and the current entries for the matching keys.
+    let counts = |id: RecordsId<'_>, value: RecordsValue<'_>| {
+        let record = CountedRecord::new(id, value);
+        range.matches(&record.key)
+            && author_filter.matches(&record.author)
+            && (query.include_empty || !record.empty)
+            && query.matches_value(record.timestamp, record.len)
+            && query.cursor().map_or(true, |cursor| {
+                index_kind.is_after_parts(
+                    cursor,
+                    &query.sort_direction,
+                    &record.key,
+                    record.author,
+                    record.timestamp,
+                )
+            })
+    };
+    let mut matching = 0;
+    for_each_history_record(db, namespace, range, |id, value| {
+        if counts(id, value) {
+            matching += 1;
+        }
+    })?;
+    let bounds = ByKeyBounds::new(namespace, range);
+    let mut current = RecordsByKeyRange::with_bounds(db, bounds)?;
+    while let Some(counted) = current.next_filtered_map(&SortDirection::Asc, |_| true, counts) {
+        if counted? {
+            matching += 1;
+        }
+    }
+    Ok(query.limit_count(matching))
+}
+
+/// The parts of a record which the count of a query depends on.
+struct CountedRecord {
+    key: Vec<u8>,
+    author: AuthorId,
+    timestamp: u64,
+    len: u64,
+    empty: bool,
+}
+
+impl CountedRecord {
+    fn new(id: RecordsId<'_>, value: RecordsValue<'_>) -> Self {
+        let (_ns, author, key) = id;
+        let (timestamp, _namespace_sig, _author_sig, len, _hash) = value;
+        Self {
+            key: key.to_vec(),
+            author: AuthorId::from(author),
+            timestamp,
+            len,
+            empty: value_is_empty(&value),
+        }
+    }
 }
 
 impl<'a> Iterator for QueryIterator<'a> {
@@ -100,9 +299,8 @@ impl<'a> Iterator for QueryIterator<'a> {
             let next = match &mut self.range {
                 QueryRange::AuthorKey { range, key_filter } => {
                     // get the next entry from the query range, filtered by the key and empty filters
-                    range.next_filtered(&self.query.sort_direction, |(_ns, _author, key), value| {
-                        key_filter.matches(key)
-                            && (self.query.include_empty || !value_is_empty(&value))
+                    range.next_filtered(&self.query.sort_direction, |id, value| {
+                        matches_author_key(&self.query, key_filter, id, value)
                     })
                 }
 
@@ -139,8 +337,24 @@ impl<'a> Iterator for QueryIterator<'a> {
                         continue;
                     }
 
+                    // skip the entry if filtered out by timestamp or content length. this is
+                    // checked after selecting the latest entry per key.
+                    if matches!(&next, Some(e) if !self.query.matches_value(e.timestamp(), e.content_len()))
+                    {
+                        continue;
+                    }
+
                     break next.map(Result::Ok);
                 },
+
+                QueryRange::History { entries } => loop {
+                    match entries.next() {
+                        Some(entry) if !self.query.include_empty && entry.is_empty() => continue,
+                        next => break next.map(Result::Ok),
+                    }
+                },
+
+                QueryRange::Empty => None,
             };
 
             // skip the entry if we didn't get past the requested offset yet.
@@ -155,6 +369,21 @@ impl<'a> Iterator for QueryIterator<'a> {
     }
 }
 
+/// Check if a row of the records table matches the key, empty, timestamp and content length
+/// filters of a query.
+fn matches_author_key(
+    query: &Query,
+    key_filter: &KeyFilter,
+    id: RecordsId<'_>,
+    value: RecordsValue<'_>,
+) -> bool {
+    let (_ns, _author, key) = id;
+    let (timestamp, _namespace_sig, _author_sig, len, _hash) = value;
+    key_filter.matches(key)
+        && (query.include_empty || !value_is_empty(&value))
+        && query.matches_value(timestamp, len)
+}
+
 fn value_is_empty(value: &RecordsValue) -> bool {
     let (_timestamp, _namespace_sig, _author_sig, _len, hash) = value;
     *hash == Hash::EMPTY.as_bytes()
@@ -181,6 +181,17 @@ impl<'a> RecordsRange<'a> {
         self.0.next_filtered(direction, filter, into_entry)
     }
 
+    /// Advance to the next item in the range without deserializing it.
+    ///
+    /// Omit items for which the `matcher` function returns false.
+    pub(super) fn skip_filtered(
+        &mut self,
+        direction: &SortDirection,
+        filter: impl for<'x> Fn(RecordsId<'x>, RecordsValue<'x>) -> bool,
+    ) -> Option<anyhow::Result<()>> {
+        self.0.next_filtered(direction, filter, |_id, _value| ())
+    }
+
     pub(super) fn next_mapped<T>(
         &mut self,
         map: impl for<'x> Fn(RecordsId<'x>, RecordsValue<'x>) -> T,
@@ -245,6 +256,18 @@ impl<'a> RecordsByKeyRange<'a> {
         direction: &SortDirection,
         filter: impl for<'x> Fn(RecordsByKeyId<'x>) -> bool,
     ) -> Option<anyhow::Result<SignedEntry>> {
+        self.next_filtered_map(direction, filter, into_entry)
+    }
+
+    /// Get the next item in the range, mapped with `map` instead of deserializing it.
+    ///
+    /// Omit items for which the `matcher` function returns false.
+    pub fn next_filtered_map<T>(
+        &mut self,
+        direction: &SortDirection,
+        filter: impl for<'x> Fn(RecordsByKeyId<'x>) -> bool,
+        map: impl for<'x> Fn(RecordsId<'x>, RecordsValue<'x>) -> T,
+    ) -> Option<anyhow::Result<T>> {
         self.0.with_mut(|fields| {
             let by_key_id = loop {
                 let next = match direction {
@@ -265,7 +288,7 @@ impl<'a> RecordsByKeyRange<'a> {
             let records_id = (namespace, author, key);
             let entry = fields.records_table.get(&records_id);
             match entry {
-                Ok(Some(entry)) => Some(Ok(into_entry(records_id, entry.value()))),
+                Ok(Some(entry)) => Some(Ok(map(records_id, entry.value()))),
                 Ok(None) => None,
                 Err(err) => Some(Err(err.into())),
             }
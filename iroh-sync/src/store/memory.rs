@@ -1,7 +1,7 @@
 //! In memory storage for replicas.
 
 use std::{
-    collections::{BTreeMap, HashMap, HashSet},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     convert::Infallible,
     sync::Arc,
 };
@@ -15,14 +15,15 @@ use parking_lot::{MappedRwLockReadGuard, RwLock, RwLockReadGuard};
 use crate::{
     keys::Author,
     ranger::{Fingerprint, Range, RangeEntry},
-    sync::{RecordIdentifier, Replica, SignedEntry},
+    sync::{system_time_now, RecordIdentifier, Replica, SignedEntry},
     AuthorId, Capability, CapabilityKind, NamespaceId, PeerIdBytes, Record,
 };
 
 use super::{
     pubkeys::MemPublicKeyStore,
-    util::{IndexKind, LatestPerKeySelector, SelectorRes},
-    DownloadPolicy, ImportNamespaceOutcome, OpenError, PublicKeyStore, Query, SortDirection,
+    util::{count_latest_per_key, sort_history, IndexKind, LatestPerKeySelector, SelectorRes},
+    AreaOfInterest, DownloadPolicy, HistoryPolicy, ImportNamespaceOutcome, IndexSpec, KeyFilter,
+    OpenError, PublicKeyStore, Query, Quota, SortDirection, Usage,
 };
 
 type SyncPeersCache = Arc<RwLock<HashMap<NamespaceId, lru::LruCache<PeerIdBytes, ()>>>>;
@@ -34,6 +35,12 @@ pub struct Store {
     namespaces: Arc<RwLock<HashMap<NamespaceId, Capability>>>,
     authors: Arc<RwLock<HashMap<AuthorId, Author>>>,
     download_policies: Arc<RwLock<HashMap<NamespaceId, DownloadPolicy>>>,
+    history_policies: Arc<RwLock<HashMap<NamespaceId, HistoryPolicy>>>,
+    quotas: Arc<RwLock<HashMap<NamespaceId, Quota>>>,
+    sync_areas: Arc<RwLock<HashMap<NamespaceId, AreaOfInterest>>>,
+    labels: Arc<RwLock<HashMap<NamespaceId, String>>>,
+    /// Secondary indexes by namespace
+    indexes: Arc<RwLock<HashMap<NamespaceId, IndexMap>>>,
     /// Stores records by namespace -> identifier + timestamp
     replica_records: Arc<RwLock<ReplicaRecordsOwned>>,
     /// Stores the latest entry for each author
@@ -50,31 +57,141 @@ type ReplicaRecordsOwned = BTreeMap<NamespaceId, RecordMap>;
 struct RecordMap {
     by_author: BTreeMap<(AuthorId, Key), SignedEntry>,
     by_key: BTreeMap<(Key, AuthorId), ()>,
+    /// Superseded entries by key, author and timestamp
+    history: BTreeMap<(Key, AuthorId, u64), SignedEntry>,
+    usage: Usage,
 }
 
 impl RecordMap {
-    fn insert(&mut self, entry: SignedEntry) {
+    fn insert(&mut self, entry: SignedEntry) -> Option<SignedEntry> {
         self.by_key
             .insert((entry.id().key_bytes(), entry.author()), ());
-        self.by_author
+        self.usage.add(&entry);
+        let replaced = self
+            .by_author
             .insert((entry.author(), entry.id().key_bytes()), entry);
+        if let Some(replaced) = &replaced {
+            self.usage.remove(replaced);
+        }
+        replaced
+    }
+    fn insert_history(
+        &mut self,
+        policy: &HistoryPolicy,
+        entries: impl IntoIterator<Item = SignedEntry>,
+    ) {
+        let mut touched = HashSet::new();
+        for entry in entries {
+            let key = (entry.id().key_bytes(), entry.author());
+            self.history
+                .insert((key.0.clone(), key.1, entry.timestamp()), entry);
+            touched.insert(key);
+        }
+        let now = system_time_now();
+        for (key, author) in touched {
+            let start = (key.clone(), author, u64::MIN);
+            let end = (key, author, u64::MAX);
+            let expired: Vec<_> = self
+                .history
+                .range(start..=end)
+                .rev()
+                .enumerate()
+                .filter(|(index, (_id, entry))| {
+                    !policy.retains(*index as u64, entry.timestamp(), now)
+                })
+                .map(|(_index, (id, _entry))| id.clone())
+                .collect();
+            for id in expired {
+                self.history.remove(&id);
+            }
+        }
     }
     fn remove(&mut self, id: &RecordIdentifier) -> Option<SignedEntry> {
         let entry = self.by_author.remove(&(id.author(), id.key_bytes()));
         self.by_key.remove(&(id.key_bytes(), id.author()));
+        if let Some(entry) = &entry {
+            self.usage.remove(entry);
+        }
         entry
     }
-    fn len(&self) -> usize {
-        self.by_author.len()
-    }
-    fn retain(&mut self, f: impl Fn(&(AuthorId, Key), &mut SignedEntry) -> bool) {
+    fn retain(
+        &mut self,
+        f: impl Fn(&(AuthorId, Key), &mut SignedEntry) -> bool,
+    ) -> Vec<SignedEntry> {
+        let mut removed = vec![];
         self.by_author.retain(|key, value| {
             let retain = f(key, value);
             if !retain {
                 self.by_key.remove(&(key.1.clone(), key.0));
+                self.usage.remove(value);
+                removed.push(value.clone());
             }
             retain
-        })
+        });
+        removed
+    }
+}
+
+#[derive(Debug, Default)]
+struct IndexMap {
+    specs: BTreeMap<String, IndexSpec>,
+    rows: IndexRows,
+}
+
+#[derive(Debug, Default)]
+struct IndexRows {
+    /// Indexed entries by index name, value, author and key
+    entries: BTreeSet<(String, Bytes, AuthorId, Key)>,
+    /// Indexed values by author, key and index name
+    values: BTreeMap<(AuthorId, Key, String), Bytes>,
+}
+
+impl IndexRows {
+    fn set(&mut self, name: &str, author: AuthorId, key: &Key, value: Option<Bytes>) {
+        self.remove(name, author, key);
+        if let Some(value) = value {
+            self.entries
+                .insert((name.to_string(), value.clone(), author, key.clone()));
+            self.values
+                .insert((author, key.clone(), name.to_string()), value);
+        }
+    }
+
+    fn remove(&mut self, name: &str, author: AuthorId, key: &Key) {
+        if let Some(value) = self.values.remove(&(author, key.clone(), name.to_string())) {
+            self.entries
+                .remove(&(name.to_string(), value, author, key.clone()));
+        }
+    }
+
+    fn remove_index(&mut self, name: &str) {
+        self.entries.retain(|(n, _value, _author, _key)| n != name);
+        self.values.retain(|(_author, _key, n), _value| n != name);
+    }
+}
+
+impl IndexMap {
+    /// Update the indexes for a new entry which replaced the entry `replaced`.
+    fn insert(&mut self, entry: &SignedEntry, replaced: Option<&SignedEntry>) {
+        let key = entry.id().key_bytes();
+        for spec in self.specs.values() {
+            // keep content based values if the content did not change
+            let same_content = replaced.is_some_and(|r| r.content_hash() == entry.content_hash());
+            if spec.extractor.uses_content() && same_content && !entry.is_empty() {
+                continue;
+            }
+            let value = match entry.is_empty() {
+                true => None,
+                false => spec.extractor.extract(entry.key(), None),
+            };
+            self.rows.set(&spec.name, entry.author(), &key, value);
+        }
+    }
+
+    fn remove(&mut self, author: AuthorId, key: &Key) {
+        for name in self.specs.keys() {
+            self.rows.remove(name, author, key);
+        }
     }
 }
 
@@ -100,7 +217,16 @@ impl super::Store for Store {
             let namespace = namespaces.get(id).ok_or(OpenError::NotFound)?;
             namespace.clone()
         };
-        let replica = Replica::new(namespace, ReplicaStoreInstance::new(*id, self.clone()));
+        let mut replica = Replica::new(namespace, ReplicaStoreInstance::new(*id, self.clone()));
+        // start the clock of the replica after the latest entry in the store
+        let mut latest = None;
+        for entry in self.get_latest_for_each_author(*id)? {
+            let (_author, timestamp, _key) = entry?;
+            latest = latest.max(Some(timestamp));
+        }
+        if let Some(latest) = latest {
+            replica.observe_timestamp(latest);
+        }
         self.open_replicas.write().insert(*id);
         Ok(replica)
     }
@@ -131,6 +257,10 @@ impl super::Store for Store {
         Ok(())
     }
 
+    fn delete_author(&self, author: &AuthorId) -> Result<bool> {
+        Ok(self.authors.write().remove(author).is_some())
+    }
+
     fn list_authors(&self) -> Result<Self::AuthorsIter<'_>> {
         // TODO: avoid collect?
         Ok(self
@@ -166,6 +296,11 @@ impl super::Store for Store {
         self.namespaces.write().remove(namespace);
         self.peers_per_doc.write().remove(namespace);
         self.download_policies.write().remove(namespace);
+        self.history_policies.write().remove(namespace);
+        self.quotas.write().remove(namespace);
+        self.sync_areas.write().remove(namespace);
+        self.labels.write().remove(namespace);
+        self.indexes.write().remove(namespace);
         Ok(())
     }
 
@@ -179,6 +314,12 @@ impl super::Store for Store {
         Ok(QueryIterator::new(records, namespace, query))
     }
 
+    fn count(&self, namespace: NamespaceId, query: impl Into<Query>) -> Result<u64> {
+        let query = query.into();
+        let records = self.replica_records.read();
+        QueryIterator::new(records, namespace, query).count_entries()
+    }
+
     fn get_exact(
         &self,
         namespace: NamespaceId,
@@ -261,6 +402,178 @@ impl super::Store for Store {
             .cloned()
             .unwrap_or_default())
     }
+
+    fn set_history_policy(&self, namespace: &NamespaceId, policy: HistoryPolicy) -> Result<()> {
+        anyhow::ensure!(
+            self.namespaces.read().contains_key(namespace),
+            "document not created"
+        );
+
+        self.history_policies.write().insert(*namespace, policy);
+        Ok(())
+    }
+
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy> {
+        Ok(self
+            .history_policies
+            .read()
+            .get(namespace)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    fn set_sync_area(&self, namespace: &NamespaceId, area: AreaOfInterest) -> Result<()> {
+        anyhow::ensure!(
+            self.namespaces.read().contains_key(namespace),
+            "document not created"
+        );
+
+        self.sync_areas.write().insert(*namespace, area);
+        Ok(())
+    }
+
+    fn get_sync_area(&self, namespace: &NamespaceId) -> Result<AreaOfInterest> {
+        Ok(self
+            .sync_areas
+            .read()
+            .get(namespace)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn set_label(&self, namespace: &NamespaceId, label: Option<String>) -> Result<()> {
+        anyhow::ensure!(
+            self.namespaces.read().contains_key(namespace),
+            "document not created"
+        );
+
+        let mut labels = self.labels.write();
+        match label {
+            Some(label) => labels.insert(*namespace, label),
+            None => labels.remove(namespace),
+        };
+        Ok(())
+    }
+
+    fn get_label(&self, namespace: &NamespaceId) -> Result<Option<String>> {
+        Ok(self.labels.read().get(namespace).cloned())
+    }
+
+    fn set_quota(&self, namespace: &NamespaceId, quota: Quota) -> Result<()> {
+        anyhow::ensure!(
+            self.namespaces.read().contains_key(namespace),
+            "document not created"
+        );
+
+        self.quotas.write().insert(*namespace, quota);
+        Ok(())
+    }
+
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota> {
+        Ok(self
+            .quotas
+            .read()
+            .get(namespace)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage> {
+        Ok(self
+            .replica_records
+            .read()
+            .get(namespace)
+            .map(|records| records.usage)
+            .unwrap_or_default())
+    }
+
+    fn create_index(&self, namespace: &NamespaceId, spec: IndexSpec) -> Result<()> {
+        anyhow::ensure!(
+            self.namespaces.read().contains_key(namespace),
+            "document not created"
+        );
+
+        let records = self.replica_records.read();
+        let mut indexes = self.indexes.write();
+        let index = indexes.entry(*namespace).or_default();
+        index.rows.remove_index(&spec.name);
+        if let Some(records) = records.get(namespace) {
+            for ((author, key), entry) in records.by_author.iter() {
+                if !entry.is_empty() {
+                    let value = spec.extractor.extract(key, None);
+                    index.rows.set(&spec.name, *author, key, value);
+                }
+            }
+        }
+        index.specs.insert(spec.name.clone(), spec);
+        Ok(())
+    }
+
+    fn remove_index(&self, namespace: &NamespaceId, name: &str) -> Result<bool> {
+        let mut indexes = self.indexes.write();
+        let Some(index) = indexes.get_mut(namespace) else {
+            return Ok(false);
+        };
+        index.rows.remove_index(name);
+        Ok(index.specs.remove(name).is_some())
+    }
+
+    fn list_indexes(&self, namespace: &NamespaceId) -> Result<Vec<IndexSpec>> {
+        Ok(self
+            .indexes
+            .read()
+            .get(namespace)
+            .map(|index| index.specs.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn index_content(&self, namespace: &NamespaceId, hash: Hash, content: &[u8]) -> Result<usize> {
+        let records = self.replica_records.read();
+        let mut indexes = self.indexes.write();
+        let (Some(records), Some(index)) = (records.get(namespace), indexes.get_mut(namespace))
+        else {
+            return Ok(0);
+        };
+        let mut count = 0;
+        for ((author, key), entry) in records.by_author.iter() {
+            if entry.is_empty() || entry.content_hash() != hash {
+                continue;
+            }
+            for spec in index.specs.values() {
+                if spec.extractor.uses_content() {
+                    let value = spec.extractor.extract(key, Some(content));
+                    index.rows.set(&spec.name, *author, key, value);
+                }
+            }
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn get_by_index(
+        &self,
+        namespace: NamespaceId,
+        name: &str,
+        filter: KeyFilter,
+    ) -> Result<Vec<SignedEntry>> {
+        let records = self.replica_records.read();
+        let indexes = self.indexes.read();
+        let (Some(records), Some(index)) = (records.get(&namespace), indexes.get(&namespace))
+        else {
+            return Ok(vec![]);
+        };
+        anyhow::ensure!(index.specs.contains_key(name), "index not found");
+        let entries = index
+            .rows
+            .entries
+            .iter()
+            .filter(|(n, value, _author, _key)| n == name && filter.matches(value))
+            .filter_map(|(_name, _value, author, key)| {
+                records.by_author.get(&(*author, key.clone())).cloned()
+            })
+            .collect();
+        Ok(entries)
+    }
 }
 
 /// Iterator over all content hashes in the memory store.
@@ -325,6 +638,8 @@ pub struct QueryIterator<'a> {
     count: u64,
     // number of entries skipped at the beginning
     offset: u64,
+    // sorted entries for history queries, collected on first use
+    history: Option<Vec<SignedEntry>>,
 }
 
 impl<'a> QueryIterator<'a> {
@@ -346,8 +661,83 @@ impl<'a> QueryIterator<'a> {
             position: 0,
             offset: 0,
             count: 0,
+            history: None,
         }
     }
+
+    /// Count the entries of the query, without cloning them.
+    fn count_entries(&self) -> Result<u64> {
+        let Some(records) = self.records.get(&self.namespace) else {
+            return Ok(0);
+        };
+        let query = &self.query;
+        let is_after_cursor = |entry: &SignedEntry| match query.cursor() {
+            None => true,
+            Some(cursor) => self.index.is_after(cursor, &query.sort_direction, entry),
+        };
+        let matches_value = |entry: &SignedEntry| {
+            (query.include_empty || !entry.is_empty())
+                && query.matches_value(entry.timestamp(), entry.content_len())
+        };
+        let matching = match &self.index {
+            IndexKind::AuthorKey { range, key_filter } => records
+                .by_author
+                .values()
+                .filter(|entry| {
+                    range.matches(&entry.author())
+                        && key_filter.matches(entry.key())
+                        && matches_value(entry)
+                        && is_after_cursor(entry)
+                })
+                .count() as u64,
+            IndexKind::KeyAuthor {
+                range,
+                author_filter,
+                latest_per_key,
+            } => {
+                let mut entries: Vec<&SignedEntry> = records
+                    .by_key
+                    .keys()
+                    .flat_map(|k| records.by_author.get(&(k.1, k.0.clone())).into_iter())
+                    .filter(|entry| {
+                        range.matches(entry.key())
+                            && author_filter.matches(&entry.author())
+                            && is_after_cursor(entry)
+                    })
+                    .collect();
+                if *latest_per_key {
+                    if matches!(query.sort_direction, SortDirection::Desc) {
+                        entries.reverse();
+                    }
+                    count_latest_per_key(
+                        entries.into_iter().map(Ok),
+                        |entry| (entry.key(), entry.timestamp()),
+                        |entry| matches_value(entry),
+                    )?
+                } else {
+                    entries
+                        .into_iter()
+                        .filter(|entry| matches_value(entry))
+                        .count() as u64
+                }
+            }
+            IndexKind::History {
+                range,
+                author_filter,
+            } => records
+                .by_author
+                .values()
+                .chain(records.history.values())
+                .filter(|entry| {
+                    range.matches(entry.key())
+                        && author_filter.matches(&entry.author())
+                        && matches_value(entry)
+                        && is_after_cursor(entry)
+                })
+                .count() as u64,
+        };
+        Ok(query.limit_count(matching))
+    }
 }
 
 impl<'a> Iterator for QueryIterator<'a> {
@@ -362,6 +752,12 @@ impl<'a> Iterator for QueryIterator<'a> {
             }
 
             let records = self.records.get(&self.namespace)?;
+            let is_after_cursor = |entry: &SignedEntry| match self.query.cursor() {
+                None => true,
+                Some(cursor) => self
+                    .index
+                    .is_after(cursor, &self.query.sort_direction, entry),
+            };
 
             let entry = match &self.index {
                 IndexKind::AuthorKey { range, key_filter } => {
@@ -372,6 +768,10 @@ impl<'a> Iterator for QueryIterator<'a> {
                             range.matches(&entry.author())
                                 && key_filter.matches(entry.key())
                                 && (self.query.include_empty || !entry.is_empty())
+                                && self
+                                    .query
+                                    .matches_value(entry.timestamp(), entry.content_len())
+                                && is_after_cursor(entry)
                         })
                         .map(|(_key, entry)| entry);
 
@@ -391,7 +791,9 @@ impl<'a> Iterator for QueryIterator<'a> {
                         .keys()
                         .flat_map(|k| records.by_author.get(&(k.1, k.0.clone())).into_iter())
                         .filter(|entry| {
-                            range.matches(entry.key()) && author_filter.matches(&entry.author())
+                            range.matches(entry.key())
+                                && author_filter.matches(&entry.author())
+                                && is_after_cursor(entry)
                         });
                     let next = match self.query.sort_direction {
                         SortDirection::Asc => iter.nth(self.position),
@@ -420,10 +822,47 @@ impl<'a> Iterator for QueryIterator<'a> {
                     if !self.query.include_empty && entry.is_empty() {
                         self.position += 1;
                         continue;
-                    } else {
-                        break Some(entry);
                     }
+                    // filter by timestamp and content length after selecting the latest entry
+                    if !self
+                        .query
+                        .matches_value(entry.timestamp(), entry.content_len())
+                    {
+                        self.position += 1;
+                        continue;
+                    }
+                    break Some(entry);
                 },
+                IndexKind::History {
+                    range,
+                    author_filter,
+                } => {
+                    let entries = self.history.get_or_insert_with(|| {
+                        let mut entries: Vec<_> = records
+                            .by_author
+                            .values()
+                            .chain(records.history.values())
+                            .filter(|entry| {
+                                range.matches(entry.key())
+                                    && author_filter.matches(&entry.author())
+                                    && (self.query.include_empty || !entry.is_empty())
+                                    && self
+                                        .query
+                                        .matches_value(entry.timestamp(), entry.content_len())
+                                    && is_after_cursor(entry)
+                            })
+                            .cloned()
+                            .collect();
+                        sort_history(&mut entries);
+                        entries
+                    });
+                    let mut iter = entries.iter();
+                    let next = match self.query.sort_direction {
+                        SortDirection::Asc => iter.nth(self.position),
+                        SortDirection::Desc => iter.nth_back(self.position),
+                    };
+                    next.cloned()
+                }
             };
 
             self.position += 1;
@@ -450,6 +889,21 @@ impl super::DownloadPolicyStore for ReplicaStoreInstance {
     }
 }
 
+impl super::QuotaStore for ReplicaStoreInstance {
+    fn get_quota(&self, namespace: &NamespaceId) -> Result<Quota> {
+        super::Store::get_quota(&self.store, namespace)
+    }
+    fn get_usage(&self, namespace: &NamespaceId) -> Result<Usage> {
+        super::Store::get_usage(&self.store, namespace)
+    }
+}
+
+impl super::HistoryPolicyStore for ReplicaStoreInstance {
+    fn get_history_policy(&self, namespace: &NamespaceId) -> Result<HistoryPolicy> {
+        self.store.get_history_policy(namespace)
+    }
+}
+
 impl PublicKeyStore for ReplicaStoreInstance {
     fn public_key(&self, id: &[u8; 32]) -> std::result::Result<VerifyingKey, SignatureError> {
         self.store.pubkeys.public_key(id)
@@ -497,6 +951,21 @@ impl ReplicaStoreInstance {
         f(value)
     }
 
+    fn with_indexes_mut(&self, f: impl FnOnce(&mut IndexMap)) {
+        if let Some(index) = self.store.indexes.write().get_mut(&self.namespace) {
+            f(index)
+        }
+    }
+
+    fn history_policy(&self) -> HistoryPolicy {
+        self.store
+            .history_policies
+            .read()
+            .get(&self.namespace)
+            .copied()
+            .unwrap_or_default()
+    }
+
     fn records_iter(&self) -> RecordsIter<'_> {
         RecordsIter {
             namespace: self.namespace,
@@ -576,9 +1045,17 @@ impl crate::ranger::Store<SignedEntry> for ReplicaStoreInstance {
         self.with_latest_mut_with_default(|records| {
             records.insert(e.author_bytes(), (e.timestamp(), e.key().to_vec()));
         });
-        self.with_records_mut_with_default(|records| {
-            records.insert(e);
+        let history_policy = self.history_policy();
+        let replaced = self.with_records_mut_with_default(|records| {
+            let replaced = records.insert(e.clone());
+            if let Some(replaced) = &replaced {
+                if history_policy.is_enabled() && replaced.timestamp() != e.timestamp() {
+                    records.insert_history(&history_policy, [replaced.clone()]);
+                }
+            }
+            replaced
         });
+        self.with_indexes_mut(|index| index.insert(&e, replaced.as_ref()));
         Ok(())
     }
 
@@ -597,6 +1074,7 @@ impl crate::ranger::Store<SignedEntry> for ReplicaStoreInstance {
     fn remove(&mut self, key: &RecordIdentifier) -> Result<Option<SignedEntry>, Self::Error> {
         // TODO: what if we are trying to remove with the wrong timestamp?
         let res = self.with_records_mut(|records| records.and_then(|records| records.remove(key)));
+        self.with_indexes_mut(|index| index.remove(key.author(), &key.key_bytes()));
         Ok(res)
     }
 
@@ -639,16 +1117,25 @@ impl crate::ranger::Store<SignedEntry> for ReplicaStoreInstance {
         prefix: &RecordIdentifier,
         predicate: impl Fn(&Record) -> bool,
     ) -> Result<usize, Self::Error> {
-        self.with_records_mut(|records| {
+        let history_policy = self.history_policy();
+        let removed = self.with_records_mut(|records| {
             let Some(records) = records else {
-                return Ok(0);
+                return vec![];
             };
-            let old_len = records.by_author.len();
-            records.retain(|(a, k), v| {
+            let removed = records.retain(|(a, k), v| {
                 !(a == &prefix.author() && k.starts_with(prefix.key()) && predicate(v.entry()))
             });
-            Ok(old_len - records.len())
-        })
+            if history_policy.is_enabled() {
+                records.insert_history(&history_policy, removed.iter().cloned());
+            }
+            removed
+        });
+        self.with_indexes_mut(|index| {
+            for entry in &removed {
+                index.remove(entry.author(), &entry.id().key_bytes());
+            }
+        });
+        Ok(removed.len())
     }
 }
 
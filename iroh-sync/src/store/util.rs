@@ -1,8 +1,8 @@
 //! Utilities useful across different store impls.
 
-use crate::SignedEntry;
+use crate::{AuthorId, SignedEntry};
 
-use super::{AuthorFilter, KeyFilter, Query, QueryKind, SortBy};
+use super::{AuthorFilter, Cursor, KeyFilter, Query, QueryKind, SortBy, SortDirection};
 
 /// A helper for stores that have by-author and by-key indexes for records.
 #[derive(Debug)]
@@ -16,6 +16,10 @@ pub enum IndexKind {
         author_filter: AuthorFilter,
         latest_per_key: bool,
     },
+    History {
+        range: KeyFilter,
+        author_filter: AuthorFilter,
+    },
 }
 
 impl From<&Query> for IndexKind {
@@ -37,10 +41,101 @@ impl From<&Query> for IndexKind {
                 author_filter: query.filter_author.clone(),
                 latest_per_key: true,
             },
+            QueryKind::History(_) => IndexKind::History {
+                range: query.filter_key.clone(),
+                author_filter: query.filter_author.clone(),
+            },
+        }
+    }
+}
+
+impl IndexKind {
+    /// Check if an entry comes after the cursor in the order of this index and `direction`.
+    pub fn is_after(
+        &self,
+        cursor: &Cursor,
+        direction: &SortDirection,
+        entry: &SignedEntry,
+    ) -> bool {
+        self.is_after_parts(
+            cursor,
+            direction,
+            entry.key(),
+            entry.author(),
+            entry.timestamp(),
+        )
+    }
+
+    /// Like [`Self::is_after`], for the key, author and timestamp of an entry.
+    pub fn is_after_parts(
+        &self,
+        cursor: &Cursor,
+        direction: &SortDirection,
+        key: &[u8],
+        author: AuthorId,
+        timestamp: u64,
+    ) -> bool {
+        let ord = match self {
+            IndexKind::AuthorKey { .. } => (author, key).cmp(&(cursor.author(), cursor.key())),
+            IndexKind::KeyAuthor {
+                latest_per_key: true,
+                ..
+            } => key.cmp(cursor.key()),
+            IndexKind::KeyAuthor { .. } => (key, author).cmp(&(cursor.key(), cursor.author())),
+            IndexKind::History { .. } => {
+                (key, author, timestamp).cmp(&(cursor.key(), cursor.author(), cursor.timestamp()))
+            }
+        };
+        match direction {
+            SortDirection::Asc => ord.is_gt(),
+            SortDirection::Desc => ord.is_lt(),
         }
     }
 }
 
+/// Sort entries for a history query: by key, then author, then timestamp.
+pub fn sort_history(entries: &mut [SignedEntry]) {
+    entries.sort_by(|a, b| {
+        (a.key(), a.author_bytes(), a.timestamp()).cmp(&(b.key(), b.author_bytes(), b.timestamp()))
+    });
+}
+
+/// Count the latest item of each key among `items`, which must be sorted by key.
+///
+/// The latest item of a key is selected like [`LatestPerKeySelector`] does, and is counted if
+/// `filter` returns true for it.
+pub fn count_latest_per_key<T>(
+    items: impl IntoIterator<Item = anyhow::Result<T>>,
+    key_and_timestamp: impl Fn(&T) -> (&[u8], u64),
+    filter: impl Fn(&T) -> bool,
+) -> anyhow::Result<u64> {
+    let mut count = 0;
+    let mut latest: Option<T> = None;
+    for item in items {
+        let item = item?;
+        latest = match latest.take() {
+            Some(last) if key_and_timestamp(&last).0 == key_and_timestamp(&item).0 => {
+                if key_and_timestamp(&item).1 > key_and_timestamp(&last).1 {
+                    Some(item)
+                } else {
+                    Some(last)
+                }
+            }
+            Some(last) => {
+                if filter(&last) {
+                    count += 1;
+                }
+                Some(item)
+            }
+            None => Some(item),
+        };
+    }
+    if matches!(&latest, Some(last) if filter(last)) {
+        count += 1;
+    }
+    Ok(count)
+}
+
 /// Helper to extract the latest entry per key from an iterator that yields [`SignedEntry`] items.
 ///
 /// Items must be pushed in key-sorted order.
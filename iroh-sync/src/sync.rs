@@ -7,7 +7,9 @@
 // This is going to change!
 
 use std::{
+    cell::{Cell, RefCell},
     cmp::Ordering,
+    collections::{HashMap, VecDeque},
     fmt::Debug,
     sync::Arc,
     time::{Duration, SystemTime},
@@ -21,14 +23,16 @@ use iroh_metrics::{inc, inc_by};
 use ed25519_dalek::{Signature, SignatureError};
 use iroh_base::{base32, hash::Hash};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
 
 pub use crate::heads::AuthorHeads;
 #[cfg(feature = "metrics")]
 use crate::metrics::Metrics;
 use crate::{
     keys::{Author, AuthorId, AuthorPublicKey, NamespaceId, NamespacePublicKey, NamespaceSecret},
+    metadata,
     ranger::{self, Fingerprint, InsertOutcome, Peer, RangeEntry, RangeKey, RangeValue},
-    store::{self, PublicKeyStore},
+    store::{self, AreaOfInterest, PublicKeyStore, Quota, RateLimit},
 };
 
 /// Protocol message for the set reconciliation protocol.
@@ -40,13 +44,29 @@ pub type ProtocolMessage = crate::ranger::Message<SignedEntry>;
 // TODO: PeerId is in iroh-net which iroh-sync doesn't depend on. Add iroh-base crate with `PeerId`.
 pub type PeerIdBytes = [u8; 32];
 
-/// Max time in the future from our wall clock time that we accept entries for.
+/// Max time in the future from our wall clock time that we accept local entries for.
 /// Value is 10 minutes.
-pub const MAX_TIMESTAMP_FUTURE_SHIFT: u64 = 10 * 60 * Duration::from_secs(1).as_millis() as u64;
+///
+/// Entries from other peers are accepted whatever their timestamp, because their hybrid logical
+/// clock may run ahead of ours. The [`Replica`]'s own clock follows them only up to this bound, so
+/// that a peer with a broken clock can not push local timestamps arbitrarily far ahead.
+pub const MAX_TIMESTAMP_FUTURE_SHIFT: u64 = 10 * 60 * Duration::from_secs(1).as_micros() as u64;
 
 /// Callback that may be set on a replica to determine the availability status for a content hash.
 pub type ContentStatusCallback = Arc<dyn Fn(Hash) -> ContentStatus + Send + Sync + 'static>;
 
+/// Application-defined validation of entries.
+///
+/// A validator may be set on a replica with [`Replica::set_validator`]. It is consulted for local
+/// and remote inserts, after the built-in checks of signatures, namespace and timestamp passed.
+/// Entries that are rejected fail with [`ValidationFailure::Rejected`].
+pub trait Validator: std::fmt::Debug + Send + Sync + 'static {
+    /// Validate an entry before it is inserted.
+    ///
+    /// Return an error with a human-readable reason to reject the entry.
+    fn validate(&self, entry: &SignedEntry, origin: &InsertOrigin) -> Result<(), String>;
+}
+
 /// Event emitted by sync when entries are added.
 #[derive(Debug, Clone)]
 pub enum Event {
@@ -77,6 +97,8 @@ pub enum Event {
 pub enum InsertOrigin {
     /// The entry was inserted locally.
     Local,
+    /// The entry was imported locally, e.g. from a snapshot or bundle, but was signed elsewhere.
+    Import,
     /// The entry was received from the remote node identified by [`PeerIdBytes`].
     Sync {
         /// The peer from which we received this entry.
@@ -106,6 +128,50 @@ pub struct SyncOutcome {
     pub num_recv: usize,
     /// Number of entries we sent.
     pub num_sent: usize,
+    /// Number of received entries we rejected because they exceeded the document's [`Quota`] or
+    /// were rejected by the replica's [`Validator`].
+    pub num_rejected: usize,
+    /// Area of interest that is reconciled.
+    pub area: AreaOfInterest,
+    /// Number of ranges that were fully reconciled.
+    pub num_ranges_reconciled: usize,
+    /// Ranges that are not yet reconciled.
+    pub pending: PendingRanges,
+}
+
+/// Ranges of a set reconciliation that were not yet reconciled.
+///
+/// These are the ranges of the last message sent to the remote. If a sync is interrupted, it can
+/// be resumed from these ranges with [`Replica::sync_resume_message`] instead of starting over.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PendingRanges {
+    area: AreaOfInterest,
+    ranges: Vec<ranger::Range<RecordIdentifier>>,
+}
+
+impl PendingRanges {
+    /// Create pending ranges from the ranges of a message sent to the remote.
+    pub fn new(area: AreaOfInterest, message: &ProtocolMessage) -> Self {
+        Self {
+            area,
+            ranges: message.ranges().cloned().collect(),
+        }
+    }
+
+    /// The area of interest the ranges were reconciled in.
+    pub fn area(&self) -> &AreaOfInterest {
+        &self.area
+    }
+
+    /// Number of pending ranges.
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Whether no ranges are pending, which means that the reconciliation is complete.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -244,17 +310,27 @@ pub enum CapabilityError {
 
 /// Local representation of a mutable, synchronizable key-value store.
 #[derive(derive_more::Debug)]
-pub struct Replica<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore> {
+pub struct Replica<
+    S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore + store::QuotaStore,
+> {
     capability: Capability,
     peer: Peer<SignedEntry, S>,
     subscribers: Subscribers,
     #[debug("ContentStatusCallback")]
     content_status_cb: Option<ContentStatusCallback>,
+    validator: Option<Arc<dyn Validator>>,
+    author_rates: AuthorRates,
+    clock: HybridClock,
     closed: bool,
 }
 
-impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore + 'static>
-    Replica<S>
+impl<
+        S: ranger::Store<SignedEntry>
+            + PublicKeyStore
+            + store::DownloadPolicyStore
+            + store::QuotaStore
+            + 'static,
+    > Replica<S>
 {
     /// Create a new replica.
     pub fn new(capability: Capability, store: S) -> Self {
@@ -264,6 +340,9 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
             subscribers: Default::default(),
             // on_insert_sender: RwLock::new(None),
             content_status_cb: None,
+            validator: None,
+            author_rates: Default::default(),
+            clock: Default::default(),
             closed: false,
         }
     }
@@ -312,6 +391,13 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
         }
     }
 
+    /// Set the validator that is consulted for local and remote inserts.
+    ///
+    /// Replaces a previously set validator. Pass `None` to remove the validator.
+    pub fn set_validator(&mut self, validator: Option<Arc<dyn Validator>>) {
+        self.validator = validator;
+    }
+
     fn ensure_open(&self) -> Result<(), InsertError<S>> {
         if self.closed() {
             Err(InsertError::Closed)
@@ -357,14 +443,29 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
             return Err(InsertError::EntryIsEmpty);
         }
         self.ensure_open()?;
+        // Only the owner author may write to the reserved key space.
+        if metadata::is_reserved_key(key.as_ref())
+            && author.id() != metadata::owner_author(self.secret_key()?).id()
+        {
+            return Err(ValidationFailure::ReservedKey.into());
+        }
         let id = RecordIdentifier::new(self.id(), author.id(), key);
-        let record = Record::new_current(hash, len);
+        let record = Record::new(hash, len, self.clock.next());
         let entry = Entry::new(id, record);
         let secret = self.secret_key()?;
         let signed_entry = entry.sign(secret, author);
         self.insert_entry(signed_entry, InsertOrigin::Local)
     }
 
+    /// Set the [`DocMetadata`](metadata::DocMetadata) of this replica to the content `hash`.
+    ///
+    /// The entry is signed by the [owner author](metadata::owner_author) of the namespace, so
+    /// this fails for read-only replicas.
+    pub fn insert_metadata(&mut self, hash: Hash, len: u64) -> Result<usize, InsertError<S>> {
+        let author = metadata::owner_author(self.secret_key()?);
+        self.insert(metadata::METADATA_KEY, &author, hash, len)
+    }
+
     /// Delete entries that match the given `author` and key `prefix`.
     ///
     /// This inserts an empty entry with the key set to `prefix`, effectively clearing all other
@@ -378,29 +479,37 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
     ) -> Result<usize, InsertError<S>> {
         self.ensure_open()?;
         let id = RecordIdentifier::new(self.id(), author.id(), prefix);
-        let entry = Entry::new_empty(id);
+        let entry = Entry::new(id, Record::empty(self.clock.next()));
         let signed_entry = entry.sign(self.secret_key()?, author);
         self.insert_entry(signed_entry, InsertOrigin::Local)
     }
 
-    /// Insert an entry into this replica which was received from a remote peer.
+    /// Insert an entry into this replica which was signed elsewhere.
     ///
     /// This will verify both the namespace and author signatures of the entry, emit an `on_insert`
-    /// event, and insert the entry into the replica store.
+    /// event, and insert the entry into the replica store. The clock of the replica is advanced to
+    /// the timestamp of the entry, so that subsequent local entries are ordered after it.
+    ///
+    /// `received_from` is the peer the entry was received from, and `content_status` whether that
+    /// peer has the content. Entries without a peer were imported locally, e.g. from a snapshot or
+    /// bundle, and are reported as local inserts; `content_status` is ignored for them.
     ///
     /// Returns the number of entries removed as a consequence of this insertion,
     /// or an error if the entry failed to validate or if a store operation failed.
     pub fn insert_remote_entry(
         &mut self,
         entry: SignedEntry,
-        received_from: PeerIdBytes,
+        received_from: Option<PeerIdBytes>,
         content_status: ContentStatus,
     ) -> Result<usize, InsertError<S>> {
         self.ensure_open()?;
         entry.validate_empty()?;
-        let origin = InsertOrigin::Sync {
-            from: received_from,
-            remote_content_status: content_status,
+        let origin = match received_from {
+            Some(from) => InsertOrigin::Sync {
+                from,
+                remote_content_status: content_status,
+            },
+            None => InsertOrigin::Import,
         };
         self.insert_entry(entry, origin)
     }
@@ -418,8 +527,31 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
         #[cfg(feature = "metrics")]
         let len = entry.content_len();
 
+        let now = system_time_now();
         let store = self.peer.store();
-        validate_entry(system_time_now(), store, namespace, &entry, &origin)?;
+        validate_entry(now, store, namespace, &entry, &origin)?;
+        if let Some(validator) = &self.validator {
+            if let Err(reason) = validator.validate(&entry, &origin) {
+                #[cfg(feature = "metrics")]
+                match origin {
+                    InsertOrigin::Local | InsertOrigin::Import => {
+                        inc!(Metrics, rejected_entries_local)
+                    }
+                    InsertOrigin::Sync { .. } => inc!(Metrics, rejected_entries_remote),
+                }
+                return Err(ValidationFailure::Rejected(reason).into());
+            }
+        }
+
+        let quota = store.get_quota(&namespace).unwrap_or_default();
+        if let Some(kind) = validate_quota(store, &quota, &self.author_rates, now, &entry)
+            .map_err(InsertError::Store)?
+        {
+            return Err(ValidationFailure::QuotaExceeded(kind).into());
+        }
+        if !matches!(origin, InsertOrigin::Local) {
+            self.clock.observe(entry.timestamp());
+        }
 
         let outcome = self.peer.put(entry.clone()).map_err(InsertError::Store)?;
 
@@ -427,9 +559,10 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
             InsertOutcome::Inserted { removed } => removed,
             InsertOutcome::NotInserted => return Err(InsertError::NewerEntryExists),
         };
+        self.author_rates.record(&quota, &entry, now);
 
         let insert_event = match origin {
-            InsertOrigin::Local => {
+            InsertOrigin::Local | InsertOrigin::Import => {
                 #[cfg(feature = "metrics")]
                 {
                     inc!(Metrics, new_entries_local);
@@ -491,13 +624,34 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
     }
 
     /// Create the initial message for the set reconciliation flow with a remote peer.
-    pub fn sync_initial_message(&self) -> anyhow::Result<crate::ranger::Message<SignedEntry>> {
+    ///
+    /// Only the entries within `area` are reconciled.
+    pub fn sync_initial_message(
+        &self,
+        area: &AreaOfInterest,
+    ) -> anyhow::Result<crate::ranger::Message<SignedEntry>> {
         self.ensure_open()?;
-        self.peer.initial_message().map_err(Into::into)
+        self.peer.initial_message(area).map_err(Into::into)
+    }
+
+    /// Create the first message to resume an interrupted set reconciliation flow.
+    ///
+    /// Only the `pending` ranges are reconciled again. If no ranges are pending, this is the same
+    /// as [`Self::sync_initial_message`].
+    pub fn sync_resume_message(
+        &self,
+        pending: &PendingRanges,
+    ) -> anyhow::Result<crate::ranger::Message<SignedEntry>> {
+        self.ensure_open()?;
+        self.peer
+            .resume_message(&pending.ranges, &pending.area)
+            .map_err(Into::into)
     }
 
     /// Process a set reconciliation message from a remote peer.
     ///
+    /// Only the entries within the area of `state` are reconciled.
+    ///
     /// Returns the next message to be sent to the peer, if any.
     pub fn sync_process_message(
         &mut self,
@@ -517,55 +671,114 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
                 .insert(entry.author(), entry.timestamp());
         }
 
+        let quota = self
+            .peer
+            .store()
+            .get_quota(&my_namespace)
+            .unwrap_or_default();
+        // The callbacks below both need access to the author rates, so move them out of `self`
+        // for the duration of the message processing.
+        let author_rates = RefCell::new(std::mem::take(&mut self.author_rates));
+        let num_rejected = Cell::new(0);
+        let area = state.area.clone();
+        let incoming_ranges: Vec<_> = message.ranges().cloned().collect();
+
         // let subscribers = std::rc::Rc::new(&mut self.subscribers);
         // l
-        let reply = self
-            .peer
-            .process_message(
-                message,
-                // validate callback: validate incoming entries, and send to on_insert channel
-                |store, entry, content_status| {
-                    let origin = InsertOrigin::Sync {
+        let reply = self.peer.process_message(
+            message,
+            &area,
+            // validate callback: validate incoming entries, and send to on_insert channel
+            |store, entry, content_status| {
+                let origin = InsertOrigin::Sync {
+                    from: from_peer,
+                    remote_content_status: content_status,
+                };
+                if validate_entry(now, store, my_namespace, entry, &origin).is_err() {
+                    return false;
+                }
+                if let Some(validator) = &self.validator {
+                    if let Err(reason) = validator.validate(entry, &origin) {
+                        debug!(%reason, author = %entry.author(), "reject entry: validator");
+                        num_rejected.set(num_rejected.get() + 1);
+                        #[cfg(feature = "metrics")]
+                        inc!(Metrics, rejected_entries_remote);
+                        return false;
+                    }
+                }
+                match validate_quota(store, &quota, &author_rates.borrow(), now, entry) {
+                    Ok(None) => true,
+                    Ok(Some(kind)) => {
+                        debug!(?kind, author = %entry.author(), "reject entry: quota exceeded");
+                        num_rejected.set(num_rejected.get() + 1);
+                        false
+                    }
+                    Err(err) => {
+                        warn!(?err, "failed to check quota, reject entry");
+                        false
+                    }
+                }
+            },
+            // on_insert callback: is called when an entry was actually inserted in the store
+            |store, entry, content_status| {
+                author_rates.borrow_mut().record(&quota, &entry, now);
+                self.clock.observe(entry.timestamp());
+                // We use `send_with` to only clone the entry if we have active subscriptions.
+                self.subscribers.send_with(|| {
+                    let download_policy =
+                        store.get_download_policy(&my_namespace).unwrap_or_default();
+                    let should_download = download_policy.matches(entry.entry());
+                    Event::RemoteInsert {
                         from: from_peer,
+                        namespace: my_namespace,
+                        entry: entry.clone(),
+                        should_download,
                         remote_content_status: content_status,
-                    };
-                    validate_entry(now, store, my_namespace, entry, &origin).is_ok()
-                },
-                // on_insert callback: is called when an entry was actually inserted in the store
-                |store, entry, content_status| {
-                    // We use `send_with` to only clone the entry if we have active subscriptions.
-                    self.subscribers.send_with(|| {
-                        let download_policy =
-                            store.get_download_policy(&my_namespace).unwrap_or_default();
-                        let should_download = download_policy.matches(entry.entry());
-                        Event::RemoteInsert {
-                            from: from_peer,
-                            namespace: my_namespace,
-                            entry: entry.clone(),
-                            should_download,
-                            remote_content_status: content_status,
-                        }
-                    })
-                },
-                // content_status callback: get content status for outgoing entries
-                |_store, entry| {
-                    if let Some(cb) = self.content_status_cb.as_ref() {
-                        cb(entry.content_hash())
-                    } else {
-                        ContentStatus::Missing
                     }
-                },
-            )
-            .map_err(Into::into)?;
+                })
+            },
+            // content_status callback: get content status for outgoing entries
+            |_store, entry| {
+                if let Some(cb) = self.content_status_cb.as_ref() {
+                    cb(entry.content_hash())
+                } else {
+                    ContentStatus::Missing
+                }
+            },
+        );
+        self.author_rates = author_rates.into_inner();
+        state.num_rejected += num_rejected.get();
+        let reply = reply.map_err(Into::into)?;
 
         // update state with outgoing data.
-        if let Some(ref reply) = reply {
-            state.num_sent += reply.value_count();
-        }
+        // Incoming ranges that do not reappear in the reply need no further reconciliation.
+        let reconciled = match reply {
+            Some(ref reply) => {
+                state.num_sent += reply.value_count();
+                state.pending = PendingRanges::new(area, reply);
+                incoming_ranges
+                    .iter()
+                    .filter(|range| reply.ranges().all(|r| r != *range))
+                    .count()
+            }
+            None => {
+                state.pending = PendingRanges::default();
+                incoming_ranges.len()
+            }
+        };
+        state.num_ranges_reconciled += reconciled;
 
         Ok(reply)
     }
 
+    /// Advance the clock of this replica past `timestamp`.
+    ///
+    /// Stores call this when opening a replica, with the timestamp of the latest entry in the
+    /// document, so that new local entries are ordered after the existing ones.
+    pub(crate) fn observe_timestamp(&mut self, timestamp: u64) {
+        self.clock.observe(timestamp);
+    }
+
     /// Get the namespace identifier for this [`Replica`].
     pub fn id(&self) -> NamespaceId {
         self.capability.id()
@@ -583,6 +796,34 @@ impl<S: ranger::Store<SignedEntry> + PublicKeyStore + store::DownloadPolicyStore
     }
 }
 
+/// Hybrid logical clock for the timestamps of local entries.
+///
+/// Timestamps are microseconds since the unix epoch, like wall clock timestamps, so they order
+/// with the timestamps already in the stores. The clock never runs backwards and is advanced past
+/// the timestamps of entries received from other peers, so that a local entry is always newer
+/// than the entries this replica has seen, even if the local wall clock lags behind.
+#[derive(Debug, Default)]
+struct HybridClock {
+    last: u64,
+}
+
+impl HybridClock {
+    /// Get the next timestamp, which is greater than all timestamps returned or observed before.
+    fn next(&mut self) -> u64 {
+        self.last = system_time_now().max(self.last + 1);
+        self.last
+    }
+
+    /// Advance the clock to a timestamp received from a remote peer or read from the store.
+    ///
+    /// The clock is advanced at most to [`MAX_TIMESTAMP_FUTURE_SHIFT`] ahead of the wall clock,
+    /// minus one for the increment in [`Self::next`], so that local entries stay valid.
+    fn observe(&mut self, timestamp: u64) {
+        let max = system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT - 1;
+        self.last = self.last.max(timestamp.min(max));
+    }
+}
+
 /// Error that occurs trying to access the [`NamespaceSecret`] of a read-only [`Capability`].
 #[derive(Debug, thiserror::Error)]
 #[error("Replica allows read access only.")]
@@ -593,7 +834,7 @@ pub struct ReadOnly;
 /// This validates that
 /// * the entry's author and namespace signatures are correct
 /// * the entry's namespace matches the current replica
-/// * the entry's timestamp is not more than 10 minutes in the future of our system time
+/// * the timestamp of a local entry is not more than 10 minutes in the future of our system time
 /// * the entry is newer than an existing entry for the same key and author, if such exists.
 fn validate_entry<S: ranger::Store<SignedEntry> + PublicKeyStore>(
     now: u64,
@@ -612,13 +853,94 @@ fn validate_entry<S: ranger::Store<SignedEntry> + PublicKeyStore>(
         return Err(ValidationFailure::BadSignature);
     }
 
-    // Verify that the timestamp of the entry is not too far in the future.
-    if entry.timestamp() > now + MAX_TIMESTAMP_FUTURE_SHIFT {
+    // Verify that the timestamp of a local entry is not too far in the future. Remote entries
+    // carry the timestamps of the hybrid clocks of other peers, which may be ahead of ours.
+    if matches!(origin, InsertOrigin::Local) && entry.timestamp() > now + MAX_TIMESTAMP_FUTURE_SHIFT
+    {
         return Err(ValidationFailure::TooFarInTheFuture);
     }
     Ok(())
 }
 
+/// Check whether inserting `entry` would exceed the `quota` of the document.
+///
+/// Returns the kind of quota that would be exceeded, if any. Empty entries are never rejected,
+/// because they only delete other entries.
+fn validate_quota<S: ranger::Store<SignedEntry> + store::QuotaStore>(
+    store: &S,
+    quota: &Quota,
+    author_rates: &AuthorRates,
+    now: u64,
+    entry: &SignedEntry,
+) -> Result<Option<QuotaKind>, S::Error> {
+    if entry.entry().record().is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(limit) = quota.max_author_rate {
+        if author_rates.exceeds(&limit, &entry.author(), now) {
+            return Ok(Some(QuotaKind::AuthorRate));
+        }
+    }
+
+    if !quota.limits_size() {
+        return Ok(None);
+    }
+    let mut usage = store.get_usage(&entry.namespace()).unwrap_or_default();
+    // The entry that will be replaced by this insert does not count against the quota.
+    if let Some(existing) = store.get(entry.id())? {
+        usage.remove(&existing);
+    }
+    if quota.max_entries.is_some_and(|max| usage.entries >= max) {
+        return Ok(Some(QuotaKind::Entries));
+    }
+    if quota
+        .max_content_len
+        .is_some_and(|max| usage.content_len + entry.content_len() > max)
+    {
+        return Ok(Some(QuotaKind::ContentLength));
+    }
+    Ok(None)
+}
+
+/// Local insertion times of recent entries, per author.
+///
+/// Used to enforce [`Quota::max_author_rate`].
+#[derive(Debug, Default)]
+struct AuthorRates(HashMap<AuthorId, VecDeque<u64>>);
+
+impl AuthorRates {
+    /// Returns `true` if the author already inserted the maximum number of entries within the
+    /// interval of the rate limit.
+    fn exceeds(&self, limit: &RateLimit, author: &AuthorId, now: u64) -> bool {
+        let window_start = now.saturating_sub(limit.interval.as_micros() as u64);
+        let count = self
+            .0
+            .get(author)
+            .map(|times| times.iter().filter(|t| **t > window_start).count())
+            .unwrap_or_default();
+        count as u64 >= limit.entries
+    }
+
+    /// Record that an entry was inserted at `now`.
+    ///
+    /// Does nothing if the quota has no rate limit, so that rates are only tracked when needed.
+    fn record(&mut self, quota: &Quota, entry: &SignedEntry, now: u64) {
+        let Some(limit) = quota.max_author_rate else {
+            return;
+        };
+        if entry.entry().record().is_empty() {
+            return;
+        }
+        let window_start = now.saturating_sub(limit.interval.as_micros() as u64);
+        let times = self.0.entry(entry.author()).or_default();
+        while times.front().is_some_and(|t| *t <= window_start) {
+            times.pop_front();
+        }
+        times.push_back(now);
+    }
+}
+
 /// Error emitted when inserting entries into a [`Replica`] failed
 #[derive(thiserror::Error, derive_more::Debug, derive_more::From)]
 pub enum InsertError<S: ranger::Store<SignedEntry>> {
@@ -658,6 +980,30 @@ pub enum ValidationFailure {
     /// Entry has length 0 but not the empty hash, or the empty hash but not length 0.
     #[error("Entry has length 0 but not the empty hash, or the empty hash but not length 0")]
     InvalidEmptyEntry,
+    /// Inserting the entry would exceed the document's [`Quota`].
+    #[error("Entry exceeds the {0} quota of the document")]
+    QuotaExceeded(QuotaKind),
+    /// Entry was rejected by the [`Validator`] of the replica.
+    #[error("Entry rejected by validator: {0}")]
+    Rejected(String),
+    /// Entry key is reserved for the [owner author](metadata::owner_author), see
+    /// [`metadata::RESERVED_KEY_PREFIX`].
+    #[error("Entry key is reserved for the owner author")]
+    ReservedKey,
+}
+
+/// The kind of [`Quota`] that an entry exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+pub enum QuotaKind {
+    /// The maximum number of entries, see [`Quota::max_entries`].
+    #[strum(serialize = "entry count")]
+    Entries,
+    /// The maximum total content length, see [`Quota::max_content_len`].
+    #[strum(serialize = "content length")]
+    ContentLength,
+    /// The maximum rate per author, see [`Quota::max_author_rate`].
+    #[strum(serialize = "author rate")]
+    AuthorRate,
 }
 
 /// A signed entry.
@@ -700,7 +1046,7 @@ impl Ord for Entry {
 }
 
 impl SignedEntry {
-    #[cfg(feature = "fs-store")]
+    #[cfg(any(test, feature = "fs-store"))]
     pub(crate) fn new(signature: EntrySignature, entry: Entry) -> Self {
         SignedEntry { signature, entry }
     }
@@ -979,7 +1325,7 @@ impl RangeKey for RecordIdentifier {
     }
 }
 
-fn system_time_now() -> u64 {
+pub(crate) fn system_time_now() -> u64 {
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .expect("time drift")
@@ -1183,7 +1529,10 @@ mod tests {
     use crate::{
         actor::SyncHandle,
         ranger::{Range, Store as _},
-        store::{self, OpenError, Query, SortBy, SortDirection, Store},
+        store::{
+            self, FlatQuery, IndexExtractor, IndexSpec, KeyFilter, OpenError, Query, QueryBuilder,
+            SortBy, SortDirection, Store,
+        },
     };
 
     use super::*;
@@ -1726,6 +2075,181 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replica_hybrid_clock_memory() -> Result<()> {
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_replica_hybrid_clock(alice_store, bob_store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_hybrid_clock_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::new(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::new(bob_dbfile.path())?;
+        test_replica_hybrid_clock(alice_store, bob_store)
+    }
+
+    fn test_replica_hybrid_clock<S: store::Store>(alice_store: S, bob_store: S) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let alice_author = Author::new(&mut rng);
+        let bob_author = Author::new(&mut rng);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut alice = alice_store.new_replica(namespace.clone())?;
+        let mut bob = bob_store.new_replica(namespace.clone())?;
+
+        // bob's clock is ahead, but within the accepted shift
+        let key = b"key";
+        let ahead = system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT / 2;
+        let record = Record::from_data(b"bob", ahead);
+        let entry = SignedEntry::from_parts(&namespace, &bob_author, key, record);
+        bob.insert_entry(entry, InsertOrigin::Local)?;
+
+        // after a sync, alice's entries are ordered after bob's, even though alice's wall clock
+        // lags behind.
+        sync::<S>(&mut alice, &mut bob)?;
+        alice.hash_and_insert(key, &alice_author, b"alice")?;
+        let alice_ts = get_entry(&alice_store, namespace.id(), alice_author.id(), key)?.timestamp();
+        assert!(alice_ts > ahead);
+        // local timestamps are strictly increasing
+        alice.hash_and_insert(key, &alice_author, b"alice2")?;
+        let alice_ts2 =
+            get_entry(&alice_store, namespace.id(), alice_author.id(), key)?.timestamp();
+        assert!(alice_ts2 > alice_ts);
+
+        // a remote insert advances the clock as well
+        let ahead = system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT * 3 / 4;
+        let record = Record::from_data(b"bob2", ahead);
+        let entry = SignedEntry::from_parts(&namespace, &bob_author, key, record);
+        alice.insert_remote_entry(entry, Some([0u8; 32]), ContentStatus::Complete)?;
+        alice.delete_prefix(key, &alice_author)?;
+        let deleted = get_entry(&alice_store, namespace.id(), alice_author.id(), key)?;
+        assert!(deleted.is_empty());
+        assert!(deleted.timestamp() > ahead);
+
+        // a reopened replica starts after the timestamps in the store
+        alice_store.close_replica(alice);
+        let mut alice = alice_store.open_replica(&namespace.id())?;
+        alice.hash_and_insert(key, &alice_author, b"alice3")?;
+        let alice_ts3 =
+            get_entry(&alice_store, namespace.id(), alice_author.id(), key)?.timestamp();
+        assert!(alice_ts3 > deleted.timestamp());
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_skewed_clock_memory() -> Result<()> {
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_replica_skewed_clock(alice_store, bob_store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_skewed_clock_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::new(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::new(bob_dbfile.path())?;
+        test_replica_skewed_clock(alice_store, bob_store)
+    }
+
+    fn test_replica_skewed_clock<S: store::Store>(alice_store: S, bob_store: S) -> Result<()> {
+        let mut rng = rand::thread_rng();
+        let alice_author = Author::new(&mut rng);
+        let carol_author = Author::new(&mut rng);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut alice = alice_store.new_replica(namespace.clone())?;
+        let mut bob = bob_store.new_replica(namespace.clone())?;
+
+        // bob received an entry from carol, whose clock is an hour ahead
+        let key = b"key";
+        let ahead = system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT * 6;
+        let record = Record::from_data(b"carol", ahead);
+        let entry = SignedEntry::from_parts(&namespace, &carol_author, key, record);
+        bob.insert_remote_entry(entry.clone(), Some([1u8; 32]), ContentStatus::Complete)?;
+
+        // the entry is synced to alice as well
+        sync::<S>(&mut alice, &mut bob)?;
+        assert_eq!(
+            get_entry(&alice_store, namespace.id(), carol_author.id(), key)?,
+            entry
+        );
+
+        // alice's clock follows carol's only up to the accepted shift, so her entries stay valid
+        alice.hash_and_insert(key, &alice_author, b"alice")?;
+        let alice_ts = get_entry(&alice_store, namespace.id(), alice_author.id(), key)?.timestamp();
+        assert!(alice_ts < ahead);
+        assert!(alice_ts <= system_time_now() + MAX_TIMESTAMP_FUTURE_SHIFT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_sync_resume_memory() -> Result<()> {
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_replica_sync_resume(alice_store, bob_store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_sync_resume_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::new(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::new(bob_dbfile.path())?;
+        test_replica_sync_resume(alice_store, bob_store)
+    }
+
+    fn test_replica_sync_resume<S: store::Store>(alice_store: S, bob_store: S) -> Result<()> {
+        let alice_peer_id = [1u8; 32];
+        let bob_peer_id = [2u8; 32];
+        let mut rng = rand::thread_rng();
+        let author = Author::new(&mut rng);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut alice = alice_store.new_replica(namespace.clone())?;
+        let mut bob = bob_store.new_replica(namespace.clone())?;
+        for i in 0..100 {
+            alice.hash_and_insert(format!("alice/{i}"), &author, b"a")?;
+            bob.hash_and_insert(format!("bob/{i}"), &author, b"b")?;
+        }
+
+        // exchange a single round trip, then drop the connection.
+        let mut alice_state = SyncOutcome::default();
+        let mut bob_state = SyncOutcome::default();
+        let msg = alice.sync_initial_message(&alice_state.area)?;
+        let msg = bob
+            .sync_process_message(msg, alice_peer_id, &mut bob_state)?
+            .expect("bob replies");
+        alice.sync_process_message(msg, bob_peer_id, &mut alice_state)?;
+        let pending = alice_state.pending.clone();
+        assert!(!pending.is_empty());
+
+        // resume from the pending ranges with fresh state.
+        let mut alice_state = SyncOutcome::default();
+        let mut bob_state = SyncOutcome::default();
+        let mut next_to_bob = Some(alice.sync_resume_message(&pending)?);
+        let mut rounds = 0;
+        while let Some(msg) = next_to_bob.take() {
+            assert!(rounds < 100, "too many rounds");
+            rounds += 1;
+            if let Some(msg) = bob.sync_process_message(msg, alice_peer_id, &mut bob_state)? {
+                next_to_bob = alice.sync_process_message(msg, bob_peer_id, &mut alice_state)?
+            }
+        }
+        // bob did not reply to alice's last message, so it settled all ranges.
+        assert!(bob_state.pending.is_empty());
+        assert!(bob_state.num_ranges_reconciled > 0);
+
+        let alice_count = alice_store.get_many(namespace.id(), Query::all())?.count();
+        let bob_count = bob_store.get_many(namespace.id(), Query::all())?.count();
+        assert_eq!(alice_count, 200);
+        assert_eq!(bob_count, 200);
+        Ok(())
+    }
+
     #[test]
     fn test_insert_empty() -> Result<()> {
         let store = store::memory::Store::default();
@@ -2160,7 +2684,7 @@ mod tests {
 
         replica1.hash_and_insert(b"foo", &author, b"init")?;
 
-        let from1 = replica1.sync_initial_message()?;
+        let from1 = replica1.sync_initial_message(&state1.area)?;
         let from2 = replica2
             .sync_process_message(from1, peer1, &mut state2)
             .unwrap()
@@ -2377,6 +2901,473 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_replica_query_ranges_mem() -> Result<()> {
+        let store = store::memory::Store::default();
+        test_replica_query_ranges(store)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_query_ranges_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::new(dbfile.path())?;
+        test_replica_query_ranges(store)?;
+        Ok(())
+    }
+
+    fn test_replica_query_ranges<S: store::Store>(store: S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut replica = store.new_replica(namespace)?;
+        let namespace = replica.id();
+        let a1 = store.new_author(&mut rng)?;
+        let a2 = store.new_author(&mut rng)?;
+
+        for (i, key) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            let data = vec![0u8; i + 1];
+            replica.insert(key, &a1, Hash::new(&data), data.len() as u64)?;
+        }
+        replica.hash_and_insert("b", &a2, "x")?;
+
+        let keys = |query: Query| -> Result<Vec<(String, AuthorId)>> {
+            store
+                .get_many(namespace, query)?
+                .map(|e| e.map(|e| (String::from_utf8(e.key().to_vec()).unwrap(), e.author())))
+                .collect()
+        };
+        let a1 = a1.id();
+        let a2 = a2.id();
+
+        // key ranges
+        assert_eq!(
+            keys(Query::key_range("b".."d").build())?,
+            keys(Query::all().build())?
+                .into_iter()
+                .filter(|(k, _)| k == "b" || k == "c")
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            keys(Query::author(a1).key_range("b"..="c").build())?,
+            vec![("b".to_string(), a1), ("c".to_string(), a1)]
+        );
+        assert_eq!(
+            keys(
+                Query::all()
+                    .key_range("c"..)
+                    .sort_by(SortBy::KeyAuthor, SortDirection::Desc)
+                    .build()
+            )?,
+            vec![("d".to_string(), a1), ("c".to_string(), a1)]
+        );
+        assert_eq!(
+            keys(Query::single_latest_per_key().key_range(..="b").build())?
+                .into_iter()
+                .map(|(k, _)| k)
+                .collect::<Vec<_>>(),
+            vec!["a", "b"]
+        );
+        assert!(keys(Query::key_range("d".."a").build())?.is_empty());
+
+        // content length ranges
+        assert_eq!(
+            keys(Query::author(a1).content_len_range(2..4).build())?,
+            vec![("b".to_string(), a1), ("c".to_string(), a1)]
+        );
+        assert_eq!(
+            keys(
+                Query::all()
+                    .content_len_range(..2)
+                    .sort_by(SortBy::KeyAuthor, SortDirection::Asc)
+                    .build()
+            )?,
+            vec![("a".to_string(), a1), ("b".to_string(), a2)]
+        );
+
+        // timestamp ranges
+        let ts_c = store
+            .get_exact(namespace, a1, "c", false)?
+            .unwrap()
+            .timestamp();
+        assert_eq!(
+            keys(Query::author(a1).timestamp_range(ts_c..).build())?,
+            vec![("c".to_string(), a1), ("d".to_string(), a1)]
+        );
+        assert_eq!(
+            keys(
+                Query::single_latest_per_key()
+                    .timestamp_range(..ts_c)
+                    .build()
+            )?,
+            vec![("a".to_string(), a1)]
+        );
+
+        // counting
+        assert_eq!(store.count(namespace, Query::all())?, 5);
+        assert_eq!(
+            store.count(namespace, Query::author(a1).key_range("b"..))?,
+            3
+        );
+        assert_eq!(store.count(namespace, Query::all().offset(1).limit(3))?, 3);
+        assert_eq!(store.count(namespace, Query::single_latest_per_key())?, 4);
+        assert_eq!(
+            store.count(namespace, Query::all().content_len_range(5..))?,
+            0
+        );
+        // counts agree with the entries returned on every index
+        let first = store
+            .get_many(namespace, Query::all().build())?
+            .next()
+            .unwrap()?;
+        let queries = [
+            Query::all()
+                .sort_by(SortBy::KeyAuthor, SortDirection::Desc)
+                .offset(1)
+                .build(),
+            Query::all()
+                .sort_by(SortBy::KeyAuthor, SortDirection::Asc)
+                .content_len_range(2..)
+                .build(),
+            Query::single_latest_per_key()
+                .sort_direction(SortDirection::Desc)
+                .timestamp_range(..ts_c)
+                .build(),
+            Query::single_latest_per_key()
+                .include_empty()
+                .limit(2)
+                .build(),
+            Query::single_latest_per_key()
+                .after((&first).into())
+                .build(),
+            Query::history("b").build(),
+            Query::history("b").author(a2).offset(1).build(),
+        ];
+        for query in queries {
+            let expected = store.get_many(namespace, query.clone())?.count() as u64;
+            assert_eq!(store.count(namespace, query)?, expected);
+        }
+
+        // cursor pagination is stable under concurrent inserts
+        let page = |query: QueryBuilder<FlatQuery>, cursor: Option<&SignedEntry>| {
+            let query = match cursor {
+                Some(entry) => query.after(entry.into()),
+                None => query,
+            };
+            store
+                .get_many(namespace, query.limit(2).build())?
+                .collect::<Result<Vec<_>>>()
+        };
+        let query = || Query::all().sort_by(SortBy::KeyAuthor, SortDirection::Asc);
+        let first = page(query(), None)?;
+        assert_eq!(first.len(), 2);
+        // insert an entry that sorts before the cursor and one that sorts after it.
+        replica.hash_and_insert("0", &store.get_author(&a1)?.unwrap(), "new")?;
+        replica.hash_and_insert("bb", &store.get_author(&a1)?.unwrap(), "new")?;
+        let second = page(query(), first.last())?;
+        let third = page(query(), second.last())?;
+        let fourth = page(query(), third.last())?;
+        let keys = [first, second, third, fourth]
+            .into_iter()
+            .flatten()
+            .map(|e| (String::from_utf8(e.key().to_vec()).unwrap(), e.author()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            vec![
+                ("a".to_string(), a1),
+                // "b" is sorted by author within the key
+                keys[1].clone(),
+                keys[2].clone(),
+                ("bb".to_string(), a1),
+                ("c".to_string(), a1),
+                ("d".to_string(), a1),
+            ]
+        );
+        assert_eq!(keys[1].0, "b");
+        assert_eq!(keys[2].0, "b");
+
+        // cursor with descending sort
+        let query = Query::single_latest_per_key().sort_direction(SortDirection::Desc);
+        let first = store
+            .get_many(namespace, query.limit(2).build())?
+            .collect::<Result<Vec<_>>>()?;
+        let rest = Query::single_latest_per_key()
+            .sort_direction(SortDirection::Desc)
+            .after(first.last().unwrap().into());
+        let rest = store
+            .get_many(namespace, rest.build())?
+            .map(|e| e.map(|e| String::from_utf8(e.key().to_vec()).unwrap()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(rest, vec!["bb", "b", "a", "0"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_replica_quota_mem() -> Result<()> {
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_replica_quota(alice_store, bob_store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_quota_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::new(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::new(bob_dbfile.path())?;
+        test_replica_quota(alice_store, bob_store)
+    }
+
+    fn test_replica_quota<S: store::Store>(alice_store: S, bob_store: S) -> Result<()> {
+        fn exceeded<T, S: ranger::Store<SignedEntry>>(
+            res: Result<T, InsertError<S>>,
+        ) -> Option<QuotaKind> {
+            match res {
+                Err(InsertError::Validation(ValidationFailure::QuotaExceeded(kind))) => Some(kind),
+                _ => None,
+            }
+        }
+
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let id = namespace.id();
+        let alice = Author::new(&mut rng);
+        let bob = Author::new(&mut rng);
+
+        let quota = Quota {
+            max_entries: Some(2),
+            max_content_len: Some(10),
+            max_author_rate: None,
+        };
+        alice_store
+            .set_quota(&id, quota)
+            .expect_err("document does not exist");
+        assert_eq!(alice_store.get_quota(&id)?, Quota::default());
+
+        let mut replica = alice_store.new_replica(namespace.clone())?;
+        alice_store.set_quota(&id, quota)?;
+        assert_eq!(alice_store.get_quota(&id)?, quota);
+
+        // entry count
+        replica.hash_and_insert("a", &alice, "aaaa")?;
+        replica.hash_and_insert("b", &alice, "bbbb")?;
+        let res = replica.hash_and_insert("c", &alice, "cc");
+        assert_eq!(exceeded(res), Some(QuotaKind::Entries));
+
+        // replacing an entry does not count the replaced entry
+        replica.hash_and_insert("a", &alice, "aaaaaa")?;
+        let res = replica.hash_and_insert("a", &alice, "aaaaaaa");
+        assert_eq!(exceeded(res), Some(QuotaKind::ContentLength));
+
+        // deletions are always allowed and free up the quota
+        replica.delete_prefix("b", &alice)?;
+        replica.hash_and_insert("c", &alice, "cc")?;
+        let usage = store::Usage {
+            entries: 2,
+            content_len: 8,
+        };
+        assert_eq!(alice_store.get_usage(&id)?, usage);
+
+        // author rate
+        let quota = Quota {
+            max_author_rate: Some(RateLimit {
+                entries: 2,
+                interval: Duration::from_secs(60 * 60),
+            }),
+            ..Default::default()
+        };
+        alice_store.set_quota(&id, quota)?;
+        replica.hash_and_insert("x", &bob, "x")?;
+        replica.hash_and_insert("y", &bob, "y")?;
+        let res = replica.hash_and_insert("z", &bob, "z");
+        assert_eq!(exceeded(res), Some(QuotaKind::AuthorRate));
+        replica.hash_and_insert("z", &alice, "z")?;
+
+        // remote inserts are rejected as well
+        let carol = Author::new(&mut rng);
+        let mut bob_replica = bob_store.new_replica(namespace.clone())?;
+        for key in ["1", "2", "3"] {
+            bob_replica.hash_and_insert(key, &carol, key)?;
+        }
+        let (alice_outcome, bob_outcome) = sync::<S>(&mut replica, &mut bob_replica)?;
+        assert_eq!(alice_outcome.num_rejected, 1);
+        assert_eq!(bob_outcome.num_rejected, 0);
+        let carol_entries = alice_store
+            .get_many(id, Query::author(carol.id()))?
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(carol_entries.len(), 2);
+
+        Ok(())
+    }
+
+    #[derive(Debug)]
+    struct KeyPrefixValidator(&'static [u8]);
+
+    impl Validator for KeyPrefixValidator {
+        fn validate(&self, entry: &SignedEntry, _origin: &InsertOrigin) -> Result<(), String> {
+            if entry.key().starts_with(self.0) {
+                Ok(())
+            } else {
+                Err("invalid key prefix".to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_replica_validator_mem() -> Result<()> {
+        let alice_store = store::memory::Store::default();
+        let bob_store = store::memory::Store::default();
+        test_replica_validator(alice_store, bob_store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_replica_validator_fs() -> Result<()> {
+        let alice_dbfile = tempfile::NamedTempFile::new()?;
+        let alice_store = store::fs::Store::new(alice_dbfile.path())?;
+        let bob_dbfile = tempfile::NamedTempFile::new()?;
+        let bob_store = store::fs::Store::new(bob_dbfile.path())?;
+        test_replica_validator(alice_store, bob_store)
+    }
+
+    fn test_replica_validator<S: store::Store>(alice_store: S, bob_store: S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = Author::new(&mut rng);
+
+        let mut alice = alice_store.new_replica(namespace.clone())?;
+        alice.set_validator(Some(Arc::new(KeyPrefixValidator(b"/app/"))));
+
+        alice.hash_and_insert("/app/a", &author, "a")?;
+        let res = alice.hash_and_insert("/other/a", &author, "a");
+        assert!(matches!(
+            res,
+            Err(InsertError::Validation(ValidationFailure::Rejected(_)))
+        ));
+
+        let mut bob = bob_store.new_replica(namespace.clone())?;
+        bob.hash_and_insert("/app/b", &author, "b")?;
+        bob.hash_and_insert("/other/b", &author, "b")?;
+        let (alice_outcome, bob_outcome) = sync::<S>(&mut alice, &mut bob)?;
+        assert_eq!(alice_outcome.num_rejected, 1);
+        assert_eq!(bob_outcome.num_rejected, 0);
+
+        let keys = alice_store
+            .get_many(namespace.id(), Query::all())?
+            .map(|e| e.map(|e| e.key().to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![b"/app/a".to_vec(), b"/app/b".to_vec()]);
+
+        // without a validator, all entries are accepted
+        alice.set_validator(None);
+        alice.hash_and_insert("/other/a", &author, "a")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_secondary_index_mem() -> Result<()> {
+        let store = store::memory::Store::default();
+        test_secondary_index(store)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_secondary_index_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::new(dbfile.path())?;
+        test_secondary_index(store)?;
+        Ok(())
+    }
+
+    fn test_secondary_index<S: store::Store>(store: S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let mut replica = store.new_replica(namespace)?;
+        let namespace = replica.id();
+        let author = store.new_author(&mut rng)?;
+
+        let keys = |name: &str, filter: KeyFilter| -> Result<Vec<String>> {
+            Ok(store
+                .get_by_index(namespace, name, filter)?
+                .into_iter()
+                .map(|e| String::from_utf8(e.key().to_vec()).unwrap())
+                .collect())
+        };
+
+        let todo = br#"{"status": "todo", "meta": {"prio": 1}}"#;
+        let done = br#"{"status": "done", "meta": {"prio": 2}}"#;
+        let todo_hash = replica.hash_and_insert("users/alice", &author, todo)?;
+        replica.hash_and_insert("users/bob", &author, todo)?;
+        let done_hash = replica.hash_and_insert("posts/1", &author, done)?;
+
+        // key based indexes are filled for existing entries
+        let kind = IndexSpec::new(
+            "kind",
+            IndexExtractor::KeySegment {
+                separator: b'/',
+                position: 0,
+            },
+        );
+        store.create_index(&namespace, kind.clone())?;
+        assert_eq!(
+            keys("kind", "users".into())?,
+            vec!["users/alice", "users/bob"]
+        );
+        assert_eq!(
+            keys("kind", KeyFilter::Prefix("p".into()))?,
+            vec!["posts/1"]
+        );
+        assert_eq!(
+            keys("kind", KeyFilter::range("q"..))?,
+            vec!["users/alice", "users/bob"]
+        );
+
+        // content based indexes are filled once the content is passed to the store
+        let status = IndexSpec::new("status", IndexExtractor::JsonField(vec!["status".into()]));
+        let prio = IndexSpec::new(
+            "prio",
+            IndexExtractor::JsonField(vec!["meta".into(), "prio".into()]),
+        );
+        store.create_index(&namespace, status.clone())?;
+        store.create_index(&namespace, prio.clone())?;
+        assert_eq!(store.list_indexes(&namespace)?, vec![kind, prio, status]);
+        assert!(keys("status", KeyFilter::Any)?.is_empty());
+        assert_eq!(store.index_content(&namespace, todo_hash, todo)?, 2);
+        assert_eq!(store.index_content(&namespace, done_hash, done)?, 1);
+        assert_eq!(
+            keys("status", "todo".into())?,
+            vec!["users/alice", "users/bob"]
+        );
+        assert_eq!(keys("status", "done".into())?, vec!["posts/1"]);
+        assert_eq!(keys("prio", "2".into())?, vec!["posts/1"]);
+
+        // new content removes the old value until the new content is indexed
+        let done_hash = replica.hash_and_insert("users/bob", &author, done)?;
+        assert_eq!(keys("status", "todo".into())?, vec!["users/alice"]);
+        assert_eq!(
+            keys("kind", "users".into())?,
+            vec!["users/alice", "users/bob"]
+        );
+        store.index_content(&namespace, done_hash, done)?;
+        assert_eq!(keys("status", "done".into())?, vec!["posts/1", "users/bob"]);
+
+        // deleted entries are removed from all indexes
+        replica.delete_prefix("users/", &author)?;
+        assert!(keys("kind", "users".into())?.is_empty());
+        assert_eq!(keys("status", KeyFilter::Any)?, vec!["posts/1"]);
+
+        assert!(store.remove_index(&namespace, "status")?);
+        assert!(!store.remove_index(&namespace, "status")?);
+        assert!(store
+            .get_by_index(namespace, "status", KeyFilter::Any)
+            .is_err());
+        assert_eq!(keys("prio", KeyFilter::Any)?, vec!["posts/1"]);
+        Ok(())
+    }
+
     #[test]
     fn test_dl_policies_mem() -> Result<()> {
         let store = store::memory::Store::default();
@@ -2411,6 +3402,141 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_history_mem() -> Result<()> {
+        let store = store::memory::Store::default();
+        test_history(&store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_history_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::new(dbfile.path())?;
+        test_history(&store)
+    }
+
+    fn test_history<S: store::Store>(store: &S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let id = namespace.id();
+        let alice = store.new_author(&mut rng)?;
+        let bob = store.new_author(&mut rng)?;
+
+        let policy = store::HistoryPolicy::KeepLast(2);
+        store
+            .set_history_policy(&id, policy)
+            .expect_err("document does not exist");
+
+        let mut replica = store.new_replica(namespace.clone())?;
+        let mut insert = |author: &Author, key: &[u8], timestamp: u64| -> Result<SignedEntry> {
+            let record = Record::from_data(timestamp.to_be_bytes(), timestamp);
+            let entry = SignedEntry::from_parts(&namespace, author, key, record);
+            replica.insert_entry(entry.clone(), InsertOrigin::Local)?;
+            Ok(entry)
+        };
+        let history = |query: Query| -> Result<Vec<SignedEntry>> {
+            store.get_many(id, query)?.collect::<Result<Vec<_>>>()
+        };
+
+        // without a history policy, superseded entries are dropped
+        assert_eq!(
+            store.get_history_policy(&id)?,
+            store::HistoryPolicy::Disabled
+        );
+        let _e1 = insert(&alice, b"foo", 1)?;
+        let e2 = insert(&alice, b"foo", 2)?;
+        assert_eq!(history(Query::history("foo").build())?, vec![e2.clone()]);
+
+        // keep the last two superseded entries
+        store.set_history_policy(&id, policy)?;
+        assert_eq!(store.get_history_policy(&id)?, policy);
+        let _e3 = insert(&alice, b"foo", 3)?;
+        let e4 = insert(&alice, b"foo", 4)?;
+        let e5 = insert(&alice, b"foo", 5)?;
+        let e6 = insert(&alice, b"foo", 6)?;
+        assert_eq!(
+            history(Query::history("foo").build())?,
+            vec![e4.clone(), e5.clone(), e6.clone()]
+        );
+        assert_eq!(
+            history(
+                Query::history("foo")
+                    .sort_direction(SortDirection::Desc)
+                    .build()
+            )?,
+            vec![e6.clone(), e5.clone(), e4.clone()]
+        );
+        assert_eq!(
+            history(Query::history("foo").offset(1).limit(1).build())?,
+            vec![e5.clone()]
+        );
+
+        // history is kept per author
+        let b1 = insert(&bob, b"foo", 1)?;
+        let b2 = insert(&bob, b"foo", 2)?;
+        assert_eq!(
+            history(Query::history("foo").author(bob.id()).build())?,
+            vec![b1.clone(), b2.clone()]
+        );
+        assert_eq!(
+            history(Query::history("foo").author(alice.id()).build())?,
+            vec![e4.clone(), e5.clone(), e6.clone()]
+        );
+
+        // entries removed by a prefix deletion are kept as history
+        insert(&alice, b"fo", 7)?;
+        assert_eq!(
+            history(Query::history("foo").author(alice.id()).build())?,
+            vec![e5.clone(), e6.clone()]
+        );
+        assert_eq!(
+            store.get_exact(id, alice.id(), b"foo", true)?,
+            None,
+            "current entry was removed"
+        );
+
+        // keep superseded entries newer than a duration
+        let policy = store::HistoryPolicy::KeepNewerThan(Duration::from_secs(60));
+        store.set_history_policy(&id, policy)?;
+        let now = system_time_now();
+        let _old = insert(&alice, b"bar", 1)?;
+        let new1 = insert(&alice, b"bar", now)?;
+        let new2 = insert(&alice, b"bar", now + 1)?;
+        assert_eq!(history(Query::history("bar").build())?, vec![new1, new2]);
+
+        // the history of several keys is ordered by key, then by author, then by timestamp
+        let b_1 = insert(&alice, b"baz/b", now)?;
+        let b_2 = insert(&alice, b"baz/b", now + 1)?;
+        let a_bob = insert(&bob, b"baz/a", now)?;
+        let a_alice_1 = insert(&alice, b"baz/a", now + 1)?;
+        let a_alice_2 = insert(&alice, b"baz/a", now + 2)?;
+        let mut expected = if alice.id() < bob.id() {
+            vec![a_alice_1, a_alice_2, a_bob]
+        } else {
+            vec![a_bob, a_alice_1, a_alice_2]
+        };
+        expected.extend([b_1, b_2]);
+        let query = Query::history("").key_prefix("baz/");
+        assert_eq!(history(query.build())?, expected);
+        expected.reverse();
+        let query = Query::history("")
+            .key_prefix("baz/")
+            .sort_direction(SortDirection::Desc);
+        assert_eq!(history(query.build())?, expected);
+
+        // removing the replica removes the history and the policy
+        store.close_replica(replica);
+        store.remove_replica(&id)?;
+        assert_eq!(
+            store.get_history_policy(&id)?,
+            store::HistoryPolicy::Disabled
+        );
+        assert!(history(Query::history("foo").build())?.is_empty());
+
+        Ok(())
+    }
+
     fn assert_keys<S: store::Store>(store: &S, namespace: NamespaceId, mut expected: Vec<Vec<u8>>) {
         expected.sort();
         assert_eq!(expected, get_keys_sorted(store, namespace));
@@ -2460,7 +3586,7 @@ mod tests {
         let mut alice_state = SyncOutcome::default();
         let mut bob_state = SyncOutcome::default();
         // Sync alice - bob
-        let mut next_to_bob = Some(alice.sync_initial_message()?);
+        let mut next_to_bob = Some(alice.sync_initial_message(&alice_state.area)?);
         let mut rounds = 0;
         while let Some(msg) = next_to_bob.take() {
             assert!(rounds < 100, "too many rounds");
@@ -2486,4 +3612,44 @@ mod tests {
         }
         Ok(())
     }
+
+    #[test]
+    fn test_author_delete_memory() -> Result<()> {
+        let store = store::memory::Store::default();
+        test_author_delete(&store)
+    }
+
+    #[cfg(feature = "fs-store")]
+    #[test]
+    fn test_author_delete_fs() -> Result<()> {
+        let dbfile = tempfile::NamedTempFile::new()?;
+        let store = store::fs::Store::new(dbfile.path())?;
+        test_author_delete(&store)
+    }
+
+    fn test_author_delete<S: store::Store>(store: &S) -> Result<()> {
+        let mut rng = rand_chacha::ChaCha12Rng::seed_from_u64(1);
+        let namespace = NamespaceSecret::new(&mut rng);
+        let author = store.new_author(&mut rng)?;
+        let mut replica = store.new_replica(namespace.clone())?;
+        replica.hash_and_insert(b"key", &author, b"value")?;
+
+        // an encrypted backup restores the same author
+        let backup = author.to_encrypted_bytes("passphrase");
+        assert!(Author::from_encrypted_bytes("wrong", &backup).is_err());
+        let restored = Author::from_encrypted_bytes("passphrase", &backup)?;
+        assert_eq!(restored.id(), author.id());
+
+        assert!(store.delete_author(&author.id())?);
+        assert!(store.get_author(&author.id())?.is_none());
+        assert!(!store.delete_author(&author.id())?);
+        // entries of the author are kept
+        assert!(store
+            .get_exact(namespace.id(), author.id(), b"key", false)?
+            .is_some());
+
+        store.import_author(restored)?;
+        assert!(store.get_author(&author.id())?.is_some());
+        Ok(())
+    }
 }
@@ -20,11 +20,16 @@ use iroh_bytes::format::collection::Collection;
 use iroh_bytes::provider::AddProgress;
 use iroh_bytes::store::{ExportMode, ValidateProgress};
 use iroh_bytes::Hash;
-use iroh_bytes::{BlobFormat, Tag};
+use iroh_bytes::{BlobFormat, HashAndFormat, Tag};
 use iroh_net::{key::PublicKey, magic_endpoint::ConnectionInfo, NodeAddr};
 use iroh_sync::actor::OpenState;
-use iroh_sync::store::DownloadPolicy;
-use iroh_sync::{store::Query, AuthorId, CapabilityKind, NamespaceId};
+use iroh_sync::bundle::SyncBundle;
+use iroh_sync::crdt::{Contributions, Crdt};
+use iroh_sync::metadata::DocMetadata;
+use iroh_sync::store::{
+    AreaOfInterest, DownloadPolicy, HistoryPolicy, IndexSpec, KeyFilter, Quota,
+};
+use iroh_sync::{store::Query, Author, AuthorHeads, AuthorId, CapabilityKind, NamespaceId};
 use iroh_sync::{ContentStatus, RecordIdentifier};
 use quic_rpc::message::RpcMsg;
 use quic_rpc::{client::BoxStreamSync, RpcClient, ServiceConnection};
@@ -34,22 +39,29 @@ use tokio_util::io::{ReaderStream, StreamReader};
 use tracing::warn;
 
 use crate::rpc_protocol::{
-    AuthorCreateRequest, AuthorListRequest, BlobAddPathRequest, BlobAddStreamRequest,
-    BlobAddStreamUpdate, BlobDeleteBlobRequest, BlobDownloadRequest, BlobGetCollectionRequest,
+    AuthorCreateRequest, AuthorDeleteRequest, AuthorExportRequest, AuthorImportRequest,
+    AuthorListRequest, BlobAddPathRequest, BlobAddStreamRequest, BlobAddStreamUpdate,
+    BlobDeleteBlobRequest, BlobDownloadRequest, BlobGetCollectionRequest,
     BlobGetCollectionResponse, BlobListCollectionsRequest, BlobListCollectionsResponse,
     BlobListIncompleteRequest, BlobListIncompleteResponse, BlobListRequest, BlobListResponse,
     BlobReadAtRequest, BlobReadAtResponse, BlobValidateRequest, CounterStats,
     CreateCollectionRequest, CreateCollectionResponse, DeleteTagRequest, DocCloseRequest,
-    DocCreateRequest, DocDelRequest, DocDelResponse, DocDropRequest, DocExportFileRequest,
-    DocGetDownloadPolicyRequest, DocGetExactRequest, DocGetManyRequest, DocImportFileRequest,
-    DocImportProgress, DocImportRequest, DocLeaveRequest, DocListRequest, DocOpenRequest,
-    DocSetDownloadPolicyRequest, DocSetHashRequest, DocSetRequest, DocShareRequest,
-    DocStartSyncRequest, DocStatusRequest, DocSubscribeRequest, DocTicket, DownloadProgress,
-    ListTagsRequest, ListTagsResponse, NodeConnectionInfoRequest, NodeConnectionInfoResponse,
-    NodeConnectionsRequest, NodeShutdownRequest, NodeStatsRequest, NodeStatusRequest,
-    NodeStatusResponse, ProviderService, SetTagOption, ShareMode, WrapOption,
+    DocCountRequest, DocCreateIndexRequest, DocCreateRequest, DocDelRequest, DocDelResponse,
+    DocDropRequest, DocExportBundleRequest, DocExportFileRequest, DocGetAuthorHeadsRequest,
+    DocGetByIndexRequest, DocGetDownloadPolicyRequest, DocGetExactRequest,
+    DocGetHistoryPolicyRequest, DocGetLabelRequest, DocGetManyRequest, DocGetMetadataRequest,
+    DocGetQuotaRequest, DocGetSyncAreaRequest, DocImportBundleRequest, DocImportFileRequest,
+    DocImportProgress, DocImportRequest, DocImportSnapshotRequest, DocLeaveRequest,
+    DocListIndexesRequest, DocListRequest, DocListResponse, DocOpenRequest, DocRemoveIndexRequest,
+    DocSetDownloadPolicyRequest, DocSetHashRequest, DocSetHistoryPolicyRequest, DocSetLabelRequest,
+    DocSetMetadataRequest, DocSetQuotaRequest, DocSetRequest, DocSetSyncAreaRequest,
+    DocShareRequest, DocSnapshotRequest, DocStartSyncRequest, DocStatusRequest,
+    DocSubscribeRequest, DocTicket, DownloadProgress, ListTagsRequest, ListTagsResponse,
+    NodeConnectionInfoRequest, NodeConnectionInfoResponse, NodeConnectionsRequest,
+    NodeShutdownRequest, NodeStatsRequest, NodeStatusRequest, NodeStatusResponse, ProviderService,
+    SetTagOption, ShareMode, WrapOption,
 };
-use crate::sync_engine::SyncEvent;
+use crate::sync_engine::{SubscribeFilter, SyncEvent, SyncProgressEvent};
 
 pub mod mem;
 pub mod quic;
@@ -166,12 +178,64 @@ where
         Ok(doc)
     }
 
+    /// Import a document from a snapshot created with [`Doc::snapshot`].
+    ///
+    /// The snapshot must be available in the node's blob store, e.g. by downloading it from a
+    /// [`BlobTicket`] first. All entries are verified before they are inserted. Entries for which
+    /// a newer entry already exists in the document are skipped.
+    ///
+    /// If the document does not exist yet, it is imported with read-only capability.
+    pub async fn import_snapshot(
+        &self,
+        snapshot: HashAndFormat,
+    ) -> Result<(Doc<C>, DocImportOutcome)> {
+        let HashAndFormat { hash, format } = snapshot;
+        let res = self
+            .rpc
+            .rpc(DocImportSnapshotRequest { hash, format })
+            .await??;
+        let outcome = DocImportOutcome {
+            inserted: res.inserted,
+            skipped: res.skipped,
+        };
+        let doc = self.open_imported(res.doc_id).await?;
+        Ok((doc, outcome))
+    }
+
+    /// Import a bundle created with [`Doc::export_bundle`] on another node.
+    ///
+    /// All entries and content blobs are verified before any of them are imported. Entries for
+    /// which a newer entry already exists in the document are skipped.
+    ///
+    /// If the document does not exist yet, it is imported with read-only capability.
+    pub async fn import_bundle(&self, bundle: SyncBundle) -> Result<(Doc<C>, DocImportOutcome)> {
+        let res = self.rpc.rpc(DocImportBundleRequest { bundle }).await??;
+        let outcome = DocImportOutcome {
+            inserted: res.inserted,
+            skipped: res.skipped,
+        };
+        let doc = self.open_imported(res.doc_id).await?;
+        Ok((doc, outcome))
+    }
+
+    /// Open a document after an import, which closes the document on the node when it is done.
+    async fn open_imported(&self, doc_id: NamespaceId) -> Result<Doc<C>> {
+        self.rpc.rpc(DocOpenRequest { doc_id }).await??;
+        Ok(Doc::new(self.rpc.clone(), doc_id))
+    }
+
     /// List all documents.
     pub async fn list(&self) -> Result<impl Stream<Item = Result<(NamespaceId, CapabilityKind)>>> {
         let stream = self.rpc.server_streaming(DocListRequest {}).await?;
         Ok(flatten(stream).map_ok(|res| (res.id, res.capability)))
     }
 
+    /// List all documents, with their name from the document metadata and their local label.
+    pub async fn list_with_names(&self) -> Result<impl Stream<Item = Result<DocListResponse>>> {
+        let stream = self.rpc.server_streaming(DocListRequest {}).await?;
+        Ok(flatten(stream))
+    }
+
     /// Get a [`Doc`] client for a single document. Return None if the document cannot be found.
     pub async fn open(&self, id: NamespaceId) -> Result<Option<Doc<C>>> {
         self.rpc.rpc(DocOpenRequest { doc_id: id }).await??;
@@ -201,6 +265,35 @@ where
         let stream = self.rpc.server_streaming(AuthorListRequest {}).await?;
         Ok(flatten(stream).map_ok(|res| res.author_id))
     }
+
+    /// Export the secret key of an author.
+    ///
+    /// Returns `None` if the author is not known to this node. See
+    /// [`Author::to_encrypted_bytes`] to create a passphrase-protected backup.
+    pub async fn export(&self, author: AuthorId) -> Result<Option<Author>> {
+        let res = self.rpc.rpc(AuthorExportRequest { author }).await??;
+        Ok(res.author)
+    }
+
+    /// Import the secret key of an author, e.g. one exported on another node.
+    pub async fn import(&self, author: Author) -> Result<AuthorId> {
+        let res = self
+            .rpc
+            .rpc(AuthorImportRequest {
+                key: author.to_bytes(),
+            })
+            .await??;
+        Ok(res.author_id)
+    }
+
+    /// Delete an author from this node.
+    ///
+    /// Entries signed by the author are kept, but no new entries can be signed by it on this
+    /// node. Export the author first to keep the key.
+    pub async fn delete(&self, author: AuthorId) -> Result<()> {
+        self.rpc.rpc(AuthorDeleteRequest { author }).await??;
+        Ok(())
+    }
 }
 
 /// Iroh tags client.
@@ -587,6 +680,15 @@ pub struct BlobDownloadOutcome {
     pub downloaded_size: u64,
 }
 
+/// Outcome of a document import from a snapshot or bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DocImportOutcome {
+    /// The number of entries that were inserted
+    pub inserted: u64,
+    /// The number of entries that were skipped because a newer entry already exists
+    pub skipped: u64,
+}
+
 /// Progress stream for blob download operations.
 #[derive(derive_more::Debug)]
 pub struct BlobDownloadProgress {
@@ -973,11 +1075,97 @@ where
         Ok(flatten(stream).map_ok(|res| res.entry.into()))
     }
 
+    /// Count the entries that match a query.
+    ///
+    /// This is cheaper than counting the entries returned from [`Self::get_many`], because the
+    /// entries are not sent to the client.
+    pub async fn count(&self, query: impl Into<Query>) -> Result<u64> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocCountRequest {
+                doc_id: self.id(),
+                query: query.into(),
+            })
+            .await??;
+        Ok(res.count)
+    }
+
     /// Get a single entry.
     pub async fn get_one(&self, query: impl Into<Query>) -> Result<Option<Entry>> {
         self.get_many(query).await?.next().await.transpose()
     }
 
+    /// Get the concurrent heads for a key.
+    ///
+    /// While [`Query::single_latest_per_key`] only returns the entry with the latest timestamp,
+    /// this returns the entries of all authors that wrote a value for the key, latest first.
+    pub async fn get_heads(&self, key: impl AsRef<[u8]>) -> Result<Vec<Entry>> {
+        let mut heads: Vec<Entry> = self
+            .get_many(Query::key_exact(key))
+            .await?
+            .try_collect()
+            .await?;
+        heads.sort_by_key(|entry| std::cmp::Reverse(entry.timestamp()));
+        Ok(heads)
+    }
+
+    /// Merge the concurrent heads for a key into a single value written by `author`.
+    ///
+    /// `merge` is called with the heads from [`Self::get_heads`] and their content, which must be
+    /// available locally. The merged value it returns is set for the key and, being newer than
+    /// all heads, becomes the latest value for the key.
+    ///
+    /// Returns the hash of the merged value, or `None` if the key has less than two heads.
+    pub async fn merge<F>(
+        &self,
+        author: AuthorId,
+        key: impl Into<Bytes>,
+        merge: F,
+    ) -> Result<Option<Hash>>
+    where
+        F: FnOnce(&[(Entry, Bytes)]) -> Result<Bytes>,
+    {
+        let key = key.into();
+        let heads = self.get_heads(&key).await?;
+        if heads.len() < 2 {
+            return Ok(None);
+        }
+        let mut values = Vec::with_capacity(heads.len());
+        for entry in heads {
+            let content = entry.content_bytes(self).await?;
+            values.push((entry, content));
+        }
+        let merged = merge(&values)?;
+        let hash = self.set_bytes(author, key, merged).await?;
+        Ok(Some(hash))
+    }
+
+    /// Get the contributions of all authors to the CRDT value stored under `key`.
+    ///
+    /// The content of the entries must be available locally. Use [`Contributions::value`] to
+    /// get the merged value.
+    pub async fn get_crdt<T: Crdt>(&self, key: impl AsRef<[u8]>) -> Result<Contributions<T>> {
+        let mut contributions = Vec::new();
+        for entry in self.get_heads(key).await? {
+            let content = entry.content_bytes(self).await?;
+            contributions.push((entry.author(), content));
+        }
+        Contributions::decode(contributions)
+    }
+
+    /// Store the contribution of `author` to the CRDT value stored under `key`.
+    ///
+    /// Other authors' contributions are left unchanged.
+    pub async fn set_crdt<T: Crdt>(
+        &self,
+        author: AuthorId,
+        key: impl Into<Bytes>,
+        contributions: &Contributions<T>,
+    ) -> Result<Hash> {
+        let value = contributions.encode(&author)?;
+        self.set_bytes(author, key, value).await
+    }
+
     /// Share this document with peers over a ticket.
     pub async fn share(&self, mode: ShareMode) -> anyhow::Result<DocTicket> {
         self.ensure_open()?;
@@ -1011,11 +1199,28 @@ where
 
     /// Subscribe to events for this document.
     pub async fn subscribe(&self) -> anyhow::Result<impl Stream<Item = anyhow::Result<LiveEvent>>> {
+        self.subscribe_filtered(SubscribeFilter::all(), false).await
+    }
+
+    /// Subscribe to the events for this document that match `filter`.
+    ///
+    /// The filter is evaluated on the node. If `replay` is true, the current entries that match
+    /// the filter are sent first as [`LiveEvent::Replay`] events, followed by
+    /// [`LiveEvent::ReplayFinished`].
+    pub async fn subscribe_filtered(
+        &self,
+        filter: SubscribeFilter,
+        replay: bool,
+    ) -> anyhow::Result<impl Stream<Item = anyhow::Result<LiveEvent>>> {
         self.ensure_open()?;
         let stream = self
             .0
             .rpc
-            .server_streaming(DocSubscribeRequest { doc_id: self.id() })
+            .server_streaming(DocSubscribeRequest {
+                doc_id: self.id(),
+                filter,
+                replay,
+            })
             .await?;
         Ok(flatten(stream)
             .map_ok(|res| res.event.into())
@@ -1046,6 +1251,223 @@ where
             .await??;
         Ok(res.policy)
     }
+
+    /// Set the area of interest that is reconciled when syncing this document.
+    ///
+    /// When syncing with a peer, only entries within the intersection of both peers' areas are
+    /// exchanged.
+    pub async fn set_sync_area(&self, area: AreaOfInterest) -> Result<()> {
+        self.rpc(DocSetSyncAreaRequest {
+            doc_id: self.id(),
+            area,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the area of interest that is reconciled when syncing this document.
+    pub async fn get_sync_area(&self) -> Result<AreaOfInterest> {
+        let res = self
+            .rpc(DocGetSyncAreaRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.area)
+    }
+
+    /// Set the metadata of this document.
+    ///
+    /// The metadata is stored in an entry signed by the document owner, so it is synced along
+    /// with the document. This requires write access to the document.
+    pub async fn set_metadata(&self, metadata: DocMetadata) -> Result<()> {
+        self.ensure_open()?;
+        self.rpc(DocSetMetadataRequest {
+            doc_id: self.id(),
+            metadata,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the metadata of this document.
+    ///
+    /// Returns `None` if no metadata was set, or if its content is not available locally yet.
+    pub async fn metadata(&self) -> Result<Option<DocMetadata>> {
+        let res = self
+            .rpc(DocGetMetadataRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.metadata)
+    }
+
+    /// Set a local label for this document, or remove it with `None`.
+    ///
+    /// Labels are only stored on this node and are not synced.
+    pub async fn set_label(&self, label: Option<String>) -> Result<()> {
+        self.rpc(DocSetLabelRequest {
+            doc_id: self.id(),
+            label,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the local label of this document.
+    pub async fn label(&self) -> Result<Option<String>> {
+        let res = self.rpc(DocGetLabelRequest { doc_id: self.id() }).await??;
+        Ok(res.label)
+    }
+
+    /// Create a snapshot of all entries of this document and store it in the blob store.
+    ///
+    /// If `include_content` is true, the snapshot is stored as a collection which also contains
+    /// all content blobs that are complete on this node. The returned hash and format can be
+    /// shared as a [`BlobTicket`] and imported with [`Docs::import_snapshot`].
+    pub async fn snapshot(
+        &self,
+        include_content: bool,
+        tag: SetTagOption,
+    ) -> Result<(HashAndFormat, Tag)> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocSnapshotRequest {
+                doc_id: self.id(),
+                include_content,
+                tag,
+            })
+            .await??;
+        Ok((
+            HashAndFormat {
+                hash: res.hash,
+                format: res.format,
+            },
+            res.tag,
+        ))
+    }
+
+    /// Get the timestamp of the latest entry of each author in this document.
+    ///
+    /// Another node can use these heads to create a bundle with [`Self::export_bundle`] that
+    /// contains the entries missing on this node.
+    pub async fn author_heads(&self) -> Result<AuthorHeads> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocGetAuthorHeadsRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.heads)
+    }
+
+    /// Create a bundle of the entries that are newer than `heads` for offline sync.
+    ///
+    /// If `include_content` is true, the bundle also contains the content of these entries that
+    /// is complete on this node. The bundle can be imported with [`Docs::import_bundle`].
+    pub async fn export_bundle(
+        &self,
+        heads: AuthorHeads,
+        include_content: bool,
+    ) -> Result<SyncBundle> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocExportBundleRequest {
+                doc_id: self.id(),
+                heads,
+                include_content,
+            })
+            .await??;
+        Ok(res.bundle)
+    }
+
+    /// Set the history policy for this document
+    pub async fn set_history_policy(&self, policy: HistoryPolicy) -> Result<()> {
+        self.rpc(DocSetHistoryPolicyRequest {
+            doc_id: self.id(),
+            policy,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the history policy for this document
+    pub async fn get_history_policy(&self) -> Result<HistoryPolicy> {
+        let res = self
+            .rpc(DocGetHistoryPolicyRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.policy)
+    }
+
+    /// Set the quota for this document.
+    ///
+    /// Local and remote inserts that would exceed the quota are rejected.
+    pub async fn set_quota(&self, quota: Quota) -> Result<()> {
+        self.rpc(DocSetQuotaRequest {
+            doc_id: self.id(),
+            quota,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Get the quota for this document
+    pub async fn get_quota(&self) -> Result<Quota> {
+        let res = self.rpc(DocGetQuotaRequest { doc_id: self.id() }).await??;
+        Ok(res.quota)
+    }
+
+    /// Create a secondary index on this document.
+    ///
+    /// The index is maintained for local and remote inserts. Values extracted from content are
+    /// indexed once the content is available locally. Creating an index with the name of an
+    /// existing index replaces it.
+    pub async fn create_index(&self, spec: IndexSpec) -> Result<()> {
+        self.ensure_open()?;
+        self.rpc(DocCreateIndexRequest {
+            doc_id: self.id(),
+            spec,
+        })
+        .await??;
+        Ok(())
+    }
+
+    /// Remove a secondary index from this document.
+    ///
+    /// Returns `true` if the index existed.
+    pub async fn remove_index(&self, name: impl Into<String>) -> Result<bool> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocRemoveIndexRequest {
+                doc_id: self.id(),
+                name: name.into(),
+            })
+            .await??;
+        Ok(res.removed)
+    }
+
+    /// List the secondary indexes of this document.
+    pub async fn list_indexes(&self) -> Result<Vec<IndexSpec>> {
+        self.ensure_open()?;
+        let res = self
+            .rpc(DocListIndexesRequest { doc_id: self.id() })
+            .await??;
+        Ok(res.indexes)
+    }
+
+    /// Get entries whose value in the secondary index `name` matches `filter`.
+    ///
+    /// Entries are returned ordered by indexed value, then author, then key.
+    pub async fn get_by_index(
+        &self,
+        name: impl Into<String>,
+        filter: impl Into<KeyFilter>,
+    ) -> Result<impl Stream<Item = Result<Entry>>> {
+        self.ensure_open()?;
+        let stream = self
+            .0
+            .rpc
+            .server_streaming(DocGetByIndexRequest {
+                doc_id: self.id(),
+                name: name.into(),
+                filter: filter.into(),
+            })
+            .await?;
+        Ok(flatten(stream).map_ok(|res| res.entry.into()))
+    }
 }
 
 impl<'a, C: ServiceConnection<ProviderService>> From<&'a Doc<C>>
@@ -1080,6 +1502,12 @@ impl From<iroh_sync::SignedEntry> for Entry {
     }
 }
 
+impl From<&Entry> for iroh_sync::store::Cursor {
+    fn from(entry: &Entry) -> Self {
+        Self::new(&entry.0)
+    }
+}
+
 impl Entry {
     /// Get the [`RecordIdentifier`] for this entry.
     pub fn id(&self) -> &RecordIdentifier {
@@ -1169,6 +1597,28 @@ pub enum LiveEvent {
     NeighborDown(PublicKey),
     /// A set-reconciliation sync finished.
     SyncFinished(SyncEvent),
+    /// A set-reconciliation sync made progress.
+    SyncProgress(SyncProgressEvent),
+    /// A remote insert created concurrent values for a key that one of our authors wrote a value
+    /// for.
+    ///
+    /// The values can be merged with [`Doc::merge`].
+    Conflict {
+        /// The key with concurrent values.
+        key: Bytes,
+        /// The entries of all authors for the key, latest first.
+        heads: Vec<Entry>,
+    },
+    /// An existing entry that matches the subscription filter, sent before the live events if
+    /// replay was requested.
+    Replay {
+        /// The entry.
+        entry: Entry,
+        /// If the content is available at the local node
+        content_status: ContentStatus,
+    },
+    /// All existing entries were replayed, the following events are live.
+    ReplayFinished,
 }
 
 impl From<crate::sync_engine::LiveEvent> for LiveEvent {
@@ -1190,6 +1640,19 @@ impl From<crate::sync_engine::LiveEvent> for LiveEvent {
             crate::sync_engine::LiveEvent::NeighborUp(node) => Self::NeighborUp(node),
             crate::sync_engine::LiveEvent::NeighborDown(node) => Self::NeighborDown(node),
             crate::sync_engine::LiveEvent::SyncFinished(details) => Self::SyncFinished(details),
+            crate::sync_engine::LiveEvent::SyncProgress(progress) => Self::SyncProgress(progress),
+            crate::sync_engine::LiveEvent::Conflict { key, heads } => Self::Conflict {
+                key,
+                heads: heads.into_iter().map(Into::into).collect(),
+            },
+            crate::sync_engine::LiveEvent::Replay {
+                entry,
+                content_status,
+            } => Self::Replay {
+                content_status,
+                entry: entry.into(),
+            },
+            crate::sync_engine::LiveEvent::ReplayFinished => Self::ReplayFinished,
         }
     }
 }
@@ -14,7 +14,10 @@ use iroh_bytes::{
     store::{GcMarkEvent, GcSweepEvent, Map, Store as BaoStore},
 };
 use iroh_gossip::net::{Gossip, GOSSIP_ALPN};
-use iroh_net::{derp::DerpMode, magic_endpoint::get_alpn, util::AbortingJoinHandle, MagicEndpoint};
+use iroh_net::{
+    derp::DerpMode, magic_endpoint::get_alpn, magicsock::Transport, util::AbortingJoinHandle,
+    MagicEndpoint,
+};
 use iroh_sync::net::SYNC_ALPN;
 use quic_rpc::{
     transport::{misc::DummyServerEndpoint, quinn::QuinnServerEndpoint},
@@ -77,6 +80,7 @@ where
     blobs_store: D,
     keylog: bool,
     derp_mode: DerpMode,
+    transports: Vec<Arc<dyn Transport>>,
     gc_policy: GcPolicy,
     docs_store: S,
 }
@@ -99,6 +103,7 @@ impl Default for Builder<iroh_bytes::store::mem::Store, iroh_sync::store::memory
             blobs_store: Default::default(),
             keylog: false,
             derp_mode: DerpMode::Default,
+            transports: Vec::new(),
             rpc_endpoint: Default::default(),
             gc_policy: GcPolicy::Disabled,
             docs_store: Default::default(),
@@ -116,6 +121,7 @@ impl<D: Map, S: DocStore> Builder<D, S> {
             blobs_store,
             keylog: false,
             derp_mode: DerpMode::Default,
+            transports: Vec::new(),
             rpc_endpoint: Default::default(),
             gc_policy: GcPolicy::Disabled,
             docs_store,
@@ -156,6 +162,7 @@ where
             keylog: self.keylog,
             rpc_endpoint: self.rpc_endpoint,
             derp_mode: self.derp_mode,
+            transports: self.transports,
             gc_policy: self.gc_policy,
             docs_store,
         })
@@ -175,6 +182,7 @@ where
             keylog: self.keylog,
             rpc_endpoint: value,
             derp_mode: self.derp_mode,
+            transports: self.transports,
             gc_policy: self.gc_policy,
             docs_store: self.docs_store,
         }
@@ -198,6 +206,7 @@ where
             keylog: self.keylog,
             rpc_endpoint: ep,
             derp_mode: self.derp_mode,
+            transports: self.transports,
             gc_policy: self.gc_policy,
             docs_store: self.docs_store,
         })
@@ -225,6 +234,14 @@ where
         self
     }
 
+    /// Adds a [`Transport`] over which the node can reach other nodes, besides UDP and DERP.
+    ///
+    /// See [`iroh_net::magic_endpoint::MagicEndpointBuilder::transport`].
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transports.push(transport);
+        self
+    }
+
     /// Binds the node service to a different socket.
     ///
     /// By default it binds to `127.0.0.1:11204`.
@@ -279,6 +296,12 @@ where
             .transport_config(transport_config)
             .concurrent_connections(MAX_CONNECTIONS)
             .derp_mode(self.derp_mode);
+        let endpoint = self
+            .transports
+            .into_iter()
+            .fold(endpoint, |endpoint, transport| {
+                endpoint.transport(transport)
+            });
         let endpoint = match self.storage {
             StorageConfig::Persistent(ref root) => {
                 let peers_data_path = IrohPaths::PeerData.with_root(root);
@@ -309,6 +332,7 @@ where
             self.docs_store,
             self.blobs_store.clone(),
             downloader,
+            lp.clone(),
         );
 
         let callbacks = Callbacks::default();
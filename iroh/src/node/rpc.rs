@@ -1,3 +1,4 @@
+use std::collections::BTreeSet;
 use std::fmt::Debug;
 use std::io;
 use std::sync::{Arc, Mutex};
@@ -18,9 +19,14 @@ use iroh_bytes::{
     provider::AddProgress,
     store::{PossiblyPartialEntry, Store as BaoStore, ValidateProgress},
     util::progress::FlumeProgressSender,
-    HashAndFormat,
+    Hash, HashAndFormat,
+};
+use iroh_io::{AsyncSliceReader, AsyncSliceReaderExt};
+use iroh_sync::{
+    metadata::DocMetadata,
+    snapshot::{Snapshot, SNAPSHOT_BLOB_NAME},
+    NamespaceId,
 };
-use iroh_io::AsyncSliceReader;
 use quic_rpc::{
     server::{RpcChannel, RpcServerError},
     ServiceEndpoint,
@@ -35,13 +41,15 @@ use crate::rpc_protocol::{
     BlobGetCollectionRequest, BlobGetCollectionResponse, BlobListCollectionsRequest,
     BlobListCollectionsResponse, BlobListIncompleteRequest, BlobListIncompleteResponse,
     BlobListRequest, BlobListResponse, BlobReadAtRequest, BlobReadAtResponse, BlobValidateRequest,
-    CreateCollectionRequest, CreateCollectionResponse, DeleteTagRequest, DocExportFileRequest,
-    DocExportFileResponse, DocImportFileRequest, DocImportFileResponse, DocImportProgress,
-    DocSetHashRequest, DownloadLocation, ListTagsRequest, ListTagsResponse,
-    NodeConnectionInfoRequest, NodeConnectionInfoResponse, NodeConnectionsRequest,
-    NodeConnectionsResponse, NodeShutdownRequest, NodeStatsRequest, NodeStatsResponse,
-    NodeStatusRequest, NodeStatusResponse, NodeWatchRequest, NodeWatchResponse, ProviderRequest,
-    ProviderService, SetTagOption,
+    CreateCollectionRequest, CreateCollectionResponse, DeleteTagRequest, DocExportBundleRequest,
+    DocExportBundleResponse, DocExportFileRequest, DocExportFileResponse, DocGetMetadataRequest,
+    DocGetMetadataResponse, DocImportFileRequest, DocImportFileResponse, DocImportProgress,
+    DocImportSnapshotRequest, DocImportSnapshotResponse, DocListRequest, DocListResponse,
+    DocSetHashRequest, DocShareRequest, DocShareResponse, DownloadLocation, ListTagsRequest,
+    ListTagsResponse, NodeConnectionInfoRequest, NodeConnectionInfoResponse,
+    NodeConnectionsRequest, NodeConnectionsResponse, NodeShutdownRequest, NodeStatsRequest,
+    NodeStatsResponse, NodeStatusRequest, NodeStatusResponse, NodeWatchRequest, NodeWatchResponse,
+    ProviderRequest, ProviderService, SetTagOption,
 };
 
 use super::{Event, NodeInner};
@@ -127,8 +135,23 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
-                AuthorImport(_msg) => {
-                    todo!()
+                AuthorImport(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.author_import(req).await
+                    })
+                    .await
+                }
+                AuthorExport(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.author_export(req).await
+                    })
+                    .await
+                }
+                AuthorDelete(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.author_delete(req).await
+                    })
+                    .await
                 }
                 DocOpen(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
@@ -148,12 +171,7 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
-                DocList(msg) => {
-                    chan.server_streaming(msg, handler, |handler, req| {
-                        handler.inner.sync.doc_list(req)
-                    })
-                    .await
-                }
+                DocList(msg) => chan.server_streaming(msg, handler, Self::doc_list).await,
                 DocCreate(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
                         handler.inner.sync.doc_create(req).await
@@ -211,24 +229,25 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
-                DocStartSync(msg) => {
+                DocCount(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
-                        handler.inner.sync.doc_start_sync(req).await
+                        handler.inner.sync.doc_count(req).await
                     })
                     .await
                 }
-                DocLeave(msg) => {
+                DocStartSync(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
-                        handler.inner.sync.doc_leave(req).await
+                        handler.inner.sync.doc_start_sync(req).await
                     })
                     .await
                 }
-                DocShare(msg) => {
+                DocLeave(msg) => {
                     chan.rpc(msg, handler, |handler, req| async move {
-                        handler.inner.sync.doc_share(req).await
+                        handler.inner.sync.doc_leave(req).await
                     })
                     .await
                 }
+                DocShare(msg) => chan.rpc(msg, handler, Self::doc_share).await,
                 DocSubscribe(msg) => {
                     chan.server_streaming(msg, handler, |handler, req| {
                         async move { handler.inner.sync.doc_subscribe(req) }.flatten_stream()
@@ -247,6 +266,108 @@ impl<D: BaoStore> Handler<D> {
                     })
                     .await
                 }
+                DocSetSyncArea(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_sync_area(req).await
+                    })
+                    .await
+                }
+                DocGetSyncArea(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_sync_area(req).await
+                    })
+                    .await
+                }
+                DocSetHistoryPolicy(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_history_policy(req).await
+                    })
+                    .await
+                }
+                DocGetHistoryPolicy(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_history_policy(req).await
+                    })
+                    .await
+                }
+                DocSetQuota(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_quota(req).await
+                    })
+                    .await
+                }
+                DocGetQuota(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_quota(req).await
+                    })
+                    .await
+                }
+                DocCreateIndex(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_create_index(req).await
+                    })
+                    .await
+                }
+                DocRemoveIndex(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_remove_index(req).await
+                    })
+                    .await
+                }
+                DocListIndexes(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_list_indexes(req).await
+                    })
+                    .await
+                }
+                DocGetByIndex(msg) => {
+                    chan.server_streaming(msg, handler, |handler, req| {
+                        handler.inner.sync.doc_get_by_index(req)
+                    })
+                    .await
+                }
+                DocSnapshot(msg) => {
+                    let bao_store = handler.inner.db.clone();
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_snapshot(&bao_store, req).await
+                    })
+                    .await
+                }
+                DocImportSnapshot(msg) => chan.rpc(msg, handler, Self::doc_import_snapshot).await,
+                DocGetAuthorHeads(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_author_heads(req).await
+                    })
+                    .await
+                }
+                DocExportBundle(msg) => chan.rpc(msg, handler, Self::doc_export_bundle).await,
+                DocImportBundle(msg) => {
+                    let bao_store = handler.inner.db.clone();
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_import_bundle(&bao_store, req).await
+                    })
+                    .await
+                }
+                DocSetMetadata(msg) => {
+                    let bao_store = handler.inner.db.clone();
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_metadata(&bao_store, req).await
+                    })
+                    .await
+                }
+                DocGetMetadata(msg) => chan.rpc(msg, handler, Self::doc_get_metadata).await,
+                DocSetLabel(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_set_label(req).await
+                    })
+                    .await
+                }
+                DocGetLabel(msg) => {
+                    chan.rpc(msg, handler, |handler, req| async move {
+                        handler.inner.sync.doc_get_label(req).await
+                    })
+                    .await
+                }
             }
         });
     }
@@ -968,6 +1089,144 @@ impl<D: BaoStore> Handler<D> {
         Ok(CreateCollectionResponse { hash, tag })
     }
 
+    async fn doc_import_snapshot(
+        self,
+        req: DocImportSnapshotRequest,
+    ) -> RpcResult<DocImportSnapshotResponse> {
+        let DocImportSnapshotRequest { hash, format } = req;
+        let db = self.inner.db.clone();
+        let snapshot = self
+            .rt()
+            .spawn_pinned(move || async move {
+                let snapshot_hash = match format {
+                    BlobFormat::Raw => hash,
+                    BlobFormat::HashSeq => Collection::load(&db, &hash)
+                        .await?
+                        .iter()
+                        .find(|(name, _hash)| name == SNAPSHOT_BLOB_NAME)
+                        .map(|(_name, hash)| *hash)
+                        .ok_or_else(|| {
+                            anyhow!("collection does not contain a document snapshot")
+                        })?,
+                };
+                let entry = db
+                    .get(&snapshot_hash)
+                    .await?
+                    .filter(|entry| entry.is_complete())
+                    .ok_or_else(|| anyhow!("snapshot blob not found"))?;
+                let bytes = entry.data_reader().await?.read_to_end().await?;
+                Snapshot::from_bytes(&bytes)
+            })
+            .await
+            .map_err(|_| anyhow!("join failed"))??;
+        self.inner.sync.doc_import_snapshot(snapshot).await
+    }
+
+    async fn doc_export_bundle(
+        self,
+        req: DocExportBundleRequest,
+    ) -> RpcResult<DocExportBundleResponse> {
+        let include_content = req.include_content;
+        let DocExportBundleResponse { bundle } = self.inner.sync.doc_export_bundle(req).await?;
+        if !include_content {
+            return Ok(DocExportBundleResponse { bundle });
+        }
+        let db = self.inner.db.clone();
+        let bundle = self
+            .rt()
+            .spawn_pinned(move || async move {
+                let mut bundle = bundle;
+                let hashes: BTreeSet<_> =
+                    bundle.entries().iter().map(|e| e.content_hash()).collect();
+                for hash in hashes {
+                    // only add content that is complete in our store.
+                    let Some(entry) = db.get(&hash).await?.filter(|entry| entry.is_complete())
+                    else {
+                        continue;
+                    };
+                    if hash != Hash::EMPTY {
+                        let content = entry.data_reader().await?.read_to_end().await?;
+                        bundle.add_content(hash, content);
+                    }
+                }
+                anyhow::Ok(bundle)
+            })
+            .await
+            .map_err(|_| anyhow!("join failed"))??;
+        Ok(DocExportBundleResponse { bundle })
+    }
+
+    /// Load the metadata of a document, if its entry and content are available locally.
+    ///
+    /// This reads from the blob store, so it must run on the local pool.
+    async fn load_doc_metadata(&self, doc_id: NamespaceId) -> Result<Option<DocMetadata>> {
+        let Some(entry) = self.inner.sync.sync.get_metadata_entry(doc_id).await? else {
+            return Ok(None);
+        };
+        let Some(blob) = self
+            .inner
+            .db
+            .get(&entry.content_hash())
+            .await?
+            .filter(|blob| blob.is_complete())
+        else {
+            return Ok(None);
+        };
+        let bytes = blob.data_reader().await?.read_to_end().await?;
+        Ok(Some(DocMetadata::from_bytes(&bytes)?))
+    }
+
+    fn doc_list(self, req: DocListRequest) -> impl Stream<Item = RpcResult<DocListResponse>> {
+        let (tx, rx) = flume::bounded(32);
+        self.rt().spawn_pinned(|| async move {
+            let docs = self.inner.sync.doc_list(req);
+            tokio::pin!(docs);
+            while let Some(mut doc) = docs.next().await {
+                if let Ok(doc) = doc.as_mut() {
+                    match self.load_doc_metadata(doc.id).await {
+                        Ok(metadata) => doc.name = metadata.map(|metadata| metadata.name),
+                        Err(err) => {
+                            debug!(doc = %doc.id.fmt_short(), "failed to load metadata: {err}")
+                        }
+                    }
+                }
+                if tx.send_async(doc).await.is_err() {
+                    break;
+                }
+            }
+        });
+        rx.into_stream()
+    }
+
+    async fn doc_share(self, req: DocShareRequest) -> RpcResult<DocShareResponse> {
+        let doc_id = req.doc_id;
+        let DocShareResponse(ticket) = self.inner.sync.doc_share(req).await?;
+        let this = self.clone();
+        let metadata = self
+            .rt()
+            .spawn_pinned(move || async move { this.load_doc_metadata(doc_id).await })
+            .await
+            .map_err(|_| anyhow!("join failed"))??;
+        let ticket = match metadata {
+            Some(metadata) => ticket.with_metadata(metadata),
+            None => ticket,
+        };
+        Ok(DocShareResponse(ticket))
+    }
+
+    async fn doc_get_metadata(
+        self,
+        req: DocGetMetadataRequest,
+    ) -> RpcResult<DocGetMetadataResponse> {
+        let this = self.clone();
+        let metadata = self
+            .rt()
+            .spawn_pinned(move || async move { this.load_doc_metadata(req.doc_id).await })
+            .await
+            .map_err(|_| anyhow!("join failed"))??;
+        Ok(DocGetMetadataResponse { metadata })
+    }
+
     async fn blob_get_collection(
         self,
         req: BlobGetCollectionRequest,
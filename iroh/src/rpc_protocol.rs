@@ -20,8 +20,10 @@ use iroh_net::{
 
 use iroh_sync::{
     actor::OpenState,
-    store::{DownloadPolicy, Query},
-    {AuthorId, CapabilityKind, Entry, NamespaceId, SignedEntry},
+    bundle::SyncBundle,
+    metadata::DocMetadata,
+    store::{AreaOfInterest, DownloadPolicy, HistoryPolicy, IndexSpec, KeyFilter, Query, Quota},
+    {Author, AuthorHeads, AuthorId, CapabilityKind, Entry, NamespaceId, SignedEntry},
 };
 use quic_rpc::{
     message::{BidiStreaming, BidiStreamingMsg, Msg, RpcMsg, ServerStreaming, ServerStreamingMsg},
@@ -33,7 +35,7 @@ pub use iroh_base::rpc::{RpcError, RpcResult};
 use iroh_bytes::store::ExportMode;
 pub use iroh_bytes::{provider::AddProgress, store::ValidateProgress};
 
-use crate::sync_engine::LiveEvent;
+use crate::sync_engine::{LiveEvent, SubscribeFilter};
 pub use crate::ticket::DocTicket;
 
 /// A 32-byte key or token
@@ -473,6 +475,41 @@ pub struct AuthorImportResponse {
     pub author_id: AuthorId,
 }
 
+/// Export the secret key of an author
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthorExportRequest {
+    /// The id of the author to export
+    pub author: AuthorId,
+}
+
+impl RpcMsg<ProviderService> for AuthorExportRequest {
+    type Response = RpcResult<AuthorExportResponse>;
+}
+
+/// Response to [`AuthorExportRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthorExportResponse {
+    /// The author, or `None` if it was not found
+    pub author: Option<Author>,
+}
+
+/// Delete an author
+///
+/// Entries signed by the author are kept.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthorDeleteRequest {
+    /// The id of the author to delete
+    pub author: AuthorId,
+}
+
+impl RpcMsg<ProviderService> for AuthorDeleteRequest {
+    type Response = RpcResult<AuthorDeleteResponse>;
+}
+
+/// Response to [`AuthorDeleteRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AuthorDeleteResponse {}
+
 /// Intended capability for document share tickets
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum ShareMode {
@@ -487,6 +524,10 @@ pub enum ShareMode {
 pub struct DocSubscribeRequest {
     /// The document id
     pub doc_id: NamespaceId,
+    /// Only send events that match this filter
+    pub filter: SubscribeFilter,
+    /// Whether to send the current entries that match the filter before the live events
+    pub replay: bool,
 }
 
 impl Msg<ProviderService> for DocSubscribeRequest {
@@ -523,6 +564,10 @@ pub struct DocListResponse {
     pub id: NamespaceId,
     /// The capability over the document.
     pub capability: CapabilityKind,
+    /// The name from the document's metadata, if available locally
+    pub name: Option<String>,
+    /// The local label of the document
+    pub label: Option<String>,
 }
 
 /// Create a new document
@@ -894,6 +939,26 @@ pub struct DocGetExactResponse {
     pub entry: Option<SignedEntry>,
 }
 
+/// Count the entries in a document that match a query
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocCountRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Query to run
+    pub query: Query,
+}
+
+impl RpcMsg<ProviderService> for DocCountRequest {
+    type Response = RpcResult<DocCountResponse>;
+}
+
+/// Response to [`DocCountRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocCountResponse {
+    /// The number of matching entries
+    pub count: u64,
+}
+
 /// Set a download policy
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DocSetDownloadPolicyRequest {
@@ -929,6 +994,381 @@ pub struct DocGetDownloadPolicyResponse {
     pub policy: DownloadPolicy,
 }
 
+/// Set the area of interest that is reconciled when syncing
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetSyncAreaRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Area of interest
+    pub area: AreaOfInterest,
+}
+
+impl RpcMsg<ProviderService> for DocSetSyncAreaRequest {
+    type Response = RpcResult<DocSetSyncAreaResponse>;
+}
+
+/// Response to [`DocSetSyncAreaRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetSyncAreaResponse {}
+
+/// Get the area of interest that is reconciled when syncing
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetSyncAreaRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetSyncAreaRequest {
+    type Response = RpcResult<DocGetSyncAreaResponse>;
+}
+
+/// Response to [`DocGetSyncAreaRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetSyncAreaResponse {
+    /// The area of interest
+    pub area: AreaOfInterest,
+}
+
+/// Set a history policy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetHistoryPolicyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// History policy
+    pub policy: HistoryPolicy,
+}
+
+impl RpcMsg<ProviderService> for DocSetHistoryPolicyRequest {
+    type Response = RpcResult<DocSetHistoryPolicyResponse>;
+}
+
+/// Response to [`DocSetHistoryPolicyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetHistoryPolicyResponse {}
+
+/// Get a history policy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetHistoryPolicyRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetHistoryPolicyRequest {
+    type Response = RpcResult<DocGetHistoryPolicyResponse>;
+}
+
+/// Response to [`DocGetHistoryPolicyRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetHistoryPolicyResponse {
+    /// The history policy
+    pub policy: HistoryPolicy,
+}
+
+/// Set a quota
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetQuotaRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Quota
+    pub quota: Quota,
+}
+
+impl RpcMsg<ProviderService> for DocSetQuotaRequest {
+    type Response = RpcResult<DocSetQuotaResponse>;
+}
+
+/// Response to [`DocSetQuotaRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetQuotaResponse {}
+
+/// Get a quota
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetQuotaRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetQuotaRequest {
+    type Response = RpcResult<DocGetQuotaResponse>;
+}
+
+/// Response to [`DocGetQuotaRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetQuotaResponse {
+    /// The quota
+    pub quota: Quota,
+}
+
+/// Create a secondary index on a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocCreateIndexRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Index definition
+    pub spec: IndexSpec,
+}
+
+impl RpcMsg<ProviderService> for DocCreateIndexRequest {
+    type Response = RpcResult<DocCreateIndexResponse>;
+}
+
+/// Response to [`DocCreateIndexRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocCreateIndexResponse {}
+
+/// Remove a secondary index from a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocRemoveIndexRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Name of the index
+    pub name: String,
+}
+
+impl RpcMsg<ProviderService> for DocRemoveIndexRequest {
+    type Response = RpcResult<DocRemoveIndexResponse>;
+}
+
+/// Response to [`DocRemoveIndexRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocRemoveIndexResponse {
+    /// Whether the index existed
+    pub removed: bool,
+}
+
+/// List the secondary indexes of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocListIndexesRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocListIndexesRequest {
+    type Response = RpcResult<DocListIndexesResponse>;
+}
+
+/// Response to [`DocListIndexesRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocListIndexesResponse {
+    /// The index definitions
+    pub indexes: Vec<IndexSpec>,
+}
+
+/// Get entries from a document by their value in a secondary index
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetByIndexRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Name of the index
+    pub name: String,
+    /// Matcher for the indexed value
+    pub filter: KeyFilter,
+}
+
+impl Msg<ProviderService> for DocGetByIndexRequest {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<ProviderService> for DocGetByIndexRequest {
+    type Response = RpcResult<DocGetByIndexResponse>;
+}
+
+/// Response to [`DocGetByIndexRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetByIndexResponse {
+    /// The document entry
+    pub entry: SignedEntry,
+}
+
+/// Create a snapshot of all entries of a document and store it as a blob
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSnapshotRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Whether to include the content of the entries.
+    ///
+    /// If true, the snapshot is stored as a collection of the snapshot blob and the content blobs
+    /// that are complete in the local blob store. Otherwise, only the snapshot blob is stored.
+    pub include_content: bool,
+    /// Tag option for the snapshot.
+    pub tag: SetTagOption,
+}
+
+impl RpcMsg<ProviderService> for DocSnapshotRequest {
+    type Response = RpcResult<DocSnapshotResponse>;
+}
+
+/// Response to [`DocSnapshotRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSnapshotResponse {
+    /// The hash of the snapshot
+    pub hash: Hash,
+    /// The format of the snapshot blob
+    pub format: BlobFormat,
+    /// The tag of the snapshot
+    pub tag: Tag,
+}
+
+/// Import a document from a snapshot stored in the local blob store
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocImportSnapshotRequest {
+    /// The hash of the snapshot
+    pub hash: Hash,
+    /// The format of the snapshot blob
+    pub format: BlobFormat,
+}
+
+impl RpcMsg<ProviderService> for DocImportSnapshotRequest {
+    type Response = RpcResult<DocImportSnapshotResponse>;
+}
+
+/// Response to [`DocImportSnapshotRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocImportSnapshotResponse {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Number of entries that were inserted
+    pub inserted: u64,
+    /// Number of entries that were skipped because a newer entry already exists
+    pub skipped: u64,
+}
+
+/// Get the timestamp of the latest entry of each author in a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetAuthorHeadsRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetAuthorHeadsRequest {
+    type Response = RpcResult<DocGetAuthorHeadsResponse>;
+}
+
+/// Response to [`DocGetAuthorHeadsRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetAuthorHeadsResponse {
+    /// The latest timestamp of each author
+    pub heads: AuthorHeads,
+}
+
+/// Export the entries of a document that a peer is missing as a bundle for offline sync
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocExportBundleRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// The author heads of the peer the bundle is created for
+    pub heads: AuthorHeads,
+    /// Whether to include the content of the entries that is complete in the local blob store
+    pub include_content: bool,
+}
+
+impl RpcMsg<ProviderService> for DocExportBundleRequest {
+    type Response = RpcResult<DocExportBundleResponse>;
+}
+
+/// Response to [`DocExportBundleRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocExportBundleResponse {
+    /// The bundle
+    pub bundle: SyncBundle,
+}
+
+/// Import a bundle created with [`DocExportBundleRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocImportBundleRequest {
+    /// The bundle
+    pub bundle: SyncBundle,
+}
+
+impl RpcMsg<ProviderService> for DocImportBundleRequest {
+    type Response = RpcResult<DocImportBundleResponse>;
+}
+
+/// Response to [`DocImportBundleRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocImportBundleResponse {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// Number of entries that were inserted
+    pub inserted: u64,
+    /// Number of entries that were skipped because a newer entry already exists
+    pub skipped: u64,
+    /// Number of content blobs that were imported
+    pub content: u64,
+}
+
+/// Set the metadata of a document
+///
+/// Only the owner of the document, i.e. a node with write access, can set the metadata.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetMetadataRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// The metadata
+    pub metadata: DocMetadata,
+}
+
+impl RpcMsg<ProviderService> for DocSetMetadataRequest {
+    type Response = RpcResult<DocSetMetadataResponse>;
+}
+
+/// Response to [`DocSetMetadataRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetMetadataResponse {}
+
+/// Get the metadata of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetMetadataRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetMetadataRequest {
+    type Response = RpcResult<DocGetMetadataResponse>;
+}
+
+/// Response to [`DocGetMetadataRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetMetadataResponse {
+    /// The metadata, if it was set and its content is available locally
+    pub metadata: Option<DocMetadata>,
+}
+
+/// Set or remove the local label of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetLabelRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+    /// The label, or `None` to remove it
+    pub label: Option<String>,
+}
+
+impl RpcMsg<ProviderService> for DocSetLabelRequest {
+    type Response = RpcResult<DocSetLabelResponse>;
+}
+
+/// Response to [`DocSetLabelRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocSetLabelResponse {}
+
+/// Get the local label of a document
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetLabelRequest {
+    /// The document id
+    pub doc_id: NamespaceId,
+}
+
+impl RpcMsg<ProviderService> for DocGetLabelRequest {
+    type Response = RpcResult<DocGetLabelResponse>;
+}
+
+/// Response to [`DocGetLabelRequest`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DocGetLabelResponse {
+    /// The label
+    pub label: Option<String>,
+}
+
 /// Get the bytes for a hash
 #[derive(Serialize, Deserialize, Debug)]
 pub struct BlobReadAtRequest {
@@ -1060,6 +1500,7 @@ pub enum ProviderRequest {
     DocSetHash(DocSetHashRequest),
     DocGet(DocGetManyRequest),
     DocGetExact(DocGetExactRequest),
+    DocCount(DocCountRequest),
     DocImportFile(DocImportFileRequest),
     DocExportFile(DocExportFileRequest),
     DocDel(DocDelRequest),
@@ -1069,10 +1510,31 @@ pub enum ProviderRequest {
     DocSubscribe(DocSubscribeRequest),
     DocGetDownloadPolicy(DocGetDownloadPolicyRequest),
     DocSetDownloadPolicy(DocSetDownloadPolicyRequest),
+    DocGetSyncArea(DocGetSyncAreaRequest),
+    DocSetSyncArea(DocSetSyncAreaRequest),
+    DocGetHistoryPolicy(DocGetHistoryPolicyRequest),
+    DocSetHistoryPolicy(DocSetHistoryPolicyRequest),
+    DocGetQuota(DocGetQuotaRequest),
+    DocSetQuota(DocSetQuotaRequest),
+    DocCreateIndex(DocCreateIndexRequest),
+    DocRemoveIndex(DocRemoveIndexRequest),
+    DocListIndexes(DocListIndexesRequest),
+    DocGetByIndex(DocGetByIndexRequest),
+    DocSnapshot(DocSnapshotRequest),
+    DocImportSnapshot(DocImportSnapshotRequest),
+    DocGetAuthorHeads(DocGetAuthorHeadsRequest),
+    DocExportBundle(DocExportBundleRequest),
+    DocImportBundle(DocImportBundleRequest),
+    DocSetMetadata(DocSetMetadataRequest),
+    DocGetMetadata(DocGetMetadataRequest),
+    DocSetLabel(DocSetLabelRequest),
+    DocGetLabel(DocGetLabelRequest),
 
     AuthorList(AuthorListRequest),
     AuthorCreate(AuthorCreateRequest),
     AuthorImport(AuthorImportRequest),
+    AuthorExport(AuthorExportRequest),
+    AuthorDelete(AuthorDeleteRequest),
 }
 
 /// The response enum, listing all possible responses.
@@ -1111,6 +1573,7 @@ pub enum ProviderResponse {
     DocSetHash(RpcResult<DocSetHashResponse>),
     DocGet(RpcResult<DocGetManyResponse>),
     DocGetExact(RpcResult<DocGetExactResponse>),
+    DocCount(RpcResult<DocCountResponse>),
     DocImportFile(DocImportFileResponse),
     DocExportFile(DocExportFileResponse),
     DocDel(RpcResult<DocDelResponse>),
@@ -1120,10 +1583,31 @@ pub enum ProviderResponse {
     DocSubscribe(RpcResult<DocSubscribeResponse>),
     DocGetDownloadPolicy(RpcResult<DocGetDownloadPolicyResponse>),
     DocSetDownloadPolicy(RpcResult<DocSetDownloadPolicyResponse>),
+    DocGetSyncArea(RpcResult<DocGetSyncAreaResponse>),
+    DocSetSyncArea(RpcResult<DocSetSyncAreaResponse>),
+    DocGetHistoryPolicy(RpcResult<DocGetHistoryPolicyResponse>),
+    DocSetHistoryPolicy(RpcResult<DocSetHistoryPolicyResponse>),
+    DocGetQuota(RpcResult<DocGetQuotaResponse>),
+    DocSetQuota(RpcResult<DocSetQuotaResponse>),
+    DocCreateIndex(RpcResult<DocCreateIndexResponse>),
+    DocRemoveIndex(RpcResult<DocRemoveIndexResponse>),
+    DocListIndexes(RpcResult<DocListIndexesResponse>),
+    DocGetByIndex(RpcResult<DocGetByIndexResponse>),
+    DocSnapshot(RpcResult<DocSnapshotResponse>),
+    DocImportSnapshot(RpcResult<DocImportSnapshotResponse>),
+    DocGetAuthorHeads(RpcResult<DocGetAuthorHeadsResponse>),
+    DocExportBundle(RpcResult<DocExportBundleResponse>),
+    DocImportBundle(RpcResult<DocImportBundleResponse>),
+    DocSetMetadata(RpcResult<DocSetMetadataResponse>),
+    DocGetMetadata(RpcResult<DocGetMetadataResponse>),
+    DocSetLabel(RpcResult<DocSetLabelResponse>),
+    DocGetLabel(RpcResult<DocGetLabelResponse>),
 
     AuthorList(RpcResult<AuthorListResponse>),
     AuthorCreate(RpcResult<AuthorCreateResponse>),
     AuthorImport(RpcResult<AuthorImportResponse>),
+    AuthorExport(RpcResult<AuthorExportResponse>),
+    AuthorDelete(RpcResult<AuthorDeleteResponse>),
 }
 
 impl Service for ProviderService {
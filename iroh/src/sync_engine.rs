@@ -2,21 +2,39 @@
 //!
 //! [`iroh_sync::Replica`] is also called documents here.
 
-use std::{io, sync::Arc};
+use std::{
+    collections::{BTreeSet, HashSet},
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use bytes::Bytes;
 use futures::{
     future::{BoxFuture, FutureExt, Shared},
     Stream, TryStreamExt,
 };
+use genawaiter::sync::Gen;
 use iroh_bytes::downloader::Downloader;
-use iroh_bytes::{store::EntryStatus, Hash};
+use iroh_bytes::{
+    store::{EntryStatus, MapEntry},
+    Hash,
+};
 use iroh_gossip::net::Gossip;
-use iroh_net::{key::PublicKey, MagicEndpoint, NodeAddr};
-use iroh_sync::{actor::SyncHandle, ContentStatus, ContentStatusCallback, Entry, NamespaceId};
+use iroh_io::AsyncSliceReaderExt;
+use iroh_net::{key::PublicKey, util::AbortingJoinHandle, MagicEndpoint, NodeAddr};
+use iroh_sync::{
+    actor::SyncHandle,
+    store::{KeyFilter, Query},
+    AuthorId, ContentStatus, ContentStatusCallback, Entry, NamespaceId,
+};
 use serde::{Deserialize, Serialize};
 use tokio::sync::{mpsc, oneshot};
 use tokio_stream::StreamExt;
+use tokio_util::task::LocalPoolHandle;
 use tracing::{error, error_span, Instrument};
 
 mod gossip;
@@ -27,7 +45,7 @@ mod state;
 use gossip::GossipActor;
 use live::{LiveActor, ToLiveActor};
 
-pub use self::live::SyncEvent;
+pub use self::live::{SyncEvent, SyncProgressEvent};
 pub use self::state::{Origin, SyncReason};
 pub use iroh_sync::net::SYNC_ALPN;
 
@@ -35,6 +53,15 @@ pub use iroh_sync::net::SYNC_ALPN;
 const ACTOR_CHANNEL_CAP: usize = 64;
 /// Capacity for the channels for [`SyncEngine::subscribe`].
 const SUBSCRIBE_CHANNEL_CAP: usize = 256;
+/// Capacity of the buffer for live events that arrive while replaying entries in
+/// [`SyncEngine::subscribe_filtered`].
+const REPLAY_LIVE_BUFFER_CAP: usize = 1024;
+/// Maximum size of content that is read to update the content based indexes of a document.
+const MAX_INDEXED_CONTENT_SIZE: u64 = 1024 * 1024;
+
+/// Callback to update the content based indexes of a document for a content hash.
+pub(crate) type IndexContentCallback =
+    Arc<dyn Fn(NamespaceId, Hash) -> BoxFuture<'static, Result<()>> + Send + Sync + 'static>;
 
 /// The sync engine coordinates actors that manage open documents, set-reconciliation syncs with
 /// peers and a gossip swarm for each syncing document.
@@ -49,6 +76,8 @@ pub struct SyncEngine {
     tasks_fut: Shared<BoxFuture<'static, ()>>,
     #[debug("ContentStatusCallback")]
     content_status_cb: ContentStatusCallback,
+    #[debug("IndexContentCallback")]
+    index_content_cb: IndexContentCallback,
 }
 
 impl SyncEngine {
@@ -62,6 +91,7 @@ impl SyncEngine {
         replica_store: S,
         bao_store: B,
         downloader: Downloader,
+        rt: LocalPoolHandle,
     ) -> Self {
         let (live_actor_tx, to_live_actor_recv) = mpsc::channel(ACTOR_CHANNEL_CAP);
         let (to_gossip_actor, to_gossip_actor_recv) = mpsc::channel(ACTOR_CHANNEL_CAP);
@@ -76,6 +106,20 @@ impl SyncEngine {
             Some(content_status_cb.clone()),
             me.clone(),
         );
+        let index_content_cb: IndexContentCallback = {
+            let sync = sync.clone();
+            let bao_store = bao_store.clone();
+            Arc::new(move |namespace, hash| {
+                let sync = sync.clone();
+                let bao_store = bao_store.clone();
+                // reading from the blob store is not `Send`, so run it on the local pool
+                rt.spawn_pinned(move || async move {
+                    index_content(&sync, &bao_store, namespace, hash).await
+                })
+                .map(|res| res.map_err(|_| anyhow!("join failed"))?)
+                .boxed()
+            })
+        };
 
         let mut actor = LiveActor::new(
             sync.clone(),
@@ -83,6 +127,7 @@ impl SyncEngine {
             gossip.clone(),
             bao_store,
             downloader.clone(),
+            index_content_cb.clone(),
             to_live_actor_recv,
             live_actor_tx.clone(),
             to_gossip_actor,
@@ -130,9 +175,18 @@ impl SyncEngine {
             to_live_actor: live_actor_tx,
             tasks_fut,
             content_status_cb,
+            index_content_cb,
         }
     }
 
+    /// Update the content based indexes of a document with the content for `hash`.
+    ///
+    /// Does nothing if the document has no content based indexes or the content is not complete
+    /// in the local blob store.
+    pub async fn index_content(&self, namespace: NamespaceId, hash: Hash) -> Result<()> {
+        (self.index_content_cb)(namespace, hash).await
+    }
+
     /// Start to sync a document.
     ///
     /// If `peers` is non-empty, it will both do an initial set-reconciliation sync with each peer,
@@ -186,45 +240,195 @@ impl SyncEngine {
         &self,
         namespace: NamespaceId,
     ) -> impl Stream<Item = Result<LiveEvent>> + Unpin + 'static {
-        let content_status_cb = self.content_status_cb.clone();
-
-        // Create a future that sends channel senders to the respective actors.
         // We clone `self` so that the future does not capture any lifetimes.
         let this = self.clone();
-        let fut = async move {
-            // Subscribe to insert events from the replica.
-            let replica_events = {
-                let (s, r) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
-                this.sync.subscribe(namespace, s).await?;
-                r.into_stream()
-                    .map(move |ev| LiveEvent::from_replica_event(ev, &content_status_cb))
+        let fut = async move { this.subscribe_inner(namespace).await };
+
+        // Flatten the future into a single stream. If the future errors, the error will be
+        // returned from the first call to [`Stream::next`].
+        // We first pin the future so that the resulting stream is `Unpin`.
+        Box::pin(fut).into_stream().try_flatten()
+    }
+
+    /// Subscribe to the events that match `filter`.
+    ///
+    /// If `replay` is true, the current entries that match the filter are sent first as
+    /// [`LiveEvent::Replay`] events, followed by [`LiveEvent::ReplayFinished`]. Entries that are
+    /// inserted while replaying may be sent both as replay and as insert event.
+    ///
+    /// Live events that arrive while replaying are buffered. If the buffer fills up before the
+    /// replay finished, the stream ends with an error after the buffered events.
+    pub fn subscribe_filtered(
+        &self,
+        namespace: NamespaceId,
+        filter: SubscribeFilter,
+        replay: bool,
+    ) -> impl Stream<Item = Result<LiveEvent>> + Unpin + 'static {
+        let this = self.clone();
+        Box::pin(Gen::new(|co| async move {
+            let mut filter = EventFilter::new(filter);
+            let live = match this.subscribe_inner(namespace).await {
+                Ok(live) => live,
+                Err(err) => {
+                    co.yield_(Err(err)).await;
+                    return;
+                }
             };
+            if !replay {
+                tokio::pin!(live);
+                while let Some(event) = live.next().await {
+                    match event {
+                        Ok(event) if !filter.accept(&event) => continue,
+                        event => co.yield_(event).await,
+                    }
+                }
+                return;
+            }
 
-            // Subscribe to events from the [`live::Actor`].
-            let sync_events = {
-                let (s, r) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
-                let (reply, reply_rx) = oneshot::channel();
-                this.to_live_actor
-                    .send(ToLiveActor::Subscribe {
-                        namespace,
-                        sender: s,
-                        reply,
-                    })
-                    .await?;
-                reply_rx.await??;
-                r.into_stream().map(|event| Ok(LiveEvent::from(event)))
+            // Live events must be received while replaying, because the replica actor blocks on
+            // full subscriber channels and is also needed for the replay query. So they are
+            // forwarded into a bounded buffer, without waiting for the buffer while replaying.
+            let (tx, rx) = flume::bounded(REPLAY_LIVE_BUFFER_CAP);
+            let replaying = Arc::new(AtomicBool::new(true));
+            let lagged = Arc::new(AtomicBool::new(false));
+            let forward_task = {
+                let replaying = replaying.clone();
+                let lagged = lagged.clone();
+                tokio::task::spawn(async move {
+                    tokio::pin!(live);
+                    while let Some(event) = live.next().await {
+                        if !replaying.load(Ordering::Relaxed) {
+                            if tx.send_async(event).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        match tx.try_send(event) {
+                            Ok(()) => {}
+                            Err(flume::TrySendError::Full(_)) => {
+                                lagged.store(true, Ordering::Relaxed);
+                                break;
+                            }
+                            Err(flume::TrySendError::Disconnected(_)) => break,
+                        }
+                    }
+                })
             };
+            let _forward_task = AbortingJoinHandle(forward_task);
+
+            let mut replay_events =
+                Box::pin(this.replay_entries(namespace, filter.filter().clone()));
+            while let Some(event) = replay_events.next().await {
+                match event {
+                    Ok(event) => {
+                        filter.accept(&event);
+                        co.yield_(Ok(event)).await;
+                    }
+                    Err(err) => {
+                        co.yield_(Err(err)).await;
+                        return;
+                    }
+                }
+            }
+            replaying.store(false, Ordering::Relaxed);
+            co.yield_(Ok(LiveEvent::ReplayFinished)).await;
+
+            while let Ok(event) = rx.recv_async().await {
+                match event {
+                    Ok(event) if !filter.accept(&event) => continue,
+                    event => co.yield_(event).await,
+                }
+            }
+            if lagged.load(Ordering::Relaxed) {
+                co.yield_(Err(anyhow!("subscription lagged behind while replaying")))
+                    .await;
+            }
+        }))
+    }
 
-            // Merge the two receivers into a single stream.
-            let stream = replica_events.merge(sync_events);
-            // We need type annotations for the error type here.
-            Result::<_, anyhow::Error>::Ok(stream)
+    /// Stream the current entries that match `filter` as [`LiveEvent::Replay`] events.
+    fn replay_entries(
+        &self,
+        namespace: NamespaceId,
+        filter: SubscribeFilter,
+    ) -> impl Stream<Item = Result<LiveEvent>> + Send + 'static {
+        let sync = self.sync.clone();
+        let content_status_cb = self.content_status_cb.clone();
+        Gen::new(|co| async move {
+            let keys = match filter.keys.is_empty() {
+                true => vec![KeyFilter::Any],
+                false => filter.keys.clone(),
+            };
+            // Entries are only yielded twice if several key filters overlap.
+            let mut seen = BTreeSet::new();
+            let dedup = keys.len() > 1;
+            for key in keys {
+                let query = match key {
+                    KeyFilter::Any => Query::all(),
+                    KeyFilter::Exact(key) => Query::all().key_exact(key),
+                    KeyFilter::Prefix(prefix) => Query::all().key_prefix(prefix),
+                    KeyFilter::Range { start, end } => Query::all().key_range((start, end)),
+                };
+                let (tx, rx) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
+                if let Err(err) = sync.get_many(namespace, query.build(), tx).await {
+                    co.yield_(Err(err)).await;
+                    return;
+                }
+                while let Ok(entry) = rx.recv_async().await {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(err) => {
+                            co.yield_(Err(err)).await;
+                            return;
+                        }
+                    };
+                    if !filter.matches_entry(entry.entry())
+                        || (dedup && !seen.insert(entry.id().clone()))
+                    {
+                        continue;
+                    }
+                    co.yield_(Ok(LiveEvent::Replay {
+                        content_status: content_status_cb(entry.content_hash()),
+                        entry: entry.into(),
+                    }))
+                    .await;
+                }
+            }
+        })
+    }
+
+    /// Subscribe to the replica and the live actor, and merge their events into one stream.
+    async fn subscribe_inner(
+        &self,
+        namespace: NamespaceId,
+    ) -> Result<impl Stream<Item = Result<LiveEvent>> + Send + Unpin + 'static> {
+        let content_status_cb = self.content_status_cb.clone();
+        // Subscribe to insert events from the replica.
+        let replica_events = {
+            let (s, r) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
+            self.sync.subscribe(namespace, s).await?;
+            r.into_stream()
+                .map(move |ev| LiveEvent::from_replica_event(ev, &content_status_cb))
         };
 
-        // Flatten the future into a single stream. If the future errors, the error will be
-        // returned from the first call to [`Stream::next`].
-        // We first pin the future so that the resulting stream is `Unpin`.
-        Box::pin(fut).into_stream().try_flatten()
+        // Subscribe to events from the [`live::Actor`].
+        let sync_events = {
+            let (s, r) = flume::bounded(SUBSCRIBE_CHANNEL_CAP);
+            let (reply, reply_rx) = oneshot::channel();
+            self.to_live_actor
+                .send(ToLiveActor::Subscribe {
+                    namespace,
+                    sender: s,
+                    reply,
+                })
+                .await?;
+            reply_rx.await??;
+            r.into_stream().map(|event| Ok(LiveEvent::from(event)))
+        };
+
+        // Merge the two receivers into a single stream.
+        let stream = replica_events.merge(sync_events);
+        Ok(stream)
     }
 
     /// Handle an incoming iroh-sync connection.
@@ -243,6 +447,27 @@ impl SyncEngine {
     }
 }
 
+async fn index_content<B: iroh_bytes::store::Store>(
+    sync: &SyncHandle,
+    bao_store: &B,
+    namespace: NamespaceId,
+    hash: Hash,
+) -> Result<()> {
+    let indexes = sync.list_indexes(namespace).await?;
+    if !indexes.iter().any(|spec| spec.extractor.uses_content()) {
+        return Ok(());
+    }
+    let Some(entry) = bao_store.get(&hash).await? else {
+        return Ok(());
+    };
+    if !entry.is_complete() || entry.size().value() > MAX_INDEXED_CONTENT_SIZE {
+        return Ok(());
+    }
+    let content = entry.data_reader().await?.read_to_end().await?;
+    sync.index_content(namespace, hash, content).await?;
+    Ok(())
+}
+
 pub(crate) fn entry_to_content_status(entry: io::Result<EntryStatus>) -> ContentStatus {
     match entry {
         Ok(EntryStatus::Complete) => ContentStatus::Complete,
@@ -283,6 +508,26 @@ pub enum LiveEvent {
     NeighborDown(PublicKey),
     /// A set-reconciliation sync finished.
     SyncFinished(SyncEvent),
+    /// A set-reconciliation sync made progress.
+    SyncProgress(SyncProgressEvent),
+    /// A remote insert created concurrent values for a key that one of our authors wrote a value
+    /// for.
+    Conflict {
+        /// The key with concurrent values.
+        key: Bytes,
+        /// The entries of all authors for the key, latest first.
+        heads: Vec<Entry>,
+    },
+    /// An existing entry that matches the subscription filter, sent before the live events if
+    /// replay was requested.
+    Replay {
+        /// The entry.
+        entry: Entry,
+        /// If the content is available at the local node
+        content_status: ContentStatus,
+    },
+    /// All existing entries were replayed, the following events are live.
+    ReplayFinished,
 }
 
 impl From<live::Event> for LiveEvent {
@@ -292,6 +537,8 @@ impl From<live::Event> for LiveEvent {
             live::Event::NeighborUp(peer) => Self::NeighborUp(peer),
             live::Event::NeighborDown(peer) => Self::NeighborDown(peer),
             live::Event::SyncFinished(ev) => Self::SyncFinished(ev),
+            live::Event::SyncProgress(ev) => Self::SyncProgress(ev),
+            live::Event::Conflict { key, heads } => Self::Conflict { key, heads },
         }
     }
 }
@@ -312,4 +559,179 @@ impl LiveEvent {
             },
         })
     }
+
+    /// The kind of this event, or `None` for replay events.
+    pub fn kind(&self) -> Option<LiveEventKind> {
+        Some(match self {
+            Self::InsertLocal { .. } => LiveEventKind::InsertLocal,
+            Self::InsertRemote { .. } => LiveEventKind::InsertRemote,
+            Self::ContentReady { .. } => LiveEventKind::ContentReady,
+            Self::NeighborUp(_) => LiveEventKind::NeighborUp,
+            Self::NeighborDown(_) => LiveEventKind::NeighborDown,
+            Self::SyncFinished(_) => LiveEventKind::SyncFinished,
+            Self::SyncProgress(_) => LiveEventKind::SyncProgress,
+            Self::Conflict { .. } => LiveEventKind::Conflict,
+            Self::Replay { .. } | Self::ReplayFinished => return None,
+        })
+    }
+}
+
+/// The kinds of [`LiveEvent`]s, to filter subscriptions by.
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    strum::Display,
+    strum::EnumString,
+)]
+#[strum(serialize_all = "kebab-case")]
+pub enum LiveEventKind {
+    /// [`LiveEvent::InsertLocal`]
+    InsertLocal,
+    /// [`LiveEvent::InsertRemote`]
+    InsertRemote,
+    /// [`LiveEvent::ContentReady`]
+    ContentReady,
+    /// [`LiveEvent::NeighborUp`]
+    NeighborUp,
+    /// [`LiveEvent::NeighborDown`]
+    NeighborDown,
+    /// [`LiveEvent::SyncFinished`]
+    SyncFinished,
+    /// [`LiveEvent::SyncProgress`]
+    SyncProgress,
+    /// [`LiveEvent::Conflict`]
+    Conflict,
+}
+
+/// Filter for the events of a document subscription.
+///
+/// The filter is evaluated by the sync engine, so events that do not match are not sent to the
+/// subscriber. The key and author filters apply to events about entries. Content ready events
+/// only pass them if the subscriber received an entry with the content hash before. Other events
+/// are only filtered by kind.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct SubscribeFilter {
+    /// Only entries with a key that matches one of these filters. If empty, all keys match.
+    pub keys: Vec<KeyFilter>,
+    /// Only entries of these authors. If `None`, all authors match.
+    pub authors: Option<BTreeSet<AuthorId>>,
+    /// Only events of these kinds. If `None`, all kinds match.
+    pub kinds: Option<BTreeSet<LiveEventKind>>,
+}
+
+impl SubscribeFilter {
+    /// A filter that matches all events.
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Also match entries with keys that start with `prefix`.
+    pub fn with_key_prefix(mut self, prefix: impl AsRef<[u8]>) -> Self {
+        let prefix = Bytes::copy_from_slice(prefix.as_ref());
+        self.keys.push(KeyFilter::Prefix(prefix));
+        self
+    }
+
+    /// Also match entries with exactly this key.
+    pub fn with_key_exact(mut self, key: impl AsRef<[u8]>) -> Self {
+        let key = Bytes::copy_from_slice(key.as_ref());
+        self.keys.push(KeyFilter::Exact(key));
+        self
+    }
+
+    /// Also match entries of `author`.
+    pub fn with_author(mut self, author: AuthorId) -> Self {
+        self.authors
+            .get_or_insert_with(Default::default)
+            .insert(author);
+        self
+    }
+
+    /// Also match events of `kind`.
+    pub fn with_kind(mut self, kind: LiveEventKind) -> Self {
+        self.kinds.get_or_insert_with(Default::default).insert(kind);
+        self
+    }
+
+    /// Whether an entry matches the key and author filters.
+    pub fn matches_entry(&self, entry: &Entry) -> bool {
+        self.matches_key(entry.key()) && self.matches_author(&entry.author())
+    }
+
+    fn matches_key(&self, key: &[u8]) -> bool {
+        self.keys.is_empty() || self.keys.iter().any(|filter| filter.matches(key))
+    }
+
+    fn matches_author(&self, author: &AuthorId) -> bool {
+        self.authors
+            .as_ref()
+            .map_or(true, |authors| authors.contains(author))
+    }
+
+    fn filters_entries(&self) -> bool {
+        !self.keys.is_empty() || self.authors.is_some()
+    }
+}
+
+/// Applies a [`SubscribeFilter`] to the events of a subscription.
+#[derive(Debug)]
+struct EventFilter {
+    filter: SubscribeFilter,
+    /// Content hashes of the matched entries, to let their content ready events pass.
+    content: HashSet<Hash>,
+}
+
+impl EventFilter {
+    fn new(filter: SubscribeFilter) -> Self {
+        Self {
+            filter,
+            content: Default::default(),
+        }
+    }
+
+    fn filter(&self) -> &SubscribeFilter {
+        &self.filter
+    }
+
+    /// Whether `event` should be sent to the subscriber.
+    fn accept(&mut self, event: &LiveEvent) -> bool {
+        if let (Some(kinds), Some(kind)) = (&self.filter.kinds, event.kind()) {
+            if !kinds.contains(&kind) {
+                return false;
+            }
+        }
+        match event {
+            LiveEvent::InsertLocal { entry }
+            | LiveEvent::InsertRemote { entry, .. }
+            | LiveEvent::Replay { entry, .. } => {
+                let matches = self.filter.matches_entry(entry);
+                if matches && self.filter.filters_entries() {
+                    self.content.insert(entry.content_hash());
+                }
+                matches
+            }
+            LiveEvent::ContentReady { hash } => {
+                !self.filter.filters_entries() || self.content.remove(hash)
+            }
+            LiveEvent::Conflict { key, heads } => {
+                self.filter.matches_key(key)
+                    && heads
+                        .iter()
+                        .any(|head| self.filter.matches_author(&head.author()))
+            }
+            LiveEvent::NeighborUp(_)
+            | LiveEvent::NeighborDown(_)
+            | LiveEvent::SyncFinished(_)
+            | LiveEvent::SyncProgress(_)
+            | LiveEvent::ReplayFinished => true,
+        }
+    }
 }
@@ -170,6 +170,12 @@ impl GossipActor {
                             true => ContentStatus::Complete,
                             false => ContentStatus::Missing,
                         };
+                        // Entries outside of our area of interest are not synced.
+                        let area = self.sync.get_sync_area(namespace).await?;
+                        if !area.includes(entry.entry()) {
+                            debug!("ignore entry outside of area of interest");
+                            return Ok(());
+                        }
                         let from = *msg.delivered_from.as_bytes();
                         self.sync
                             .insert_remote(namespace, entry, from, content_status)
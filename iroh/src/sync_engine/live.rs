@@ -3,7 +3,8 @@
 use std::{collections::HashMap, time::SystemTime};
 
 use anyhow::{Context, Result};
-use futures::FutureExt;
+use bytes::Bytes;
+use futures::{FutureExt, TryStreamExt};
 use iroh_bytes::downloader::{DownloadKind, Downloader, Role};
 use iroh_bytes::{store::EntryStatus, Hash};
 use iroh_gossip::{net::Gossip, proto::TopicId};
@@ -12,9 +13,10 @@ use iroh_sync::{
     actor::{OpenOpts, SyncHandle},
     net::{
         connect_and_sync, handle_connection, AbortReason, AcceptError, AcceptOutcome, ConnectError,
-        SyncFinished,
+        SyncFinished, SyncProgress,
     },
-    AuthorHeads, ContentStatus, NamespaceId, SignedEntry,
+    store::Query,
+    AuthorHeads, AuthorId, ContentStatus, Entry, NamespaceId, SignedEntry,
 };
 use serde::{Deserialize, Serialize};
 use tokio::{
@@ -25,6 +27,10 @@ use tracing::{debug, error, info, instrument, trace, warn, Instrument, Span};
 
 use super::gossip::ToGossipActor;
 use super::state::{NamespaceStates, Origin, SyncReason};
+use super::IndexContentCallback;
+
+/// Capacity of the channels to collect entries and authors when checking for conflicts.
+const CONFLICT_CHANNEL_CAP: usize = 64;
 
 /// An iroh-sync operation
 ///
@@ -98,6 +104,10 @@ pub enum ToLiveActor {
         namespace: NamespaceId,
         peer: PublicKey,
     },
+    SyncProgress {
+        origin: Origin,
+        progress: SyncProgress,
+    },
 }
 
 /// Events informing about actions of the live sync progress.
@@ -114,6 +124,16 @@ pub enum Event {
     NeighborDown(PublicKey),
     /// A set-reconciliation sync finished.
     SyncFinished(SyncEvent),
+    /// A set-reconciliation sync made progress.
+    SyncProgress(SyncProgressEvent),
+    /// A remote insert created a concurrent value for a key that one of our authors wrote a
+    /// value for.
+    Conflict {
+        /// The key with concurrent values.
+        key: Bytes,
+        /// The entries of all authors for the key, latest first.
+        heads: Vec<Entry>,
+    },
 }
 
 type SyncConnectRes = (
@@ -123,6 +143,7 @@ type SyncConnectRes = (
     Result<SyncFinished, ConnectError>,
 );
 type SyncAcceptRes = Result<SyncFinished, AcceptError>;
+type ConflictCheckRes = (NamespaceId, Result<Option<Event>>);
 
 // Currently peers might double-sync in both directions.
 pub struct LiveActor<B: iroh_bytes::store::Store> {
@@ -133,6 +154,7 @@ pub struct LiveActor<B: iroh_bytes::store::Store> {
     gossip: Gossip,
     bao_store: B,
     downloader: Downloader,
+    index_content_cb: IndexContentCallback,
     replica_events_tx: flume::Sender<iroh_sync::Event>,
     replica_events_rx: flume::Receiver<iroh_sync::Event>,
 
@@ -148,6 +170,8 @@ pub struct LiveActor<B: iroh_bytes::store::Store> {
     running_sync_accept: JoinSet<SyncAcceptRes>,
     /// Running download futures.
     pending_downloads: JoinSet<Option<(NamespaceId, Hash)>>,
+    /// Running conflict checks for remote inserts.
+    running_conflict_checks: JoinSet<ConflictCheckRes>,
 
     /// Subscribers to actor events
     subscribers: SubscribersMap,
@@ -164,6 +188,7 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
         gossip: Gossip,
         bao_store: B,
         downloader: Downloader,
+        index_content_cb: IndexContentCallback,
         inbox: mpsc::Receiver<ToLiveActor>,
         sync_actor_tx: mpsc::Sender<ToLiveActor>,
         gossip_actor_tx: mpsc::Sender<ToGossipActor>,
@@ -178,12 +203,14 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
             gossip,
             bao_store,
             downloader,
+            index_content_cb,
             sync_actor_tx,
             gossip_actor_tx,
             running_sync_connect: Default::default(),
             running_sync_accept: Default::default(),
             subscribers: Default::default(),
             pending_downloads: Default::default(),
+            running_conflict_checks: Default::default(),
             state: Default::default(),
         }
     }
@@ -233,12 +260,24 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
                     trace!(?i, "tick: pending_downloads");
                     let res = res.context("pending_downloads closed")?;
                     if let Some((namespace, hash)) = res {
+                        self.index_content(namespace, hash);
                         self.subscribers.send(&namespace, Event::ContentReady { hash }).await;
                         // Inform our neighbors that we have new content ready.
                         self.broadcast_neighbors(namespace, &Op::ContentReady(hash)).await;
                     }
 
                 }
+                Some(res) = self.running_conflict_checks.join_next(), if !self.running_conflict_checks.is_empty() => {
+                    trace!(?i, "tick: running_conflict_checks");
+                    let (namespace, res) = res.context("running_conflict_checks closed")?;
+                    match res {
+                        Ok(Some(event)) => {
+                            self.subscribers.send(&namespace, event).await;
+                        }
+                        Ok(None) => {}
+                        Err(err) => warn!(?err, "failed to check for conflicts"),
+                    }
+                }
             }
         }
         debug!("close (shutdown)");
@@ -309,6 +348,9 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
                 let outcome = self.accept_sync_request(namespace, peer);
                 reply.send(outcome).ok();
             }
+            ToLiveActor::SyncProgress { origin, progress } => {
+                self.on_sync_progress(origin, progress).await;
+            }
         };
         Ok(true)
     }
@@ -318,10 +360,14 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
         if !self.state.start_connect(&namespace, peer, reason) {
             return;
         }
+        let resume = self.state.pending(&namespace, peer);
         let endpoint = self.endpoint.clone();
         let sync = self.sync.clone();
+        let progress_cb = progress_callback(self.sync_actor_tx.clone(), Origin::Connect(reason));
         let fut = async move {
-            let res = connect_and_sync(&endpoint, &sync, namespace, NodeAddr::new(peer)).await;
+            let peer_addr = NodeAddr::new(peer);
+            let res =
+                connect_and_sync(&endpoint, &sync, namespace, peer_addr, resume, progress_cb).await;
             (namespace, peer, reason, res)
         }
         .instrument(Span::current());
@@ -497,6 +543,7 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
                 info!(
                     sent = %details.outcome.num_sent,
                     recv = %details.outcome.num_recv,
+                    rejected = %details.outcome.num_rejected,
                     t_connect = ?details.timings.connect,
                     t_process = ?details.timings.process,
                     "sync finished",
@@ -555,6 +602,31 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
         }
     }
 
+    async fn on_sync_progress(&mut self, origin: Origin, progress: SyncProgress) {
+        let SyncProgress {
+            namespace,
+            peer,
+            num_sent,
+            num_recv,
+            num_ranges_reconciled,
+            pending,
+        } = progress;
+        if !self.state.progress(&namespace, peer, &origin, &pending) {
+            return;
+        }
+        let ev = SyncProgressEvent {
+            peer,
+            origin,
+            num_sent,
+            num_recv,
+            num_ranges_reconciled,
+            num_ranges_pending: pending.len(),
+        };
+        self.subscribers
+            .send(&namespace, Event::SyncProgress(ev))
+            .await;
+    }
+
     async fn broadcast_neighbors(&self, namespace: NamespaceId, op: &Op) {
         if !self.state.is_syncing(&namespace) {
             return;
@@ -627,10 +699,22 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
                 should_download,
                 remote_content_status,
             } => {
+                // Check whether the entry conflicts with a value of one of our authors. The check
+                // queries the sync actor, so it must not block the actor loop.
+                let sync = self.sync.clone();
+                let conflict_entry = entry.clone();
+                self.running_conflict_checks.spawn(async move {
+                    let res = find_conflict(&sync, namespace, &conflict_entry).await;
+                    (namespace, res)
+                });
+
                 // A new entry was inserted from initial sync or gossip. Queue downloading the
                 // content.
                 let hash = entry.content_hash();
                 let entry_status = self.bao_store.entry_status(&hash).await?;
+                if entry_status == EntryStatus::Complete {
+                    self.index_content(namespace, hash);
+                }
                 // TODO: Make downloads configurable.
                 if matches!(entry_status, EntryStatus::NotFound | EntryStatus::Partial)
                     && should_download
@@ -657,6 +741,16 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
         Ok(())
     }
 
+    /// Update the content based indexes of a document in a background task.
+    fn index_content(&self, namespace: NamespaceId, hash: Hash) {
+        let fut = (self.index_content_cb)(namespace, hash);
+        tokio::task::spawn(async move {
+            if let Err(err) = fut.await {
+                warn!(?err, "failed to index content");
+            }
+        });
+    }
+
     #[instrument("accept", skip_all)]
     pub async fn handle_connection(&mut self, conn: quinn::Connecting) {
         let to_actor_tx = self.sync_actor_tx.clone();
@@ -684,10 +778,12 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
             }
             .boxed()
         };
+        let progress_cb = progress_callback(self.sync_actor_tx.clone(), Origin::Accept);
         debug!("incoming connection");
         let sync = self.sync.clone();
-        self.running_sync_accept
-            .spawn(async move { handle_connection(sync, conn, accept_request_cb).await });
+        self.running_sync_accept.spawn(async move {
+            handle_connection(sync, conn, accept_request_cb, progress_cb).await
+        });
     }
 
     pub fn accept_sync_request(
@@ -700,6 +796,86 @@ impl<B: iroh_bytes::store::Store> LiveActor<B> {
     }
 }
 
+/// Check whether a remote entry was written concurrently to the value of one of our authors for
+/// the same key.
+///
+/// Local entries are stamped by a hybrid logical clock, which is advanced past all entries a
+/// replica has seen. Had the remote author seen our value, its entry would thus be newer than our
+/// value. And we had not seen the remote entry before either, because it was just inserted. So the
+/// values are concurrent if our value is newer than the remote entry and has different content.
+///
+/// Returns an [`Event::Conflict`] with the heads for the key if so.
+async fn find_conflict(
+    sync: &SyncHandle,
+    namespace: NamespaceId,
+    entry: &SignedEntry,
+) -> Result<Option<Event>> {
+    if entry.is_empty() {
+        return Ok(None);
+    }
+    let (tx, rx) = flume::bounded(CONFLICT_CHANNEL_CAP);
+    sync.get_many(namespace, Query::key_exact(entry.key()).build(), tx)
+        .await?;
+    let mut heads: Vec<SignedEntry> = rx.into_stream().try_collect().await?;
+    let concurrent: Vec<AuthorId> = heads
+        .iter()
+        .filter(|head| {
+            head.author() != entry.author()
+                && head.timestamp() > entry.timestamp()
+                && head.content_hash() != entry.content_hash()
+        })
+        .map(|head| head.author())
+        .collect();
+    if concurrent.is_empty() {
+        return Ok(None);
+    }
+    let (tx, rx) = flume::bounded(CONFLICT_CHANNEL_CAP);
+    sync.list_authors(tx).await?;
+    let authors: Vec<AuthorId> = rx.into_stream().try_collect().await?;
+    if !concurrent.iter().any(|author| authors.contains(author)) {
+        return Ok(None);
+    }
+    heads.sort_by_key(|head| std::cmp::Reverse(head.timestamp()));
+    Ok(Some(Event::Conflict {
+        key: Bytes::copy_from_slice(entry.key()),
+        heads: heads.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Create a callback that forwards the progress of a sync operation to the actor.
+fn progress_callback(
+    to_actor_tx: mpsc::Sender<ToLiveActor>,
+    origin: Origin,
+) -> impl Fn(SyncProgress) {
+    move |progress| {
+        let msg = ToLiveActor::SyncProgress {
+            origin: origin.clone(),
+            progress,
+        };
+        // Progress is informational, so drop it instead of blocking the sync if the actor is busy.
+        if let Err(err) = to_actor_tx.try_send(msg) {
+            trace!(?err, "failed to forward sync progress");
+        }
+    }
+}
+
+/// Event emitted when a sync operation makes progress
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct SyncProgressEvent {
+    /// Peer we sync with
+    pub peer: PublicKey,
+    /// Origin of the sync exchange
+    pub origin: Origin,
+    /// Number of entries sent so far
+    pub num_sent: usize,
+    /// Number of entries received so far
+    pub num_recv: usize,
+    /// Number of ranges reconciled so far
+    pub num_ranges_reconciled: usize,
+    /// Number of ranges that still need to be reconciled, as an estimate of the remaining work
+    pub num_ranges_pending: usize,
+}
+
 /// Event emitted when a sync operation completes
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct SyncEvent {
@@ -1,23 +1,48 @@
 //! This module contains an impl block on [`SyncEngine`] with handlers for RPC requests
 
+use std::{collections::BTreeSet, ops::Bound};
+
 use anyhow::anyhow;
 use futures::Stream;
-use iroh_bytes::{store::Store as BaoStore, BlobFormat};
-use iroh_sync::{Author, NamespaceSecret};
+use iroh_bytes::{
+    format::collection::Collection,
+    store::{EntryStatus, Store as BaoStore},
+    BlobFormat, Hash,
+};
+use iroh_sync::{
+    actor::OpenOpts,
+    bundle::SyncBundle,
+    snapshot::{Snapshot, SNAPSHOT_BLOB_NAME},
+    store::Query,
+    Author, Capability, NamespaceId, NamespaceSecret, SignedEntry,
+};
 use tokio_stream::StreamExt;
 
 use crate::{
     rpc_protocol::{
-        AuthorCreateRequest, AuthorCreateResponse, AuthorListRequest, AuthorListResponse,
-        DocCloseRequest, DocCloseResponse, DocCreateRequest, DocCreateResponse, DocDelRequest,
-        DocDelResponse, DocDropRequest, DocDropResponse, DocGetDownloadPolicyRequest,
-        DocGetDownloadPolicyResponse, DocGetExactRequest, DocGetExactResponse, DocGetManyRequest,
-        DocGetManyResponse, DocImportRequest, DocImportResponse, DocLeaveRequest, DocLeaveResponse,
-        DocListRequest, DocListResponse, DocOpenRequest, DocOpenResponse,
-        DocSetDownloadPolicyRequest, DocSetDownloadPolicyResponse, DocSetHashRequest,
-        DocSetHashResponse, DocSetRequest, DocSetResponse, DocShareRequest, DocShareResponse,
-        DocStartSyncRequest, DocStartSyncResponse, DocStatusRequest, DocStatusResponse,
-        DocSubscribeRequest, DocSubscribeResponse, DocTicket, RpcResult, ShareMode,
+        AuthorCreateRequest, AuthorCreateResponse, AuthorDeleteRequest, AuthorDeleteResponse,
+        AuthorExportRequest, AuthorExportResponse, AuthorImportRequest, AuthorImportResponse,
+        AuthorListRequest, AuthorListResponse, DocCloseRequest, DocCloseResponse, DocCountRequest,
+        DocCountResponse, DocCreateIndexRequest, DocCreateIndexResponse, DocCreateRequest,
+        DocCreateResponse, DocDelRequest, DocDelResponse, DocDropRequest, DocDropResponse,
+        DocExportBundleRequest, DocExportBundleResponse, DocGetAuthorHeadsRequest,
+        DocGetAuthorHeadsResponse, DocGetByIndexRequest, DocGetByIndexResponse,
+        DocGetDownloadPolicyRequest, DocGetDownloadPolicyResponse, DocGetExactRequest,
+        DocGetExactResponse, DocGetHistoryPolicyRequest, DocGetHistoryPolicyResponse,
+        DocGetLabelRequest, DocGetLabelResponse, DocGetManyRequest, DocGetManyResponse,
+        DocGetQuotaRequest, DocGetQuotaResponse, DocGetSyncAreaRequest, DocGetSyncAreaResponse,
+        DocImportBundleRequest, DocImportBundleResponse, DocImportRequest, DocImportResponse,
+        DocImportSnapshotResponse, DocLeaveRequest, DocLeaveResponse, DocListIndexesRequest,
+        DocListIndexesResponse, DocListRequest, DocListResponse, DocOpenRequest, DocOpenResponse,
+        DocRemoveIndexRequest, DocRemoveIndexResponse, DocSetDownloadPolicyRequest,
+        DocSetDownloadPolicyResponse, DocSetHashRequest, DocSetHashResponse,
+        DocSetHistoryPolicyRequest, DocSetHistoryPolicyResponse, DocSetLabelRequest,
+        DocSetLabelResponse, DocSetMetadataRequest, DocSetMetadataResponse, DocSetQuotaRequest,
+        DocSetQuotaResponse, DocSetRequest, DocSetResponse, DocSetSyncAreaRequest,
+        DocSetSyncAreaResponse, DocShareRequest, DocShareResponse, DocSnapshotRequest,
+        DocSnapshotResponse, DocStartSyncRequest, DocStartSyncResponse, DocStatusRequest,
+        DocStatusResponse, DocSubscribeRequest, DocSubscribeResponse, DocTicket, RpcResult,
+        SetTagOption, ShareMode,
     },
     sync_engine::SyncEngine,
 };
@@ -39,6 +64,24 @@ impl SyncEngine {
         })
     }
 
+    pub async fn author_import(&self, req: AuthorImportRequest) -> RpcResult<AuthorImportResponse> {
+        let author = Author::from_bytes(&req.key);
+        let author_id = self.sync.import_author(author).await?;
+        Ok(AuthorImportResponse { author_id })
+    }
+
+    pub async fn author_export(&self, req: AuthorExportRequest) -> RpcResult<AuthorExportResponse> {
+        let author = self.sync.export_author(req.author).await?;
+        Ok(AuthorExportResponse { author })
+    }
+
+    pub async fn author_delete(&self, req: AuthorDeleteRequest) -> RpcResult<AuthorDeleteResponse> {
+        if !self.sync.delete_author(req.author).await? {
+            return Err(anyhow!("author not found").into());
+        }
+        Ok(AuthorDeleteResponse {})
+    }
+
     pub fn author_list(
         &self,
         _req: AuthorListRequest,
@@ -77,17 +120,29 @@ impl SyncEngine {
     pub fn doc_list(&self, _req: DocListRequest) -> impl Stream<Item = RpcResult<DocListResponse>> {
         let (tx, rx) = flume::bounded(ITER_CHANNEL_CAP);
         let sync = self.sync.clone();
+        let sync2 = self.sync.clone();
         // we need to spawn a task to send our request to the sync handle, because the method
         // itself must be sync.
+        // the name from the document metadata is added by the node, because reading blobs is
+        // not `Send` for all stores.
         tokio::task::spawn(async move {
             let tx2 = tx.clone();
             if let Err(err) = sync.list_replicas(tx).await {
                 tx2.send_async(Err(err)).await.ok();
             }
         });
-        rx.into_stream().map(|r| {
-            r.map(|(id, capability)| DocListResponse { id, capability })
-                .map_err(Into::into)
+        rx.into_stream().then(move |r| {
+            let sync = sync2.clone();
+            async move {
+                let (id, capability) = r?;
+                let label = sync.get_label(id).await?;
+                Ok(DocListResponse {
+                    id,
+                    capability,
+                    name: None,
+                    label,
+                })
+            }
         })
     }
 
@@ -116,17 +171,19 @@ impl SyncEngine {
             }
         };
         self.start_sync(req.doc_id, vec![]).await?;
-        Ok(DocShareResponse(DocTicket {
-            capability,
-            nodes: vec![me],
-        }))
+        Ok(DocShareResponse(DocTicket::new(capability, vec![me])))
     }
 
     pub fn doc_subscribe(
         &self,
         req: DocSubscribeRequest,
     ) -> impl Stream<Item = RpcResult<DocSubscribeResponse>> {
-        let stream = self.subscribe(req.doc_id);
+        let DocSubscribeRequest {
+            doc_id,
+            filter,
+            replay,
+        } = req;
+        let stream = self.subscribe_filtered(doc_id, filter, replay);
         stream.map(|res| {
             res.map(|event| DocSubscribeResponse { event })
                 .map_err(Into::into)
@@ -137,6 +194,7 @@ impl SyncEngine {
         let DocImportRequest(DocTicket {
             capability,
             nodes: peers,
+            ..
         }) = req;
         let doc_id = self.sync.import_namespace(capability).await?;
         self.sync.open(doc_id, Default::default()).await?;
@@ -175,6 +233,7 @@ impl SyncEngine {
         self.sync
             .insert_local(doc_id, author_id, key.clone(), *tag.hash(), len as u64)
             .await?;
+        self.index_content(doc_id, *tag.hash()).await?;
         let entry = self
             .sync
             .get_exact(doc_id, author_id, key, false)
@@ -204,6 +263,7 @@ impl SyncEngine {
         self.sync
             .insert_local(doc_id, author_id, key.clone(), hash, size)
             .await?;
+        self.index_content(doc_id, hash).await?;
         Ok(DocSetHashResponse {})
     }
 
@@ -242,6 +302,12 @@ impl SyncEngine {
         Ok(DocGetExactResponse { entry })
     }
 
+    pub async fn doc_count(&self, req: DocCountRequest) -> RpcResult<DocCountResponse> {
+        let DocCountRequest { doc_id, query } = req;
+        let count = self.sync.count(doc_id, query).await?;
+        Ok(DocCountResponse { count })
+    }
+
     pub async fn doc_set_download_policy(
         &self,
         req: DocSetDownloadPolicyRequest,
@@ -258,4 +324,301 @@ impl SyncEngine {
         let policy = self.sync.get_download_policy(req.doc_id).await?;
         Ok(DocGetDownloadPolicyResponse { policy })
     }
+    pub async fn doc_set_sync_area(
+        &self,
+        req: DocSetSyncAreaRequest,
+    ) -> RpcResult<DocSetSyncAreaResponse> {
+        self.sync.set_sync_area(req.doc_id, req.area).await?;
+        Ok(DocSetSyncAreaResponse {})
+    }
+    pub async fn doc_get_sync_area(
+        &self,
+        req: DocGetSyncAreaRequest,
+    ) -> RpcResult<DocGetSyncAreaResponse> {
+        let area = self.sync.get_sync_area(req.doc_id).await?;
+        Ok(DocGetSyncAreaResponse { area })
+    }
+    pub async fn doc_set_history_policy(
+        &self,
+        req: DocSetHistoryPolicyRequest,
+    ) -> RpcResult<DocSetHistoryPolicyResponse> {
+        self.sync.set_history_policy(req.doc_id, req.policy).await?;
+        Ok(DocSetHistoryPolicyResponse {})
+    }
+    pub async fn doc_get_history_policy(
+        &self,
+        req: DocGetHistoryPolicyRequest,
+    ) -> RpcResult<DocGetHistoryPolicyResponse> {
+        let policy = self.sync.get_history_policy(req.doc_id).await?;
+        Ok(DocGetHistoryPolicyResponse { policy })
+    }
+    pub async fn doc_set_quota(&self, req: DocSetQuotaRequest) -> RpcResult<DocSetQuotaResponse> {
+        self.sync.set_quota(req.doc_id, req.quota).await?;
+        Ok(DocSetQuotaResponse {})
+    }
+    pub async fn doc_get_quota(&self, req: DocGetQuotaRequest) -> RpcResult<DocGetQuotaResponse> {
+        let quota = self.sync.get_quota(req.doc_id).await?;
+        Ok(DocGetQuotaResponse { quota })
+    }
+
+    pub async fn doc_create_index(
+        &self,
+        req: DocCreateIndexRequest,
+    ) -> RpcResult<DocCreateIndexResponse> {
+        let DocCreateIndexRequest { doc_id, spec } = req;
+        let uses_content = spec.extractor.uses_content();
+        self.sync.create_index(doc_id, spec).await?;
+        if uses_content {
+            // index the content of existing entries that is available locally
+            let (tx, rx) = flume::bounded(ITER_CHANNEL_CAP);
+            self.sync.get_many(doc_id, Query::all().build(), tx).await?;
+            let mut hashes = BTreeSet::new();
+            while let Ok(entry) = rx.recv_async().await {
+                hashes.insert(entry?.content_hash());
+            }
+            for hash in hashes {
+                self.index_content(doc_id, hash).await?;
+            }
+        }
+        Ok(DocCreateIndexResponse {})
+    }
+
+    pub async fn doc_remove_index(
+        &self,
+        req: DocRemoveIndexRequest,
+    ) -> RpcResult<DocRemoveIndexResponse> {
+        let removed = self.sync.remove_index(req.doc_id, req.name).await?;
+        Ok(DocRemoveIndexResponse { removed })
+    }
+
+    pub async fn doc_list_indexes(
+        &self,
+        req: DocListIndexesRequest,
+    ) -> RpcResult<DocListIndexesResponse> {
+        let indexes = self.sync.list_indexes(req.doc_id).await?;
+        Ok(DocListIndexesResponse { indexes })
+    }
+
+    pub fn doc_get_by_index(
+        &self,
+        req: DocGetByIndexRequest,
+    ) -> impl Stream<Item = RpcResult<DocGetByIndexResponse>> {
+        let DocGetByIndexRequest {
+            doc_id,
+            name,
+            filter,
+        } = req;
+        let (tx, rx) = flume::bounded(ITER_CHANNEL_CAP);
+        let sync = self.sync.clone();
+        // we need to spawn a task to send our request to the sync handle, because the method
+        // itself must be sync.
+        tokio::task::spawn(async move {
+            let tx2 = tx.clone();
+            if let Err(err) = sync.get_by_index(doc_id, name, filter, tx).await {
+                tx2.send_async(Err(err)).await.ok();
+            }
+        });
+        rx.into_stream().map(|r| {
+            r.map(|entry| DocGetByIndexResponse { entry })
+                .map_err(Into::into)
+        })
+    }
+
+    pub async fn doc_snapshot<B: BaoStore>(
+        &self,
+        bao_store: &B,
+        req: DocSnapshotRequest,
+    ) -> RpcResult<DocSnapshotResponse> {
+        let DocSnapshotRequest {
+            doc_id,
+            include_content,
+            tag,
+        } = req;
+        let (tx, rx) = flume::bounded(ITER_CHANNEL_CAP);
+        let query = Query::all().include_empty().build();
+        self.sync.get_many(doc_id, query, tx).await?;
+        let mut entries = vec![];
+        while let Ok(entry) = rx.recv_async().await {
+            entries.push(entry?);
+        }
+        let snapshot = Snapshot::new(doc_id, entries);
+        let snapshot_tag = bao_store
+            .import_bytes(snapshot.to_bytes()?.into(), BlobFormat::Raw)
+            .await?;
+
+        let temp_tag = if include_content {
+            // only add content that is complete in our store, so that the collection can be
+            // served in full.
+            let mut content = BTreeSet::new();
+            for hash in snapshot.entries().iter().map(|e| e.content_hash()) {
+                if hash != Hash::EMPTY
+                    && matches!(bao_store.entry_status(&hash).await?, EntryStatus::Complete)
+                {
+                    content.insert(hash);
+                }
+            }
+            let collection: Collection =
+                std::iter::once((SNAPSHOT_BLOB_NAME.to_string(), *snapshot_tag.hash()))
+                    .chain(content.into_iter().map(|hash| (hash.to_string(), hash)))
+                    .collect();
+            collection.store(bao_store).await?
+        } else {
+            snapshot_tag
+        };
+        let hash_and_format = *temp_tag.inner();
+        let tag = match tag {
+            SetTagOption::Named(tag) => {
+                bao_store
+                    .set_tag(tag.clone(), Some(hash_and_format))
+                    .await?;
+                tag
+            }
+            SetTagOption::Auto => bao_store.create_tag(hash_and_format).await?,
+        };
+        Ok(DocSnapshotResponse {
+            hash: hash_and_format.hash,
+            format: hash_and_format.format,
+            tag,
+        })
+    }
+
+    pub async fn doc_import_snapshot(
+        &self,
+        snapshot: Snapshot,
+    ) -> RpcResult<DocImportSnapshotResponse> {
+        // verify all entries before inserting any of them
+        snapshot.verify().map_err(anyhow::Error::from)?;
+
+        let capability = Capability::Read(snapshot.namespace());
+        let doc_id = self.sync.import_namespace(capability).await?;
+        let (inserted, skipped) = self.import_entries(doc_id, snapshot.into_entries()).await?;
+        Ok(DocImportSnapshotResponse {
+            doc_id,
+            inserted,
+            skipped,
+        })
+    }
+
+    pub async fn doc_set_metadata<B: BaoStore>(
+        &self,
+        bao_store: &B,
+        req: DocSetMetadataRequest,
+    ) -> RpcResult<DocSetMetadataResponse> {
+        let DocSetMetadataRequest { doc_id, metadata } = req;
+        let bytes = metadata.to_bytes()?;
+        let len = bytes.len();
+        let tag = bao_store
+            .import_bytes(bytes.into(), BlobFormat::Raw)
+            .await?;
+        self.sync
+            .insert_metadata(doc_id, *tag.hash(), len as u64)
+            .await?;
+        Ok(DocSetMetadataResponse {})
+    }
+
+    pub async fn doc_set_label(&self, req: DocSetLabelRequest) -> RpcResult<DocSetLabelResponse> {
+        self.sync.set_label(req.doc_id, req.label).await?;
+        Ok(DocSetLabelResponse {})
+    }
+
+    pub async fn doc_get_label(&self, req: DocGetLabelRequest) -> RpcResult<DocGetLabelResponse> {
+        let label = self.sync.get_label(req.doc_id).await?;
+        Ok(DocGetLabelResponse { label })
+    }
+
+    pub async fn doc_get_author_heads(
+        &self,
+        req: DocGetAuthorHeadsRequest,
+    ) -> RpcResult<DocGetAuthorHeadsResponse> {
+        let heads = self.sync.get_author_heads(req.doc_id).await?;
+        Ok(DocGetAuthorHeadsResponse { heads })
+    }
+
+    /// Create a bundle with the entries the peer is missing.
+    ///
+    /// The content is not added here, because reading blobs is not `Send` for all stores.
+    pub async fn doc_export_bundle(
+        &self,
+        req: DocExportBundleRequest,
+    ) -> RpcResult<DocExportBundleResponse> {
+        let DocExportBundleRequest { doc_id, heads, .. } = req;
+        // only query the entries of each author which are newer than the peer's head.
+        let mut entries = vec![];
+        for (author, _) in self.sync.get_author_heads(doc_id).await?.iter() {
+            let mut query = Query::author(*author).include_empty();
+            if let Some(timestamp) = heads.get(author) {
+                query = query.timestamp_range((Bound::Excluded(timestamp), Bound::Unbounded));
+            }
+            let (tx, rx) = flume::bounded(ITER_CHANNEL_CAP);
+            self.sync.get_many(doc_id, query.build(), tx).await?;
+            while let Ok(entry) = rx.recv_async().await {
+                entries.push(entry?);
+            }
+        }
+        let bundle = SyncBundle::new(doc_id, entries, &heads);
+        Ok(DocExportBundleResponse { bundle })
+    }
+
+    pub async fn doc_import_bundle<B: BaoStore>(
+        &self,
+        bao_store: &B,
+        req: DocImportBundleRequest,
+    ) -> RpcResult<DocImportBundleResponse> {
+        let DocImportBundleRequest { bundle } = req;
+        // verify all entries and content before importing any of them
+        bundle.verify().map_err(anyhow::Error::from)?;
+
+        let doc_id = bundle.namespace();
+        let capability = Capability::Read(doc_id);
+        self.sync.import_namespace(capability).await?;
+
+        let (entries, content) = bundle.into_parts();
+        // keep the temp tags until the entries that protect the content are inserted.
+        let mut temp_tags = Vec::with_capacity(content.len());
+        for (_hash, content) in content {
+            temp_tags.push(bao_store.import_bytes(content, BlobFormat::Raw).await?);
+        }
+        let (inserted, skipped) = self.import_entries(doc_id, entries).await?;
+        Ok(DocImportBundleResponse {
+            doc_id,
+            inserted,
+            skipped,
+            content: temp_tags.len() as u64,
+        })
+    }
+
+    /// Insert verified entries from a snapshot or bundle into a document.
+    ///
+    /// The entries are inserted with [`iroh_sync::Replica::insert_remote_entry`] without
+    /// a peer, so that they are announced to our peers like local inserts. Entries for which a
+    /// newer entry exists are skipped, any other failure fails the import.
+    ///
+    /// The document is closed again afterwards. Returns the number of inserted and skipped
+    /// entries.
+    async fn import_entries(
+        &self,
+        doc_id: NamespaceId,
+        entries: impl IntoIterator<Item = SignedEntry>,
+    ) -> anyhow::Result<(u64, u64)> {
+        self.sync.open(doc_id, OpenOpts::default()).await?;
+        let res = self.insert_imported_entries(doc_id, entries).await;
+        self.sync.close(doc_id).await?;
+        res
+    }
+
+    async fn insert_imported_entries(
+        &self,
+        doc_id: NamespaceId,
+        entries: impl IntoIterator<Item = SignedEntry>,
+    ) -> anyhow::Result<(u64, u64)> {
+        let (mut inserted, mut skipped) = (0, 0);
+        for entry in entries {
+            if self.sync.insert_imported(doc_id, entry).await? {
+                inserted += 1;
+            } else {
+                skipped += 1;
+            }
+        }
+        Ok((inserted, skipped))
+    }
 }
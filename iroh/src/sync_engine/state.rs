@@ -2,7 +2,7 @@ use anyhow::Result;
 use iroh_net::NodeId;
 use iroh_sync::{
     net::{AbortReason, AcceptOutcome, SyncFinished},
-    NamespaceId,
+    NamespaceId, PendingRanges,
 };
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
@@ -119,6 +119,29 @@ impl NamespaceStates {
         state.finish(origin, result)
     }
 
+    /// Record the progress of a running sync operation.
+    ///
+    /// Returns false if no sync with this `origin` is running, in which case the progress is
+    /// outdated and should be ignored.
+    pub fn progress(
+        &mut self,
+        namespace: &NamespaceId,
+        node: NodeId,
+        origin: &Origin,
+        pending: &PendingRanges,
+    ) -> bool {
+        let Some(state) = self.entry(namespace, node) else {
+            return false;
+        };
+        state.progress(origin, pending)
+    }
+
+    /// Get the ranges that were left pending by an interrupted sync request to a node.
+    pub fn pending(&mut self, namespace: &NamespaceId, node: NodeId) -> Option<PendingRanges> {
+        let state = self.entry(namespace, node)?;
+        state.pending.clone()
+    }
+
     /// Remove a namespace from the set of syncing namespaces.
     pub fn remove(&mut self, namespace: &NamespaceId) -> bool {
         self.0.remove(namespace).is_some()
@@ -140,6 +163,8 @@ struct PeerState {
     state: SyncState,
     resync_requested: bool,
     last_sync: Option<(Instant, Result<SyncFinished>)>,
+    /// Pending ranges of the last sync request we initiated, if it did not complete.
+    pending: Option<PendingRanges>,
 }
 
 impl PeerState {
@@ -164,11 +189,28 @@ impl PeerState {
             }
         };
 
+        if result.is_ok() && matches!(origin, Origin::Connect(_)) {
+            self.pending = None;
+        }
         self.last_sync = Some((Instant::now(), result));
         self.state = SyncState::Idle;
         start.map(|s| (s, self.resync_requested))
     }
 
+    fn progress(&mut self, origin: &Origin, pending: &PendingRanges) -> bool {
+        match &self.state {
+            SyncState::Running {
+                origin: origin2, ..
+            } if origin2 == origin => {
+                if matches!(origin, Origin::Connect(_)) {
+                    self.pending = Some(pending.clone());
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
     fn start_connect(&mut self, reason: SyncReason) -> bool {
         match self.state {
             // never run two syncs at the same time
@@ -1,8 +1,8 @@
 //! Tickets for [`iroh-sync`] documents.
 
-use iroh_base::ticket;
+use iroh_base::{node_addr::NodeAddrV0, ticket};
 use iroh_net::NodeAddr;
-use iroh_sync::Capability;
+use iroh_sync::{metadata::DocMetadata, Capability};
 use serde::{Deserialize, Serialize};
 
 /// Contains both a key (either secret or public) to a document, and a list of peers to join.
@@ -13,29 +13,57 @@ pub struct DocTicket {
     pub capability: Capability,
     /// A list of nodes to contact.
     pub nodes: Vec<NodeAddr>,
+    /// A preview of the document's metadata, to show before joining.
+    pub metadata: Option<DocMetadata>,
 }
 
 /// Wire format for [`DocTicket`].
 ///
-/// In the future we might have multiple variants (not versions, since they
-/// might be both equally valid), so this is a single variant enum to force
-/// postcard to add a discriminator.
+/// Tickets without metadata and transport addresses are encoded as
+/// [`TicketWireFormat::Variant0`], so that they stay readable by nodes that know about
+/// neither.
 #[derive(Serialize, Deserialize)]
 enum TicketWireFormat {
-    Variant0(DocTicket),
+    Variant0(Variant0DocTicket),
+    Variant1(DocTicket),
+}
+
+/// A [`DocTicket`] without metadata and transport addresses.
+#[derive(Serialize, Deserialize)]
+struct Variant0DocTicket {
+    capability: Capability,
+    nodes: Vec<NodeAddrV0>,
 }
 
 impl ticket::Ticket for DocTicket {
     const KIND: &'static str = "doc";
 
     fn to_bytes(&self) -> Vec<u8> {
-        let data = TicketWireFormat::Variant0(self.clone());
+        let has_transport_addrs = self
+            .nodes
+            .iter()
+            .any(|node| !node.info.transport_addrs.is_empty());
+        let data = if self.metadata.is_none() && !has_transport_addrs {
+            TicketWireFormat::Variant0(Variant0DocTicket {
+                capability: self.capability.clone(),
+                nodes: self.nodes.iter().cloned().map(Into::into).collect(),
+            })
+        } else {
+            TicketWireFormat::Variant1(self.clone())
+        };
         postcard::to_stdvec(&data).expect("postcard serialization failed")
     }
 
     fn from_bytes(bytes: &[u8]) -> Result<Self, ticket::Error> {
         let res: TicketWireFormat = postcard::from_bytes(bytes).map_err(ticket::Error::Postcard)?;
-        let TicketWireFormat::Variant0(res) = res;
+        let res = match res {
+            TicketWireFormat::Variant0(Variant0DocTicket { capability, nodes }) => DocTicket {
+                capability,
+                nodes: nodes.into_iter().map(Into::into).collect(),
+                metadata: None,
+            },
+            TicketWireFormat::Variant1(res) => res,
+        };
         if res.nodes.is_empty() {
             return Err(ticket::Error::Verify("addressing info cannot be empty"));
         }
@@ -49,8 +77,15 @@ impl DocTicket {
         Self {
             capability,
             nodes: peers,
+            metadata: None,
         }
     }
+
+    /// Add a preview of the document's metadata.
+    pub fn with_metadata(mut self, metadata: DocMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
 }
 
 impl std::str::FromStr for DocTicket {
@@ -86,6 +121,7 @@ mod tests {
         let ticket = DocTicket {
             capability: Capability::Read(namespace_id),
             nodes: vec![NodeAddr::from_parts(node_id, None, vec![])],
+            metadata: None,
         };
         let base32 = base32::parse_vec(ticket.to_string().strip_prefix("doc").unwrap()).unwrap();
         let expected = parse_hexdump("
@@ -99,4 +135,57 @@ mod tests {
         ").unwrap();
         assert_eq_hex!(base32, expected);
     }
+
+    #[test]
+    fn test_ticket_base32_transport_addrs() {
+        let node_id =
+            PublicKey::from_str("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                .unwrap();
+        let namespace_id = NamespaceId::from(
+            &<[u8; 32]>::try_from(
+                hex::decode("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                    .unwrap(),
+            )
+            .unwrap(),
+        );
+
+        let ticket = DocTicket {
+            capability: Capability::Read(namespace_id),
+            nodes: vec![NodeAddr::new(node_id)
+                .with_transport_addrs(["unix:///run/a.sock".parse().unwrap()])],
+            metadata: None,
+        };
+        let base32 = base32::parse_vec(ticket.to_string().strip_prefix("doc").unwrap()).unwrap();
+        let expected = parse_hexdump("
+            01 # variant
+            01 # capability discriminator, 1 = read
+            ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6 # namespace id, 32 bytes, see above
+            01 # one node
+            ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6 # node id, 32 bytes, see above
+            00 # no derp url
+            00 # no direct addresses
+            01 # one transport address
+            12 756e69783a2f2f2f72756e2f612e736f636b # transport address, 18 bytes, see above
+            00 # no metadata
+        ").unwrap();
+        let decoded = DocTicket::from_str(&ticket.to_string()).unwrap();
+        assert_eq!(decoded.nodes, ticket.nodes);
+        assert_eq_hex!(base32, expected);
+    }
+
+    #[test]
+    fn test_ticket_metadata_roundtrip() {
+        let node_id =
+            PublicKey::from_str("ae58ff8833241ac82d6ff7611046ed67b5072d142c588d0063e942d9a75502b6")
+                .unwrap();
+        let namespace_id = NamespaceId::from(&[1u8; 32]);
+        let ticket = DocTicket::new(
+            Capability::Read(namespace_id),
+            vec![NodeAddr::from_parts(node_id, None, vec![])],
+        )
+        .with_metadata(DocMetadata::new("notes").with_description("my notes"));
+        let decoded = DocTicket::from_str(&ticket.to_string()).unwrap();
+        assert_eq!(decoded.metadata, ticket.metadata);
+        assert_eq!(decoded.capability.raw(), ticket.capability.raw());
+    }
 }
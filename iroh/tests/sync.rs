@@ -7,11 +7,12 @@ use std::{
 
 use anyhow::{anyhow, bail, Context, Result};
 use bytes::Bytes;
-use futures::{Stream, StreamExt};
+use futures::{Stream, StreamExt, TryStreamExt};
 use iroh::{
     client::{mem::Doc, Entry, LiveEvent},
     node::{Builder, Node},
-    rpc_protocol::ShareMode,
+    rpc_protocol::{BlobDownloadRequest, DownloadLocation, SetTagOption, ShareMode},
+    sync_engine::{LiveEventKind, SubscribeFilter},
 };
 use iroh_net::key::{PublicKey, SecretKey};
 use quic_rpc::transport::misc::DummyServerEndpoint;
@@ -20,10 +21,16 @@ use tracing::{debug, info};
 use tracing_subscriber::{prelude::*, EnvFilter};
 
 use iroh_bytes::Hash;
-use iroh_net::derp::DerpMode;
+use iroh_net::{
+    derp::DerpMode,
+    sim::{Link, Nat, SimNetwork},
+};
 use iroh_sync::{
-    store::{self, DownloadPolicy, FilterKind, Query},
-    AuthorId, ContentStatus,
+    bundle::SyncBundle,
+    crdt::{OrSet, PnCounter},
+    metadata::DocMetadata,
+    store::{self, DownloadPolicy, FilterKind, IndexExtractor, IndexSpec, Query},
+    Author, AuthorId, ContentStatus,
 };
 
 const TIMEOUT: Duration = Duration::from_secs(60);
@@ -85,12 +92,12 @@ async fn sync_simple() -> Result<()> {
     assert_latest(&doc0, b"k1", b"v1").await;
     let ticket = doc0.share(ShareMode::Write).await?;
 
-    let mut events0 = doc0.subscribe().await?;
+    let mut events0 = subscribe_without_progress(&doc0).await?;
 
     info!("node1: join");
     let peer1 = nodes[1].node_id();
     let doc1 = clients[1].docs.import(ticket.clone()).await?;
-    let mut events1 = doc1.subscribe().await?;
+    let mut events1 = subscribe_without_progress(&doc1).await?;
     info!("node1: assert 4 events");
     assert_next_unordered(
         &mut events1,
@@ -122,6 +129,106 @@ async fn sync_simple() -> Result<()> {
     Ok(())
 }
 
+/// Tests a sync between two nodes which only reach each other over a simulated network,
+/// with one of them behind a NAT.
+///
+/// This runs on the real clock: the sync actor of a node runs on its own thread, so a paused
+/// clock would advance while it works and fire the QUIC timeouts early. Therefore the
+/// datagrams lost on the network differ between runs, and the test must pass regardless.
+#[tokio::test]
+async fn sync_simulated_network() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_simulated_network");
+    let net = SimNetwork::new(rng.gen());
+    net.set_default_link(Link {
+        latency: Duration::from_millis(20),
+        loss: 0.01,
+    });
+    let mut nodes = vec![];
+    for nat in [Nat::None, Nat::AddressDependent] {
+        let node = test_node(SecretKey::generate_with_rng(&mut rng))
+            .transport(Arc::new(net.add_node(nat)))
+            .spawn()
+            .await?;
+        nodes.push(node);
+    }
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let peer0 = nodes[0].node_id();
+    let author0 = clients[0].authors.create().await?;
+    let doc0 = clients[0].docs.create().await?;
+    let hash0 = doc0
+        .set_bytes(author0, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    let mut ticket = doc0.share(ShareMode::Write).await?;
+    // Only leave the simulated network as a path between the nodes.
+    for node_addr in ticket.nodes.iter_mut() {
+        node_addr.info.direct_addresses.clear();
+    }
+
+    let doc1 = clients[1].docs.import(ticket).await?;
+    let mut events1 = subscribe_without_progress(&doc1).await?;
+    assert_next_unordered(
+        &mut events1,
+        TIMEOUT,
+        vec![
+            Box::new(move |e| matches!(e, LiveEvent::NeighborUp(peer) if *peer == peer0)),
+            Box::new(move |e| matches!(e, LiveEvent::InsertRemote { from, .. } if *from == peer0 )),
+            Box::new(move |e| match_sync_finished(e, peer0)),
+            Box::new(move |e| matches!(e, LiveEvent::ContentReady { hash } if *hash == hash0)),
+        ],
+    )
+    .await;
+    assert_latest(&doc1, b"k1", b"v1").await;
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+/// Test that a sync reports its progress
+#[tokio::test]
+async fn sync_progress() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_progress");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let peer0 = nodes[0].node_id();
+    let author0 = clients[0].authors.create().await?;
+    let doc0 = clients[0].docs.create().await?;
+    for i in 0..20 {
+        doc0.set_bytes(author0, format!("k{i}"), b"v".to_vec())
+            .await?;
+    }
+    let ticket = doc0.share(ShareMode::Write).await?;
+
+    let doc1 = clients[1].docs.import(ticket).await?;
+    let mut events1 = doc1.subscribe().await?;
+    let mut last_progress = None;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match next(&mut events1).await {
+                LiveEvent::SyncProgress(progress) if progress.peer == peer0 => {
+                    last_progress = Some(progress)
+                }
+                e if match_sync_finished(&e, peer0) => break,
+                _ => {}
+            }
+        }
+    })
+    .await?;
+    let progress = last_progress.expect("no progress reported");
+    assert_eq!(progress.num_recv, 20);
+    assert!(progress.num_ranges_reconciled > 0);
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
 /// Test subscribing to replica events (without sync)
 #[tokio::test]
 async fn sync_subscribe_no_sync() -> Result<()> {
@@ -241,7 +348,7 @@ async fn sync_full_basic() -> Result<()> {
     let peer0 = nodes[0].node_id();
     let author0 = clients[0].authors.create().await?;
     let doc0 = clients[0].docs.create().await?;
-    let mut events0 = doc0.subscribe().await?;
+    let mut events0 = subscribe_without_progress(&doc0).await?;
     let key0 = b"k1";
     let value0 = b"v1";
     let hash0 = doc0
@@ -264,7 +371,7 @@ async fn sync_full_basic() -> Result<()> {
     let doc1 = clients[1].docs.import(ticket.clone()).await?;
 
     info!("peer1: wait for 4 events (for sync and join with peer0)");
-    let mut events1 = doc1.subscribe().await?;
+    let mut events1 = subscribe_without_progress(&doc1).await?;
     assert_next_unordered(
         &mut events1,
         TIMEOUT,
@@ -326,7 +433,7 @@ async fn sync_full_basic() -> Result<()> {
     clients.push(nodes.last().unwrap().client());
     let doc2 = clients[2].docs.import(ticket).await?;
     let peer2 = nodes[2].node_id();
-    let mut events2 = doc2.subscribe().await?;
+    let mut events2 = subscribe_without_progress(&doc2).await?;
 
     info!("peer2: wait for 8 events (from sync with peers)");
     assert_next_unordered_with_optionals(
@@ -881,6 +988,236 @@ async fn doc_delete() -> Result<()> {
     Ok(())
 }
 
+/// Test that concurrent values for a key are reported as conflicts and can be merged.
+#[tokio::test]
+async fn sync_conflict_merge() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"sync_conflict_merge");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let author0 = clients[0].authors.create().await?;
+    let author1 = clients[1].authors.create().await?;
+    let doc0 = clients[0].docs.create().await?;
+    let mut ticket = doc0.share(ShareMode::Write).await?;
+    // unset peers to not yet start sync
+    let peers = ticket.nodes.clone();
+    ticket.nodes = vec![];
+    let doc1 = clients[1].docs.import(ticket).await?;
+
+    // both nodes write a value for the same key without seeing the other one
+    let hash1 = doc1
+        .set_bytes(author1, b"k".to_vec(), b"b".to_vec())
+        .await?;
+    doc0.set_bytes(author0, b"k".to_vec(), b"a".to_vec())
+        .await?;
+
+    // node0 receives the older value of node1, which is a conflict for node0
+    let mut events0 = subscribe_without_progress(&doc0).await?;
+    let mut events1 = subscribe_without_progress(&doc1).await?;
+    doc0.start_sync(vec![]).await?;
+    doc1.start_sync(peers).await?;
+    let (mut conflict, mut ready) = (None, false);
+    tokio::time::timeout(TIMEOUT, async {
+        while conflict.is_none() || !ready {
+            match next(&mut events0).await {
+                LiveEvent::Conflict { key, heads } => conflict = Some((key, heads)),
+                LiveEvent::ContentReady { hash } if hash == hash1 => ready = true,
+                _ => {}
+            }
+        }
+    })
+    .await?;
+    tokio::time::timeout(TIMEOUT, async {
+        while !matches!(next(&mut events1).await, LiveEvent::InsertRemote { .. }) {}
+    })
+    .await?;
+    let (key, heads) = conflict.unwrap();
+    assert_eq!(&key[..], b"k");
+    let authors = heads.iter().map(|e| e.author()).collect::<Vec<_>>();
+    assert_eq!(authors, vec![author0, author1]);
+    assert_eq!(doc0.get_heads(b"k").await?, heads);
+    assert_eq!(doc1.get_heads(b"k").await?, heads);
+
+    let merged = doc0
+        .merge(author0, b"k".to_vec(), |heads| {
+            let mut values = heads.iter().map(|(_, v)| v.to_vec()).collect::<Vec<_>>();
+            values.sort();
+            Ok(values.concat().into())
+        })
+        .await?;
+    assert!(merged.is_some());
+    assert_latest(&doc0, b"k", b"ab").await;
+    let heads = doc0.get_heads(b"k").await?;
+    assert_eq!(heads[0].author(), author0);
+
+    // nothing to merge for a key with a single head
+    doc0.set_bytes(author0, b"single".to_vec(), b"x".to_vec())
+        .await?;
+    let merged = doc0
+        .merge(author0, b"single".to_vec(), |_| Ok(Bytes::new()))
+        .await?;
+    assert!(merged.is_none());
+
+    // a value written after seeing the other values is not a conflict
+    let hash2 = doc1
+        .set_bytes(author1, b"k".to_vec(), b"c".to_vec())
+        .await?;
+    tokio::time::timeout(TIMEOUT, async {
+        loop {
+            match next(&mut events0).await {
+                LiveEvent::Conflict { .. } => panic!("unexpected conflict"),
+                LiveEvent::ContentReady { hash } if hash == hash2 => break,
+                _ => {}
+            }
+        }
+    })
+    .await?;
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+/// Test the typed CRDT values on a document.
+#[tokio::test]
+async fn doc_crdt() -> Result<()> {
+    let node = Node::memory().spawn().await?;
+    let client = node.client();
+    let doc = client.docs.create().await?;
+    let alice = client.authors.create().await?;
+    let bob = client.authors.create().await?;
+
+    let mut counter = doc.get_crdt::<PnCounter>(b"counter").await?;
+    counter.increment(alice, 5);
+    doc.set_crdt(alice, b"counter".to_vec(), &counter).await?;
+    let mut counter = doc.get_crdt::<PnCounter>(b"counter").await?;
+    counter.decrement(bob, 2);
+    doc.set_crdt(bob, b"counter".to_vec(), &counter).await?;
+    let counter = doc.get_crdt::<PnCounter>(b"counter").await?;
+    assert_eq!(counter.value(), 3);
+
+    let mut set = doc.get_crdt::<OrSet<String>>(b"set").await?;
+    set.insert(alice, "a".to_string());
+    set.insert(bob, "b".to_string());
+    doc.set_crdt(alice, b"set".to_vec(), &set).await?;
+    doc.set_crdt(bob, b"set".to_vec(), &set).await?;
+    let mut set = doc.get_crdt::<OrSet<String>>(b"set").await?;
+    set.remove(bob, &"a".to_string());
+    doc.set_crdt(bob, b"set".to_vec(), &set).await?;
+    let set = doc.get_crdt::<OrSet<String>>(b"set").await?;
+    assert_eq!(set.value(), ["b".to_string()].into());
+
+    node.shutdown();
+    Ok(())
+}
+
+#[tokio::test]
+async fn doc_query_count_and_cursor() -> Result<()> {
+    let node = Node::memory().spawn().await?;
+    let client = node.client();
+    let doc = client.docs.create().await?;
+    let author = client.authors.create().await?;
+    for key in ["a", "b", "c"] {
+        doc.set_bytes(author, key.as_bytes().to_vec(), b"v".to_vec())
+            .await?;
+    }
+
+    assert_eq!(doc.count(Query::all()).await?, 3);
+    assert_eq!(doc.count(Query::key_range("b"..)).await?, 2);
+
+    let first = doc
+        .get_one(Query::single_latest_per_key())
+        .await?
+        .expect("entry exists");
+    let rest = doc
+        .get_many(Query::single_latest_per_key().after((&first).into()))
+        .await?
+        .map_ok(|entry| entry.key().to_vec())
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(rest, vec![b"b".to_vec(), b"c".to_vec()]);
+    node.shutdown();
+    Ok(())
+}
+
+/// Test secondary indexes over content for local and remote inserts.
+#[tokio::test]
+async fn doc_secondary_index() -> Result<()> {
+    setup_logging();
+    let mut rng = test_rng(b"doc_secondary_index");
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let status = IndexSpec::new("status", IndexExtractor::JsonField(vec!["status".into()]));
+    let author0 = clients[0].authors.create().await?;
+    let doc0 = clients[0].docs.create().await?;
+    doc0.create_index(status.clone()).await?;
+    doc0.set_bytes(
+        author0,
+        b"task/1".to_vec(),
+        br#"{"status":"done"}"#.to_vec(),
+    )
+    .await?;
+    doc0.set_bytes(
+        author0,
+        b"task/2".to_vec(),
+        br#"{"status":"todo"}"#.to_vec(),
+    )
+    .await?;
+    assert_eq!(doc0.list_indexes().await?, vec![status.clone()]);
+    assert_eq!(indexed_keys(&doc0, "done").await?, vec![b"task/1".to_vec()]);
+    assert_eq!(indexed_keys(&doc0, "todo").await?, vec![b"task/2".to_vec()]);
+
+    // indexes are local to a node, node1 creates its own and fills it from synced content
+    let ticket = doc0.share(ShareMode::Write).await?;
+    let doc1 = clients[1].docs.import(ticket).await?;
+    doc1.create_index(status).await?;
+    let author1 = clients[1].authors.create().await?;
+    doc1.set_bytes(
+        author1,
+        b"task/3".to_vec(),
+        br#"{"status":"todo"}"#.to_vec(),
+    )
+    .await?;
+
+    let expected = vec![b"task/2".to_vec(), b"task/3".to_vec()];
+    wait_for_indexed_keys(&doc1, "todo", &expected).await?;
+    wait_for_indexed_keys(&doc0, "todo", &expected).await?;
+
+    assert!(doc0.remove_index("status").await?);
+    assert!(doc0.list_indexes().await?.is_empty());
+    assert!(indexed_keys(&doc0, "todo").await.is_err());
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+async fn indexed_keys(doc: &Doc, status: &str) -> Result<Vec<Vec<u8>>> {
+    let mut keys = doc
+        .get_by_index("status", status)
+        .await?
+        .map_ok(|entry| entry.key().to_vec())
+        .try_collect::<Vec<_>>()
+        .await?;
+    keys.sort();
+    Ok(keys)
+}
+
+async fn wait_for_indexed_keys(doc: &Doc, status: &str, expected: &[Vec<u8>]) -> Result<()> {
+    tokio::time::timeout(TIMEOUT, async {
+        while indexed_keys(doc, status).await? != expected {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await
+    .context("timeout waiting for indexed keys")?
+}
+
 #[tokio::test]
 async fn sync_drop_doc() -> Result<()> {
     let mut rng = test_rng(b"sync_drop_doc");
@@ -912,6 +1249,238 @@ async fn sync_drop_doc() -> Result<()> {
     Ok(())
 }
 
+/// Test exporting a document as a snapshot and importing it on another node.
+#[tokio::test]
+async fn doc_snapshot() -> Result<()> {
+    let mut rng = test_rng(b"doc_snapshot");
+    setup_logging();
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let doc0 = clients[0].docs.create().await?;
+    let author = clients[0].authors.create().await?;
+    doc0.set_bytes(author, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    doc0.set_bytes(author, b"k2".to_vec(), b"v2".to_vec())
+        .await?;
+
+    let (snapshot, _tag) = doc0.snapshot(true, SetTagOption::Auto).await?;
+    clients[1]
+        .blobs
+        .download(BlobDownloadRequest {
+            hash: snapshot.hash,
+            format: snapshot.format,
+            peer: nodes[0].my_addr().await?,
+            tag: SetTagOption::Auto,
+            out: DownloadLocation::Internal,
+        })
+        .await?
+        .finish()
+        .await?;
+
+    let (doc1, outcome) = clients[1].docs.import_snapshot(snapshot).await?;
+    assert_eq!(outcome.inserted, 2);
+    assert_eq!(doc1.id(), doc0.id());
+    assert_latest(&doc1, b"k1", b"v1").await;
+    assert_latest(&doc1, b"k2", b"v2").await;
+
+    // importing again does not insert anything new
+    let (doc1, outcome) = clients[1].docs.import_snapshot(snapshot).await?;
+    assert_eq!((outcome.inserted, outcome.skipped), (0, 2));
+    let entries = doc1.get_many(Query::all()).await?.collect::<Vec<_>>().await;
+    assert_eq!(entries.len(), 2);
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn doc_bundle() -> Result<()> {
+    let mut rng = test_rng(b"doc_bundle");
+    setup_logging();
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let doc0 = clients[0].docs.create().await?;
+    let author = clients[0].authors.create().await?;
+    doc0.set_bytes(author, b"k1".to_vec(), b"v1".to_vec())
+        .await?;
+    doc0.set_bytes(author, b"k2".to_vec(), b"v2".to_vec())
+        .await?;
+
+    // the nodes never connect, the bundles are carried over as bytes.
+    let bundle = doc0.export_bundle(Default::default(), true).await?;
+    let bundle = SyncBundle::from_bytes(&bundle.to_bytes()?)?;
+    let (doc1, _outcome) = clients[1].docs.import_bundle(bundle).await?;
+    assert_eq!(doc1.id(), doc0.id());
+    assert_latest(&doc1, b"k1", b"v1").await;
+    assert_latest(&doc1, b"k2", b"v2").await;
+
+    // a bundle for the heads of node 1 only contains the new entry
+    doc0.set_bytes(author, b"k3".to_vec(), b"v3".to_vec())
+        .await?;
+    let heads = doc1.author_heads().await?;
+    let bundle = doc0.export_bundle(heads, true).await?;
+    assert_eq!(bundle.entries().len(), 1);
+    assert_eq!(bundle.content().len(), 1);
+    let (_doc, outcome) = clients[1].docs.import_bundle(bundle).await?;
+    assert_eq!(outcome.inserted, 1);
+    assert_latest(&doc1, b"k3", b"v3").await;
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn doc_metadata_and_label() -> Result<()> {
+    let mut rng = test_rng(b"doc_metadata_and_label");
+    setup_logging();
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let doc0 = clients[0].docs.create().await?;
+    let metadata = DocMetadata::new("notes").with_description("shared notes");
+    doc0.set_metadata(metadata.clone()).await?;
+    assert_eq!(doc0.metadata().await?, Some(metadata.clone()));
+
+    // the ticket contains a preview of the metadata
+    let ticket = doc0.share(ShareMode::Read).await?;
+    assert_eq!(ticket.metadata, Some(metadata.clone()));
+
+    // the metadata syncs with the document
+    let doc1 = clients[1].docs.import(ticket).await?;
+    tokio::time::timeout(TIMEOUT, async {
+        while doc1.metadata().await?.is_none() {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        anyhow::Ok(())
+    })
+    .await??;
+    assert_eq!(doc1.metadata().await?, Some(metadata));
+
+    // only the owner can set the metadata
+    assert!(doc1.set_metadata(DocMetadata::new("fake")).await.is_err());
+
+    // labels are local
+    doc1.set_label(Some("work".to_string())).await?;
+    assert_eq!(doc1.label().await?, Some("work".to_string()));
+    assert_eq!(doc0.label().await?, None);
+
+    let docs = clients[1]
+        .docs
+        .list_with_names()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(docs.len(), 1);
+    assert_eq!(docs[0].name.as_deref(), Some("notes"));
+    assert_eq!(docs[0].label.as_deref(), Some("work"));
+
+    doc1.set_label(None).await?;
+    assert_eq!(doc1.label().await?, None);
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn author_export_import_delete() -> Result<()> {
+    let mut rng = test_rng(b"author_export_import_delete");
+    setup_logging();
+    let nodes = spawn_nodes(2, &mut rng).await?;
+    let clients = nodes.iter().map(|node| node.client()).collect::<Vec<_>>();
+
+    let author = clients[0].authors.create().await?;
+    let exported = clients[0]
+        .authors
+        .export(author)
+        .await?
+        .expect("author exists");
+    let backup = exported.to_encrypted_bytes("passphrase");
+
+    // move the author to the other node
+    let restored = Author::from_encrypted_bytes("passphrase", &backup)?;
+    assert_eq!(clients[1].authors.import(restored).await?, author);
+    let doc = clients[1].docs.create().await?;
+    doc.set_bytes(author, b"k".to_vec(), b"v".to_vec()).await?;
+
+    // and retire it on the first node
+    clients[0].authors.delete(author).await?;
+    assert!(clients[0].authors.export(author).await?.is_none());
+    assert!(clients[0].authors.delete(author).await.is_err());
+    let authors = clients[0]
+        .authors
+        .list()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert!(!authors.contains(&author));
+
+    for node in nodes {
+        node.shutdown();
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn subscribe_filtered() -> Result<()> {
+    let mut rng = test_rng(b"subscribe_filtered");
+    setup_logging();
+    let node = spawn_node(0, &mut rng).await?;
+    let client = node.client();
+    let doc = client.docs.create().await?;
+    let alice = client.authors.create().await?;
+    let bob = client.authors.create().await?;
+    doc.set_bytes(alice, b"a/1".to_vec(), b"1".to_vec()).await?;
+    doc.set_bytes(bob, b"a/2".to_vec(), b"2".to_vec()).await?;
+    doc.set_bytes(alice, b"b/1".to_vec(), b"1".to_vec()).await?;
+
+    // replay the matching entries, then stream live changes
+    let filter = SubscribeFilter::all().with_key_prefix(b"a/");
+    let mut events = doc.subscribe_filtered(filter, true).await?;
+    let mut replayed = vec![];
+    loop {
+        match next(&mut events).await {
+            LiveEvent::Replay { entry, .. } => replayed.push(entry.key().to_vec()),
+            LiveEvent::ReplayFinished => break,
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+    replayed.sort();
+    assert_eq!(replayed, vec![b"a/1".to_vec(), b"a/2".to_vec()]);
+
+    doc.set_bytes(alice, b"b/2".to_vec(), b"2".to_vec()).await?;
+    doc.set_bytes(alice, b"a/3".to_vec(), b"3".to_vec()).await?;
+    match next(&mut events).await {
+        LiveEvent::InsertLocal { entry } => assert_eq!(entry.key(), b"a/3"),
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    // filter by author and kind
+    let filter = SubscribeFilter::all()
+        .with_author(bob)
+        .with_kind(LiveEventKind::InsertLocal);
+    let mut events = doc.subscribe_filtered(filter, false).await?;
+    doc.set_bytes(alice, b"a/4".to_vec(), b"4".to_vec()).await?;
+    doc.set_bytes(bob, b"b/3".to_vec(), b"3".to_vec()).await?;
+    match next(&mut events).await {
+        LiveEvent::InsertLocal { entry } => {
+            assert_eq!(entry.key(), b"b/3");
+            assert_eq!(entry.author(), bob);
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+
+    node.shutdown();
+    Ok(())
+}
+
 async fn assert_latest(doc: &Doc, key: &[u8], value: &[u8]) {
     let content = get_latest(doc, key).await.unwrap();
     assert_eq!(content, value.to_vec());
@@ -1038,6 +1607,14 @@ async fn assert_next_unordered_with_optionals<T: std::fmt::Debug + Clone>(
     events
 }
 
+/// Subscribe to the events of a document, skipping [`LiveEvent::SyncProgress`] events.
+async fn subscribe_without_progress(
+    doc: &Doc,
+) -> Result<impl Stream<Item = Result<LiveEvent>> + Unpin + Send> {
+    let events = doc.subscribe().await?;
+    Ok(events.try_filter(|e| futures::future::ready(!matches!(e, LiveEvent::SyncProgress(_)))))
+}
+
 /// Asserts that the event is a [`LiveEvent::SyncFinished`] and that the contained [`SyncEvent`]
 /// has no error and matches `peer` and `namespace`.
 fn match_sync_finished(event: &LiveEvent, peer: PublicKey) -> bool {